@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use qsh_rs::orderbook::{NopListener, OrderBook, OrderLogStream, Storage};
+use qsh_rs::types::OrderLog;
+use qsh_rs::{header, inflate, OrderLogReader, QshRead};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+const SI: &str = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+const STORAGES: [(&str, Storage); 2] =
+    [("sorted", Storage::Sorted), ("ladder", Storage::Ladder { step: 1 })];
+
+fn replay(records: &[OrderLog], storage: Storage) -> OrderBook {
+    let mut book = OrderBook::with_storage(storage);
+    for &rec in records {
+        book.apply(rec, &mut NopListener).unwrap();
+    }
+    book
+}
+
+fn thick_levels(c: &mut Criterion) {
+    // few levels with thousands of resting orders each
+    let records: Vec<_> = common::Workload::new(1, 3).take(200_000).collect();
+    for (name, storage) in STORAGES {
+        c.bench_function(&format!("replay/thick_levels/{name}"), |b| {
+            b.iter_batched(|| &records, |r| replay(r, storage), BatchSize::LargeInput)
+        });
+    }
+}
+
+fn wide_book(c: &mut Criterion) {
+    let records: Vec<_> = common::Workload::new(1, 2_000).take(200_000).collect();
+    for (name, storage) in STORAGES {
+        c.bench_function(&format!("replay/wide_book/{name}"), |b| {
+            b.iter_batched(|| &records, |r| replay(r, storage), BatchSize::LargeInput)
+        });
+    }
+}
+
+fn snapshot(c: &mut Criterion) {
+    let book =
+        replay(&common::Workload::new(1, 40).take(20_000).collect::<Vec<_>>(), Storage::Sorted);
+    let depth = 10;
+    c.bench_function("snapshot/alloc", |b| b.iter(|| black_box(book.snapshot(depth))));
+    let mut out = vec![0; depth * 4];
+    c.bench_function("snapshot/into", |b| {
+        b.iter(|| black_box(book.snapshot_into(depth, &mut out).unwrap()))
+    });
+}
+
+fn fixture(c: &mut Criterion) {
+    // the fixture isn't part of the repo
+    let Ok(mut parser) = inflate(SI.into()) else {
+        return eprintln!("{SI} is not available, skipped");
+    };
+    header(&mut parser).unwrap();
+    let txs: Vec<_> = parser.into_iter::<OrderLogReader>().transactions().collect();
+    for (name, storage) in STORAGES {
+        c.bench_function(&format!("replay/si/{name}"), |b| {
+            b.iter(|| {
+                let mut book = OrderBook::with_storage(storage);
+                txs.iter().for_each(|tx| book.apply_tx(tx, &mut NopListener).unwrap());
+                book
+            })
+        });
+    }
+}
+
+criterion_group!(benches, thick_levels, wide_book, snapshot, fixture);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use qsh_rs::orderbook::{tx_end, PartitionBy};
+use qsh_rs::types::{OLFlags, OrderLog};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+// transactions of 1-4 records
+fn records() -> Vec<OrderLog> {
+    common::Workload::new(1, 20)
+        .take(200_000)
+        .enumerate()
+        .map(|(i, r)| if i % 4 == 0 || i % 3 == 0 { common::with(r, OLFlags::TxEnd) } else { r })
+        .collect()
+}
+
+fn partition(c: &mut Criterion) {
+    let records = records();
+    c.bench_function("partition/owned", |b| {
+        b.iter(|| {
+            records
+                .iter()
+                .copied()
+                .partition_by(tx_end)
+                .map(|tx| black_box(tx).len())
+                .sum::<usize>()
+        })
+    });
+    c.bench_function("partition/reuse", |b| {
+        b.iter(|| {
+            let (mut txs, mut n) = (records.iter().copied().partition_by_reuse(tx_end), 0);
+            while let Some(tx) = txs.next_partition() {
+                n += black_box(tx).len();
+            }
+            n
+        })
+    });
+    c.bench_function("partition/for_each", |b| {
+        b.iter(|| {
+            let mut n = 0;
+            records
+                .iter()
+                .copied()
+                .partition_by_reuse(tx_end)
+                .for_each_partition(|tx| n += black_box(tx).len());
+            n
+        })
+    });
+}
+
+criterion_group!(benches, partition);
+criterion_main!(benches);
@@ -0,0 +1,89 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use qsh_rs::orderbook::{NopListener, OrderBook, OrderLogStream};
+use qsh_rs::types::OrderLog;
+use qsh_rs::{header, inflate, OrderLogReader, QshRead};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+const SI: &str = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+const SESSIONS: usize = 5;
+
+// counts the allocations, the replays report the count per session
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// the same session replayed `SESSIONS` times by the book cleared in between, as the book of the
+// multi-session file is, allocations per session
+fn replay(book: &mut OrderBook, session: &[OrderLog]) -> Vec<usize> {
+    (0..SESSIONS)
+        .map(|_| {
+            let before = ALLOCATIONS.load(Relaxed);
+            book.clear();
+            session.iter().for_each(|&rec| book.apply(rec, &mut NopListener).unwrap());
+            ALLOCATIONS.load(Relaxed) - before
+        })
+        .collect()
+}
+
+fn synthetic(c: &mut Criterion) {
+    let session: Vec<_> = common::Workload::new(1, 500).take(50_000).collect();
+    eprintln!("allocations per session: {:?}", replay(&mut OrderBook::default(), &session));
+    c.bench_function("sessions/synthetic", |b| {
+        let mut book = OrderBook::default();
+        b.iter(|| replay(&mut book, &session))
+    });
+}
+
+fn fixture(c: &mut Criterion) {
+    // the fixture isn't part of the repo
+    let Ok(mut parser) = inflate(SI.into()) else {
+        return eprintln!("{SI} is not available, skipped");
+    };
+    header(&mut parser).unwrap();
+    let txs: Vec<_> = parser.into_iter::<OrderLogReader>().transactions().collect();
+    // the file has its own NewSession clears, the replays are cleared on top of them
+    let mut book = OrderBook::default();
+    let per_replay: Vec<_> = (0..SESSIONS)
+        .map(|_| {
+            let before = ALLOCATIONS.load(Relaxed);
+            book.clear();
+            txs.iter().for_each(|tx| book.apply_tx(tx, &mut NopListener).unwrap());
+            ALLOCATIONS.load(Relaxed) - before
+        })
+        .collect();
+    eprintln!("{SI} allocations per replay: {per_replay:?}");
+    c.bench_function("sessions/si", |b| {
+        b.iter(|| {
+            book.clear();
+            txs.iter().for_each(|tx| book.apply_tx(tx, &mut NopListener).unwrap());
+        })
+    });
+}
+
+criterion_group!(benches, synthetic, fixture);
+criterion_main!(benches);
@@ -0,0 +1,86 @@
+/// tokio adapters.
+///
+/// Decompression and parsing are synchronous, they run on the blocking thread pool reading
+/// from the async source through the `SyncIoBridge`, records are delivered to the async side
+/// over a bounded channel.
+use crate::{header, types::Header, QshError, QshParser, QshRead};
+use flate2::bufread::GzDecoder;
+use futures_core::Stream;
+use std::{
+    io::BufReader,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::AsyncRead,
+    sync::{mpsc, oneshot},
+};
+use tokio_util::io::SyncIoBridge;
+
+const CHANNEL_CAPACITY: usize = 1 << 12;
+
+/// Stream of the parsed records, ends after the first error
+pub struct RecordStream<T>(mpsc::Receiver<Result<T, QshError>>);
+
+impl<T> Stream for RecordStream<T> {
+    type Item = Result<T, QshError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+/// Reads the header of gzipped qsh data from `src` and streams the records with the `T` reader.
+/// Must be called from within the tokio runtime.
+///
+/// ```no_run
+/// # async fn f() -> Result<(), qsh_rs::QshError> {
+/// let file = tokio::fs::File::open("Si-3.20.2020-03-17.OrdLog.qsh").await?;
+/// let (header, records) = qsh_rs::aio::inflate_async::<qsh_rs::OrderLogReader, _>(file).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn inflate_async<T, R>(src: R) -> Result<(Header, RecordStream<T::Item>), QshError>
+where
+    T: QshParser + Send + 'static,
+    T::Item: Send + 'static,
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let (header_tx, header_rx) = oneshot::channel();
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        let mut reader = BufReader::new(GzDecoder::new(BufReader::new(SyncIoBridge::new(src))));
+        match header(&mut reader) {
+            Ok(h) => {
+                if header_tx.send(Ok(h)).is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                let _ = header_tx.send(Err(err));
+                return;
+            }
+        }
+
+        let mut parser = T::default();
+        loop {
+            let rec = match reader.eof() {
+                Ok(true) => return,
+                Ok(false) => parser.parse(&mut reader),
+                Err(err) => Err(err),
+            };
+            if rec.is_ok() && parser.skipped() {
+                continue;
+            }
+            let failed = rec.is_err();
+            // receiver is dropped
+            if tx.blocking_send(rec).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    let header = header_rx.await.map_err(|err| QshError::General { source: Box::new(err) })??;
+    Ok((header, RecordStream(rx)))
+}
@@ -0,0 +1,27 @@
+//! Book snapshots as JSON Lines, one `{"ts":..,"bids":[[price,volume],..],"asks":[..]}` object
+//! per line, `ts` is the unix time of the book
+
+use crate::{orderbook::BookSnapshot, QshError};
+use std::io::{Error, Write};
+
+/// Writes the `snapshots` truncated to `depth` levels per side, `0` for all of them. Returns
+/// the number of the lines written.
+pub fn write_snapshots_jsonl<W: Write>(
+    snapshots: impl IntoIterator<Item = impl Into<BookSnapshot>>,
+    depth: usize,
+    mut w: W,
+) -> Result<usize, QshError> {
+    let mut n = 0;
+    for snapshot in snapshots {
+        let mut snapshot = snapshot.into();
+        if depth > 0 {
+            snapshot.bids.truncate(depth);
+            snapshot.asks.truncate(depth);
+        }
+        serde_json::to_writer(&mut w, &snapshot).map_err(Error::from)?;
+        w.write_all(b"\n")?;
+        n += 1;
+    }
+    w.flush()?;
+    Ok(n)
+}
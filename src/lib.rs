@@ -1,18 +1,33 @@
 use flate2::bufread::GzDecoder;
 use std::{
-    fs::File,
+    cell::Cell,
     io::{BufRead, BufReader, ErrorKind, Read},
-    path::PathBuf,
+    rc::Rc,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, path::PathBuf};
 use thiserror::Error;
+#[cfg(feature = "tokio")]
+pub mod aio;
+#[cfg(feature = "jsonl")]
+pub mod export;
 pub mod orderbook;
 mod parse;
+pub mod policy;
+pub mod source;
+pub mod time;
 pub mod types;
 pub mod utils;
-pub use parse::{AuxInfoReader, DealReader, OrderLogReader, QshParser, QuotesReader};
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub use parse::{
+    AuxInfoReader, DealReader, DealTimeReader, OrderLogReader, QshParser, QuotesReader, TimedDeal,
+};
 
-use crate::types::Header;
-use leb128::read as leb128;
+use crate::{
+    policy::ParsePolicy,
+    types::{Header, Stream},
+};
 
 #[derive(Error, Debug)]
 pub enum QshError {
@@ -32,17 +47,31 @@ pub enum QshError {
     InvalidState(String),
     #[error("QSH parsing error: `{0}`")]
     Parsing(String),
+    /// the gzip stream is cut off or fails its CRC/length check, e.g. a partial download
+    #[error("Truncated gzip stream: `{0}`")]
+    Truncated(String),
+    /// the data ends inside a record while the gzip stream is intact
+    #[error("Record cut off by the end of the data: `{0}`")]
+    Corrupted(String),
 }
 
 unsafe impl Send for QshError {}
 unsafe impl Sync for QshError {}
 
+/// Opens the gzipped qsh file, `-` reads it from the standard input
+#[cfg(not(target_arch = "wasm32"))]
 pub fn inflate(path: PathBuf) -> Result<impl BufRead, QshError> {
-    File::open(path)
-        .map(BufReader::new)
-        .map(GzDecoder::new)
-        .map(BufReader::new)
-        .map_err(|err| err.into())
+    let source: Box<dyn Read + Send> = if path.as_os_str() == "-" {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    Ok(inflate_reader(source))
+}
+
+/// Decompresses the qsh bytes of any source, no seeking required
+pub fn inflate_reader<R: Read>(source: R) -> impl BufRead {
+    BufReader::new(GzDecoder::new(BufReader::new(source)))
 }
 
 pub trait QshRead: Read + Sized {
@@ -82,12 +111,39 @@ pub trait QshRead: Read + Sized {
         })
     }
 
+    // LEB128 byte by byte through `consume_with`, so the end of the data inside the number is
+    // told from the truncated gzip stream the same way as for the fixed size fields
     fn uleb(&mut self) -> Result<u64, QshError> {
-        leb128::unsigned(self).map_err(|err| QshError::General { source: Box::new(err) })
+        let (mut res, mut shift) = (0u64, 0);
+        loop {
+            let byte = self.byte()?;
+            if shift == 63 && byte > 1 {
+                return Err(QshError::Parsing("unsigned LEB128 overflow".into()));
+            }
+            res |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(res);
+            }
+            shift += 7;
+        }
     }
 
     fn leb(&mut self) -> Result<i64, QshError> {
-        leb128::signed(self).map_err(|err| QshError::General { source: Box::new(err) })
+        let (mut res, mut shift) = (0i64, 0);
+        loop {
+            let byte = self.byte()?;
+            if shift == 63 && byte != 0x00 && byte != 0x7f {
+                return Err(QshError::Parsing("signed LEB128 overflow".into()));
+            }
+            res |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    res |= !0 << shift;
+                }
+                return Ok(res);
+            }
+        }
     }
 
     fn growing(&mut self) -> Result<i64, QshError> {
@@ -107,7 +163,48 @@ pub trait QshRead: Read + Sized {
     }
 
     fn into_iter<T: QshParser>(self) -> RecordIter<T, Self> {
-        RecordIter(T::default(), self)
+        RecordIter(T::default(), self, 0)
+    }
+
+    /// Same as `into_iter`, failing with `QshError::Validation` if the `header` of the file
+    /// is not of the stream of the reader, see [`QshParser::EXPECTED_STREAM`]
+    fn into_checked_iter<T: QshParser>(
+        self,
+        header: &Header,
+    ) -> Result<RecordIter<T, Self>, QshError> {
+        if header.stream != T::EXPECTED_STREAM {
+            return Err(QshError::Validation(format!(
+                "{:?} file read as {:?}",
+                header.stream,
+                T::EXPECTED_STREAM
+            )));
+        }
+        Ok(self.into_iter::<T>())
+    }
+
+    /// same as `into_iter`, with the preconfigured reader, e.g. `OrderLogReader::strict()`
+    fn into_iter_with<T: QshParser>(self, reader: T) -> RecordIter<T, Self> {
+        RecordIter(reader, self, 0)
+    }
+
+    /// same as `into_iter`, with the reader of the `policy`, see [`QshParser::with_policy`]
+    fn into_iter_with_policy<T: QshParser>(self, policy: ParsePolicy) -> RecordIter<T, Self> {
+        self.into_iter_with(T::with_policy(policy))
+    }
+
+    /// same as `into_iter`, yielding the parsing error instead of panicking, the iteration
+    /// stops after it
+    fn try_iter<T: QshParser>(self) -> TryRecordIter<T, Self> {
+        self.try_iter_with(T::default())
+    }
+
+    fn try_iter_with<T: QshParser>(self, reader: T) -> TryRecordIter<T, Self> {
+        TryRecordIter(RecordIter(reader, self, 0), false)
+    }
+
+    /// same as `try_iter`, with the reader of the `policy`, see [`QshParser::with_policy`]
+    fn try_iter_with_policy<T: QshParser>(self, policy: ParsePolicy) -> TryRecordIter<T, Self> {
+        self.try_iter_with(T::with_policy(policy))
     }
 
     fn consume_with<F, T>(&mut self, n: usize, f: F) -> Result<T, QshError>
@@ -127,7 +224,7 @@ where
     {
         debug_assert!(n > 0, "requested byte slice size should be > 0");
 
-        let buf = self.fill_buf()?;
+        let buf = self.fill_buf().map_err(source_error)?;
 
         match buf.len() {
             x if x >= n => {
@@ -135,7 +232,7 @@ where
                 self.consume(n);
                 Ok(ret)
             }
-            0 => Err(QshError::IO { source: ErrorKind::UnexpectedEof.into() }),
+            0 => Err(QshError::Corrupted(format!("{n} bytes requested at the end of the data"))),
             x => {
                 // workaround for buffer boundary
                 // [..x][x..]
@@ -148,10 +245,10 @@ where
                 self.consume(x);
 
                 let rem = n - x;
-                let buf = self.fill_buf()?;
+                let buf = self.fill_buf().map_err(source_error)?;
 
                 if buf.len() < rem {
-                    Err(QshError::IO { source: ErrorKind::UnexpectedEof.into() })
+                    Err(QshError::Corrupted(format!("{n} bytes requested, {} left", x + buf.len())))
                 } else {
                     b.extend_from_slice(&buf[..rem]);
                     let ret = f(&b[..]);
@@ -165,7 +262,19 @@ where
     }
 
     fn eof(&mut self) -> Result<bool, QshError> {
-        self.fill_buf().map(|b| b.is_empty()).map_err(|err| err.into())
+        self.fill_buf().map(|b| b.is_empty()).map_err(source_error)
+    }
+}
+
+// The source is the gzip decoder in practice: the stream cut off mid-member or inside the
+// trailer is an `UnexpectedEof`, the CRC/length mismatch is an `InvalidInput` of its own message.
+pub(crate) fn source_error(err: std::io::Error) -> QshError {
+    match err.kind() {
+        ErrorKind::UnexpectedEof => QshError::Truncated(err.to_string()),
+        ErrorKind::InvalidInput if err.to_string().contains("checksum") => {
+            QshError::Truncated(err.to_string())
+        }
+        _ => err.into(),
     }
 }
 
@@ -212,8 +321,13 @@ pub fn header<Q: QshRead>(parser: &mut Q) -> Result<Header, QshError> {
         _ => (),
     };
 
-    let recording_time = i64::max(recording_time, 0);
     let (stream_type, instrument) = (parser.byte()?, parser.string()?);
+    let supported = [Stream::QUOTES, Stream::DEALS, Stream::AUXINFO, Stream::ORDERLOG];
+    if !supported.iter().any(|stream| stream.as_byte() == stream_type) {
+        return Err(QshError::Validation(format!(
+            "Неподдерживаемый тип потока: {stream_type:#04x}"
+        )));
+    }
     Ok(Header {
         version,
         recorder,
@@ -224,16 +338,179 @@ pub fn header<Q: QshRead>(parser: &mut Q) -> Result<Header, QshError> {
     })
 }
 
-pub struct RecordIter<T, Q>(T, Q);
+// the reader, the source and the number of the records expected, see `with_capacity_hint`
+pub struct RecordIter<T, Q>(T, Q, usize);
+
+impl<T, Q> RecordIter<T, Q> {
+    /// the stream reader, e.g. to inspect `OrderLogReader::skipped_count`
+    pub fn reader(&self) -> &T {
+        &self.0
+    }
+
+    /// Sets the estimate of the number of the records, the capacity
+    /// [`collect_vec`](Self::collect_vec) reserves, less the records yielded. The stream has no
+    /// record count and the records are of no fixed size, so the estimate is the caller's, e.g.
+    /// of the previous files of the instrument, and may overshoot. It's not the lower bound of
+    /// the `size_hint` then, which stays `0`. The records past it are yielded all the same.
+    pub fn with_capacity_hint(mut self, n: usize) -> Self {
+        self.2 = n;
+        self
+    }
+}
+
+impl<T: QshParser, Q: QshRead> RecordIter<T, Q> {
+    fn try_next(&mut self) -> Result<Option<T::Item>, QshError> {
+        loop {
+            if self.1.eof()? {
+                return Ok(None);
+            }
+            let rec = self.0.parse(&mut self.1)?;
+            if !self.0.skipped() {
+                self.2 = self.2.saturating_sub(1);
+                return Ok(Some(rec));
+            }
+        }
+    }
+}
+
+impl<T: QshParser, Q: QshRead> RecordIter<T, Q> {
+    /// Decodes and drops the next `n` records, returns the number of the records skipped, less
+    /// than `n` at the stream end. There's no skipping the bytes, as the records are the deltas
+    /// of the previous ones, the reader state follows the skipped records the same as
+    /// `Iterator::skip` does. The frame time of the skipped records is lost, e.g. the worker
+    /// reading the records `[k*n, (k+1)*n)` with `skip_records(k*n)` and `take_records(n)` sums
+    /// the `frame_time_delta`s itself to get it.
+    pub fn skip_records(&mut self, n: usize) -> usize {
+        self.by_ref().take(n).count()
+    }
+
+    /// the next `n` records, see [`skip_records`](Self::skip_records)
+    pub fn take_records(&mut self, n: usize) -> std::iter::Take<&mut Self> {
+        self.by_ref().take(n)
+    }
+
+    /// the rest of the records, the vector reserves the
+    /// [`with_capacity_hint`](Self::with_capacity_hint) estimate upfront
+    pub fn collect_vec(self) -> Vec<T::Item> {
+        let mut records = Vec::with_capacity(self.2);
+        records.extend(self);
+        records
+    }
+}
 
 impl<T: QshParser, Q: QshRead> Iterator for RecordIter<T, Q> {
     type Item = T::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.1.eof().unwrap() {
-            None
-        } else {
-            Some(self.0.parse(&mut self.1).unwrap())
+        self.try_next().unwrap()
+    }
+}
+
+/// [`RecordIter`] yielding the errors, see [`QshRead::try_iter`]
+pub struct TryRecordIter<T, Q>(RecordIter<T, Q>, bool);
+
+impl<T, Q> TryRecordIter<T, Q> {
+    pub fn reader(&self) -> &T {
+        self.0.reader()
+    }
+
+    /// see [`RecordIter::with_capacity_hint`]
+    pub fn with_capacity_hint(self, n: usize) -> Self {
+        Self(self.0.with_capacity_hint(n), self.1)
+    }
+}
+
+impl<T: QshParser, Q: QshRead> TryRecordIter<T, Q> {
+    /// same as [`RecordIter::skip_records`], fails on the error of the skipped record
+    pub fn skip_records(&mut self, n: usize) -> Result<usize, QshError> {
+        self.by_ref().take(n).try_fold(0, |skipped, rec| rec.map(|_| skipped + 1))
+    }
+
+    /// the next `n` records, see [`RecordIter::skip_records`]
+    pub fn take_records(&mut self, n: usize) -> std::iter::Take<&mut Self> {
+        self.by_ref().take(n)
+    }
+
+    /// same as [`RecordIter::collect_vec`], fails on the first error
+    pub fn collect_vec(self) -> Result<Vec<T::Item>, QshError> {
+        let mut records = Vec::with_capacity(self.0 .2);
+        for rec in self {
+            records.push(rec?);
+        }
+        Ok(records)
+    }
+}
+
+impl<T: QshParser, Q: QshRead> Iterator for TryRecordIter<T, Q> {
+    type Item = Result<T::Item, QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.1 {
+            return None;
+        }
+        let next = self.0.try_next();
+        self.1 = next.is_err();
+        next.transpose()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.1 {
+            true => (0, Some(0)),
+            false => (0, None),
         }
     }
 }
+
+/// Error of the [`Shunt`], shared with its consumer
+pub type ErrorSlot = Rc<Cell<Option<QshError>>>;
+
+/// `Ok` values of the `Result` iterator up to the first error, which is put into the slot. Lets
+/// the infallible adapters, e.g. `partition_by`, run over the fallible streams.
+pub struct Shunt<I> {
+    iter: I,
+    slot: ErrorSlot,
+    failed: bool,
+}
+
+impl<I> Shunt<I> {
+    pub fn new(iter: I) -> (Self, ErrorSlot) {
+        let slot = ErrorSlot::default();
+        (Self { iter, slot: Rc::clone(&slot), failed: false }, slot)
+    }
+}
+
+impl<I, T> Iterator for Shunt<I>
+where
+    I: Iterator<Item = Result<T, QshError>>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.iter.next()? {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.failed = true;
+                self.slot.set(Some(e));
+                None
+            }
+        }
+    }
+}
+
+/// Runs `f` over the `Ok` values of the `iter`, short-circuits on the first error and returns
+/// it instead of the result of `f`, as `itertools::process_results`
+pub fn process_results<I, T, F, R>(iter: I, f: F) -> Result<R, QshError>
+where
+    I: IntoIterator<Item = Result<T, QshError>>,
+    F: FnOnce(Shunt<I::IntoIter>) -> R,
+{
+    let (shunt, slot) = Shunt::new(iter.into_iter());
+    let ret = f(shunt);
+    match slot.take() {
+        Some(e) => Err(e),
+        None => Ok(ret),
+    }
+}
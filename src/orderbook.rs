@@ -1,15 +1,463 @@
 use crate::{
-    types::{L2Message, OLFlags, OrderLog, OrderType, Price, Side, Timestamp, Volume},
+    policy::{Crossed, ParsePolicy},
+    time::{self, ticks_to_unix_ns},
+    types::{
+        Deal, L2Message, OLFlagSet, OLMsgType, OrderLog, OrderType, Price, Quotes, Side, Timestamp,
+        Volume, UID,
+    },
     QshError,
 };
+use bincode::{config, Decode, Encode};
+use levels::{Levels, Spare};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{Read, Write},
+    iter::Peekable,
+};
+
+mod levels;
 
 pub type MidPrice = f64;
+/// book time, unix nanos, and the levels, see [`OrderBook::snapshot`]
 pub type Snapshot = (Timestamp, Vec<i64>);
 pub type Level = (Price, Volume, Vec<OrderLog>);
 pub type Quote = (Price, Volume);
 
-#[derive(Debug, Default)]
-pub struct OrderBook(Vec<Level>, Vec<Level>, Timestamp);
+// checkpoint header: magic and format version
+const CHECKPOINT_MAGIC: &[u8; 4] = b"QSHB";
+const CHECKPOINT_VERSION: u8 = 11;
+
+// compact level beyond `max_depth`, keeps (order_id, amount) of the resting orders only
+type FarLevel = (Volume, Vec<(UID, Volume)>);
+
+#[derive(Debug, Default, Encode, Decode)]
+pub struct OrderBook {
+    bids: Levels,
+    asks: Levels,
+    ts: Timestamp,
+    // order_id -> level of the resting order
+    index: HashMap<UID, (Side, Price)>,
+    // 0 - unbounded
+    max_depth: usize,
+    far_bids: BTreeMap<Price, FarLevel>,
+    far_asks: BTreeMap<Price, FarLevel>,
+    // price -> time of the last change of the level, unix nanos, the far levels included. The
+    // entries of the removed levels are overwritten once the level is back.
+    bid_times: HashMap<Price, Timestamp>,
+    ask_times: HashMap<Price, Timestamp>,
+    // fail the mutations leaving the book crossed
+    strict: bool,
+    // within `apply_tx`, the crossed check is deferred to the transaction end
+    in_tx: bool,
+    cross_trades: usize,
+    policy: Strictness,
+    lookup: Lookup,
+    anomalies: Anomalies,
+    counters: BookCounters,
+    shadows: Vec<Shadow>,
+    // order queues of the removed levels
+    spare: Spare,
+}
+
+/// Backing store of the book levels, see [`OrderBook::with_storage`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Storage {
+    /// levels vector sorted best-to-worst, the inserts and removals shift the worse levels
+    #[default]
+    Sorted,
+    /// Slot per `step` between the worst and the best levels, constant-time inserts and
+    /// removals at the cost of a slot per empty price step. The prices are to be on the grid
+    /// of the `step`, which has to be positive.
+    Ladder { step: Price },
+}
+
+/// Handling of the records inconsistent with the book, see [`OrderBook::set_policy`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub enum Strictness {
+    /// fail with `QshError::InvalidState`
+    #[default]
+    Strict,
+    /// skip or adjust the record, count the anomaly
+    Lenient,
+}
+
+/// Location of the order the cancel or fill refers to, see [`OrderBook::set_lookup`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub enum Lookup {
+    /// the order id index vouches for the order resting at the level of the record, its queue
+    /// is binary searched by the id. The record the index disagrees with is looked up as of
+    /// `Price`, and fails or is counted as the anomaly the same.
+    #[default]
+    Index,
+    /// level of the side and price of the record, its queue scanned for the order id
+    Price,
+}
+
+/// Anomalies tolerated by the [`Strictness::Lenient`] book
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct Anomalies {
+    /// cancel or fill at the price level the book doesn't have, skipped
+    pub missing_level: usize,
+    /// cancel or fill of the order absent at its level, skipped
+    pub unknown_order: usize,
+    /// cancel leaving more than the order has, skipped, or fill over the order amount, which
+    /// fills the order amount only
+    pub volume_mismatch: usize,
+    /// cancel or fill of the order placed before the snapshot the book is seeded from, taken
+    /// off the synthetic order of the level, see [`OrderBook::from_snapshot`]
+    pub synthetic: usize,
+}
+
+/// Records passed to the book since the last [`OrderBook::clear`], see
+/// [`OrderBook::events_since_clear`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub struct BookCounters {
+    pub adds: usize,
+    /// cancels, including the cross-trade and move removals
+    pub cancels: usize,
+    pub trades: usize,
+    /// number of the clears, it's not reset by them
+    pub clears: usize,
+}
+
+/// Receiver of the book updates, `ts` is the unix time(nanos) of the record causing the update
+#[allow(unused_variables)]
+pub trait BookListener {
+    /// new aggregated volume of the level
+    fn on_quote(&mut self, side: Side, price: Price, size: Volume, ts: Timestamp) {}
+    fn on_remove(&mut self, side: Side, price: Price, ts: Timestamp) {}
+    /// resting order of the `side` is filled, followed by the level update
+    fn on_trade(&mut self, side: Side, price: Price, amount: Volume, ts: Timestamp) {}
+    fn on_clear(&mut self) {}
+}
+
+/// discards the updates
+pub struct NopListener;
+
+impl BookListener for NopListener {}
+
+/// collects the updates and the trades as `L2Message`s
+pub struct VecSink<'a>(pub &'a mut Vec<L2Message>);
+
+impl BookListener for VecSink<'_> {
+    #[inline]
+    fn on_quote(&mut self, side: Side, price: Price, size: Volume, _: Timestamp) {
+        self.0.push(L2Message::Quote { side, price, size });
+    }
+
+    #[inline]
+    fn on_remove(&mut self, side: Side, price: Price, _: Timestamp) {
+        self.0.push(L2Message::Remove { side, price });
+    }
+
+    #[inline]
+    fn on_trade(&mut self, side: Side, price: Price, size: Volume, _: Timestamp) {
+        self.0.push(L2Message::Trade { side, price, size });
+    }
+
+    #[inline]
+    fn on_clear(&mut self) {
+        self.0.push(L2Message::Clear);
+    }
+}
+
+/// Book levels per side, best-to-worst, `ts` is the unix time of the book, nanos
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookSnapshot {
+    pub ts: Timestamp,
+    pub bids: Vec<Quote>,
+    pub asks: Vec<Quote>,
+    /// number of the resting orders per level of `(bids, asks)`, if requested
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub counts: Option<(Vec<usize>, Vec<usize>)>,
+}
+
+impl BookSnapshot {
+    /// Levels in the `OrderBook::snapshot` layout, `[bid_px, bid_vol, ask_px, ask_vol]` per
+    /// level, truncated or zero padded to `width` levels
+    pub fn flatten(&self, width: usize) -> Vec<i64> {
+        let mut levels = vec![0; width * 4];
+        for (j, side) in [(0, &self.bids), (2, &self.asks)] {
+            for (i, &(p, v)) in side.iter().take(width).enumerate() {
+                (levels[i * 4 + j], levels[i * 4 + j + 1]) = (p, v);
+            }
+        }
+        levels
+    }
+}
+
+/// Typed access to the levels of the [`Snapshot`] layout, `[bid_px, bid_vol, ask_px, ask_vol]`
+/// per level, the zero padding levels are of no volume
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotView<'a> {
+    pub ts: Timestamp,
+    levels: &'a [i64],
+}
+
+impl<'a> SnapshotView<'a> {
+    pub fn new(snapshot: &'a Snapshot) -> Self {
+        Self::from_levels(snapshot.0, &snapshot.1)
+    }
+
+    /// view of the flat levels, e.g. of `OrderBook::snapshot_into`, panics unless there are
+    /// whole levels
+    pub fn from_levels(ts: Timestamp, levels: &'a [i64]) -> Self {
+        assert!(
+            levels.chunks_exact(4).remainder().is_empty(),
+            "snapshot of {} values is not of whole levels",
+            levels.len()
+        );
+        Self { ts, levels }
+    }
+
+    /// number of the levels, the zero padding ones included
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.levels.len() / 4
+    }
+
+    /// price of the `i`th bid level, panics if `i` is out of the `depth`
+    #[inline]
+    pub fn bid_px(&self, i: usize) -> Price {
+        self.levels[i * 4]
+    }
+
+    #[inline]
+    pub fn bid_vol(&self, i: usize) -> Volume {
+        self.levels[i * 4 + 1]
+    }
+
+    #[inline]
+    pub fn ask_px(&self, i: usize) -> Price {
+        self.levels[i * 4 + 2]
+    }
+
+    #[inline]
+    pub fn ask_vol(&self, i: usize) -> Volume {
+        self.levels[i * 4 + 3]
+    }
+
+    /// `i`th level of the `side`, `None` for the zero padding one or out of the `depth`
+    #[inline]
+    pub fn level(&self, side: Side, i: usize) -> Option<Quote> {
+        let j = if side == Side::Buy { 0 } else { 2 };
+        self.levels.get(i * 4 + j..i * 4 + j + 2).map(|l| (l[0], l[1])).filter(|&(_, v)| v > 0)
+    }
+
+    /// levels of the `side`, best-to-worst, the zero padding ones skipped
+    pub fn levels(&self, side: Side) -> impl Iterator<Item = Quote> + 'a {
+        let j = if side == Side::Buy { 0 } else { 2 };
+        self.levels.chunks_exact(4).map(move |l| (l[j], l[j + 1])).filter(|&(_, v)| v > 0)
+    }
+
+    #[inline]
+    pub fn best_bid(&self) -> Option<Quote> {
+        self.level(Side::Buy, 0)
+    }
+
+    #[inline]
+    pub fn best_ask(&self) -> Option<Quote> {
+        self.level(Side::Sell, 0)
+    }
+
+    /// `None` if any side is empty
+    #[inline]
+    pub fn mid_price(&self) -> Option<MidPrice> {
+        Some((self.best_bid()?.0 + self.best_ask()?.0) as MidPrice * 0.5)
+    }
+
+    /// best ask less the best bid, `None` if any side is empty
+    #[inline]
+    pub fn spread(&self) -> Option<Price> {
+        Some(self.best_ask()?.0 - self.best_bid()?.0)
+    }
+
+    /// round-trip cost of the deal at `price`, twice its distance to the mid price
+    #[inline]
+    pub fn effective_spread(&self, price: Price) -> Option<f64> {
+        Some(2. * (price as f64 - self.mid_price()?).abs())
+    }
+
+    /// same as [`OrderBook::microprice`]
+    #[inline]
+    pub fn microprice(&self) -> Option<f64> {
+        self.weighted_mid(1)
+    }
+
+    /// same as [`OrderBook::weighted_mid`]
+    pub fn weighted_mid(&self, depth: usize) -> Option<f64> {
+        let depth = if depth == 0 { usize::MAX } else { depth };
+        weigh_sides(self.levels(Side::Buy).take(depth), self.levels(Side::Sell).take(depth))
+    }
+}
+
+// volume-weighted prices of the `bids` and the `asks` weighted by the opposite side volume,
+// `None` if any side is empty
+#[inline]
+fn weigh_sides(
+    bids: impl Iterator<Item = Quote>,
+    asks: impl Iterator<Item = Quote>,
+) -> Option<f64> {
+    fn top(levels: impl Iterator<Item = Quote>) -> (f64, Volume) {
+        levels.fold((0., 0), |(notional, volume), (p, v)| (notional + (p * v) as f64, volume + v))
+    }
+    let ((bid_notional, bid_vol), (ask_notional, ask_vol)) = (top(bids), top(asks));
+    if bid_vol == 0 || ask_vol == 0 {
+        return None;
+    }
+    let (bid_px, ask_px) = (bid_notional / bid_vol as f64, ask_notional / ask_vol as f64);
+    Some((bid_px * ask_vol as f64 + ask_px * bid_vol as f64) / (bid_vol + ask_vol) as f64)
+}
+
+/// [`SnapshotView::microprice`] of the `snapshot`
+#[inline]
+pub fn microprice(snapshot: &Snapshot) -> Option<f64> {
+    SnapshotView::new(snapshot).microprice()
+}
+
+/// [`SnapshotView::weighted_mid`] of the `snapshot`
+#[inline]
+pub fn weighted_mid(snapshot: &Snapshot, depth: usize) -> Option<f64> {
+    SnapshotView::new(snapshot).weighted_mid(depth)
+}
+
+/// reference price of the [`OrderBook::snapshot_band_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandAnchor {
+    /// mid price, the best level of the side if the other one is empty
+    Mid,
+    /// best level of each side
+    Best,
+}
+
+impl From<Snapshot> for BookSnapshot {
+    /// the zero padding levels of the `L2Book::snapshot` are skipped
+    fn from((ts, levels): Snapshot) -> Self {
+        let mut snapshot = BookSnapshot { ts, ..Default::default() };
+        for level in levels.chunks_exact(4) {
+            if level[1] > 0 {
+                snapshot.bids.push((level[0], level[1]));
+            }
+            if level[3] > 0 {
+                snapshot.asks.push((level[2], level[3]));
+            }
+        }
+        snapshot
+    }
+}
+
+/// cost of the immediate execution against the book, see [`OrderBook::vwap_for_size`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FillEstimate {
+    pub vwap: f64,
+    /// the worst price touched
+    pub worst_price: Price,
+    pub levels_consumed: usize,
+    /// volume left unfilled if the book is thinner than requested
+    pub unfilled: Volume,
+}
+
+/// difference of the book levels, see [`OrderBook::diff`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum LevelDiff {
+    /// the level is absent in the other book
+    Missing {
+        side: Side,
+        price: Price,
+        volume: Volume,
+    },
+    /// the level is present in the other book only
+    Extra {
+        side: Side,
+        price: Price,
+        volume: Volume,
+    },
+    Volume {
+        side: Side,
+        price: Price,
+        left: Volume,
+        right: Volume,
+    },
+    /// ids of the orders queued at the level of one book only
+    Orders {
+        side: Side,
+        price: Price,
+        missing: Vec<UID>,
+        extra: Vec<UID>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BookDiff(pub Vec<LevelDiff>);
+
+impl BookDiff {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Display for BookDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no differences");
+        }
+        for d in &self.0 {
+            match d {
+                LevelDiff::Missing { side, price, volume } => {
+                    writeln!(f, "{side:?} {price}: missing level, volume {volume}")
+                }
+                LevelDiff::Extra { side, price, volume } => {
+                    writeln!(f, "{side:?} {price}: extra level, volume {volume}")
+                }
+                LevelDiff::Volume { side, price, left, right } => {
+                    writeln!(f, "{side:?} {price}: volume {left} != {right}")
+                }
+                LevelDiff::Orders { side, price, missing, extra } => {
+                    writeln!(f, "{side:?} {price}: orders missing {missing:?}, extra {extra:?}")
+                }
+            }?;
+        }
+        Ok(())
+    }
+}
+
+/// position of the resting order within the book
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderRef {
+    pub side: Side,
+    pub price: Price,
+    /// index in the level queue
+    pub index: usize,
+    /// cumulative volume of the orders ahead in the queue
+    pub ahead: Volume,
+}
+
+/// Handle of the virtual order, see [`OrderBook::shadow_add`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShadowId(usize);
+
+/// Queue position of the virtual order
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShadowStatus {
+    /// volume of the orders resting ahead of it
+    pub ahead: Volume,
+    /// volume traded at its position or through its price since it's reached
+    pub filled: Volume,
+    /// trades have reached it
+    pub done: bool,
+}
+
+#[derive(Debug, Encode, Decode)]
+struct Shadow {
+    side: Side,
+    price: Price,
+    ts: Timestamp,
+    // the orders resting at the price on posting, ids grow so the later ones are behind
+    last_ahead: UID,
+    filled: Volume,
+}
 
 macro_rules! assert_valid {
     ($cond:expr, $msg:expr) => {
@@ -27,48 +475,161 @@ macro_rules! assert_state {
 }
 
 impl OrderBook {
-    pub fn add<'a, I>(&'a mut self, rec: OrderLog, events: I) -> Result<(), QshError>
+    /// Book keeping only the best `max_depth` levels per side fully materialized, the rest are
+    /// stored as price -> volume and the ids/amounts of their orders. Far levels lose the order
+    /// details(timestamps, flags), and are restored when they come back into range.
+    ///
+    /// `depth`, `snapshot` and the level accessors see the materialized levels only, emitted
+    /// events are the same as of the unbounded book.
+    pub fn with_max_depth(max_depth: usize) -> Self {
+        Self { max_depth, ..Default::default() }
+    }
+
+    /// Book failing with `QshError::InvalidState` on the mutations leaving it crossed. The
+    /// aggressive orders cross the book transiently, so within `apply_tx` it's checked once the
+    /// whole transaction is applied.
+    pub fn strict() -> Self {
+        Self { strict: true, ..Default::default() }
+    }
+
+    /// Book of the crossed and the inconsistency handling of the `policy`, see
+    /// [`strict`](Self::strict) and [`set_policy`](Self::set_policy)
+    pub fn with_policy(policy: ParsePolicy) -> Self {
+        let strict = policy.on_crossed == Crossed::Fail;
+        Self { strict, policy: policy.on_inconsistent, ..Default::default() }
+    }
+
+    /// Book backed by the `storage`, the API and the emitted events are the same for any of
+    /// them. The book with `Storage::Ladder` rejects adds off the price grid with
+    /// `QshError::Validation`.
+    pub fn with_storage(storage: Storage) -> Self {
+        Self { bids: Levels::new(storage), asks: Levels::new(storage), ..Default::default() }
+    }
+
+    /// Lenient book with the levels of the `snapshot`, each held by a single synthetic order,
+    /// see [`is_synthetic`]. The cancels and fills of the orders unknown to the book take the
+    /// volume off the synthetic order of their level, counted as `Anomalies::synthetic`.
+    pub fn from_snapshot(snapshot: &BookSnapshot) -> Self {
+        let mut book = Self { policy: Strictness::Lenient, ..Default::default() };
+        let timestamp = time::unix_ns_to_ticks(snapshot.ts);
+        let levels = Side::both().into_iter().zip([&snapshot.bids, &snapshot.asks]);
+        let quotes = levels.flat_map(|(side, quotes)| quotes.iter().map(move |&q| (side, q)));
+        for (id, (side, (price, amount))) in quotes.filter(|(_, q)| q.1 > 0).enumerate() {
+            let side_flag = if side == Side::Buy { OLFlagSet::BUY } else { OLFlagSet::SELL };
+            let order = OrderLog {
+                timestamp,
+                order_id: -(id as UID) - 1,
+                price,
+                amount,
+                amount_rest: amount,
+                order_flags: OLFlagSet::ADD | OLFlagSet::QUOTE | OLFlagSet::SNAPSHOT | side_flag,
+                side,
+                event: OLMsgType::Add,
+                type_: OrderType::Limit,
+                ..Default::default()
+            };
+            // the same price twice adds up
+            book.add(order, &mut NopListener).expect("valid synthetic order");
+        }
+        (book.ts, book.counters) = (snapshot.ts, BookCounters::default());
+        book
+    }
+
+    /// Applies the leading `OLFlags::Snapshot` records of the `input`, the non-system ones are
+    /// skipped, notifying no listener. Returns the number of the records consumed, the first
+    /// record past the snapshot is left in the `input`.
+    pub fn seed_from_records<I>(&mut self, input: &mut Peekable<I>) -> Result<usize, QshError>
     where
-        I: Into<Option<&'a mut Vec<L2Message>>>,
+        I: Iterator<Item = OrderLog>,
     {
-        assert_valid!(OLFlags::Fill % rec.order_flags == false, "is Fill");
-        assert_valid!(OLFlags::Canceled % rec.order_flags == false, "is Canceled");
-        assert_valid!(OLFlags::CanceledGroup % rec.order_flags == false, "is CanceledGroup");
+        let mut consumed = 0;
+        while let Some(rec) = input.next_if(|rec| rec.order_flags.contains(OLFlagSet::SNAPSHOT)) {
+            consumed += 1;
+            if system_record(&rec) {
+                self.apply(rec, &mut NopListener)?;
+            }
+        }
+        Ok(consumed)
+    }
+
+    pub fn add(&mut self, rec: OrderLog, listener: &mut impl BookListener) -> Result<(), QshError> {
+        assert_valid!(rec.order_flags.contains(OLFlagSet::FILL) == false, "is Fill");
+        assert_valid!(rec.order_flags.contains(OLFlagSet::CANCELED) == false, "is Canceled");
+        assert_valid!(
+            rec.order_flags.contains(OLFlagSet::CANCELED_GROUP) == false,
+            "is CanceledGroup"
+        );
         assert_valid!(rec.amount_rest != 0, format!("{}", ol_msg("amount_rest == 0", rec)));
         assert_valid!(rec.amount == rec.amount_rest, "invalid Order, amount != amount_rest ");
+        assert_valid!(rec.side != Side::UNKNOWN, ol_msg("unknown side", rec));
+        self.counters.adds += 1;
+
+        let levels = side_levels(&mut self.bids, &mut self.asks, rec.side);
+        assert_valid!(levels.accepts(rec.price), ol_msg("price off the ladder grid", rec));
 
-        let size = match self.find_level(rec.side, rec.price) {
-            (Err(ix), side) => {
-                side.insert(ix, (rec.price, rec.amount, vec![rec]));
+        // the order moved keeping its id, its former level goes first
+        if rec.order_flags.contains(OLFlagSet::MOVED) && self.index.contains_key(&rec.order_id) {
+            self.remove_order(rec.order_id, ticks_to_unix_ns(rec.timestamp), listener);
+        }
+
+        self.rehydrate(rec.side, rec.price);
+        let levels = side_levels(&mut self.bids, &mut self.asks, rec.side);
+        let size = match levels.get_mut(rec.side, rec.price) {
+            None => {
+                let mut orders = self.spare.take();
+                orders.push(rec);
+                levels.insert(rec.side, (rec.price, rec.amount, orders));
                 rec.amount
             }
-            (Ok(ix), side) => {
-                let lvl = side.get_mut(ix).unwrap();
+            Some(lvl) => {
                 lvl.2.push(rec);
                 lvl.1 += rec.amount;
                 lvl.1
             }
         };
 
-        events.into().map(|e| e.push(L2Message::Quote { side: rec.side, price: rec.price, size }));
+        self.index.insert(rec.order_id, (rec.side, rec.price));
 
-        self.2 = ticks_to_unix_time(rec.timestamp);
-        Ok(())
+        self.ts = ticks_to_unix_ns(rec.timestamp);
+        self.touch(rec.side, rec.price, self.ts);
+        listener.on_quote(rec.side, rec.price, size, self.ts);
+
+        self.rebalance(rec.side);
+        self.check_crossed()
     }
 
-    pub fn cancel<'a, I>(&mut self, rec: OrderLog, events: I) -> Result<(), QshError>
-    where
-        I: Into<Option<&'a mut Vec<L2Message>>>,
-    {
+    pub fn cancel(
+        &mut self,
+        mut rec: OrderLog,
+        listener: &mut impl BookListener,
+    ) -> Result<(), QshError> {
         assert_valid!(
-            OLFlags::Fill % rec.order_flags == false,
+            rec.order_flags.contains(OLFlagSet::FILL) == false,
             format!("{}", ol_msg("is Fill", rec))
         );
-        assert_valid!(OLFlags::Add % rec.order_flags == false, "is Add");
+        assert_valid!(rec.order_flags.contains(OLFlagSet::ADD) == false, "is Add");
+        self.counters.cancels += 1;
+
+        if rec.order_flags.contains(OLFlagSet::CROSS_TRADE) {
+            return self.remove_cross_trade(rec, listener);
+        }
+        if rec.order_flags.contains(OLFlagSet::MOVED) {
+            return self.remove_moved(rec, listener);
+        }
+        assert_valid!(rec.side != Side::UNKNOWN, ol_msg("unknown side", rec));
+        if self.degrade(&rec, false, listener) {
+            return self.check_crossed();
+        }
+        if !self.tolerate(&mut rec, false) {
+            return Ok(());
+        }
 
-        if let (Ok(ix), side) = self.find_level(rec.side, rec.price) {
-            let level = &mut side.get_mut(ix).unwrap();
-            let tgt = level.2.iter().position(|r| r.order_id == rec.order_id);
+        let ts = ticks_to_unix_ns(rec.timestamp);
+        let indexed = self.indexed(&rec);
+        self.rehydrate(rec.side, rec.price);
+        let levels = side_levels(&mut self.bids, &mut self.asks, rec.side);
+        if let Some(level) = levels.get_mut(rec.side, rec.price) {
+            let tgt = queue_position(&level.2, rec.order_id, indexed);
 
             match (tgt, rec.amount_rest) {
                 (Some(i), 0) => {
@@ -76,27 +637,20 @@ impl OrderBook {
                     assert_state!(level.1 >= diff, "remaining level volume < order.amount ");
 
                     level.2.remove(i);
+                    self.index.remove(&rec.order_id);
                     level.1 -= diff;
                     if level.2.len() == 0 {
                         assert_state!(
                             level.1 == 0,
                             "remaining level volume and orders number mismatch"
                         );
-                        side.remove(ix);
+                        self.spare.put(levels.remove(rec.side, rec.price).unwrap().2);
 
-                        events.into().map(|e| {
-                            e.push(L2Message::Remove { side: rec.side, price: rec.price })
-                        });
+                        listener.on_remove(rec.side, rec.price, ts);
                     } else if level.1 == 0 {
                         assert_state!(false, "there are some active orders left at the level, but total level volume is 0");
                     } else {
-                        events.into().map(|e| {
-                            e.push(L2Message::Quote {
-                                side: rec.side,
-                                price: rec.price,
-                                size: level.1,
-                            })
-                        });
+                        listener.on_quote(rec.side, rec.price, level.1, ts);
                     }
                 }
                 (Some(i), rest) => {
@@ -113,9 +667,7 @@ impl OrderBook {
                     level.2[i].amount = rest;
                     level.2[i].amount_rest = rest;
 
-                    events.into().map(|e| {
-                        e.push(L2Message::Quote { side: rec.side, price: rec.price, size: level.1 })
-                    });
+                    listener.on_quote(rec.side, rec.price, level.1, ts);
                 }
                 _ => assert_state!(
                     false,
@@ -126,28 +678,49 @@ impl OrderBook {
             assert_state!(false, format!("level not found, {:#?}", rec));
         }
 
-        self.2 = ticks_to_unix_time(rec.timestamp);
+        self.touch(rec.side, rec.price, ts);
+        self.rebalance(rec.side);
+        self.ts = ts;
 
-        Ok(())
+        self.check_crossed()
     }
 
-    pub fn trade<'a, I>(&mut self, rec: OrderLog, events: I) -> Result<(), QshError>
-    where
-        I: Into<Option<&'a mut Vec<L2Message>>>,
-    {
-        assert_valid!(OLFlags::Add % rec.order_flags == false, "is Add");
-        assert_valid!(OLFlags::Canceled % rec.order_flags == false, "is Canceled");
-        assert_valid!(OLFlags::CanceledGroup % rec.order_flags == false, "is CanceledGroup");
+    pub fn trade(
+        &mut self,
+        mut rec: OrderLog,
+        listener: &mut impl BookListener,
+    ) -> Result<(), QshError> {
+        assert_valid!(rec.order_flags.contains(OLFlagSet::ADD) == false, "is Add");
+        assert_valid!(rec.order_flags.contains(OLFlagSet::CANCELED) == false, "is Canceled");
+        assert_valid!(
+            rec.order_flags.contains(OLFlagSet::CANCELED_GROUP) == false,
+            "is CanceledGroup"
+        );
         assert_valid!(rec.amount != 0, "invalid Order, rec.amount == 0 ");
+        assert_valid!(rec.side != Side::UNKNOWN, ol_msg("unknown side", rec));
+        self.counters.trades += 1;
+        if self.degrade(&rec, true, listener) {
+            return self.check_crossed();
+        }
+        if !self.tolerate(&mut rec, true) {
+            return Ok(());
+        }
+        if !self.shadows.is_empty() {
+            self.fill_shadows(&rec);
+        }
 
-        match self.find_level(rec.side, rec.price) {
-            (Err(_), _) => assert_state!(false, "level not exists"),
-            (Ok(ix), side) => {
-                let level = &mut side.get_mut(ix).unwrap();
-                if let Some(i) = level.2.iter().position(|r| r.order_id == rec.order_id) {
+        let ts = ticks_to_unix_ns(rec.timestamp);
+        let indexed = self.indexed(&rec);
+        self.rehydrate(rec.side, rec.price);
+        let levels = side_levels(&mut self.bids, &mut self.asks, rec.side);
+        match levels.get_mut(rec.side, rec.price) {
+            None => assert_state!(false, "level not exists"),
+            Some(level) => {
+                if let Some(i) = queue_position(&level.2, rec.order_id, indexed) {
                     let order = level.2.get_mut(i).unwrap();
                     if order.amount == rec.amount {
                         level.2.remove(i);
+                        self.index.remove(&rec.order_id);
                     } else {
                         assert_state!(
                             order.amount > rec.amount && order.amount_rest > rec.amount,
@@ -161,6 +734,7 @@ impl OrderBook {
                         "remaining level volume is less than order.amount"
                     );
                     level.1 -= rec.amount;
+                    listener.on_trade(rec.side, rec.price, rec.amount, ts);
                 } else {
                     assert_state!(
                         false,
@@ -170,121 +744,1277 @@ impl OrderBook {
 
                 if level.2.len() == 0 {
                     assert_state!(level.1 == 0, "remaining level volume > 0");
-                    side.remove(ix);
-                    events
-                        .into()
-                        .map(|e| e.push(L2Message::Remove { side: rec.side, price: rec.price }));
+                    self.spare.put(levels.remove(rec.side, rec.price).unwrap().2);
+                    listener.on_remove(rec.side, rec.price, ts);
                 } else if level.1 == 0 {
                     assert_state!(false, "level volume is 0, but there are some active orders left")
                 } else {
-                    events.into().map(|e| {
-                        e.push(L2Message::Quote { side: rec.side, price: rec.price, size: level.1 })
-                    });
+                    listener.on_quote(rec.side, rec.price, level.1, ts);
                 }
             }
         }
 
-        self.2 = ticks_to_unix_time(rec.timestamp);
+        self.touch(rec.side, rec.price, ts);
+        self.rebalance(rec.side);
+        self.ts = ts;
 
-        Ok(())
+        self.check_crossed()
     }
-}
 
-impl OrderBook {
-    #[inline(always)]
-    fn find_level(&mut self, side: Side, price: Price) -> (Result<usize, usize>, &mut Vec<Level>) {
-        match side {
-            Side::Buy => (self.0.binary_search_by(|(p, _, _)| price.cmp(&p)), &mut self.0),
-            Side::Sell => (self.1.binary_search_by(|(p, _, _)| p.cmp(&price)), &mut self.1),
-            Side::UNKNOWN => unreachable!(),
+    // Removal of the order remainder due to the cross-trade. The amounts of such records aren't
+    // reliable, e.g. around the clearing, so whatever remains of the order is removed, and the
+    // order may be gone already.
+    fn remove_cross_trade(
+        &mut self,
+        rec: OrderLog,
+        listener: &mut impl BookListener,
+    ) -> Result<(), QshError> {
+        self.cross_trades += 1;
+        let ts = ticks_to_unix_ns(rec.timestamp);
+        self.remove_order(rec.order_id, ts, listener);
+        self.ts = ts;
+        self.check_crossed()
+    }
+
+    // Removal of the order moved to the other price, the Add record follows. The order is
+    // removed whole, whatever the `amount_rest` of the record is, as the Add brings its rest.
+    fn remove_moved(
+        &mut self,
+        rec: OrderLog,
+        listener: &mut impl BookListener,
+    ) -> Result<(), QshError> {
+        let ts = ticks_to_unix_ns(rec.timestamp);
+        if !self.remove_order(rec.order_id, ts, listener) && !self.degrade(&rec, false, listener) {
+            assert_state!(self.policy == Strictness::Lenient, ol_msg("moved order not found", rec));
+            self.anomalies.unknown_order += 1;
+            log::warn!("{}", ol_msg("moved order not found, skipped", rec));
         }
+        self.ts = ts;
+        self.check_crossed()
     }
 
-    #[inline]
-    pub fn clear(&mut self) {
-        self.0.clear();
-        self.1.clear();
+    // removes whatever remains of the resting order, `false` if there is no such order
+    fn remove_order(
+        &mut self,
+        order_id: UID,
+        ts: Timestamp,
+        listener: &mut impl BookListener,
+    ) -> bool {
+        let Some(&(side, price)) = self.index.get(&order_id) else {
+            return false;
+        };
+
+        self.rehydrate(side, price);
+        let levels = side_levels(&mut self.bids, &mut self.asks, side);
+        let mut removed = false;
+        if let Some(level) = levels.get_mut(side, price) {
+            if let Some(i) = position(&level.2, order_id) {
+                let order = level.2.remove(i);
+                self.index.remove(&order_id);
+                level.1 -= order.amount;
+                if level.2.is_empty() {
+                    self.spare.put(levels.remove(side, price).unwrap().2);
+                    listener.on_remove(side, price, ts);
+                } else {
+                    listener.on_quote(side, price, level.1, ts);
+                }
+                removed = true;
+            }
+        }
+
+        if removed {
+            self.touch(side, price, ts);
+        }
+        self.rebalance(side);
+        removed
     }
 
-    #[inline]
-    pub fn depth(&self, side: Side) -> usize {
-        if side == Side::Buy {
-            self.0.len()
+    // In the `Lenient` mode, takes the `amount` of the cancel or fill of the order unknown to the
+    // book off the synthetic order of its level. `false` if there is no such order.
+    fn degrade(&mut self, rec: &OrderLog, fill: bool, listener: &mut impl BookListener) -> bool {
+        if self.policy == Strictness::Strict || self.index.contains_key(&rec.order_id) {
+            return false;
+        }
+        self.rehydrate(rec.side, rec.price);
+        let levels = side_levels(&mut self.bids, &mut self.asks, rec.side);
+        let Some((_, volume, orders)) = levels.get_mut(rec.side, rec.price) else {
+            return false;
+        };
+        // the synthetic order is the first at its level, as the level is created with it
+        let Some(order) = orders.first_mut().filter(|order| is_synthetic(order)) else {
+            return false;
+        };
+
+        let ts = ticks_to_unix_ns(rec.timestamp);
+        let amount = rec.amount.min(order.amount);
+        order.amount -= amount;
+        order.amount_rest -= amount;
+        *volume -= amount;
+        if order.amount == 0 {
+            self.index.remove(&order.order_id);
+            orders.remove(0);
+        }
+        if fill && amount > 0 {
+            listener.on_trade(rec.side, rec.price, amount, ts);
+        }
+        if orders.is_empty() {
+            self.spare.put(levels.remove(rec.side, rec.price).unwrap().2);
+            listener.on_remove(rec.side, rec.price, ts);
         } else {
-            self.1.len()
+            listener.on_quote(rec.side, rec.price, *volume, ts);
         }
+        self.anomalies.synthetic += 1;
+        log::warn!("{}", ol_msg("unknown order, taken off the synthetic one", *rec));
+
+        self.touch(rec.side, rec.price, ts);
+        self.rebalance(rec.side);
+        self.ts = ts;
+        true
     }
 
+    /// Sets the handling of the cancels and fills inconsistent with the book, `Strict` by default
     #[inline]
-    pub fn level_summary(&self, side: Side, depth: usize) -> (Price, Volume) {
-        let (p, v, _) = if side == Side::Buy { &self.0[depth] } else { &self.1[depth] };
-        (*p, *v)
+    pub fn set_policy(&mut self, policy: Strictness) {
+        self.policy = policy;
     }
 
-    pub fn snapshot(&self, depth: usize) -> Snapshot {
-        (
-            self.2,
-            (0..depth).into_iter().fold(vec![0; depth * 4], |mut snapshot, i| {
-                let j = i * 4;
-                assert!(self.0[i].1 > 0);
-                assert!(self.1[i].1 > 0);
-                snapshot[j + 0] = self.0[i].0;
-                snapshot[j + 1] = self.0[i].1;
-                snapshot[j + 2] = self.1[i].0;
-                snapshot[j + 3] = self.1[i].1;
-                snapshot
-            }),
-        )
+    /// Sets the location of the orders the cancels and fills refer to, `Index` by default
+    #[inline]
+    pub fn set_lookup(&mut self, lookup: Lookup) {
+        self.lookup = lookup;
     }
 
+    // the order id index has the order of the cancel or fill at the level of the record
     #[inline]
-    pub fn mid_price(&self) -> MidPrice {
-        (self.0[0].0 + self.1[0].0) as MidPrice * 0.5
+    fn indexed(&self, rec: &OrderLog) -> bool {
+        self.lookup == Lookup::Index
+            && self.index.get(&rec.order_id) == Some(&(rec.side, rec.price))
     }
-}
 
-fn ol_msg(msg: &str, rec: OrderLog) -> String {
-    format!("{}\n{rec}", msg,)
-}
+    /// anomalies tolerated in the `Lenient` mode
+    #[inline]
+    pub fn anomalies(&self) -> &Anomalies {
+        &self.anomalies
+    }
 
-#[inline(always)]
-pub fn fiok_with_trades(tx: &Vec<OrderLog>) -> bool {
-    match OrderType::from(tx[0].order_flags) {
-        OrderType::IOK | OrderType::FOK => tx.len() > 2,
-        _ => true,
+    // In the `Lenient` mode, checks the order the cancel or fill refers to. `false` if the record
+    // is to be skipped, a fill over the order amount is cut to it.
+    fn tolerate(&mut self, rec: &mut OrderLog, fill: bool) -> bool {
+        if self.policy == Strictness::Strict {
+            return true;
+        }
+        self.rehydrate(rec.side, rec.price);
+        let amount = self
+            .level_at(rec.side, rec.price)
+            .map(|level| position(&level.2, rec.order_id).map(|i| level.2[i].amount));
+        let anomalies = &mut self.anomalies;
+        match (amount, fill) {
+            (None, _) => {
+                anomalies.missing_level += 1;
+                log::warn!("{}", ol_msg("level not found, skipped", *rec));
+            }
+            (Some(None), _) => {
+                anomalies.unknown_order += 1;
+                log::warn!("{}", ol_msg("order not found, skipped", *rec));
+            }
+            (Some(Some(amount)), true) if rec.amount > amount => {
+                anomalies.volume_mismatch += 1;
+                log::warn!("{}", ol_msg(&format!("fill over the order amount {amount}"), *rec));
+                rec.amount = amount;
+                return true;
+            }
+            (Some(Some(amount)), false) if rec.amount_rest >= amount && rec.amount_rest > 0 => {
+                anomalies.volume_mismatch += 1;
+                log::warn!("{}", ol_msg(&format!("cancel over the order amount {amount}"), *rec));
+            }
+            _ => return true,
+        }
+        false
+    }
+
+    /// number of the order remainders removed due to the cross-trades
+    #[inline]
+    pub fn cross_trades(&self) -> usize {
+        self.cross_trades
+    }
+
+    /// Applies the record according to its `event`
+    pub fn apply(
+        &mut self,
+        rec: OrderLog,
+        listener: &mut impl BookListener,
+    ) -> Result<(), QshError> {
+        match rec.event {
+            OLMsgType::Add => self.add(rec, listener),
+            OLMsgType::Fill => self.trade(rec, listener),
+            OLMsgType::Cancel | OLMsgType::Remove => self.cancel(rec, listener),
+            OLMsgType::UNKNOWN => Err(QshError::Validation(ol_msg("unknown record type", rec))),
+        }
+    }
+
+    /// Applies the transaction. The book is cleared at the first NewSession record, wherever
+    /// it is in the transaction, the listener is notified with `on_clear` then and the rest of
+    /// the records, starting with the NewSession one, are applied to the fresh book.
+    pub fn apply_tx(
+        &mut self,
+        tx: &[OrderLog],
+        listener: &mut impl BookListener,
+    ) -> Result<(), QshError> {
+        let (before, after) = tx.split_at(new_session_start(tx));
+        self.in_tx = true;
+        let mut applied = before.iter().try_for_each(|&rec| self.apply(rec, listener));
+        if applied.is_ok() && !after.is_empty() {
+            self.clear();
+            listener.on_clear();
+            applied = after.iter().try_for_each(|&rec| self.apply(rec, listener));
+        }
+        self.in_tx = false;
+        applied?;
+        self.check_crossed()
+    }
+
+    #[inline]
+    fn check_crossed(&self) -> Result<(), QshError> {
+        if self.strict && !self.in_tx && self.is_crossed() {
+            assert_state!(
+                false,
+                format!("crossed book, bid {:?} >= ask {:?}", self.best_bid(), self.best_ask())
+            );
+        }
+        Ok(())
     }
 }
 
 #[inline(always)]
-pub fn non_system_record(rec: &OrderLog) -> bool {
-    OLFlags::NonSystem % rec.order_flags
-        || OLFlags::NonZeroReplAct % rec.order_flags
-        || rec.side == Side::UNKNOWN
+fn side_levels<'a>(bids: &'a mut Levels, asks: &'a mut Levels, side: Side) -> &'a mut Levels {
+    // the records of the unknown side are rejected by `add`, `cancel` and `trade`
+    debug_assert!(side != Side::UNKNOWN);
+    match side {
+        Side::Buy => bids,
+        _ => asks,
+    }
 }
 
+// orders are queued in arrival order and ids grow monotonically, so the level queue is normally
+// sorted by id. Falls back to the linear scan if it's not.
 #[inline(always)]
-pub fn system_record(rec: &OrderLog) -> bool {
-    !non_system_record(rec)
+fn position(orders: &[OrderLog], order_id: UID) -> Option<usize> {
+    match orders.binary_search_by_key(&order_id, |r| r.order_id) {
+        Ok(ix) => Some(ix),
+        Err(_) => orders.iter().position(|r| r.order_id == order_id),
+    }
 }
 
+// position of the order in the level queue, see `Lookup`
 #[inline(always)]
-pub fn tx_end(rec: &OrderLog) -> bool {
-    OLFlags::TxEnd % rec.order_flags
+fn queue_position(orders: &[OrderLog], order_id: UID, indexed: bool) -> Option<usize> {
+    match indexed {
+        true => position(orders, order_id),
+        false => orders.iter().position(|r| r.order_id == order_id),
+    }
 }
 
-/// windows 100ns ticks to unix time
-#[inline]
-pub fn ticks_to_unix_time(v: Timestamp) -> Timestamp {
-    v - 62135596800000
-}
+impl OrderBook {
+    // moves the far level into the materialized range, to the tail, as it's worse than any of
+    // the materialized levels
+    #[inline]
+    fn rehydrate(&mut self, side: Side, price: Price) {
+        if self.max_depth == 0 {
+            return;
+        }
+        let (levels, far) = match side {
+            Side::Buy => (&mut self.bids, &mut self.far_bids),
+            _ => (&mut self.asks, &mut self.far_asks),
+        };
+        if let Some(far_level) = far.remove(&price) {
+            levels.insert(side, restore(side, price, far_level));
+        }
+    }
 
-pub struct Partition<I, KeyFn>
-where
-    I: Iterator,
-{
-    iter: I,
-    split_fn: KeyFn,
-    acc: Vec<I::Item>,
+    #[inline]
+    fn touch(&mut self, side: Side, price: Price, ts: Timestamp) {
+        let times = if side == Side::Buy { &mut self.bid_times } else { &mut self.ask_times };
+        times.insert(price, ts);
+    }
+
+    // keeps exactly `max_depth` levels materialized, if there are enough
+    #[inline]
+    fn rebalance(&mut self, side: Side) {
+        if self.max_depth == 0 {
+            return;
+        }
+        let (levels, far) = match side {
+            Side::Buy => (&mut self.bids, &mut self.far_bids),
+            _ => (&mut self.asks, &mut self.far_asks),
+        };
+        while levels.len() > self.max_depth {
+            let (price, volume, orders) = levels.pop_worst(side).unwrap();
+            far.insert(price, (volume, orders.iter().map(|r| (r.order_id, r.amount)).collect()));
+            self.spare.put(orders);
+        }
+        while levels.len() < self.max_depth {
+            let best = if side == Side::Buy { far.pop_last() } else { far.pop_first() };
+            match best {
+                Some((price, far_level)) => levels.insert(side, restore(side, price, far_level)),
+                None => break,
+            }
+        }
+    }
+
+    /// Writes the book state, including the order queues and the far levels, for `load`
+    pub fn save(&self, mut w: impl Write) -> Result<(), QshError> {
+        w.write_all(CHECKPOINT_MAGIC)?;
+        w.write_all(&[CHECKPOINT_VERSION])?;
+        bincode::encode_into_std_write(self, &mut w, config::standard())
+            .map_err(Box::<dyn std::error::Error>::from)?;
+        Ok(())
+    }
+
+    pub fn load(mut r: impl Read) -> Result<Self, QshError> {
+        let mut header = [0; 5];
+        r.read_exact(&mut header)?;
+        assert_valid!(&header[..4] == CHECKPOINT_MAGIC, "not an OrderBook checkpoint");
+        assert_valid!(
+            header[4] == CHECKPOINT_VERSION,
+            format!("unsupported checkpoint version {}", header[4])
+        );
+        bincode::decode_from_std_read(&mut r, config::standard())
+            .map_err(|e| Box::<dyn std::error::Error>::from(e).into())
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.bids.clear(&mut self.spare);
+        self.asks.clear(&mut self.spare);
+        self.far_bids.clear();
+        self.far_asks.clear();
+        self.bid_times.clear();
+        self.ask_times.clear();
+        self.index.clear();
+        self.counters = BookCounters { clears: self.counters.clears + 1, ..Default::default() };
+    }
+
+    /// time of the last record applied, unix nanos, `None` for the book no record was applied to
+    #[inline]
+    pub fn last_update(&self) -> Option<Timestamp> {
+        (self.ts != 0).then_some(self.ts)
+    }
+
+    /// records passed to `add`, `cancel` and `trade` since the last clear, the `Lenient` book
+    /// counts the skipped ones too
+    #[inline]
+    pub fn events_since_clear(&self) -> BookCounters {
+        self.counters
+    }
+
+    /// number of resting orders
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// number of materialized levels, at most `max_depth` for the bounded book
+    #[inline]
+    pub fn depth(&self, side: Side) -> usize {
+        if side == Side::Buy {
+            self.bids.len()
+        } else {
+            self.asks.len()
+        }
+    }
+
+    /// price, volume and the time of the last change, unix nanos, of the `depth`th materialized
+    /// level, `0` for the best one
+    #[inline]
+    pub fn level_summary(&self, side: Side, depth: usize) -> (Price, Volume, Timestamp) {
+        let (p, v, _) = self.levels(side).iter(side).nth(depth).expect("depth out of range");
+        (*p, *v, self.level_updated_at(side, *p).unwrap_or_default())
+    }
+
+    /// Time of the last add, cancel or fill at the level, unix nanos, the far one included,
+    /// `None` if there is no such level. Finds the stale levels, e.g. the frozen quotes of the
+    /// thin book.
+    pub fn level_updated_at(&self, side: Side, price: Price) -> Option<Timestamp> {
+        let times = if side == Side::Buy { &self.bid_times } else { &self.ask_times };
+        self.contains_level(side, price).then(|| times.get(&price).copied()).flatten()
+    }
+
+    /// levels of the `side`, ordered best-to-worst
+    #[inline]
+    pub fn iter_levels(
+        &self,
+        side: Side,
+    ) -> impl Iterator<Item = (&Price, &Volume, &[OrderLog])> + '_ {
+        self.levels(side).iter(side).map(|(p, v, orders)| (p, v, &orders[..]))
+    }
+
+    #[inline]
+    pub fn level_at(&self, side: Side, price: Price) -> Option<&Level> {
+        (side != Side::UNKNOWN).then(|| self.levels(side).get(side, price)).flatten()
+    }
+
+    /// resting volume at the level, the far one included, `0` if there is no such level
+    pub fn volume_at(&self, side: Side, price: Price) -> Volume {
+        let far = if side == Side::Buy { &self.far_bids } else { &self.far_asks };
+        match (self.level_at(side, price), far.get(&price)) {
+            (Some((_, v, _)), _) => *v,
+            (None, Some((v, _))) if side != Side::UNKNOWN => *v,
+            _ => 0,
+        }
+    }
+
+    /// the side has the level, the far one included
+    #[inline]
+    pub fn contains_level(&self, side: Side, price: Price) -> bool {
+        self.volume_at(side, price) > 0
+    }
+
+    /// price of the `n`th level of the side, `0` for the best one, the far levels included
+    pub fn price_at_depth(&self, side: Side, n: usize) -> Option<Price> {
+        (side != Side::UNKNOWN).then(|| self.quotes(side).nth(n).map(|(p, _)| p)).flatten()
+    }
+
+    /// resting orders at the level in queue order, empty if there is no such level
+    #[inline]
+    pub fn orders_at(&self, side: Side, price: Price) -> &[OrderLog] {
+        self.level_at(side, price).map(|(_, _, orders)| &orders[..]).unwrap_or(&[])
+    }
+
+    /// locates resting order by id
+    pub fn find_order(&self, order_id: UID) -> Option<OrderRef> {
+        let &(side, price) = self.index.get(&order_id)?;
+        let orders = self.orders_at(side, price);
+        position(orders, order_id).map(|index| OrderRef {
+            side,
+            price,
+            index,
+            ahead: orders[..index].iter().map(|r| r.amount).sum(),
+        })
+    }
+
+    /// volume resting ahead of the order at its level
+    #[inline]
+    pub fn queue_ahead(&self, order_id: UID) -> Option<Volume> {
+        self.find_order(order_id).map(|r| r.ahead)
+    }
+
+    /// Registers the virtual order posted at `ts`(unix nanos) at the `price`, behind the orders
+    /// resting there. It doesn't affect the book, its queue position follows the cancels and
+    /// trades of the orders ahead, and it's reached by the trades of the orders behind it or at
+    /// the worse prices, the ones timed before `ts` excluded. The virtual orders outlive `clear`.
+    pub fn shadow_add(&mut self, side: Side, price: Price, ts: Timestamp) -> ShadowId {
+        let last_ahead = self.resting(side, price).map(|(id, _)| id).max().unwrap_or_default();
+        self.shadows.push(Shadow { side, price, ts, last_ahead, filled: 0 });
+        ShadowId(self.shadows.len() - 1)
+    }
+
+    /// # Panics
+    /// if `id` is of the other book
+    pub fn shadow_status(&self, id: ShadowId) -> ShadowStatus {
+        let shadow = &self.shadows[id.0];
+        let ahead = self
+            .resting(shadow.side, shadow.price)
+            .filter(|&(id, _)| id <= shadow.last_ahead)
+            .map(|(_, amount)| amount)
+            .sum();
+        ShadowStatus { ahead, filled: shadow.filled, done: shadow.filled > 0 }
+    }
+
+    // (order_id, amount) of the orders resting at the level, the far one included
+    fn resting(&self, side: Side, price: Price) -> Box<dyn Iterator<Item = (UID, Volume)> + '_> {
+        let far = if side == Side::Buy { &self.far_bids } else { &self.far_asks };
+        match far.get(&price) {
+            Some((_, orders)) => Box::new(orders.iter().copied()),
+            None => Box::new(self.orders_at(side, price).iter().map(|r| (r.order_id, r.amount))),
+        }
+    }
+
+    // the trade reaches the virtual orders behind the traded one or at the better prices
+    fn fill_shadows(&mut self, rec: &OrderLog) {
+        let ts = ticks_to_unix_ns(rec.timestamp);
+        for shadow in self.shadows.iter_mut().filter(|s| s.side == rec.side && s.ts <= ts) {
+            let reached = match (rec.side, rec.price.cmp(&shadow.price)) {
+                (_, std::cmp::Ordering::Equal) => rec.order_id > shadow.last_ahead,
+                (Side::Buy, ord) => ord.is_lt(),
+                (_, ord) => ord.is_gt(),
+            };
+            if reached {
+                shadow.filled += rec.amount;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn levels(&self, side: Side) -> &Levels {
+        if side == Side::Buy {
+            &self.bids
+        } else {
+            &self.asks
+        }
+    }
+
+    /// Top `depth` levels, `[bid_px, bid_vol, ask_px, ask_vol]` per level, panics if any side
+    /// is shallower. `0` for all the levels, the shallower side zero padded to
+    /// [`snapshot_width`](Self::snapshot_width).
+    pub fn snapshot(&self, depth: usize) -> Snapshot {
+        let mut snapshot = vec![0; self.snapshot_width(depth) * 4];
+        self.snapshot_into(depth, &mut snapshot).unwrap();
+        (self.ts, snapshot)
+    }
+
+    /// levels per side of the `depth` snapshot, the deeper side's depth for `0`(unlimited)
+    #[inline]
+    pub fn snapshot_width(&self, depth: usize) -> usize {
+        if depth == 0 {
+            self.depth(Side::Buy).max(self.depth(Side::Sell))
+        } else {
+            depth
+        }
+    }
+
+    /// Writes the [`snapshot`](Self::snapshot) levels into `out` of exactly `depth * 4` items,
+    /// returns the book time. Fails with `QshError::Validation` if the `out` size doesn't match
+    /// or any side has less than `depth` levels. For `0` the `out` is of
+    /// [`snapshot_width`](Self::snapshot_width) levels.
+    pub fn snapshot_into(&self, depth: usize, out: &mut [i64]) -> Result<Timestamp, QshError> {
+        if depth == 0 {
+            return self.snapshot_upto_into(self.snapshot_width(0), out).map(|_| self.ts);
+        }
+        assert_valid!(
+            out.len() == depth * 4,
+            format!("snapshot buffer of {} for the depth {depth}", out.len())
+        );
+        assert_valid!(
+            self.bids.len() >= depth && self.asks.len() >= depth,
+            format!("book is shallower than {depth}")
+        );
+        let levels = self.bids.iter(Side::Buy).zip(self.asks.iter(Side::Sell));
+        for (level, (bid, ask)) in out.chunks_exact_mut(4).zip(levels) {
+            assert!(bid.1 > 0);
+            assert!(ask.1 > 0);
+            level.copy_from_slice(&[bid.0, bid.1, ask.0, ask.1]);
+        }
+        Ok(self.ts)
+    }
+
+    /// All the levels within `ticks` price steps of the mid price, see [`BandAnchor::Mid`]
+    #[inline]
+    pub fn snapshot_band(&self, ticks: Price) -> BookSnapshot {
+        self.snapshot_band_with(ticks, BandAnchor::Mid)
+    }
+
+    /// All the levels within `ticks` price steps of the `anchor`, the boundary included. The
+    /// width is variable, see [`BookSnapshot::flatten`].
+    pub fn snapshot_band_with(&self, ticks: Price, anchor: BandAnchor) -> BookSnapshot {
+        // doubled prices keep the mid integer
+        let mid = match (anchor, self.best_bid(), self.best_ask()) {
+            (BandAnchor::Mid, Some((bid, _)), Some((ask, _))) => Some(bid + ask),
+            _ => None,
+        };
+        let band = |side| {
+            let mut quotes = self.quotes(side).peekable();
+            let anchor = mid.or_else(|| quotes.peek().map(|&(p, _)| p * 2)).unwrap_or_default();
+            quotes.take_while(|&(p, _)| (p * 2 - anchor).abs() <= ticks * 2).collect()
+        };
+        BookSnapshot { ts: self.ts, bids: band(Side::Buy), asks: band(Side::Sell), counts: None }
+    }
+
+    /// Top `depth` levels per side, fewer if the book is thinner, `0` for all of them
+    pub fn book_snapshot(&self, depth: usize) -> BookSnapshot {
+        let depth = if depth == 0 { usize::MAX } else { depth };
+        BookSnapshot {
+            ts: self.ts,
+            bids: self.quotes(Side::Buy).take(depth).collect(),
+            asks: self.quotes(Side::Sell).take(depth).collect(),
+            counts: None,
+        }
+    }
+
+    /// Same as [`book_snapshot`](Self::book_snapshot) with the order counts per level
+    pub fn book_snapshot_with_counts(&self, depth: usize) -> BookSnapshot {
+        let mut snapshot = self.book_snapshot(depth);
+        let counts = |side, n| self.order_counts(side).take(n).collect();
+        snapshot.counts =
+            Some((counts(Side::Buy, snapshot.bids.len()), counts(Side::Sell, snapshot.asks.len())));
+        snapshot
+    }
+
+    /// Exactly `bid_depth` and `ask_depth` top levels of the sides, zero padded if the book is
+    /// thinner
+    pub fn snapshot_sides(&self, bid_depth: usize, ask_depth: usize) -> BookSnapshot {
+        let side =
+            |side, depth| self.quotes(side).chain(std::iter::repeat((0, 0))).take(depth).collect();
+        BookSnapshot {
+            ts: self.ts,
+            bids: side(Side::Buy, bid_depth),
+            asks: side(Side::Sell, ask_depth),
+            counts: None,
+        }
+    }
+
+    /// [`snapshot_sides`](Self::snapshot_sides) of `depth` per side, with the number of the
+    /// actual levels of `(bids, asks)` up to `depth`. Unlike [`snapshot`](Self::snapshot) never
+    /// fails on the thin book. `0` for all the levels.
+    pub fn snapshot_upto(&self, depth: usize) -> (BookSnapshot, (usize, usize)) {
+        let depth = self.snapshot_width(depth);
+        let snapshot = self.snapshot_sides(depth, depth);
+        let actual = |quotes: &[Quote]| quotes.iter().take_while(|q| q.1 > 0).count();
+        let depths = (actual(&snapshot.bids), actual(&snapshot.asks));
+        (snapshot, depths)
+    }
+
+    /// Writes the [`snapshot_upto`](Self::snapshot_upto) levels into `out` of exactly
+    /// `depth * 4` items in the [`snapshot`](Self::snapshot) layout, returns the actual depths.
+    /// Fails with `QshError::Validation` if the `out` size doesn't match. For `0` the `out` is of
+    /// [`snapshot_width`](Self::snapshot_width) levels.
+    pub fn snapshot_upto_into(
+        &self,
+        depth: usize,
+        out: &mut [i64],
+    ) -> Result<(usize, usize), QshError> {
+        let depth = self.snapshot_width(depth);
+        assert_valid!(
+            out.len() == depth * 4,
+            format!("snapshot buffer of {} for the depth {depth}", out.len())
+        );
+        out.fill(0);
+        let mut depths = (0, 0);
+        for (j, side, n) in [(0, Side::Buy, &mut depths.0), (2, Side::Sell, &mut depths.1)] {
+            for (level, (p, v)) in out.chunks_exact_mut(4).zip(self.quotes(side)) {
+                (level[j], level[j + 1]) = (p, v);
+                *n += 1;
+            }
+        }
+        Ok(depths)
+    }
+
+    /// Same as [`snapshot`](Self::snapshot), with the number of the resting orders per level:
+    /// `[bid_px, bid_vol, bid_cnt, ask_px, ask_vol, ask_cnt]`
+    pub fn snapshot_with_counts(&self, depth: usize) -> Snapshot {
+        let mut snapshot = vec![0; self.snapshot_width(depth) * 6];
+        for (j, side) in [(0, Side::Buy), (3, Side::Sell)] {
+            let mut levels = self.levels(side).iter(side);
+            for level in snapshot.chunks_exact_mut(6) {
+                match levels.next() {
+                    Some((p, v, orders)) => {
+                        assert!(*v > 0);
+                        level[j..j + 3].copy_from_slice(&[*p, *v, orders.len() as i64]);
+                    }
+                    None => assert!(depth == 0, "book is shallower than {depth}"),
+                }
+            }
+        }
+        (self.ts, snapshot)
+    }
+
+    #[inline]
+    pub fn mid_price(&self) -> MidPrice {
+        let (bid, ask) = (self.bids.best(Side::Buy).unwrap(), self.asks.best(Side::Sell).unwrap());
+        (bid.0 + ask.0) as MidPrice * 0.5
+    }
+
+    /// total resting volume of the `side`, including the far levels of the bounded book
+    #[inline]
+    pub fn total_volume(&self, side: Side) -> Volume {
+        self.quotes(side).map(|(_, v)| v).sum()
+    }
+
+    /// resting volume at the levels within `ticks` price steps from the best level
+    pub fn volume_within_ticks(&self, side: Side, ticks: Price) -> Volume {
+        let mut quotes = self.quotes(side).peekable();
+        let best = match quotes.peek() {
+            Some(&(best, _)) => best,
+            None => return 0,
+        };
+        quotes.take_while(|(p, _)| (p - best).abs() <= ticks).map(|(_, v)| v).sum()
+    }
+
+    /// resting volume at the levels strictly better than `price`
+    pub fn volume_better_than(&self, side: Side, price: Price) -> Volume {
+        self.quotes(side)
+            .take_while(|&(p, _)| if side == Side::Buy { p > price } else { p < price })
+            .map(|(_, v)| v)
+            .sum()
+    }
+
+    /// Estimates the execution of `qty` by an aggressive order of the `side`, i.e. walks the asks
+    /// for `Side::Buy`. `None` if the opposite side is empty.
+    pub fn vwap_for_size(&self, side: Side, qty: Volume) -> Option<FillEstimate> {
+        let mut est = FillEstimate { vwap: 0., worst_price: 0, levels_consumed: 0, unfilled: qty };
+        let mut notional = 0.;
+        for (price, volume) in self.quotes(side.opposite()) {
+            if est.unfilled <= 0 {
+                break;
+            }
+            let filled = volume.min(est.unfilled);
+            notional += (price * filled) as f64;
+            est.unfilled -= filled;
+            est.worst_price = price;
+            est.levels_consumed += 1;
+        }
+        (est.levels_consumed > 0).then(|| {
+            est.vwap = notional / (qty - est.unfilled) as f64;
+            est
+        })
+    }
+
+    // all the levels of the side, best-to-worst, including the far ones
+    fn quotes(&self, side: Side) -> impl Iterator<Item = Quote> + '_ {
+        let far: Box<dyn Iterator<Item = (&Price, &FarLevel)>> = if side == Side::Buy {
+            Box::new(self.far_bids.iter().rev())
+        } else {
+            Box::new(self.far_asks.iter())
+        };
+        self.levels(side)
+            .iter(side)
+            .map(|(p, v, _)| (*p, *v))
+            .chain(far.map(|(p, (v, _))| (*p, *v)))
+    }
+
+    // number of the resting orders per level, in the `quotes` order
+    fn order_counts(&self, side: Side) -> impl Iterator<Item = usize> + '_ {
+        let far: Box<dyn Iterator<Item = &FarLevel>> = if side == Side::Buy {
+            Box::new(self.far_bids.values().rev())
+        } else {
+            Box::new(self.far_asks.values())
+        };
+        self.levels(side).iter(side).map(|(_, _, o)| o.len()).chain(far.map(|(_, o)| o.len()))
+    }
+
+    /// Differences of the `other` book materialized levels relative to this one, level by
+    /// level, best-to-worst per side
+    pub fn diff(&self, other: &OrderBook) -> BookDiff {
+        let mut diff = vec![];
+        for side in Side::both() {
+            let mut levels: BTreeMap<Price, (Option<&Level>, Option<&Level>)> = BTreeMap::new();
+            self.levels(side).iter(side).for_each(|l| levels.entry(l.0).or_default().0 = Some(l));
+            other.levels(side).iter(side).for_each(|l| levels.entry(l.0).or_default().1 = Some(l));
+
+            let levels: Box<dyn Iterator<Item = _>> = if side == Side::Buy {
+                Box::new(levels.into_iter().rev())
+            } else {
+                Box::new(levels.into_iter())
+            };
+            for (price, pair) in levels {
+                match pair {
+                    (Some(l), None) => diff.push(LevelDiff::Missing { side, price, volume: l.1 }),
+                    (None, Some(r)) => diff.push(LevelDiff::Extra { side, price, volume: r.1 }),
+                    (Some(l), Some(r)) => {
+                        if l.1 != r.1 {
+                            diff.push(LevelDiff::Volume { side, price, left: l.1, right: r.1 });
+                        }
+                        let ids = |a: &Level, b: &Level| -> Vec<UID> {
+                            let b: HashSet<_> = b.2.iter().map(|r| r.order_id).collect();
+                            a.2.iter().map(|r| r.order_id).filter(|id| !b.contains(id)).collect()
+                        };
+                        let (missing, extra) = (ids(l, r), ids(r, l));
+                        if !missing.is_empty() || !extra.is_empty() {
+                            diff.push(LevelDiff::Orders { side, price, missing, extra });
+                        }
+                    }
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        BookDiff(diff)
+    }
+
+    /// Stable(FNV-1a) hash of the top `depth` levels prices and volumes of both sides, for the
+    /// cheap comparison of the book states
+    pub fn checksum(&self, depth: usize) -> u64 {
+        let mut hash = 0xcbf29ce484222325_u64;
+        for side in Side::both() {
+            for (p, v) in self.quotes(side).take(depth) {
+                for b in p.to_le_bytes().into_iter().chain(v.to_le_bytes()) {
+                    hash = (hash ^ b as u64).wrapping_mul(0x100000001b3);
+                }
+            }
+            // side separator
+            hash = (hash ^ 0xff).wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+
+    /// Bid share of the volume at the top `depth` levels, `None` if any side is empty.
+    ///
+    /// ```no_run
+    /// use qsh_rs::orderbook::{self as ob, NopListener, OrderLogStream};
+    /// use qsh_rs::{header, inflate, OrderLogReader, QshRead};
+    ///
+    /// let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    /// header(&mut parser).unwrap();
+    ///
+    /// let mut book = ob::OrderBook::default();
+    /// let mut series = vec![];
+    /// for tx in parser.into_iter::<OrderLogReader>().transactions() {
+    ///     book.apply_tx(&tx, &mut NopListener).unwrap();
+    ///     if let (Some(ts), Some(imbalance)) = (book.last_update(), book.imbalance(5)) {
+    ///         series.push((ts, imbalance));
+    ///     }
+    /// }
+    /// // `series` is ready to be plotted
+    /// ```
+    pub fn imbalance(&self, depth: usize) -> Option<f64> {
+        let (bid, ask) = (self.top_volume(Side::Buy, depth)?, self.top_volume(Side::Sell, depth)?);
+        Some(bid as f64 / (bid + ask) as f64)
+    }
+
+    /// Top of the book prices weighted by the opposite side volume, `None` if any side is empty
+    #[inline]
+    pub fn microprice(&self) -> Option<f64> {
+        self.weighted_mid(1)
+    }
+
+    /// `microprice` over the top `depth` levels, `0` for all the levels: volume-weighted prices
+    /// of the sides weighted by the opposite side volume
+    pub fn weighted_mid(&self, depth: usize) -> Option<f64> {
+        let depth = if depth == 0 { usize::MAX } else { depth };
+        let top = |side| self.levels(side).iter(side).take(depth).map(|&(p, v, _)| (p, v));
+        weigh_sides(top(Side::Buy), top(Side::Sell))
+    }
+
+    #[inline]
+    fn top_volume(&self, side: Side, depth: usize) -> Option<Volume> {
+        let levels = self.levels(side);
+        (!levels.is_empty()).then(|| levels.iter(side).take(depth).map(|(_, v, _)| v).sum())
+    }
+
+    /// best bid is at or above the best ask
+    #[inline]
+    pub fn is_crossed(&self) -> bool {
+        matches!((self.best_bid(), self.best_ask()), (Some((bid, _)), Some((ask, _))) if bid >= ask)
+    }
+
+    #[inline]
+    pub fn best_bid(&self) -> Option<Quote> {
+        self.bids.best(Side::Buy).map(|(p, v, _)| (*p, *v))
+    }
+
+    #[inline]
+    pub fn best_ask(&self) -> Option<Quote> {
+        self.asks.best(Side::Sell).map(|(p, v, _)| (*p, *v))
+    }
+}
+
+/// Aggregated book driven by the L2 updates, e.g. Quotes stream or `l3tol2` output
+#[derive(Debug, Default, Clone)]
+pub struct L2Book {
+    bids: BTreeMap<Price, Volume>,
+    asks: BTreeMap<Price, Volume>,
+    ts: Timestamp,
+}
+
+impl L2Book {
+    pub fn from_quotes(quotes: &Quotes) -> Self {
+        Self {
+            bids: quotes.bid.iter().copied().collect(),
+            asks: quotes.ask.iter().copied().collect(),
+            ts: 0,
+        }
+    }
+
+    /// Quote sets the level volume, Remove deletes the level, Clear wipes the book, Snapshot
+    /// replaces it, Trade doesn't change it
+    pub fn apply(&mut self, msg: L2Message) -> Result<(), QshError> {
+        match msg {
+            L2Message::Quote { side, price, size } => {
+                assert_valid!(size > 0, format!("non-positive level size {msg:?}"));
+                self.side_mut(side)?.insert(price, size);
+            }
+            L2Message::Remove { side, price } => {
+                if self.side_mut(side)?.remove(&price).is_none() {
+                    assert_state!(false, format!("level to remove not found {msg:?}"));
+                }
+            }
+            L2Message::Clear => self.clear(),
+            L2Message::Trade { .. } => {}
+            L2Message::Snapshot { ts, bids, asks } => {
+                (self.bids, self.asks) = (bids.into_iter().collect(), asks.into_iter().collect());
+                self.ts = ts;
+            }
+        }
+        Ok(())
+    }
+
+    /// stamps the book state, unix nanos, as the L2 updates carry no time
+    #[inline]
+    pub fn set_timestamp(&mut self, ts: Timestamp) {
+        self.ts = ts;
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    /// best bid is at or above the best ask, the input is inconsistent then
+    #[inline]
+    pub fn is_crossed(&self) -> bool {
+        matches!((self.best_bid(), self.best_ask()), (Some((bid, _)), Some((ask, _))) if bid >= ask)
+    }
+
+    #[inline]
+    pub fn depth(&self, side: Side) -> usize {
+        if side == Side::Buy {
+            self.bids.len()
+        } else {
+            self.asks.len()
+        }
+    }
+
+    /// levels of the `side`, ordered best-to-worst
+    pub fn iter_levels(&self, side: Side) -> Box<dyn Iterator<Item = Quote> + '_> {
+        let quote = |(&p, &v): (&Price, &Volume)| (p, v);
+        if side == Side::Buy {
+            Box::new(self.bids.iter().rev().map(quote))
+        } else {
+            Box::new(self.asks.iter().map(quote))
+        }
+    }
+
+    /// same layout as `OrderBook::snapshot`, missing levels are zeroed, `0` for all the levels
+    pub fn snapshot(&self, depth: usize) -> Snapshot {
+        let depth = if depth == 0 { self.bids.len().max(self.asks.len()) } else { depth };
+        let mut snapshot = vec![0; depth * 4];
+        for (i, (p, v)) in self.iter_levels(Side::Buy).take(depth).enumerate() {
+            (snapshot[i * 4], snapshot[i * 4 + 1]) = (p, v);
+        }
+        for (i, (p, v)) in self.iter_levels(Side::Sell).take(depth).enumerate() {
+            (snapshot[i * 4 + 2], snapshot[i * 4 + 3]) = (p, v);
+        }
+        (self.ts, snapshot)
+    }
+
+    /// Top `depth` levels per side, fewer if the book is thinner, `0` for all of them
+    pub fn book_snapshot(&self, depth: usize) -> BookSnapshot {
+        let depth = if depth == 0 { usize::MAX } else { depth };
+        BookSnapshot {
+            ts: self.ts,
+            bids: self.iter_levels(Side::Buy).take(depth).collect(),
+            asks: self.iter_levels(Side::Sell).take(depth).collect(),
+            counts: None,
+        }
+    }
+
+    #[inline]
+    pub fn mid_price(&self) -> MidPrice {
+        (self.best_bid().unwrap().0 + self.best_ask().unwrap().0) as MidPrice * 0.5
+    }
+
+    #[inline]
+    pub fn best_bid(&self) -> Option<Quote> {
+        self.bids.last_key_value().map(|(&p, &v)| (p, v))
+    }
+
+    #[inline]
+    pub fn best_ask(&self) -> Option<Quote> {
+        self.asks.first_key_value().map(|(&p, &v)| (p, v))
+    }
+
+    #[inline]
+    fn side_mut(&mut self, side: Side) -> Result<&mut BTreeMap<Price, Volume>, QshError> {
+        match side {
+            Side::Buy => Ok(&mut self.bids),
+            Side::Sell => Ok(&mut self.asks),
+            Side::UNKNOWN => Err(QshError::Validation("unknown side".to_string())),
+        }
+    }
+}
+
+/// L2 book mirroring the L3 one
+impl BookListener for L2Book {
+    fn on_quote(&mut self, side: Side, price: Price, size: Volume, ts: Timestamp) {
+        if let Ok(levels) = self.side_mut(side) {
+            levels.insert(price, size);
+        }
+        self.ts = ts;
+    }
+
+    fn on_remove(&mut self, side: Side, price: Price, ts: Timestamp) {
+        if let Ok(levels) = self.side_mut(side) {
+            levels.remove(&price);
+        }
+        self.ts = ts;
+    }
+
+    fn on_clear(&mut self) {
+        self.clear();
+    }
+}
+
+/// Aggressor side of the deal, inferred from the book state before the deal: at or above the
+/// best ask is buyer-initiated, at or below the best bid is seller-initiated.
+///
+/// Returns `deal.side` as is if it's known. The inference is ambiguous for the deals inside the
+/// spread, as well as for the deals against an empty opposite side, those yield `Side::UNKNOWN`.
+pub fn infer_aggressor(deal: &Deal, book: &OrderBook) -> Side {
+    if deal.side != Side::UNKNOWN {
+        return deal.side;
+    }
+    match (book.best_bid(), book.best_ask()) {
+        (_, Some((ask, _))) if deal.price >= ask => Side::Buy,
+        (Some((bid, _)), _) if deal.price <= bid => Side::Sell,
+        _ => Side::UNKNOWN,
+    }
+}
+
+// far level orders as if they were just added
+fn restore(side: Side, price: Price, (volume, orders): FarLevel) -> Level {
+    let side_flag = if side == Side::Buy { OLFlagSet::BUY } else { OLFlagSet::SELL };
+    let orders = orders
+        .into_iter()
+        .map(|(order_id, amount)| OrderLog {
+            order_id,
+            price,
+            amount,
+            amount_rest: amount,
+            order_flags: OLFlagSet::ADD | OLFlagSet::QUOTE | side_flag,
+            side,
+            event: OLMsgType::Add,
+            type_: OrderType::Limit,
+            ..Default::default()
+        })
+        .collect();
+    (price, volume, orders)
+}
+
+fn ol_msg(msg: &str, rec: OrderLog) -> String {
+    format!("{}\n{rec}", msg,)
+}
+
+/// index of the first NewSession record of the transaction, `tx.len()` if there's none
+#[inline]
+pub fn new_session_start(tx: &[OrderLog]) -> usize {
+    tx.iter().position(|r| r.order_flags.contains(OLFlagSet::NEW_SESSION)).unwrap_or(tx.len())
+}
+
+#[inline(always)]
+pub fn fiok_with_trades(tx: &Vec<OrderLog>) -> bool {
+    match OrderType::from(tx[0].order_flags) {
+        OrderType::IOK | OrderType::FOK => tx.len() > 2,
+        _ => true,
+    }
+}
+
+/// order holding the whole level of the snapshot the book is seeded from, the synthetic orders
+/// have the negative ids, see [`OrderBook::from_snapshot`]
+#[inline]
+pub fn is_synthetic(order: &OrderLog) -> bool {
+    order.order_id < 0
+}
+
+#[inline(always)]
+pub fn non_system_record(rec: &OrderLog) -> bool {
+    rec.order_flags.contains(OLFlagSet::NON_SYSTEM)
+        || rec.order_flags.contains(OLFlagSet::NON_ZERO_REPL_ACT)
+        || rec.side == Side::UNKNOWN
+}
+
+#[inline(always)]
+pub fn system_record(rec: &OrderLog) -> bool {
+    !non_system_record(rec)
+}
+
+/// Configurable `system_record`, counting the dropped records per reason, see
+/// [`OrderLogStream::filter_with`]. The default drops the same records as `non_system_record`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordFilter {
+    pub non_system: bool,
+    pub non_zero_repl_act: bool,
+    pub unknown_side: bool,
+    stats: FilterStats,
+}
+
+/// Counters of the [`RecordFilter`], a record matching several criteria is counted once, by
+/// the first of them in the declaration order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterStats {
+    pub passed: usize,
+    pub non_system: usize,
+    pub non_zero_repl_act: usize,
+    pub unknown_side: usize,
+    /// unix time(nanos) of the first and the last dropped records
+    pub first_dropped: Option<Timestamp>,
+    pub last_dropped: Option<Timestamp>,
+}
+
+impl FilterStats {
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.non_system + self.non_zero_repl_act + self.unknown_side
+    }
+}
+
+impl Default for RecordFilter {
+    fn default() -> Self {
+        Self {
+            non_system: true,
+            non_zero_repl_act: true,
+            unknown_side: true,
+            stats: Default::default(),
+        }
+    }
+}
+
+impl RecordFilter {
+    /// `true` if the record passes, counts it
+    pub fn accept(&mut self, rec: &OrderLog) -> bool {
+        let flags = rec.order_flags;
+        let reason = if self.non_system && flags.contains(OLFlagSet::NON_SYSTEM) {
+            &mut self.stats.non_system
+        } else if self.non_zero_repl_act && flags.contains(OLFlagSet::NON_ZERO_REPL_ACT) {
+            &mut self.stats.non_zero_repl_act
+        } else if self.unknown_side && rec.side == Side::UNKNOWN {
+            &mut self.stats.unknown_side
+        } else {
+            self.stats.passed += 1;
+            return true;
+        };
+        *reason += 1;
+        let ts = ticks_to_unix_ns(rec.timestamp);
+        self.stats.first_dropped.get_or_insert(ts);
+        self.stats.last_dropped = Some(ts);
+        false
+    }
+
+    #[inline]
+    pub fn stats(&self) -> &FilterStats {
+        &self.stats
+    }
+}
+
+#[inline(always)]
+pub fn tx_end(rec: &OrderLog) -> bool {
+    rec.order_flags.contains(OLFlagSet::TX_END)
+}
+
+/// Options of [`OrderLogStream::transactions_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxOptions {
+    /// drop the records failing `system_record`
+    pub system_only: bool,
+    /// drop the FOK/IOK transactions without trades, see `fiok_with_trades`
+    pub fiok_with_trades: bool,
+    /// what to do with the trailing incomplete transaction
+    pub flush: FlushPolicy,
+}
+
+impl Default for TxOptions {
+    fn default() -> Self {
+        Self { system_only: true, fiok_with_trades: true, flush: FlushPolicy::Drop }
+    }
+}
+
+/// The standard OrderLog pipeline: `system_record` / `partition_by(tx_end)` /
+/// `fiok_with_trades`
+pub trait OrderLogStream: Iterator<Item = OrderLog> + Sized {
+    #[inline]
+    fn transactions(self) -> impl Iterator<Item = Vec<OrderLog>> {
+        self.filter(system_record).partition_by(tx_end).filter(fiok_with_trades)
+    }
+
+    /// Same pipeline with the stages controlled by `opts`, the error is yielded only with the
+    /// `FlushPolicy::Error`
+    #[inline]
+    fn transactions_with(
+        self,
+        opts: TxOptions,
+    ) -> impl Iterator<Item = Result<Vec<OrderLog>, QshError>> {
+        self.filter(move |rec| !opts.system_only || system_record(rec))
+            .partition_by_with(tx_end, opts.flush)
+            .filter(move |tx| match tx {
+                Ok(tx) => !opts.fiok_with_trades || fiok_with_trades(tx),
+                Err(_) => true,
+            })
+    }
+
+    /// Records passing the `filter`, its stats are available once the iterator is dropped
+    #[inline]
+    fn filter_with<'a>(self, filter: &'a mut RecordFilter) -> impl Iterator<Item = OrderLog> + 'a
+    where
+        Self: 'a,
+    {
+        self.filter(move |rec| filter.accept(rec))
+    }
+}
+
+impl<I> OrderLogStream for I where I: Iterator<Item = OrderLog> {}
+
+/// Replays the orderlog through the usual `system_record` / `partition_by(tx_end)` /
+/// `fiok_with_trades` pipeline into a fresh book, yields the `depth` levels snapshot after
+/// every applied transaction. Transactions leaving less than `depth` levels on any side are
+/// skipped, the iteration stops after the first error.
+pub fn reconstruct(
+    input: impl Iterator<Item = OrderLog>,
+    depth: usize,
+) -> impl Iterator<Item = Result<Snapshot, QshError>> {
+    let (mut book, mut failed) = (OrderBook::default(), false);
+    input
+        .transactions()
+        .map_while(move |tx| {
+            if failed {
+                return None;
+            }
+            Some(match book.apply_tx(&tx, &mut NopListener) {
+                Err(e) => {
+                    failed = true;
+                    Some(Err(e))
+                }
+                Ok(()) => (book.depth(Side::Buy) >= depth && book.depth(Side::Sell) >= depth)
+                    .then(|| Ok(book.snapshot(depth))),
+            })
+        })
+        .flatten()
+}
+
+/// record time to unix millis
+#[deprecated(since = "0.3.0", note = "use `time::ticks_to_unix_ms`, the book time is unix nanos")]
+#[inline]
+pub fn ticks_to_unix_time(v: Timestamp) -> Timestamp {
+    time::ticks_to_unix_ms(v)
+}
+
+pub struct Partition<I, KeyFn>
+where
+    I: Iterator,
+{
+    iter: I,
+    split_fn: KeyFn,
+    acc: Vec<I::Item>,
+    dropped: usize,
+    // running totals for the capacity of the next partition
+    items: usize,
+    partitions: usize,
+    // the least capacity of the partition
+    capacity: usize,
+}
+
+/// What to do with the trailing items after the last split, e.g. the incomplete transaction
+/// of the truncated file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// drop them silently, the `partition_by` behavior
+    #[default]
+    Drop,
+    /// yield them as the last partition
+    Yield,
+    /// yield the error instead
+    Error,
+}
+
+/// `Partition` with the explicit [`FlushPolicy`], see [`PartitionBy::partition_by_with`]
+pub struct PartitionWith<I, KeyFn>
+where
+    I: Iterator,
+{
+    inner: Partition<I, KeyFn>,
+    policy: FlushPolicy,
 }
 
 pub trait PartitionBy: Iterator {
@@ -293,29 +2023,178 @@ pub trait PartitionBy: Iterator {
         Self: Sized,
         F: FnMut(&Self::Item) -> bool,
     {
-        Partition { iter: self, split_fn: f, acc: vec![] }
+        self.partition_by_with_capacity(f, 0)
+    }
+
+    /// Same partitioning, the partitions are allocated with at least `capacity` items, e.g. of
+    /// the typical transaction length of the instrument, the running average is used otherwise
+    fn partition_by_with_capacity<F>(self, f: F, capacity: usize) -> Partition<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Partition {
+            iter: self,
+            split_fn: f,
+            acc: Vec::with_capacity(capacity),
+            dropped: 0,
+            items: 0,
+            partitions: 0,
+            capacity,
+        }
+    }
+
+    /// Same partitioning with the trailing items handled according to the `policy`
+    fn partition_by_with<F>(self, f: F, policy: FlushPolicy) -> PartitionWith<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        PartitionWith { inner: self.partition_by(f), policy }
+    }
+
+    /// Same partitioning, but the partitions are borrowed from the single reused buffer, see
+    /// [`PartitionReuse::next_partition`]
+    fn partition_by_reuse<F>(self, f: F) -> PartitionReuse<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        PartitionReuse { iter: self, split_fn: f, acc: vec![] }
     }
 }
 
 impl<I> PartitionBy for I where I: Iterator {}
 
-impl<I, F, T> Iterator for Partition<I, F>
+impl<I, F> Iterator for Partition<I, F>
 where
-    I: Iterator<Item = T>,
-    T: Clone,
+    I: Iterator,
     F: FnMut(&I::Item) -> bool,
 {
     type Item = Vec<I::Item>;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_split();
+        if next.is_none() {
+            self.drop_trailing();
+        }
+        next
+    }
+}
+
+impl<I, F> Partition<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    /// number of the trailing items dropped after the inner iterator is exhausted
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    // next complete partition, the trailing items are left in `acc`
+    #[inline]
+    fn next_split(&mut self) -> Option<Vec<I::Item>> {
         while let Some(x) = self.iter.next() {
             let split = (self.split_fn)(&x);
             self.acc.push(x);
             if split {
-                return Some(std::mem::replace(&mut self.acc, Vec::with_capacity(10)));
+                self.items += self.acc.len();
+                self.partitions += 1;
+                // the average partition length, rounded up
+                let capacity = self.items.div_ceil(self.partitions).max(self.capacity);
+                return Some(std::mem::replace(&mut self.acc, Vec::with_capacity(capacity)));
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn drop_trailing(&mut self) -> usize {
+        let n = self.acc.len();
+        self.dropped += n;
+        self.acc.clear();
+        n
+    }
+}
+
+impl<I, F> PartitionWith<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    /// number of the trailing items dropped or reported as the error
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.inner.dropped
+    }
+}
+
+impl<I, F> Iterator for PartitionWith<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    type Item = Result<Vec<I::Item>, QshError>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(partition) = self.inner.next_split() {
+            return Some(Ok(partition));
+        }
+        if self.inner.acc.is_empty() {
+            return None;
+        }
+        match self.policy {
+            FlushPolicy::Drop => {
+                self.inner.drop_trailing();
+                None
+            }
+            FlushPolicy::Yield => Some(Ok(std::mem::take(&mut self.inner.acc))),
+            FlushPolicy::Error => {
+                let n = self.inner.drop_trailing();
+                Some(Err(QshError::InvalidState(format!("{n} trailing items without the split"))))
+            }
+        }
+    }
+}
+
+pub struct PartitionReuse<I, KeyFn>
+where
+    I: Iterator,
+{
+    iter: I,
+    split_fn: KeyFn,
+    acc: Vec<I::Item>,
+}
+
+impl<I, F> PartitionReuse<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    /// next partition, valid until the following call. Trailing items without the split are
+    /// dropped, as by `Partition`
+    #[inline]
+    pub fn next_partition(&mut self) -> Option<&[I::Item]> {
+        self.acc.clear();
+        for x in self.iter.by_ref() {
+            let split = (self.split_fn)(&x);
+            self.acc.push(x);
+            if split {
+                return Some(&self.acc);
             }
         }
         None
     }
+
+    /// Calls `f` for every partition, no allocation per partition
+    #[inline]
+    pub fn for_each_partition(mut self, mut f: impl FnMut(&[I::Item])) {
+        while let Some(partition) = self.next_partition() {
+            f(partition);
+        }
+    }
 }
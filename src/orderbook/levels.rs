@@ -0,0 +1,257 @@
+//! Backing stores of the book side levels, see [`Storage`](super::Storage)
+
+use super::{Level, Storage};
+use crate::types::{OrderLog, Price, Side};
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+use std::collections::VecDeque;
+
+// bound of the pooled order queues, the book of the usual depth frees far less per clear
+const MAX_SPARE: usize = 4096;
+
+#[derive(Debug, Encode, Decode)]
+pub(super) enum Levels {
+    // best-to-worst
+    Sorted(Vec<Level>),
+    Ladder(Ladder),
+}
+
+// slot per price step between the worst and the best levels
+#[derive(Debug, Encode, Decode)]
+pub(super) struct Ladder {
+    step: Price,
+    // price of the first slot, anchors the price grid once the first level is inserted
+    base: Option<Price>,
+    // ascending prices, the end slots are occupied
+    slots: VecDeque<Option<Level>>,
+    len: usize,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Levels::Sorted(vec![])
+    }
+}
+
+impl Levels {
+    pub(super) fn new(storage: Storage) -> Self {
+        match storage {
+            Storage::Sorted => Levels::Sorted(vec![]),
+            Storage::Ladder { step } => {
+                assert!(step > 0, "ladder step must be positive");
+                Levels::Ladder(Ladder { step, base: None, slots: VecDeque::new(), len: 0 })
+            }
+        }
+    }
+
+    #[inline]
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Levels::Sorted(levels) => levels.len(),
+            Levels::Ladder(ladder) => ladder.len,
+        }
+    }
+
+    #[inline]
+    pub(super) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all the levels, their order queues go to the `spare` ones
+    pub(super) fn clear(&mut self, spare: &mut Spare) {
+        match self {
+            Levels::Sorted(levels) => levels.drain(..).for_each(|level| spare.put(level.2)),
+            Levels::Ladder(ladder) => {
+                ladder.slots.drain(..).flatten().for_each(|level| spare.put(level.2));
+                (ladder.base, ladder.len) = (None, 0);
+            }
+        }
+    }
+
+    /// the price is on the ladder grid
+    #[inline]
+    pub(super) fn accepts(&self, price: Price) -> bool {
+        match self {
+            Levels::Sorted(_) => true,
+            Levels::Ladder(ladder) => {
+                ladder.base.is_none_or(|base| (price - base).rem_euclid(ladder.step) == 0)
+            }
+        }
+    }
+
+    #[inline]
+    pub(super) fn get(&self, side: Side, price: Price) -> Option<&Level> {
+        match self {
+            Levels::Sorted(levels) => search(levels, side, price).ok().map(|ix| &levels[ix]),
+            Levels::Ladder(ladder) => ladder.slot(price).and_then(|ix| ladder.slots[ix].as_ref()),
+        }
+    }
+
+    #[inline]
+    pub(super) fn get_mut(&mut self, side: Side, price: Price) -> Option<&mut Level> {
+        match self {
+            Levels::Sorted(levels) => search(levels, side, price).ok().map(|ix| &mut levels[ix]),
+            Levels::Ladder(ladder) => ladder.slot(price).and_then(|ix| ladder.slots[ix].as_mut()),
+        }
+    }
+
+    /// Inserts the level absent from the side, the price is to be [`accepts`](Self::accepts)ed
+    pub(super) fn insert(&mut self, side: Side, level: Level) {
+        match self {
+            Levels::Sorted(levels) => {
+                let ix = search(levels, side, level.0).unwrap_err();
+                levels.insert(ix, level);
+            }
+            Levels::Ladder(ladder) => ladder.insert(level),
+        }
+    }
+
+    pub(super) fn remove(&mut self, side: Side, price: Price) -> Option<Level> {
+        match self {
+            Levels::Sorted(levels) => search(levels, side, price).ok().map(|ix| levels.remove(ix)),
+            Levels::Ladder(ladder) => ladder.remove(price),
+        }
+    }
+
+    pub(super) fn pop_worst(&mut self, side: Side) -> Option<Level> {
+        match self {
+            Levels::Sorted(levels) => levels.pop(),
+            Levels::Ladder(ladder) => {
+                let price = ladder.worst(side)?;
+                ladder.remove(price)
+            }
+        }
+    }
+
+    #[inline]
+    pub(super) fn best(&self, side: Side) -> Option<&Level> {
+        match self {
+            Levels::Sorted(levels) => levels.first(),
+            Levels::Ladder(ladder) => {
+                let best =
+                    if side == Side::Buy { ladder.slots.back() } else { ladder.slots.front() };
+                best.and_then(Option::as_ref)
+            }
+        }
+    }
+
+    /// levels best-to-worst
+    pub(super) fn iter(&self, side: Side) -> Box<dyn Iterator<Item = &Level> + '_> {
+        match self {
+            Levels::Sorted(levels) => Box::new(levels.iter()),
+            Levels::Ladder(ladder) if side == Side::Buy => {
+                Box::new(ladder.slots.iter().rev().flatten())
+            }
+            Levels::Ladder(ladder) => Box::new(ladder.slots.iter().flatten()),
+        }
+    }
+}
+
+/// Order queues of the removed levels, reused by the new ones to spare the allocations of the
+/// book replayed across the sessions. Not a part of the checkpoint.
+#[derive(Default)]
+pub(super) struct Spare(Vec<Vec<OrderLog>>);
+
+impl Spare {
+    /// empty queue, the pooled one if any
+    #[inline]
+    pub(super) fn take(&mut self) -> Vec<OrderLog> {
+        self.0.pop().unwrap_or_default()
+    }
+
+    #[inline]
+    pub(super) fn put(&mut self, mut orders: Vec<OrderLog>) {
+        if orders.capacity() != 0 && self.0.len() < MAX_SPARE {
+            orders.clear();
+            self.0.push(orders);
+        }
+    }
+}
+
+impl std::fmt::Debug for Spare {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Spare").field(&self.0.len()).finish()
+    }
+}
+
+impl Encode for Spare {
+    fn encode<E: Encoder>(&self, _: &mut E) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+impl<C> Decode<C> for Spare {
+    fn decode<D: Decoder<Context = C>>(_: &mut D) -> Result<Self, DecodeError> {
+        Ok(Spare::default())
+    }
+}
+
+bincode::impl_borrow_decode!(Spare);
+
+#[inline(always)]
+fn search(levels: &[Level], side: Side, price: Price) -> Result<usize, usize> {
+    debug_assert!(side != Side::UNKNOWN);
+    match side {
+        Side::Buy => levels.binary_search_by(|(p, _, _)| price.cmp(p)),
+        _ => levels.binary_search_by(|(p, _, _)| p.cmp(&price)),
+    }
+}
+
+impl Ladder {
+    #[inline]
+    fn slot(&self, price: Price) -> Option<usize> {
+        let offset = price - self.base?;
+        (offset >= 0 && offset % self.step == 0)
+            .then(|| (offset / self.step) as usize)
+            .filter(|&ix| ix < self.slots.len())
+    }
+
+    fn worst(&self, side: Side) -> Option<Price> {
+        let worst = if side == Side::Buy { self.slots.front() } else { self.slots.back() };
+        worst.and_then(Option::as_ref).map(|level| level.0)
+    }
+
+    fn insert(&mut self, level: Level) {
+        let price = level.0;
+        debug_assert!(self.base.is_none_or(|base| (price - base) % self.step == 0));
+        match self.base {
+            Some(base) if !self.slots.is_empty() && price < base => {
+                let gap = ((base - price) / self.step) as usize;
+                self.slots.reserve(gap);
+                (1..gap).for_each(|_| self.slots.push_front(None));
+                self.slots.push_front(Some(level));
+                self.base = Some(price);
+            }
+            Some(base) if !self.slots.is_empty() => {
+                let ix = ((price - base) / self.step) as usize;
+                if ix >= self.slots.len() {
+                    self.slots.resize_with(ix + 1, || None);
+                }
+                self.slots[ix] = Some(level);
+            }
+            // empty ladder keeps the grid of the former levels
+            _ => {
+                self.slots.push_back(Some(level));
+                self.base = Some(price);
+            }
+        }
+        self.len += 1;
+    }
+
+    fn remove(&mut self, price: Price) -> Option<Level> {
+        let level = self.slot(price).and_then(|ix| self.slots[ix].take())?;
+        self.len -= 1;
+        while let Some(None) = self.slots.back() {
+            self.slots.pop_back();
+        }
+        while let Some(None) = self.slots.front() {
+            self.slots.pop_front();
+            self.base = self.base.map(|base| base + self.step);
+        }
+        Some(level)
+    }
+}
@@ -1,7 +1,9 @@
 use crate::{
+    policy::{BothSides, InvalidVolume, MissingKey, ParsePolicy},
+    time::{ms_to_ns, recording_time_to_unix_ms, ticks_to_unix_ms},
     types::{
-        AuxInfo, AuxInfoFlags, Deal, DealFlags, OLEntryFlags, OLFlags, OLMsgType, OrderLog,
-        OrderType, Price, Quotes, Side, Volume, UID,
+        AuxInfo, AuxInfoFlags, Deal, DealFlags, Header, OLEntryFlags, OLFlags, OLMsgType, OrderLog,
+        OrderType, Price, Quotes, Side, Stream, Timestamp, Volume, UID,
     },
     QshError, QshRead,
 };
@@ -9,7 +11,38 @@ use std::collections::BTreeMap;
 
 pub trait QshParser: Default {
     type Item;
+    /// stream of the records the reader parses, see [`QshRead::into_checked_iter`]
+    const EXPECTED_STREAM: Stream;
+
     fn parse(&mut self, parser: &mut impl QshRead) -> Result<Self::Item, QshError>;
+
+    /// Same as `parse`, but writes the record into the caller-owned `out`, reusing its buffers,
+    /// e.g. the bid/ask vectors of `Quotes`.
+    ///
+    /// `out` is overwritten as a whole and never read, the readers keep the delta decoding state
+    /// on their own, so the value may be modified between the calls. On error `out` is left
+    /// partially updated.
+    fn parse_into(
+        &mut self,
+        parser: &mut impl QshRead,
+        out: &mut Self::Item,
+    ) -> Result<(), QshError> {
+        *out = self.parse(parser)?;
+        Ok(())
+    }
+
+    /// Reader of the `policy`, the readers not having the anomalies of the policy take the
+    /// default one
+    fn with_policy(policy: ParsePolicy) -> Self {
+        let _ = policy;
+        Self::default()
+    }
+
+    /// whether the last parsed record is to be dropped, e.g. corrupt record in lenient mode.
+    /// `RecordIter` skips such records.
+    fn skipped(&self) -> bool {
+        false
+    }
 }
 
 // batch flag check - execute body block if bit flag is set
@@ -21,10 +54,13 @@ macro_rules! bitcheck {
     };
 }
 
-// 'checked add' wrapper. panics on overflow.
+// 'checked add' wrapper, the overflow is handled by the `ParsePolicy` of the reader
 macro_rules! cadd {
-    ($tgt:expr, $value:expr) => {
-        $tgt.checked_add($value).unwrap()
+    ($policy:expr, $tgt:expr, $value:expr) => {
+        match $tgt.checked_add($value) {
+            Some(v) => v,
+            None => $policy.overflow(stringify!($tgt))?,
+        }
     };
 }
 
@@ -36,27 +72,72 @@ pub struct OrderLogReader {
     deal_id: UID,
     deal_price: Price,
     oi: Volume,
+    policy: ParsePolicy,
+    skipped: bool,
+    skipped_count: usize,
+}
+
+impl OrderLogReader {
+    /// reader validating volume invariants of every record, see [`OrderLogReader::validate`]
+    pub fn strict() -> Self {
+        let policy = ParsePolicy { on_invalid_volume: InvalidVolume::Fail, ..Default::default() };
+        Self::with_policy(policy)
+    }
+
+    /// reader skipping records with both 'Buy' and 'Sell' flags set instead of failing
+    pub fn lenient() -> Self {
+        Self::with_policy(ParsePolicy { on_both_sides: BothSides::Skip, ..Default::default() })
+    }
+
+    /// number of records skipped in lenient mode
+    pub fn skipped_count(&self) -> usize {
+        self.skipped_count
+    }
+
+    // Add: amount > 0, amount_rest == amount
+    // Fill: amount > 0, amount_rest >= 0
+    // Cancel/Remove: amount_rest >= 0
+    fn validate(rec: &OrderLog) -> Result<(), QshError> {
+        let valid = match rec.event {
+            OLMsgType::Add => rec.amount > 0 && rec.amount_rest == rec.amount,
+            OLMsgType::Fill => rec.amount > 0 && rec.amount_rest >= 0,
+            _ => rec.amount_rest >= 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(QshError::Parsing(format!(
+                "некорректный объём заявки {:?}: amount={}, amount_rest={}\n{}",
+                rec.event, rec.amount, rec.amount_rest, rec
+            )))
+        }
+    }
 }
 
 impl QshParser for OrderLogReader {
     type Item = OrderLog;
+    const EXPECTED_STREAM: Stream = Stream::ORDERLOG;
+
+    fn with_policy(policy: ParsePolicy) -> Self {
+        Self { policy, ..Default::default() }
+    }
 
     fn parse(&mut self, p: &mut impl QshRead) -> Result<Self::Item, QshError> {
         let (frame_time_delta, entry_flags, order_flags) = (p.growing()?, p.byte()?, p.u16()?);
 
         self.prev.frame_time_delta = frame_time_delta;
-        self.prev.order_flags = order_flags;
+        self.prev.order_flags = order_flags.into();
         self.prev.entry_flags = entry_flags;
 
         bitcheck!(entry_flags {
-            OLEntryFlags::DateTime => self.prev.timestamp = cadd!(self.prev.timestamp, p.growing()?),
+            OLEntryFlags::DateTime => self.prev.timestamp = cadd!(self.policy, self.prev.timestamp, p.growing()?),
             OLEntryFlags::OrderId  => if OLFlags::Add % order_flags{
-                                          self.order_id = cadd!(self.order_id, p.growing()?);
+                                          self.order_id = cadd!(self.policy, self.order_id, p.growing()?);
                                           self.prev.order_id = self.order_id;
                                       } else{
-                                          self.prev.order_id = cadd!(self.order_id, p.leb()?);
+                                          self.prev.order_id = cadd!(self.policy, self.order_id, p.leb()?);
                                       },
-            OLEntryFlags::Price    => self.prev.price = cadd!(self.prev.price, p.leb()?),
+            OLEntryFlags::Price    => self.prev.price = cadd!(self.policy, self.prev.price, p.leb()?),
             OLEntryFlags::Amount   => self.prev.amount = p.leb()?
         });
 
@@ -73,9 +154,9 @@ impl QshParser for OrderLogReader {
             OLFlags::Fill => {
                 bitcheck!(entry_flags {
                     OLEntryFlags::AmountRest => self.prev.amount_rest = p.leb()?,
-                    OLEntryFlags::DealId     => self.deal_id    = cadd!(self.deal_id, p.growing()?),
-                    OLEntryFlags::DealPrice  => self.deal_price = cadd!(self.deal_price, p.leb()?),
-                    OLEntryFlags::OI         => self.oi         = cadd!(self.oi, p.leb()?)
+                    OLEntryFlags::DealId     => self.deal_id    = cadd!(self.policy, self.deal_id, p.growing()?),
+                    OLEntryFlags::DealPrice  => self.deal_price = cadd!(self.policy, self.deal_price, p.leb()?),
+                    OLEntryFlags::OI         => self.oi         = cadd!(self.policy, self.oi, p.leb()?)
                 });
                 self.prev.deal_id    = self.deal_id;
                 self.prev.deal_price = self.deal_price;
@@ -87,8 +168,16 @@ impl QshParser for OrderLogReader {
         let buy = OLFlags::Buy % order_flags;
         let sell = OLFlags::Sell % order_flags;
 
+        self.skipped = false;
         self.prev.side =
             match (buy, sell) {
+                (true, true) if self.policy.on_both_sides == BothSides::Skip => {
+                    // the record is consumed entirely, so the running state stays consistent
+                    self.skipped = true;
+                    self.skipped_count += 1;
+                    log::warn!("skipped the record with both Buy and Sell flags\n{}", self.prev);
+                    Side::UNKNOWN
+                }
                 (true, true) => return Err(QshError::Parsing(
                     "ордер имеет одновременно установленные флаги 'bid' и 'ask' для стороны сделки"
                         .into(),
@@ -101,8 +190,16 @@ impl QshParser for OrderLogReader {
         self.prev.type_ = OrderType::from(order_flags);
         self.prev.event = OLMsgType::from(&self.prev);
 
+        if self.policy.on_invalid_volume == InvalidVolume::Fail && !self.skipped {
+            Self::validate(&self.prev)?;
+        }
+
         Ok(self.prev.clone())
     }
+
+    fn skipped(&self) -> bool {
+        self.skipped
+    }
 }
 
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - Quotes
@@ -110,29 +207,61 @@ impl QshParser for OrderLogReader {
 pub struct QuotesReader {
     map: BTreeMap<Price, Volume>,
     key: Price,
-    q: Quotes,
+    totals: bool,
+    policy: ParsePolicy,
+}
+
+impl QuotesReader {
+    /// reader filling `Quotes::bid_total`/`ask_total`, e.g. for the files without AuxInfo
+    pub fn with_totals() -> Self {
+        Self { totals: true, ..Default::default() }
+    }
 }
 
 impl QshParser for QuotesReader {
     type Item = Quotes;
+    const EXPECTED_STREAM: Stream = Stream::QUOTES;
+
+    fn with_policy(policy: ParsePolicy) -> Self {
+        Self { policy, ..Default::default() }
+    }
 
     fn parse(&mut self, p: &mut impl QshRead) -> Result<Self::Item, QshError> {
-        self.q.bid.clear();
-        self.q.ask.clear();
+        let mut quotes = Quotes::default();
+        self.parse_into(p, &mut quotes)?;
+        Ok(quotes)
+    }
+
+    fn parse_into(&mut self, p: &mut impl QshRead, quotes: &mut Quotes) -> Result<(), QshError> {
+        quotes.bid.clear();
+        quotes.ask.clear();
+        quotes.removed.clear();
 
         let (frame_time_delta, nrows) = (p.growing()?, p.leb()?);
-        let mut quotes = self.q.clone();
         quotes.frame_time_delta = frame_time_delta;
 
         for _ in 0..nrows {
-            self.key = cadd!(self.key, p.leb()?);
+            self.key = cadd!(self.policy, self.key, p.leb()?);
             let v = p.leb()?;
             if v == 0 {
-                self.map.remove(&self.key).expect("key not found");
+                match self.map.remove(&self.key) {
+                    Some(v) => {
+                        quotes.removed.push((if v < 0 { Side::Buy } else { Side::Sell }, self.key))
+                    }
+                    None if self.policy.on_missing_key == MissingKey::Fail => {
+                        return Err(QshError::Parsing(format!(
+                            "removal of the missing quote at {}",
+                            self.key
+                        )))
+                    }
+                    None => log::warn!("removal of the missing quote at {}", self.key),
+                }
             } else {
                 self.map.insert(self.key, v);
             }
         }
+        // removed and then restored within the frame
+        quotes.removed.retain(|(_, price)| !self.map.contains_key(price));
 
         self.map.iter().for_each(|(&k, &v)| {
             if v < 0 {
@@ -141,8 +270,12 @@ impl QshParser for QuotesReader {
                 quotes.ask.push((k, v));
             }
         });
+        if self.totals {
+            quotes.bid_total = quotes.bid.iter().map(|(_, v)| v).sum();
+            quotes.ask_total = quotes.ask.iter().map(|(_, v)| v).sum();
+        }
 
-        Ok(quotes)
+        Ok(())
     }
 }
 
@@ -150,21 +283,27 @@ impl QshParser for QuotesReader {
 #[derive(Debug, Default)]
 pub struct DealReader {
     prev: Deal,
+    policy: ParsePolicy,
 }
 
 impl QshParser for DealReader {
     type Item = Deal;
+    const EXPECTED_STREAM: Stream = Stream::DEALS;
+
+    fn with_policy(policy: ParsePolicy) -> Self {
+        Self { policy, ..Default::default() }
+    }
 
     fn parse(&mut self, p: &mut impl QshRead) -> Result<Self::Item, QshError> {
         let (frame_time_delta, flags) = (p.growing()?, p.byte()?);
 
         bitcheck!(flags {
-            DealFlags::Timestamp => self.prev.timestamp = cadd!(self.prev.timestamp, p.growing()?),
-            DealFlags::DealId    => self.prev.deal_id   = cadd!(self.prev.deal_id,   p.growing()?),
-            DealFlags::OrderId   => self.prev.order_id  = cadd!(self.prev.order_id,  p.leb()?),
-            DealFlags::Price     => self.prev.price     = cadd!(self.prev.price,     p.leb()?),
+            DealFlags::Timestamp => self.prev.timestamp = cadd!(self.policy, self.prev.timestamp, p.growing()?),
+            DealFlags::DealId    => self.prev.deal_id   = cadd!(self.policy, self.prev.deal_id,   p.growing()?),
+            DealFlags::OrderId   => self.prev.order_id  = cadd!(self.policy, self.prev.order_id,  p.leb()?),
+            DealFlags::Price     => self.prev.price     = cadd!(self.policy, self.prev.price,     p.leb()?),
             DealFlags::Amount    => self.prev.amount    = p.leb()?,
-            DealFlags::OI        => self.prev.oi        = cadd!(self.prev.oi,        p.leb()?)
+            DealFlags::OI        => self.prev.oi        = cadd!(self.policy, self.prev.oi,        p.leb()?)
         });
         self.prev.side = (flags & 0x03).into();
         self.prev.frame_time_delta = frame_time_delta;
@@ -172,25 +311,81 @@ impl QshParser for DealReader {
     }
 }
 
+impl DealReader {
+    /// reader resolving the unix time of the deals, see [`TimedDeal`]
+    pub fn with_absolute_time(header: &Header) -> DealTimeReader {
+        let recording_time = header.recording_time_or_err().unwrap_or_else(|e| {
+            log::warn!("{e}, clamped to 0");
+            0
+        });
+        DealTimeReader {
+            inner: Default::default(),
+            frame_time: recording_time_to_unix_ms(recording_time),
+        }
+    }
+}
+
+/// deal with the resolved unix time
+#[derive(Debug, Default, Clone)]
+pub struct TimedDeal {
+    /// unix nanos of the exchange `deal.timestamp`, or of the frame if the exchange time is not
+    /// known yet, as the stream starts with the deals lacking it
+    pub ts: Timestamp,
+    pub deal: Deal,
+}
+
+#[derive(Debug, Default)]
+pub struct DealTimeReader {
+    inner: DealReader,
+    // unix millis
+    frame_time: Timestamp,
+}
+
+impl QshParser for DealTimeReader {
+    type Item = TimedDeal;
+    const EXPECTED_STREAM: Stream = Stream::DEALS;
+
+    fn with_policy(policy: ParsePolicy) -> Self {
+        Self { inner: DealReader::with_policy(policy), ..Default::default() }
+    }
+
+    fn parse(&mut self, p: &mut impl QshRead) -> Result<Self::Item, QshError> {
+        // the exchange time is carried over from the previous deal if not set
+        let deal = self.inner.parse(p)?;
+        self.frame_time += deal.frame_time_delta;
+        let ts = match deal.timestamp {
+            0 => ms_to_ns(self.frame_time),
+            t => ms_to_ns(ticks_to_unix_ms(t)),
+        };
+        Ok(TimedDeal { ts, deal })
+    }
+}
+
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - AuxInfo
 #[derive(Debug, Default)]
 pub struct AuxInfoReader {
     prev: AuxInfo,
+    policy: ParsePolicy,
 }
 
 impl QshParser for AuxInfoReader {
     type Item = AuxInfo;
+    const EXPECTED_STREAM: Stream = Stream::AUXINFO;
+
+    fn with_policy(policy: ParsePolicy) -> Self {
+        Self { policy, ..Default::default() }
+    }
 
     fn parse(&mut self, p: &mut impl QshRead) -> Result<Self::Item, QshError> {
         let (frame_time_delta, flags) = (p.growing()?, p.byte()?);
         self.prev.frame_time_delta = frame_time_delta;
 
         bitcheck!(flags {
-            AuxInfoFlags::Timestamp   => self.prev.timestamp = cadd!(self.prev.timestamp, p.growing()?),
-            AuxInfoFlags::AskTotal    => self.prev.ask_total = cadd!(self.prev.ask_total, p.leb()?),
-            AuxInfoFlags::BidTotal    => self.prev.bid_total = cadd!(self.prev.bid_total, p.leb()?),
-            AuxInfoFlags::OI          => self.prev.oi        = cadd!(self.prev.oi,        p.leb()?),
-            AuxInfoFlags::Price       => self.prev.price     = cadd!(self.prev.price,     p.leb()?),
+            AuxInfoFlags::Timestamp   => self.prev.timestamp = cadd!(self.policy, self.prev.timestamp, p.growing()?),
+            AuxInfoFlags::AskTotal    => self.prev.ask_total = cadd!(self.policy, self.prev.ask_total, p.leb()?),
+            AuxInfoFlags::BidTotal    => self.prev.bid_total = cadd!(self.policy, self.prev.bid_total, p.leb()?),
+            AuxInfoFlags::OI          => self.prev.oi        = cadd!(self.policy, self.prev.oi,        p.leb()?),
+            AuxInfoFlags::Price       => self.prev.price     = cadd!(self.policy, self.prev.price,     p.leb()?),
             AuxInfoFlags::SessionInfo => { self.prev.hi_limit  = p.leb()?;
                                            self.prev.low_limit = p.leb()?;
                                            self.prev.deposit   = p.f64()?; },
@@ -0,0 +1,102 @@
+//! Tolerance of the pipeline to the imperfect data, see [`ParsePolicy`]
+
+use crate::{orderbook::Strictness, QshError};
+
+/// Removal of the Quotes level the reader doesn't have
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingKey {
+    /// fail with `QshError::Parsing`
+    Fail,
+    /// log the warning, skip the removal
+    #[default]
+    Warn,
+}
+
+/// Delta decoded value out of the `i64` range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    #[default]
+    Panic,
+    /// fail with `QshError::Parsing`
+    Fail,
+}
+
+/// OrderLog record having both the Buy and the Sell flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BothSides {
+    /// fail with `QshError::Parsing`
+    #[default]
+    Fail,
+    /// log the warning, skip the record, see `OrderLogReader::skipped_count`
+    Skip,
+}
+
+/// OrderLog record of the volumes inconsistent with its kind, see `OrderLogReader::strict`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidVolume {
+    #[default]
+    Accept,
+    /// fail with `QshError::Parsing`
+    Fail,
+}
+
+/// Book left crossed by the record, or by the transaction within `OrderBook::apply_tx`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Crossed {
+    #[default]
+    Accept,
+    /// fail with `QshError::InvalidState`
+    Fail,
+}
+
+/// Handling of the anomalies of the readers and of the book, in one place. The default is the
+/// one of the plain `OrderLogReader::default()` and `OrderBook::default()`.
+///
+/// The readers take it by [`QshParser::with_policy`](crate::QshParser::with_policy), e.g. of
+/// `QshRead::into_iter_with_policy`, the book by `OrderBook::with_policy`, the L2 conversion by
+/// `ConvertOptions::policy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParsePolicy {
+    pub on_missing_key: MissingKey,
+    pub on_overflow: Overflow,
+    pub on_both_sides: BothSides,
+    pub on_invalid_volume: InvalidVolume,
+    pub on_crossed: Crossed,
+    /// cancels and fills inconsistent with the book, see `OrderBook::set_policy`
+    pub on_inconsistent: Strictness,
+}
+
+impl ParsePolicy {
+    /// fail on any anomaly
+    pub fn strict() -> Self {
+        Self {
+            on_missing_key: MissingKey::Fail,
+            on_overflow: Overflow::Fail,
+            on_both_sides: BothSides::Fail,
+            on_invalid_volume: InvalidVolume::Fail,
+            on_crossed: Crossed::Fail,
+            on_inconsistent: Strictness::Strict,
+        }
+    }
+
+    /// go on past any anomaly but the overflow, which fails rather than panics
+    pub fn lenient() -> Self {
+        Self {
+            on_missing_key: MissingKey::Warn,
+            on_overflow: Overflow::Fail,
+            on_both_sides: BothSides::Skip,
+            on_invalid_volume: InvalidVolume::Accept,
+            on_crossed: Crossed::Accept,
+            on_inconsistent: Strictness::Lenient,
+        }
+    }
+
+    // the delta of the `field` of the reader overflows
+    pub(crate) fn overflow(&self, field: &str) -> Result<i64, QshError> {
+        let field = field.trim_start_matches("self.").trim_start_matches("prev.");
+        match self.on_overflow {
+            Overflow::Panic => panic!("{field} overflow"),
+            Overflow::Fail => Err(QshError::Parsing(format!("{field} overflow"))),
+        }
+    }
+}
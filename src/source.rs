@@ -0,0 +1,117 @@
+//! Records of the stream picked at runtime, e.g. by the header of the file
+//!
+//! ```no_run
+//! use qsh_rs::{header, inflate, source::record_source};
+//!
+//! let mut parser = inflate("Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+//! let header = header(&mut parser).unwrap();
+//! for rec in record_source(header.stream, parser).unwrap() {
+//!     println!("{:?}", rec.unwrap());
+//! }
+//! ```
+
+use crate::{
+    types::{AuxInfo, Deal, OrderLog, Quotes, Stream, Timestamp},
+    AuxInfoReader, DealReader, OrderLogReader, QshError, QshParser, QshRead, QuotesReader,
+    TryRecordIter,
+};
+
+/// Record of any supported stream
+#[derive(Debug, Clone)]
+pub enum Record {
+    Quotes(Quotes),
+    Deal(Deal),
+    AuxInfo(AuxInfo),
+    OrderLog(OrderLog),
+}
+
+impl Record {
+    /// millis since the previous record of the stream
+    pub fn frame_time_delta(&self) -> Timestamp {
+        match self {
+            Record::Quotes(q) => q.frame_time_delta,
+            Record::Deal(d) => d.frame_time_delta,
+            Record::AuxInfo(a) => a.frame_time_delta,
+            Record::OrderLog(r) => r.frame_time_delta,
+        }
+    }
+}
+
+impl From<Quotes> for Record {
+    fn from(v: Quotes) -> Self {
+        Record::Quotes(v)
+    }
+}
+
+impl From<Deal> for Record {
+    fn from(v: Deal) -> Self {
+        Record::Deal(v)
+    }
+}
+
+impl From<AuxInfo> for Record {
+    fn from(v: AuxInfo) -> Self {
+        Record::AuxInfo(v)
+    }
+}
+
+impl From<OrderLog> for Record {
+    fn from(v: OrderLog) -> Self {
+        Record::OrderLog(v)
+    }
+}
+
+/// Object safe records iterator, the iteration stops after the first error as of
+/// [`QshRead::try_iter`]. `Box<dyn BufRead>` is a [`QshRead`] as well, so both the source bytes
+/// and the stream may be picked at runtime.
+pub trait RecordSource: Iterator<Item = Result<Record, QshError>> {
+    fn stream(&self) -> Stream;
+}
+
+struct Source<T, Q> {
+    iter: TryRecordIter<T, Q>,
+    stream: Stream,
+}
+
+impl<T: QshParser, Q: QshRead> Iterator for Source<T, Q>
+where
+    T::Item: Into<Record>,
+{
+    type Item = Result<Record, QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|rec| rec.map(Into::into))
+    }
+}
+
+impl<T: QshParser, Q: QshRead> RecordSource for Source<T, Q>
+where
+    T::Item: Into<Record>,
+{
+    fn stream(&self) -> Stream {
+        self.stream
+    }
+}
+
+/// Records of the `stream` read from the `parser` positioned right after the header, fails on
+/// the stream of no reader, e.g. `Stream::MESSAGES`
+pub fn record_source<'a, Q: QshRead + 'a>(
+    stream: Stream,
+    parser: Q,
+) -> Result<Box<dyn RecordSource + 'a>, QshError> {
+    fn boxed<'a, T, Q>(stream: Stream, parser: Q) -> Box<dyn RecordSource + 'a>
+    where
+        T: QshParser + 'a,
+        T::Item: Into<Record>,
+        Q: QshRead + 'a,
+    {
+        Box::new(Source { iter: parser.try_iter::<T>(), stream })
+    }
+    Ok(match stream {
+        Stream::QUOTES => boxed::<QuotesReader, _>(stream, parser),
+        Stream::DEALS => boxed::<DealReader, _>(stream, parser),
+        Stream::AUXINFO => boxed::<AuxInfoReader, _>(stream, parser),
+        Stream::ORDERLOG => boxed::<OrderLogReader, _>(stream, parser),
+        other => return Err(QshError::Parsing(format!("no reader of the {other:?} stream"))),
+    })
+}
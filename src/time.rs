@@ -0,0 +1,81 @@
+//! Conversions of the QSH times to the unix time
+//!
+//! The records carry the exchange time in milliseconds since 0001-01-01, the header
+//! `recording_time` is in 100ns ticks since the same date. The crate exposes the unix time in
+//! nanoseconds: the book and the snapshot timestamps, the listener `ts`, etc.
+
+use crate::types::Timestamp;
+
+/// milliseconds from 0001-01-01 to 1970-01-01
+pub const UNIX_EPOCH_MS: Timestamp = 62135596800000;
+
+pub const NANOS_PER_MILLI: Timestamp = 1_000_000;
+
+/// record time, millis since 0001-01-01, to unix millis
+#[inline]
+pub fn ticks_to_unix_ms(v: Timestamp) -> Timestamp {
+    v - UNIX_EPOCH_MS
+}
+
+/// record time, millis since 0001-01-01, to unix nanos
+#[inline]
+pub fn ticks_to_unix_ns(v: Timestamp) -> Timestamp {
+    ms_to_ns(ticks_to_unix_ms(v))
+}
+
+/// unix nanos to the record time, millis since 0001-01-01
+#[inline]
+pub fn unix_ns_to_ticks(v: Timestamp) -> Timestamp {
+    ns_to_ms(v) + UNIX_EPOCH_MS
+}
+
+/// header `recording_time`, 100ns ticks since 0001-01-01, to unix nanos
+#[inline]
+pub fn recording_time_to_unix_ns(v: Timestamp) -> Timestamp {
+    (v - UNIX_EPOCH_MS * 10_000).saturating_mul(100)
+}
+
+/// header `recording_time` to unix millis, the resolution of the record `frame_time_delta`s
+#[inline]
+pub fn recording_time_to_unix_ms(v: Timestamp) -> Timestamp {
+    ticks_to_unix_ms(v / 10_000)
+}
+
+/// saturates out of the nanos range, years 1677..2262
+#[inline]
+pub fn ms_to_ns(v: Timestamp) -> Timestamp {
+    v.saturating_mul(NANOS_PER_MILLI)
+}
+
+/// rounds towards the negative infinity, so the times before the epoch stay ordered
+#[inline]
+pub fn ns_to_ms(v: Timestamp) -> Timestamp {
+    v.div_euclid(NANOS_PER_MILLI)
+}
+
+/// Receive time of the records, the header `recording_time` advanced by the `frame_time_delta`s
+/// of the records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameClock {
+    // unix millis
+    ms: Timestamp,
+}
+
+impl FrameClock {
+    pub fn new(recording_time: Timestamp) -> Self {
+        Self { ms: recording_time_to_unix_ms(recording_time) }
+    }
+
+    /// advances the clock by the `frame_time_delta` of the record, returns its frame time
+    #[inline]
+    pub fn advance(&mut self, frame_time_delta: Timestamp) -> Timestamp {
+        self.ms += frame_time_delta;
+        self.now()
+    }
+
+    /// frame time of the last record, unix nanos
+    #[inline]
+    pub fn now(&self) -> Timestamp {
+        ms_to_ns(self.ms)
+    }
+}
@@ -1,5 +1,6 @@
+use crate::QshError;
 use bincode::{Decode, Encode};
-use std::ops::Rem;
+use std::ops::{BitOr, BitOrAssign, Rem};
 
 pub type Price = i64;
 pub type Volume = i64;
@@ -18,15 +19,47 @@ pub enum Stream {
     ORDERLOG,
 }
 
+impl Stream {
+    /// all the streams of the format, by their type byte
+    pub const ALL: [Stream; 7] = [
+        Stream::QUOTES,
+        Stream::DEALS,
+        Stream::OWNORDERS,
+        Stream::OWNTRADES,
+        Stream::MESSAGES,
+        Stream::AUXINFO,
+        Stream::ORDERLOG,
+    ];
+
+    /// stream type byte of the file header
+    pub const fn as_byte(self) -> u8 {
+        match self {
+            Stream::QUOTES => 0x10,
+            Stream::DEALS => 0x20,
+            Stream::OWNORDERS => 0x30,
+            Stream::OWNTRADES => 0x40,
+            Stream::MESSAGES => 0x50,
+            Stream::AUXINFO => 0x60,
+            Stream::ORDERLOG => 0x70,
+        }
+    }
+
+    /// the stream of the type byte, `None` for the unknown one
+    pub fn from_byte(v: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|stream| stream.as_byte() == v)
+    }
+}
+
+/// panics on the unknown type byte, see [`Stream::from_byte`]
 impl From<u8> for Stream {
     fn from(v: u8) -> Self {
-        match v {
-            0x10 => Stream::QUOTES,
-            0x20 => Stream::DEALS,
-            0x60 => Stream::AUXINFO,
-            0x70 => Stream::ORDERLOG,
-            _ => panic!("Неподдерживаемый тип потока: {}", v),
-        }
+        Stream::from_byte(v).unwrap_or_else(|| panic!("Неподдерживаемый тип потока: {}", v))
+    }
+}
+
+impl From<Stream> for u8 {
+    fn from(stream: Stream) -> Self {
+        stream.as_byte()
     }
 }
 
@@ -40,6 +73,80 @@ pub struct Header {
     pub comment: String,
 }
 
+impl Header {
+    /// `recording_time` as is, failing on the negative(corrupt) values
+    pub fn recording_time_or_err(&self) -> Result<Timestamp, QshError> {
+        if self.recording_time < 0 {
+            return Err(QshError::Validation(format!(
+                "некорректное время записи: {}",
+                self.recording_time
+            )));
+        }
+        Ok(self.recording_time)
+    }
+
+    /// price step of the instrument, the last field of the QScalp instrument string, e.g.
+    /// `Plaza2:Si-3.20::1252209:1`
+    pub fn price_step(&self) -> Option<f64> {
+        self.instrument.rsplit(':').next()?.parse().ok().filter(|&step: &f64| step > 0.)
+    }
+
+    /// best-effort parse of the `recorder` and `comment` fields, e.g. `QshWriter.6870` and
+    /// `Zerich QSH Service`, the raw strings are kept in the header
+    pub fn recorder_info(&self) -> RecorderInfo {
+        let recorder = self.recorder.trim();
+        // the version starts with the first digit following the separator
+        let split = recorder.char_indices().zip(recorder.chars().skip(1)).find(|((_, c), next)| {
+            matches!(c, ' ' | '.' | '/' | '-' | '_') && next.is_ascii_digit()
+        });
+        let (name, version) = match split {
+            Some(((i, _), _)) => (&recorder[..i], Some(recorder[i + 1..].trim().to_string())),
+            None => (recorder, None),
+        };
+        let comment = self.comment.trim();
+        let service = comment.to_ascii_lowercase().rfind("qsh service");
+        let source = service.map(|i| comment[..i].trim()).filter(|s| !s.is_empty());
+        RecorderInfo {
+            name: name.trim().to_string(),
+            version,
+            source: source.map(str::to_lowercase),
+        }
+    }
+}
+
+/// Recorder of the file, see [`Header::recorder_info`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecorderInfo {
+    /// recorder application, e.g. `QshWriter`
+    pub name: String,
+    /// recorder version, e.g. `6870`
+    pub version: Option<String>,
+    /// lowercase data provider of the `<Provider> QSH Service` comment, e.g. `zerich`
+    pub source: Option<String>,
+}
+
+/// Prices are stored in the price steps, `PriceScale` converts them to the real prices
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceScale {
+    pub step: f64,
+}
+
+impl PriceScale {
+    pub fn new(step: f64) -> Self {
+        Self { step }
+    }
+
+    /// scale recorded in the header, if any, otherwise has to be configured per instrument
+    pub fn from_header(header: &Header) -> Option<Self> {
+        header.price_step().map(Self::new)
+    }
+
+    #[inline]
+    pub fn scaled_price(&self, raw: Price) -> f64 {
+        raw as f64 * self.step
+    }
+}
+
 #[derive(PartialEq, Debug, Copy, Clone, Encode, Decode)]
 pub enum Side {
     Buy = 1,
@@ -53,6 +160,32 @@ impl Default for Side {
     }
 }
 
+impl Side {
+    #[inline]
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+            Side::UNKNOWN => Side::UNKNOWN,
+        }
+    }
+
+    #[inline]
+    pub fn both() -> [Side; 2] {
+        [Side::Buy, Side::Sell]
+    }
+
+    /// +1 for Buy, -1 for Sell, 0 for UNKNOWN
+    #[inline]
+    pub fn as_sign(self) -> i64 {
+        match self {
+            Side::Buy => 1,
+            Side::Sell => -1,
+            Side::UNKNOWN => 0,
+        }
+    }
+}
+
 impl From<u8> for Side {
     fn from(b: u8) -> Self {
         match b {
@@ -63,7 +196,7 @@ impl From<u8> for Side {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Encode, Decode)]
 pub enum OrderType {
     Limit,
     IOK,
@@ -76,6 +209,13 @@ impl Default for OrderType {
     }
 }
 
+impl From<OLFlagSet> for OrderType {
+    #[inline]
+    fn from(order_flags: OLFlagSet) -> Self {
+        order_flags.0.into()
+    }
+}
+
 impl From<u16> for OrderType {
     fn from(order_flags: u16) -> Self {
         if OLFlags::Counter % order_flags {
@@ -85,7 +225,8 @@ impl From<u16> for OrderType {
         } else if OLFlags::Quote % order_flags {
             OrderType::Limit
         } else {
-            unreachable!("Неизвестный тип ордера: {}", order_flags);
+            log::warn!("unknown order type, flags {order_flags:#06x}");
+            OrderType::UNKNOWN
         }
     }
 }
@@ -152,7 +293,105 @@ flags!(OLFlags u16
     CrossTrade      = 1 << 15   // Признак удаления остатка заявки по причине кросс-сделки
 );
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Set of the `OLFlags`, the `order_flags` of the `OrderLog`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct OLFlagSet(pub u16);
+
+impl OLFlagSet {
+    pub const NON_ZERO_REPL_ACT: Self = Self(OLFlags::NonZeroReplAct as u16);
+    pub const NEW_SESSION: Self = Self(OLFlags::NewSession as u16);
+    pub const ADD: Self = Self(OLFlags::Add as u16);
+    pub const FILL: Self = Self(OLFlags::Fill as u16);
+    pub const BUY: Self = Self(OLFlags::Buy as u16);
+    pub const SELL: Self = Self(OLFlags::Sell as u16);
+    pub const SNAPSHOT: Self = Self(OLFlags::Snapshot as u16);
+    pub const QUOTE: Self = Self(OLFlags::Quote as u16);
+    pub const COUNTER: Self = Self(OLFlags::Counter as u16);
+    pub const NON_SYSTEM: Self = Self(OLFlags::NonSystem as u16);
+    pub const TX_END: Self = Self(OLFlags::TxEnd as u16);
+    pub const FILL_OR_KILL: Self = Self(OLFlags::FillOrKill as u16);
+    pub const MOVED: Self = Self(OLFlags::Moved as u16);
+    pub const CANCELED: Self = Self(OLFlags::Canceled as u16);
+    pub const CANCELED_GROUP: Self = Self(OLFlags::CanceledGroup as u16);
+    pub const CROSS_TRADE: Self = Self(OLFlags::CrossTrade as u16);
+
+    #[inline]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    #[inline]
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// all of the `other` flags are set
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// any of the `other` flags is set
+    #[inline]
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    #[inline]
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    #[inline]
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for OLFlagSet {
+    type Output = Self;
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for OLFlagSet {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl From<OLFlags> for OLFlagSet {
+    #[inline]
+    fn from(flag: OLFlags) -> Self {
+        Self(flag as u16)
+    }
+}
+
+impl From<u16> for OLFlagSet {
+    #[inline]
+    fn from(bits: u16) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<OLFlagSet> for u16 {
+    #[inline]
+    fn from(flags: OLFlagSet) -> Self {
+        flags.0
+    }
+}
+
+impl Rem<OLFlagSet> for OLFlags {
+    type Output = bool;
+    fn rem(self, rhs: OLFlagSet) -> Self::Output {
+        self % rhs.0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Encode, Decode)]
 pub enum OLMsgType {
     Add,
     Fill,
@@ -169,28 +408,49 @@ impl Default for OLMsgType {
 
 impl From<&OrderLog> for OLMsgType {
     fn from(r: &OrderLog) -> OLMsgType {
-        if OLFlags::Add % r.order_flags {
+        if r.order_flags.contains(OLFlagSet::ADD) {
             OLMsgType::Add
-        } else if OLFlags::Fill % r.order_flags {
+        } else if r.order_flags.contains(OLFlagSet::FILL) {
             OLMsgType::Fill
-        } else if OLFlags::Canceled % r.order_flags
-            || OLFlags::CanceledGroup % r.order_flags
-            || OLFlags::Moved % r.order_flags
+        } else if r.order_flags.contains(OLFlagSet::CANCELED)
+            || r.order_flags.contains(OLFlagSet::CANCELED_GROUP)
+            || r.order_flags.contains(OLFlagSet::MOVED)
         {
             OLMsgType::Cancel
-        } else if OLFlags::CrossTrade % r.order_flags || r.amount_rest == 0 {
+        } else if r.order_flags.contains(OLFlagSet::CROSS_TRADE) || r.amount_rest == 0 {
             OLMsgType::Remove
         } else {
-            unreachable!("Ошибка в логике программы или корявый ордер \n{}", r);
+            log::warn!("unknown record type\n{r}");
+            OLMsgType::UNKNOWN
         }
     }
 }
 
-#[derive(Encode, Decode, Debug, Clone, Copy)]
+#[derive(Encode, Decode, Debug, Clone)]
 pub enum L2Message {
-    Quote { side: Side, price: Price, size: Volume },
-    Remove { side: Side, price: Price },
+    Quote {
+        side: Side,
+        price: Price,
+        size: Volume,
+    },
+    Remove {
+        side: Side,
+        price: Price,
+    },
     Clear,
+    /// execution at the level, precedes the level update it causes
+    Trade {
+        side: Side,
+        price: Price,
+        size: Volume,
+    },
+    /// Whole state of the book, unix nanos `ts`, the levels best-to-worst. The stream can be
+    /// read starting from it.
+    Snapshot {
+        ts: Timestamp,
+        bids: Vec<(Price, Volume)>,
+        asks: Vec<(Price, Volume)>,
+    },
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -200,7 +460,7 @@ pub enum L3Message {
     Trade(OrderLog),
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Encode, Decode)]
 pub struct OrderLog {
     pub frame_time_delta: Timestamp,
     pub timestamp: Timestamp,
@@ -211,13 +471,36 @@ pub struct OrderLog {
     pub deal_id: UID,
     pub deal_price: Price,
     pub oi: Volume,
-    pub order_flags: u16,
+    pub order_flags: OLFlagSet,
     pub entry_flags: u8,
     pub side: Side,
     pub event: OLMsgType,
     pub type_: OrderType,
 }
 
+/// deal fields of the Fill record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradeView {
+    pub deal_id: UID,
+    pub deal_price: Price,
+    /// filled volume
+    pub amount: Volume,
+    pub oi: Volume,
+}
+
+impl OrderLog {
+    /// Deal fields, `None` for the non-Fill records as those have them stale or zeroed
+    #[inline]
+    pub fn as_trade(&self) -> Option<TradeView> {
+        (self.order_flags.contains(OLFlagSet::FILL)).then_some(TradeView {
+            deal_id: self.deal_id,
+            deal_price: self.deal_price,
+            amount: self.amount,
+            oi: self.oi,
+        })
+    }
+}
+
 impl std::fmt::Display for OrderLog {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -226,16 +509,16 @@ impl std::fmt::Display for OrderLog {
             self,
             OrderType::from(self.order_flags),
             (
-                OLFlags::Add % self.order_flags,
-                OLFlags::Fill % self.order_flags,
-                OLFlags::Moved % self.order_flags,
-                OLFlags::Counter % self.order_flags,
-                OLFlags::FillOrKill % self.order_flags,
-                OLFlags::NewSession % self.order_flags,
-                OLFlags::Canceled % self.order_flags,
-                OLFlags::CanceledGroup % self.order_flags,
-                OLFlags::CrossTrade % self.order_flags,
-                OLFlags::TxEnd % self.order_flags,
+                self.order_flags.contains(OLFlagSet::ADD),
+                self.order_flags.contains(OLFlagSet::FILL),
+                self.order_flags.contains(OLFlagSet::MOVED),
+                self.order_flags.contains(OLFlagSet::COUNTER),
+                self.order_flags.contains(OLFlagSet::FILL_OR_KILL),
+                self.order_flags.contains(OLFlagSet::NEW_SESSION),
+                self.order_flags.contains(OLFlagSet::CANCELED),
+                self.order_flags.contains(OLFlagSet::CANCELED_GROUP),
+                self.order_flags.contains(OLFlagSet::CROSS_TRADE),
+                self.order_flags.contains(OLFlagSet::TX_END),
             )
         )
     }
@@ -246,6 +529,11 @@ pub struct Quotes {
     pub frame_time_delta: Timestamp,
     pub bid: Vec<(Price, Volume)>,
     pub ask: Vec<(Price, Volume)>,
+    /// levels removed in this frame, absent from `bid`/`ask`
+    pub removed: Vec<(Side, Price)>,
+    /// total volume of the side, `0` unless read with `QuotesReader::with_totals`
+    pub bid_total: Volume,
+    pub ask_total: Volume,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -0,0 +1,189 @@
+//! OHLCV bars of the deals, see [`bars`]
+
+use crate::{
+    types::{Price, Timestamp, Volume},
+    TimedDeal,
+};
+
+/// Deals stream has no session marks, the quiet period this long starts the new session. The
+/// clearing breaks are 5 minutes at least.
+pub const SESSION_GAP_NS: Timestamp = 180 * 1_000_000_000;
+
+/// Bar closing rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// Fixed grid of the unix time, nanos. The grid boundaries are the multiples of the period
+    /// since the unix epoch, the bars without deals are not emitted. Both parts of the bar split
+    /// by the session gap start at its boundary.
+    Time(Timestamp),
+    /// the bar closes with the deal bringing its volume up to the amount, the deal isn't split
+    Volume(Volume),
+    /// the bar closes with the `n`th deal
+    Ticks(usize),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bar {
+    /// unix nanos, the grid boundary of the `Period::Time` bar, the first deal time otherwise
+    pub start_ts: Timestamp,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume: Volume,
+    pub trades: usize,
+    /// sum of the price times the amount of the deals
+    pub turnover: i128,
+}
+
+impl Bar {
+    fn open(start_ts: Timestamp, price: Price) -> Self {
+        Self {
+            start_ts,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0,
+            trades: 0,
+            turnover: 0,
+        }
+    }
+
+    fn push(&mut self, price: Price, amount: Volume) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += amount;
+        self.trades += 1;
+        self.turnover += price as i128 * amount as i128;
+    }
+}
+
+/// Bars of the `deals`, see [`DealReader::with_absolute_time`](crate::DealReader::with_absolute_time),
+/// no bar spans the session gap of [`SESSION_GAP_NS`]
+pub fn bars<I>(deals: I, period: Period) -> Bars<I::IntoIter>
+where
+    I: IntoIterator<Item = TimedDeal>,
+{
+    bars_with_gap(deals, period, SESSION_GAP_NS)
+}
+
+/// Same as [`bars`], the bar is closed at the quiet period longer than `session_gap_ns`
+pub fn bars_with_gap<I>(deals: I, period: Period, session_gap_ns: Timestamp) -> Bars<I::IntoIter>
+where
+    I: IntoIterator<Item = TimedDeal>,
+{
+    match period {
+        Period::Time(t) => assert!(t > 0, "period must be positive"),
+        Period::Volume(v) => assert!(v > 0, "period must be positive"),
+        Period::Ticks(n) => assert!(n > 0, "period must be positive"),
+    }
+    Bars {
+        deals: deals.into_iter(),
+        period,
+        session_gap: session_gap_ns,
+        bar: None,
+        ready: None,
+        last_ts: 0,
+        fill: false,
+        empty: None,
+    }
+}
+
+pub struct Bars<I> {
+    deals: I,
+    period: Period,
+    session_gap: Timestamp,
+    bar: Option<Bar>,
+    // full bar following the one closed by the gap or the grid
+    ready: Option<Bar>,
+    // time of the last deal
+    last_ts: Timestamp,
+    // the `Period::Time` grid boundaries without deals get the bars of no volume at the previous
+    // close, but the ones of the session gap
+    pub(crate) fill: bool,
+    // the next empty bar and the boundary of the open one
+    empty: Option<(Bar, Timestamp)>,
+}
+
+impl<I> Bars<I> {
+    #[inline]
+    fn start(&self, ts: Timestamp) -> Timestamp {
+        match self.period {
+            Period::Time(t) => ts - ts.rem_euclid(t),
+            _ => ts,
+        }
+    }
+
+    // the deal at `ts` doesn't belong to the open bar
+    #[inline]
+    fn closes(&self, bar: &Bar, ts: Timestamp) -> bool {
+        ts - self.last_ts > self.session_gap
+            || match self.period {
+                Period::Time(t) => ts >= bar.start_ts + t,
+                _ => false,
+            }
+    }
+
+    // the open bar is complete
+    #[inline]
+    fn full(&self, bar: &Bar) -> bool {
+        match self.period {
+            Period::Time(_) => false,
+            Period::Volume(v) => bar.volume >= v,
+            Period::Ticks(n) => bar.trades >= n,
+        }
+    }
+}
+
+impl<I> Iterator for Bars<I>
+where
+    I: Iterator<Item = TimedDeal>,
+{
+    type Item = Bar;
+
+    fn next(&mut self) -> Option<Bar> {
+        if let (Some((bar, end)), Period::Time(t)) = (self.empty.as_mut(), self.period) {
+            let empty = *bar;
+            bar.start_ts += t;
+            if bar.start_ts >= *end {
+                self.empty = None;
+            }
+            return Some(empty);
+        }
+        if let Some(bar) = self.ready.take() {
+            return Some(bar);
+        }
+        loop {
+            let Some(TimedDeal { ts, deal }) = self.deals.next() else {
+                return self.bar.take();
+            };
+            let closed = match self.bar {
+                Some(bar) if self.closes(&bar, ts) => self.bar.take(),
+                _ => None,
+            };
+            let mut bar = self.bar.take().unwrap_or_else(|| Bar::open(self.start(ts), deal.price));
+            if let (Some(closed), Period::Time(t), true) = (closed, self.period, self.fill) {
+                let next = closed.start_ts + t;
+                if ts - self.last_ts <= self.session_gap && next < bar.start_ts {
+                    self.empty = Some((Bar::open(next, closed.close), bar.start_ts));
+                }
+            }
+            bar.push(deal.price, deal.amount);
+            self.last_ts = ts;
+            match (closed, self.full(&bar)) {
+                (None, true) => return Some(bar),
+                (None, false) => self.bar = Some(bar),
+                (Some(closed), true) => {
+                    self.ready = Some(bar);
+                    return Some(closed);
+                }
+                (Some(closed), false) => {
+                    self.bar = Some(bar);
+                    return Some(closed);
+                }
+            }
+        }
+    }
+}
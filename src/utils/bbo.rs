@@ -0,0 +1,139 @@
+//! Best bid and offer ticks, the top of the book changes only, see [`stream`] and [`quotes`]
+
+use crate::{
+    orderbook::{NopListener, OrderBook, Quote},
+    time::{self, FrameClock},
+    types::{Header, OrderLog, Price, Quotes, Timestamp, Volume},
+    QshError,
+};
+
+/// Top of the book, the price is `None` and the size is `0` for the empty side
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BboTick {
+    /// unix nanos
+    pub ts: Timestamp,
+    pub bid_px: Option<Price>,
+    pub bid_sz: Volume,
+    pub ask_px: Option<Price>,
+    pub ask_sz: Volume,
+}
+
+impl BboTick {
+    fn new(ts: Timestamp, bid: Option<Quote>, ask: Option<Quote>) -> Self {
+        Self {
+            ts,
+            bid_px: bid.map(|q| q.0),
+            bid_sz: bid.map_or(0, |q| q.1),
+            ask_px: ask.map(|q| q.0),
+            ask_sz: ask.map_or(0, |q| q.1),
+        }
+    }
+
+    #[inline]
+    pub fn bid(&self) -> Option<Quote> {
+        self.bid_px.map(|p| (p, self.bid_sz))
+    }
+
+    #[inline]
+    pub fn ask(&self) -> Option<Quote> {
+        self.ask_px.map(|p| (p, self.ask_sz))
+    }
+
+    // the same prices and sizes, whatever the time
+    #[inline]
+    fn same_top(&self, other: &Self) -> bool {
+        (self.bid(), self.ask()) == (other.bid(), other.ask())
+    }
+}
+
+/// Replays the `txs` into the fresh book, yields the tick after the transaction changing the
+/// price or the size of either top level, timed by the last record of the transaction. The
+/// empty book emits nothing until the first level, the book emptied, e.g. by the session
+/// start, emits the tick of no prices. The iteration stops after the book error.
+pub fn stream<I>(txs: I) -> BboStream<I::IntoIter>
+where
+    I: IntoIterator<Item = Vec<OrderLog>>,
+{
+    BboStream {
+        txs: txs.into_iter(),
+        book: OrderBook::default(),
+        last: BboTick::default(),
+        failed: false,
+    }
+}
+
+pub struct BboStream<I> {
+    txs: I,
+    book: OrderBook,
+    last: BboTick,
+    failed: bool,
+}
+
+impl<I> Iterator for BboStream<I>
+where
+    I: Iterator<Item = Vec<OrderLog>>,
+{
+    type Item = Result<BboTick, QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        for tx in self.txs.by_ref() {
+            if let Err(e) = self.book.apply_tx(&tx, &mut NopListener) {
+                self.failed = true;
+                return Some(Err(e));
+            }
+            let ts = tx.last().map_or(0, |rec| time::ticks_to_unix_ns(rec.timestamp));
+            let tick = BboTick::new(ts, self.book.best_bid(), self.book.best_ask());
+            if !tick.same_top(&self.last) {
+                self.last = tick;
+                return Some(Ok(tick));
+            }
+        }
+        None
+    }
+}
+
+/// Same as [`stream`] over the `QuotesReader` records of the file of the `header`, timed by the
+/// frame time
+pub fn quotes<I>(quotes: I, header: &Header) -> QuotesBbo<I::IntoIter>
+where
+    I: IntoIterator<Item = Quotes>,
+{
+    let recording_time = header.recording_time_or_err().unwrap_or_else(|e| {
+        log::warn!("{e}, clamped to 0");
+        0
+    });
+    QuotesBbo {
+        quotes: quotes.into_iter(),
+        clock: FrameClock::new(recording_time),
+        last: BboTick::default(),
+    }
+}
+
+pub struct QuotesBbo<I> {
+    quotes: I,
+    clock: FrameClock,
+    last: BboTick,
+}
+
+impl<I> Iterator for QuotesBbo<I>
+where
+    I: Iterator<Item = Quotes>,
+{
+    type Item = BboTick;
+
+    fn next(&mut self) -> Option<BboTick> {
+        for q in self.quotes.by_ref() {
+            let ts = self.clock.advance(q.frame_time_delta);
+            // the levels ascend by price
+            let tick = BboTick::new(ts, q.bid.last().copied(), q.ask.first().copied());
+            if !tick.same_top(&self.last) {
+                self.last = tick;
+                return Some(tick);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,72 @@
+//! Time bars with the volume weighted price, see [`time_bars`]
+
+use super::bars::{bars, Bar, Bars, Period};
+use crate::{types::Timestamp, TimedDeal};
+
+/// [`Period::Time`] bar with the volume weighted price
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Candle {
+    pub bar: Bar,
+    /// volume weighted price, `close` of the bar without volume
+    pub vwap: f64,
+}
+
+impl From<Bar> for Candle {
+    fn from(bar: Bar) -> Self {
+        let vwap = match bar.volume {
+            0 => bar.close as f64,
+            v => bar.turnover as f64 / v as f64,
+        };
+        Self { bar, vwap }
+    }
+}
+
+/// Grid boundaries without deals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyBars {
+    /// no bar is emitted
+    #[default]
+    Skip,
+    /// the bar of no volume at the previous close, the quiet period longer than
+    /// [`SESSION_GAP_NS`](super::bars::SESSION_GAP_NS), e.g. the overnight break, is skipped anyway
+    ForwardFill,
+}
+
+/// Candles of the [`bars`] of the `interval_ns` grid, the boundaries without deals are skipped
+pub fn time_bars<I>(deals: I, interval_ns: Timestamp) -> TimeBars<I::IntoIter>
+where
+    I: IntoIterator<Item = TimedDeal>,
+{
+    time_bars_with(deals, interval_ns, EmptyBars::Skip)
+}
+
+/// Same as [`time_bars`], the boundaries without deals are handled by `empty`
+pub fn time_bars_with<I>(
+    deals: I,
+    interval_ns: Timestamp,
+    empty: EmptyBars,
+) -> TimeBars<I::IntoIter>
+where
+    I: IntoIterator<Item = TimedDeal>,
+{
+    assert!(interval_ns > 0, "interval must be positive");
+    let mut bars = bars(deals, Period::Time(interval_ns));
+    bars.fill = empty == EmptyBars::ForwardFill;
+    TimeBars { bars }
+}
+
+pub struct TimeBars<I> {
+    bars: Bars<I>,
+}
+
+impl<I> Iterator for TimeBars<I>
+where
+    I: Iterator<Item = TimedDeal>,
+{
+    type Item = Candle;
+
+    #[inline]
+    fn next(&mut self) -> Option<Candle> {
+        self.bars.next().map(Candle::from)
+    }
+}
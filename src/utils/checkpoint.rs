@@ -0,0 +1,69 @@
+//! OrderBook checkpoints: replay of the orderlog up to the given time, saving the book state
+//! to resume the replay later without starting from the beginning of the file
+
+use crate::{
+    orderbook::{self as ob, NopListener, OrderBook},
+    time,
+    types::{OrderLog, Timestamp},
+    QshError,
+};
+use std::io::{Read, Write};
+use std::iter::Peekable;
+
+/// Replays the transactions of the `input` into the `book` up to the first one started after
+/// `until`(unix nanos, the same as the book timestamps), which is left in the `input`.
+///
+/// Applies the same filtering as the usual `system_record` / `partition_by(tx_end)` /
+/// `fiok_with_trades` pipeline. Returns the number of the records consumed.
+pub fn replay_until<I>(
+    input: &mut Peekable<I>,
+    book: &mut OrderBook,
+    until: Timestamp,
+) -> Result<usize, QshError>
+where
+    I: Iterator<Item = OrderLog>,
+{
+    let (mut consumed, mut tx) = (0, Vec::with_capacity(10));
+    while let Some(rec) = input.peek() {
+        let system = ob::system_record(rec);
+        if system && tx.is_empty() && time::ticks_to_unix_ns(rec.timestamp) > until {
+            break;
+        }
+        let rec = input.next().unwrap();
+        consumed += 1;
+        if !system {
+            continue;
+        }
+        tx.push(rec);
+        if ob::tx_end(&rec) {
+            if ob::fiok_with_trades(&tx) {
+                book.apply_tx(&tx, &mut NopListener)?;
+            }
+            tx.clear();
+        }
+    }
+    Ok(consumed)
+}
+
+/// Replays the `input` from the start up to `until`, see [`replay_until`], and writes the
+/// checkpoint: the book state followed by the number of the records consumed
+pub fn save(
+    input: impl Iterator<Item = OrderLog>,
+    until: Timestamp,
+    mut w: impl Write,
+) -> Result<OrderBook, QshError> {
+    let mut book = OrderBook::default();
+    let consumed = replay_until(&mut input.peekable(), &mut book, until)?;
+    book.save(&mut w)?;
+    w.write_all(&(consumed as u64).to_le_bytes())?;
+    Ok(book)
+}
+
+/// Reads the checkpoint written by [`save`], returns the book and the number of the records
+/// to skip from the start of the orderlog to continue the replay
+pub fn load(mut r: impl Read) -> Result<(OrderBook, usize), QshError> {
+    let book = OrderBook::load(&mut r)?;
+    let mut consumed = [0; 8];
+    r.read_exact(&mut consumed)?;
+    Ok((book, u64::from_le_bytes(consumed) as usize))
+}
@@ -0,0 +1,168 @@
+//! Validation of the OrderLog-built book against the Quotes stream of the same instrument
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{header, inflate, OrderLogReader, QshRead, QuotesReader};
+use crate::{
+    orderbook::{self as ob, NopListener, OrderBook},
+    time,
+    types::{OLFlagSet, OrderLog, Quotes, Side, Timestamp},
+    QshError,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+/// number of the mismatches kept in the report
+pub const MAX_MISMATCHES: usize = 100;
+
+/// top levels of the both books at the Quotes frame, in the `OrderBook::snapshot` layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// frame time, unix nanos
+    pub ts: Timestamp,
+    /// Quotes frame number
+    pub frame: usize,
+    pub book: Vec<i64>,
+    pub quotes: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrosscheckReport {
+    pub frames: usize,
+    pub compared: usize,
+    pub matched: usize,
+    /// frames not compared: the book is being restored from the snapshot, or any of the books
+    /// is one-sided or crossed, e.g. during the auction
+    pub skipped: usize,
+    /// the first `MAX_MISMATCHES` mismatches
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl CrosscheckReport {
+    /// share of the compared frames that matched, in percent
+    pub fn match_pct(&self) -> f64 {
+        if self.compared == 0 {
+            return 0.;
+        }
+        self.matched as f64 * 100. / self.compared as f64
+    }
+}
+
+impl std::fmt::Display for CrosscheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "frames: {}, compared: {}, skipped: {}, matched: {:.2}%",
+            self.frames,
+            self.compared,
+            self.skipped,
+            self.match_pct()
+        )?;
+        for m in &self.mismatches {
+            writeln!(f, "#{} at {}: book {:?} != quotes {:?}", m.frame, m.ts, m.book, m.quotes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays the OrderLog and the Quotes files of the same instrument in the frame time lockstep
+/// and compares the top `depth` levels at every Quotes frame
+#[cfg(not(target_arch = "wasm32"))]
+pub fn validate(
+    ordlog: impl Into<PathBuf>,
+    quotes: impl Into<PathBuf>,
+    depth: usize,
+) -> Result<CrosscheckReport, QshError> {
+    let mut ol = inflate(ordlog.into())?;
+    let ol_start = header(&mut ol)?.recording_time_or_err()?;
+    let mut q = inflate(quotes.into())?;
+    let q_start = header(&mut q)?.recording_time_or_err()?;
+
+    validate_streams(
+        ol.into_iter::<OrderLogReader>(),
+        ol_start,
+        q.into_iter::<QuotesReader>(),
+        q_start,
+        depth,
+    )
+}
+
+/// Same as [`validate`] over the parsed streams, `*_start` are the header recording times
+pub fn validate_streams(
+    ordlog: impl Iterator<Item = OrderLog>,
+    ordlog_start: Timestamp,
+    quotes: impl Iterator<Item = Quotes>,
+    quotes_start: Timestamp,
+    depth: usize,
+) -> Result<CrosscheckReport, QshError> {
+    let mut ordlog = ordlog.peekable();
+    let mut ol_time = time::recording_time_to_unix_ms(ordlog_start);
+    let mut q_time = time::recording_time_to_unix_ms(quotes_start);
+
+    let (mut book, mut tx, mut restoring) = (OrderBook::default(), Vec::with_capacity(10), false);
+    let mut report = CrosscheckReport::default();
+    for (frame, q) in quotes.enumerate() {
+        q_time += q.frame_time_delta;
+        while let Some(rec) = ordlog.next_if(|r| ol_time + r.frame_time_delta <= q_time) {
+            ol_time += rec.frame_time_delta;
+            if ob::non_system_record(&rec) {
+                continue;
+            }
+            restoring = rec.order_flags.contains(OLFlagSet::SNAPSHOT);
+            tx.push(rec);
+            if ob::tx_end(&rec) {
+                if ob::fiok_with_trades(&tx) {
+                    book.apply_tx(&tx, &mut NopListener)?;
+                }
+                tx.clear();
+            }
+        }
+
+        report.frames += 1;
+        if restoring || !tx.is_empty() || !two_sided(&book) || !two_sided_quotes(&q) {
+            report.skipped += 1;
+            continue;
+        }
+        report.compared += 1;
+        let (from_book, from_quotes) = (book_levels(&book, depth), quotes_levels(&q, depth));
+        if from_book == from_quotes {
+            report.matched += 1;
+        } else if report.mismatches.len() < MAX_MISMATCHES {
+            report.mismatches.push(Mismatch {
+                ts: time::ms_to_ns(q_time),
+                frame,
+                book: from_book,
+                quotes: from_quotes,
+            });
+        }
+    }
+    Ok(report)
+}
+
+fn two_sided(book: &OrderBook) -> bool {
+    matches!((book.best_bid(), book.best_ask()), (Some((bid, _)), Some((ask, _))) if bid < ask)
+}
+
+fn two_sided_quotes(q: &Quotes) -> bool {
+    matches!((q.bid.last(), q.ask.first()), (Some((bid, _)), Some((ask, _))) if bid < ask)
+}
+
+fn book_levels(book: &OrderBook, depth: usize) -> Vec<i64> {
+    let mut levels = vec![0; depth * 4];
+    for (j, side) in [(0, Side::Buy), (2, Side::Sell)] {
+        for (i, (&p, &v, _)) in book.iter_levels(side).take(depth).enumerate() {
+            (levels[i * 4 + j], levels[i * 4 + j + 1]) = (p, v);
+        }
+    }
+    levels
+}
+
+fn quotes_levels(q: &Quotes, depth: usize) -> Vec<i64> {
+    let mut levels = vec![0; depth * 4];
+    for (i, &(p, v)) in q.bid.iter().rev().take(depth).enumerate() {
+        (levels[i * 4], levels[i * 4 + 1]) = (p, v);
+    }
+    for (i, &(p, v)) in q.ask.iter().take(depth).enumerate() {
+        (levels[i * 4 + 2], levels[i * 4 + 3]) = (p, v);
+    }
+    levels
+}
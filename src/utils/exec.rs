@@ -0,0 +1,231 @@
+//! Execution prices of the deals, VWAP and TWAP, of the window, see [`vwap`] and
+//! [`rolling_vwap`]
+
+use super::trades::Trade;
+use crate::{
+    time,
+    types::{Deal, Price, PriceScale, Timestamp, Volume},
+    TimedDeal,
+};
+use std::collections::VecDeque;
+
+/// Deal of the tape, e.g. of `DealReader`, `DealReader::with_absolute_time` or
+/// [`trades::from_orderlog`](super::trades::from_orderlog)
+pub trait Execution {
+    /// unix nanos, `0` if unknown
+    fn ts(&self) -> Timestamp;
+    fn price(&self) -> Price;
+    fn amount(&self) -> Volume;
+}
+
+impl Execution for Deal {
+    fn ts(&self) -> Timestamp {
+        if self.timestamp == 0 {
+            0
+        } else {
+            time::ticks_to_unix_ns(self.timestamp)
+        }
+    }
+
+    fn price(&self) -> Price {
+        self.price
+    }
+
+    fn amount(&self) -> Volume {
+        self.amount
+    }
+}
+
+impl Execution for TimedDeal {
+    fn ts(&self) -> Timestamp {
+        self.ts
+    }
+
+    fn price(&self) -> Price {
+        self.deal.price
+    }
+
+    fn amount(&self) -> Volume {
+        self.deal.amount
+    }
+}
+
+impl Execution for Trade {
+    fn ts(&self) -> Timestamp {
+        self.ts
+    }
+
+    fn price(&self) -> Price {
+        self.price
+    }
+
+    fn amount(&self) -> Volume {
+        self.amount
+    }
+}
+
+/// `(ts, price, amount)`
+impl Execution for (Timestamp, Price, Volume) {
+    fn ts(&self) -> Timestamp {
+        self.0
+    }
+
+    fn price(&self) -> Price {
+        self.1
+    }
+
+    fn amount(&self) -> Volume {
+        self.2
+    }
+}
+
+/// Deals of the window, the prices are in the price steps, see [`scaled_vwap`](Self::scaled_vwap)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExecStats {
+    /// volume weighted price, the mean price of the deals of no volume
+    pub vwap: f64,
+    /// Time weighted price, the price of the deal holds till the next one, from the first deal
+    /// to the last one of the window. The mean price of the deals of the same time.
+    pub twap: f64,
+    pub volume: Volume,
+    pub trades: usize,
+    pub high: Price,
+    pub low: Price,
+}
+
+impl ExecStats {
+    #[inline]
+    pub fn scaled_vwap(&self, scale: &PriceScale) -> f64 {
+        self.vwap * scale.step
+    }
+
+    #[inline]
+    pub fn scaled_twap(&self, scale: &PriceScale) -> f64 {
+        self.twap * scale.step
+    }
+}
+
+/// Stats of the `deals` of the `[from_ts, to_ts)` unix nanos, `None` if there are none. The
+/// deals go in the time order, the ones lacking the time are skipped.
+pub fn vwap<I>(deals: I, from_ts: Timestamp, to_ts: Timestamp) -> Option<ExecStats>
+where
+    I: IntoIterator,
+    I::Item: Execution,
+{
+    let mut window = Window::default();
+    for deal in deals {
+        let ts = deal.ts();
+        if ts != 0 && (from_ts..to_ts).contains(&ts) {
+            window.push(ts, deal.price(), deal.amount());
+        }
+    }
+    window.stats()
+}
+
+/// Stats of the deals within `window_ns` up to the deal, with its time, unix nanos, per deal of
+/// the `deals` in the time order. The deals lacking the time are skipped.
+pub fn rolling_vwap<I>(deals: I, window_ns: Timestamp) -> RollingVwap<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: Execution,
+{
+    assert!(window_ns > 0, "window must be positive");
+    RollingVwap { deals: deals.into_iter(), window: window_ns, inner: Window::default() }
+}
+
+pub struct RollingVwap<I> {
+    deals: I,
+    window: Timestamp,
+    inner: Window,
+}
+
+impl<I> Iterator for RollingVwap<I>
+where
+    I: Iterator,
+    I::Item: Execution,
+{
+    type Item = (Timestamp, ExecStats);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let deal = self.deals.by_ref().find(|deal| deal.ts() != 0)?;
+        let ts = deal.ts();
+        while self.inner.deals.front().is_some_and(|&(t, ..)| t <= ts - self.window) {
+            self.inner.pop();
+        }
+        self.inner.push(ts, deal.price(), deal.amount());
+        Some((ts, self.inner.stats().expect("deal of the window")))
+    }
+}
+
+// running sums of the deals of the window, the products are exact in i128
+#[derive(Debug, Default)]
+struct Window {
+    // time, price, amount and the sequence number
+    deals: VecDeque<(Timestamp, Price, Volume, usize)>,
+    seq: usize,
+    notional: i128,
+    volume: i128,
+    prices: i128,
+    // price of the deal by the time till the next one
+    weighted: i128,
+    // the candidates of the high and of the low, price and sequence number
+    highs: VecDeque<(Price, usize)>,
+    lows: VecDeque<(Price, usize)>,
+}
+
+impl Window {
+    fn push(&mut self, ts: Timestamp, price: Price, amount: Volume) {
+        if let Some(&(last_ts, last_price, ..)) = self.deals.back() {
+            self.weighted += last_price as i128 * (ts - last_ts) as i128;
+        }
+        self.notional += price as i128 * amount as i128;
+        self.volume += amount as i128;
+        self.prices += price as i128;
+        while self.highs.back().is_some_and(|&(p, _)| p <= price) {
+            self.highs.pop_back();
+        }
+        while self.lows.back().is_some_and(|&(p, _)| p >= price) {
+            self.lows.pop_back();
+        }
+        self.highs.push_back((price, self.seq));
+        self.lows.push_back((price, self.seq));
+        self.deals.push_back((ts, price, amount, self.seq));
+        self.seq += 1;
+    }
+
+    fn pop(&mut self) {
+        let Some((ts, price, amount, seq)) = self.deals.pop_front() else { return };
+        if let Some(&(next_ts, ..)) = self.deals.front() {
+            self.weighted -= price as i128 * (next_ts - ts) as i128;
+        }
+        self.notional -= price as i128 * amount as i128;
+        self.volume -= amount as i128;
+        self.prices -= price as i128;
+        if self.highs.front().is_some_and(|&(_, s)| s == seq) {
+            self.highs.pop_front();
+        }
+        if self.lows.front().is_some_and(|&(_, s)| s == seq) {
+            self.lows.pop_front();
+        }
+    }
+
+    fn stats(&self) -> Option<ExecStats> {
+        let (&(first, ..), &(last, ..)) = (self.deals.front()?, self.deals.back()?);
+        let trades = self.deals.len();
+        let mean = ratio(self.prices, trades as i128);
+        let span = (last - first) as i128;
+        Some(ExecStats {
+            vwap: if self.volume == 0 { mean } else { ratio(self.notional, self.volume) },
+            twap: if span == 0 { mean } else { ratio(self.weighted, span) },
+            volume: self.volume as Volume,
+            trades,
+            high: self.highs.front()?.0,
+            low: self.lows.front()?.0,
+        })
+    }
+}
+
+// the quotient is taken in integers, the sums may be beyond the `f64` mantissa
+fn ratio(num: i128, den: i128) -> f64 {
+    (num / den) as f64 + (num % den) as f64 / den as f64
+}
@@ -0,0 +1,35 @@
+//! Instrument, date and stream kind encoded in the QScalp file names, e.g.
+//! `Si-3.20.2020-03-17.OrdLog.qsh`
+
+use crate::types::Stream;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QshFileInfo {
+    /// may contain dots, e.g. `Si-3.20`
+    pub instrument: String,
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub stream: Stream,
+}
+
+/// Parses the `{instrument}.{YYYY-MM-DD}.{OrdLog|Quotes|Deals|AuxInfo}.qsh` file name, the
+/// directories, if any, are ignored. `None` for the names of the other layout.
+pub fn parse_qsh_filename(name: &str) -> Option<QshFileInfo> {
+    let name = name.rsplit(['/', '\\']).next()?;
+    let mut parts = name.strip_suffix(".qsh")?.rsplitn(3, '.');
+    let stream = match parts.next()? {
+        "OrdLog" => Stream::ORDERLOG,
+        "Quotes" => Stream::QUOTES,
+        "Deals" => Stream::DEALS,
+        "AuxInfo" => Stream::AUXINFO,
+        _ => return None,
+    };
+    let date = parts.next()?;
+    let instrument = parts.next().filter(|s| !s.is_empty())?;
+    let valid_date = date.len() == 10
+        && date.bytes().enumerate().all(|(i, b)| match i {
+            4 | 7 => b == b'-',
+            _ => b.is_ascii_digit(),
+        });
+    valid_date.then(|| QshFileInfo { instrument: instrument.into(), date: date.into(), stream })
+}
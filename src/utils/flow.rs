@@ -0,0 +1,372 @@
+//! Order flow imbalance(OFI) of the best quotes of the replayed book, see [`ofi`], the message
+//! rates of the records, see [`message_rates`], and the deals signed by the aggressor, see
+//! [`signed_trades`]
+//!
+//! The OFI of the transaction is of the best quotes before it against the ones after it:
+//! `e = 1{pb' >= pb} * qb' - 1{pb' <= pb} * qb - 1{pa' <= pa} * qa' + 1{pa' >= pa} * qa`,
+//! the primed ones are after. The side empty either before or after brings nothing, the
+//! transaction opening the session brings nothing as the book is rebuilt by it.
+
+use super::{bars::SESSION_GAP_NS, trades::tx_trades};
+use crate::{
+    orderbook::{self as ob, new_session_start, BookListener, NopListener, OrderBook, Quote},
+    time,
+    types::{OLFlagSet, OLMsgType, OrderLog, Price, Side, Timestamp, Volume, UID},
+    QshError,
+};
+use std::collections::{HashSet, VecDeque};
+
+const SECOND_NS: Timestamp = 1_000_000_000;
+
+/// OFI per transaction of the `txs` replayed into the fresh book, with the time of the last
+/// record of the transaction, unix nanos. The iteration stops after the book error.
+pub fn tx_ofi<I>(txs: I) -> TxOfi<I::IntoIter>
+where
+    I: IntoIterator<Item = Vec<OrderLog>>,
+{
+    TxOfi { txs: txs.into_iter(), book: OrderBook::default(), failed: false }
+}
+
+/// [`tx_ofi`] summed per `interval_ns` bucket of the exchange time, the bucket start is the
+/// multiple of the interval since the unix epoch. The buckets without transactions are skipped.
+pub fn ofi<I>(txs: I, interval_ns: Timestamp) -> Ofi<I::IntoIter>
+where
+    I: IntoIterator<Item = Vec<OrderLog>>,
+{
+    assert!(interval_ns > 0, "interval must be positive");
+    Ofi { inner: tx_ofi(txs), interval: interval_ns, open: None, error: None }
+}
+
+pub struct TxOfi<I> {
+    txs: I,
+    book: OrderBook,
+    failed: bool,
+}
+
+impl<I> Iterator for TxOfi<I>
+where
+    I: Iterator<Item = Vec<OrderLog>>,
+{
+    type Item = Result<(Timestamp, f64), QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let tx = self.txs.next()?;
+        let before = (self.book.best_bid(), self.book.best_ask());
+        if let Err(e) = self.book.apply_tx(&tx, &mut NopListener) {
+            self.failed = true;
+            return Some(Err(e));
+        }
+        let ts = tx.last().map_or(0, |rec| time::ticks_to_unix_ns(rec.timestamp));
+        if new_session_start(&tx) < tx.len() {
+            return Some(Ok((ts, 0.)));
+        }
+        let after = (self.book.best_bid(), self.book.best_ask());
+        Some(Ok((ts, (bid_flow(before.0, after.0) - ask_flow(before.1, after.1)) as f64)))
+    }
+}
+
+// size added at the best bid
+fn bid_flow(before: Option<Quote>, after: Option<Quote>) -> i64 {
+    let (Some((pb, qb)), Some((pb_, qb_))) = (before, after) else { return 0 };
+    (pb_ >= pb) as i64 * qb_ - (pb_ <= pb) as i64 * qb
+}
+
+// size added at the best ask
+fn ask_flow(before: Option<Quote>, after: Option<Quote>) -> i64 {
+    let (Some((pa, qa)), Some((pa_, qa_))) = (before, after) else { return 0 };
+    (pa_ <= pa) as i64 * qa_ - (pa_ >= pa) as i64 * qa
+}
+
+pub struct Ofi<I> {
+    inner: TxOfi<I>,
+    interval: Timestamp,
+    // start of the open bucket and its sum
+    open: Option<(Timestamp, f64)>,
+    // the error of the book is yielded after the bucket of the transactions before it
+    error: Option<QshError>,
+}
+
+impl<I> Iterator for Ofi<I>
+where
+    I: Iterator<Item = Vec<OrderLog>>,
+{
+    /// bucket start, unix nanos, and the OFI of its transactions
+    type Item = Result<(Timestamp, f64), QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        loop {
+            let (ts, e) = match self.inner.next() {
+                Some(Ok(tx)) => tx,
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    return self.open.take().map(Ok).or_else(|| self.error.take().map(Err));
+                }
+                None => return self.open.take().map(Ok),
+            };
+            let start = ts - ts.rem_euclid(self.interval);
+            match self.open.as_mut() {
+                Some((open, sum)) if *open == start => *sum += e,
+                _ => {
+                    if let Some(closed) = self.open.replace((start, e)) {
+                        return Some(Ok(closed));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Records of the [`message_rates`] bucket
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateBucket {
+    /// unix nanos, the multiple of the interval
+    pub start: Timestamp,
+    /// records of all kinds
+    pub messages: usize,
+    pub adds: usize,
+    pub cancels: usize,
+    /// deals, the Fill records of the deal counted once
+    pub trades: usize,
+    /// the most records within the same second of the exchange time
+    pub peak_per_second: usize,
+}
+
+impl RateBucket {
+    fn empty(start: Timestamp) -> Self {
+        Self { start, ..Default::default() }
+    }
+
+    /// `None` without trades
+    #[inline]
+    pub fn cancel_to_trade(&self) -> Option<f64> {
+        (self.trades > 0).then(|| self.cancels as f64 / self.trades as f64)
+    }
+
+    /// `None` without trades
+    #[inline]
+    pub fn add_to_trade(&self) -> Option<f64> {
+        (self.trades > 0).then(|| self.adds as f64 / self.trades as f64)
+    }
+}
+
+/// Record counts per `interval_ns` bucket of the exchange time, the bucket start is the multiple
+/// of the interval since the unix epoch. No book is replayed, so any record stream goes.
+///
+/// The buckets without records are emitted within the session, the ones of the quiet period
+/// longer than [`SESSION_GAP_NS`] or ended by the `OLFlags::NewSession` record are skipped.
+pub fn message_rates<I>(records: I, interval_ns: Timestamp) -> MessageRates<I::IntoIter>
+where
+    I: IntoIterator<Item = OrderLog>,
+{
+    assert!(interval_ns > 0, "interval must be positive");
+    MessageRates {
+        records: records.into_iter(),
+        interval: interval_ns,
+        open: None,
+        second: (0, 0),
+        tx_deals: HashSet::new(),
+        last_ts: 0,
+        fill: None,
+    }
+}
+
+pub struct MessageRates<I> {
+    records: I,
+    interval: Timestamp,
+    open: Option<RateBucket>,
+    // the second of the last record and the records within it
+    second: (Timestamp, usize),
+    // deals of the current transaction, the two Fill records of the deal are within it
+    tx_deals: HashSet<UID>,
+    // exchange time of the last record
+    last_ts: Timestamp,
+    // start of the next empty bucket and the one of the open bucket
+    fill: Option<(Timestamp, Timestamp)>,
+}
+
+impl<I> MessageRates<I> {
+    fn push(&mut self, ts: Timestamp, rec: &OrderLog) {
+        let second = ts - ts.rem_euclid(SECOND_NS);
+        self.second =
+            if self.second.0 == second { (second, self.second.1 + 1) } else { (second, 1) };
+        let bucket = self.open.as_mut().expect("open bucket");
+        bucket.messages += 1;
+        bucket.peak_per_second = bucket.peak_per_second.max(self.second.1);
+        match rec.event {
+            OLMsgType::Add => bucket.adds += 1,
+            OLMsgType::Cancel => bucket.cancels += 1,
+            OLMsgType::Fill if self.tx_deals.insert(rec.deal_id) => bucket.trades += 1,
+            _ => {}
+        }
+        if ob::tx_end(rec) {
+            self.tx_deals.clear();
+        }
+        self.last_ts = ts;
+    }
+}
+
+impl<I> Iterator for MessageRates<I>
+where
+    I: Iterator<Item = OrderLog>,
+{
+    type Item = RateBucket;
+
+    fn next(&mut self) -> Option<RateBucket> {
+        if let Some((start, end)) = self.fill.as_mut() {
+            let empty = RateBucket::empty(*start);
+            *start += self.interval;
+            if *start >= *end {
+                self.fill = None;
+            }
+            return Some(empty);
+        }
+        loop {
+            let Some(rec) = self.records.next() else {
+                return self.open.take();
+            };
+            let ts = time::ticks_to_unix_ns(rec.timestamp);
+            let start = ts - ts.rem_euclid(self.interval);
+            let closed = match self.open {
+                Some(open) if open.start != start => self.open.replace(RateBucket::empty(start)),
+                Some(_) => None,
+                None => self.open.replace(RateBucket::empty(start)),
+            };
+            if let Some(closed) = closed {
+                let next = closed.start + self.interval;
+                let new_session = rec.order_flags.contains(OLFlagSet::NEW_SESSION);
+                if !new_session && ts - self.last_ts <= SESSION_GAP_NS && next < start {
+                    self.fill = Some((next, start));
+                }
+                self.second = (0, 0);
+                self.push(ts, &rec);
+                return Some(closed);
+            }
+            self.push(ts, &rec);
+        }
+    }
+}
+
+/// Deal labelled with the side of the taker, see [`signed_trades`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignedTrade {
+    /// unix nanos
+    pub ts: Timestamp,
+    pub price: Price,
+    pub amount: Volume,
+    /// side of the taker, `UNKNOWN` if neither leg is the order added in the transaction
+    pub aggressor: Side,
+    /// the maker was the last order of its level, the book removed the level by the fill
+    pub level_exhausted: bool,
+}
+
+impl SignedTrade {
+    /// side of the book the deal took, the one of the maker
+    #[inline]
+    pub fn consumed(&self) -> Side {
+        self.aggressor.opposite()
+    }
+
+    /// `amount` of the buyer aggressor, negated of the seller one, 0 if the roles are unknown
+    #[inline]
+    pub fn signed_amount(&self) -> Volume {
+        match self.aggressor {
+            Side::Buy => self.amount,
+            Side::Sell => -self.amount,
+            Side::UNKNOWN => 0,
+        }
+    }
+}
+
+/// Deals of the `txs` replayed into the fresh book, the legs are paired and the roles are
+/// assigned as of [`from_orderlog`](super::trades::from_orderlog): the taker is the order added
+/// in the transaction, the IOK/FOK one included though it never rests. The iteration stops after
+/// the book error.
+pub fn signed_trades<I>(txs: I) -> SignedTrades<I::IntoIter>
+where
+    I: IntoIterator<Item = Vec<OrderLog>>,
+{
+    SignedTrades {
+        txs: txs.into_iter(),
+        book: OrderBook::default(),
+        pending: VecDeque::new(),
+        failed: false,
+    }
+}
+
+pub struct SignedTrades<I> {
+    txs: I,
+    book: OrderBook,
+    pending: VecDeque<SignedTrade>,
+    failed: bool,
+}
+
+// notes the removal of the level
+struct Removal(bool);
+
+impl BookListener for Removal {
+    #[inline]
+    fn on_remove(&mut self, _: Side, _: Price, _: Timestamp) {
+        self.0 = true;
+    }
+}
+
+impl<I> SignedTrades<I> {
+    // Applies the transaction record by record, returns the order and the deal ids of the Fill
+    // records removing their level. The book crossed by the aggressor within the transaction
+    // is of no concern, as it's not `OrderBook::strict`.
+    fn replay(&mut self, tx: &[OrderLog]) -> Result<Vec<(UID, UID)>, QshError> {
+        let session = new_session_start(tx);
+        let mut exhausted = vec![];
+        for (i, &rec) in tx.iter().enumerate() {
+            if i == session {
+                self.book.clear();
+            }
+            let mut removal = Removal(false);
+            self.book.apply(rec, &mut removal)?;
+            if removal.0 && rec.event == OLMsgType::Fill {
+                exhausted.push((rec.order_id, rec.deal_id));
+            }
+        }
+        Ok(exhausted)
+    }
+}
+
+impl<I> Iterator for SignedTrades<I>
+where
+    I: Iterator<Item = Vec<OrderLog>>,
+{
+    type Item = Result<SignedTrade, QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(trade) = self.pending.pop_front() {
+                return Some(Ok(trade));
+            }
+            if self.failed {
+                return None;
+            }
+            let tx = self.txs.next()?;
+            let exhausted = match self.replay(&tx) {
+                Ok(exhausted) => exhausted,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            };
+            self.pending.extend(tx_trades(tx).into_iter().map(|t| SignedTrade {
+                ts: t.ts,
+                price: t.price,
+                amount: t.amount,
+                aggressor: t.aggressor,
+                level_exhausted:
+                    t.maker_order_id.is_some_and(|maker| exhausted.contains(&(maker, t.deal_id))),
+            }));
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! As-of join of the AuxInfo stream to the records of the other stream, see [`with_auxinfo`]
+
+use crate::{
+    source::Record,
+    time::FrameClock,
+    types::{AuxInfo, Deal, Header, OrderLog, Price, Quotes, Timestamp, Volume},
+    QshError,
+};
+use std::iter::Peekable;
+
+/// record with its frame time, unix nanos
+pub type Timed<T> = (Timestamp, T);
+
+/// Record having the `frame_time_delta`, see [`frame_timed`]
+pub trait Framed {
+    /// millis since the previous record of the stream
+    fn frame_time_delta(&self) -> Timestamp;
+}
+
+impl Framed for OrderLog {
+    fn frame_time_delta(&self) -> Timestamp {
+        self.frame_time_delta
+    }
+}
+
+impl Framed for Deal {
+    fn frame_time_delta(&self) -> Timestamp {
+        self.frame_time_delta
+    }
+}
+
+impl Framed for AuxInfo {
+    fn frame_time_delta(&self) -> Timestamp {
+        self.frame_time_delta
+    }
+}
+
+impl Framed for Quotes {
+    fn frame_time_delta(&self) -> Timestamp {
+        self.frame_time_delta
+    }
+}
+
+impl Framed for Record {
+    fn frame_time_delta(&self) -> Timestamp {
+        Record::frame_time_delta(self)
+    }
+}
+
+/// `records` of the file of the `header` with their frame time, see [`FrameClock`]
+pub fn frame_timed<I>(
+    records: I,
+    header: &Header,
+) -> Result<impl Iterator<Item = Timed<I::Item>>, QshError>
+where
+    I: IntoIterator,
+    I::Item: Framed,
+{
+    let mut clock = FrameClock::new(header.recording_time_or_err()?);
+    Ok(records.into_iter().map(move |rec| (clock.advance(rec.frame_time_delta()), rec)))
+}
+
+/// State of the AuxInfo stream as of the record, zeroed before the first AuxInfo one
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AuxSnapshot {
+    /// frame time of the last AuxInfo record, unix nanos, `None` before the first one
+    pub ts: Option<Timestamp>,
+    /// price of the last deal
+    pub price: Price,
+    pub oi: Volume,
+    pub hi_limit: Price,
+    pub low_limit: Price,
+    pub deposit: f64,
+}
+
+impl AuxSnapshot {
+    fn update(&mut self, ts: Timestamp, aux: &AuxInfo) {
+        *self = Self {
+            ts: Some(ts),
+            price: aux.price,
+            oi: aux.oi,
+            hi_limit: aux.hi_limit,
+            low_limit: aux.low_limit,
+            deposit: aux.deposit,
+        };
+    }
+}
+
+/// Pairs the `primary` records with the state of the `aux` stream as of their frame time, the
+/// AuxInfo record of the same frame time included. Both go in the frame time order, e.g. of
+/// [`frame_timed`], the AuxInfo records are read one ahead of the primary ones.
+pub fn with_auxinfo<P, A, T>(primary: P, aux: A) -> WithAuxInfo<P::IntoIter, A::IntoIter>
+where
+    P: IntoIterator<Item = Timed<T>>,
+    A: IntoIterator<Item = Timed<AuxInfo>>,
+{
+    WithAuxInfo {
+        primary: primary.into_iter(),
+        aux: aux.into_iter().peekable(),
+        state: AuxSnapshot::default(),
+    }
+}
+
+pub struct WithAuxInfo<P, A: Iterator> {
+    primary: P,
+    aux: Peekable<A>,
+    state: AuxSnapshot,
+}
+
+impl<P, A, T> Iterator for WithAuxInfo<P, A>
+where
+    P: Iterator<Item = Timed<T>>,
+    A: Iterator<Item = Timed<AuxInfo>>,
+{
+    type Item = (Timed<T>, AuxSnapshot);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (ts, rec) = self.primary.next()?;
+        while let Some((aux_ts, aux)) = self.aux.next_if(|(aux_ts, _)| *aux_ts <= ts) {
+            self.state.update(aux_ts, &aux);
+        }
+        Some(((ts, rec), self.state))
+    }
+}
@@ -0,0 +1,182 @@
+//! Fixed-depth snapshots of the incremental `L2Message`s, e.g. of the `l3tol2` output, see
+//! [`snapshots`]
+
+use crate::{
+    orderbook::{BookSnapshot, L2Book},
+    types::{L2Message, Timestamp},
+    QshError,
+};
+
+/// When the book is sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotTrigger {
+    /// after every message, the trades included
+    EveryMessage,
+    /// after every `n`th message
+    EveryNMessages(usize),
+    /// before the first message past every multiple of the period, nanos, so the snapshot is
+    /// the book as of the boundary
+    EveryInterval(Timestamp),
+}
+
+/// Snapshots of the crossed, the empty or the one-sided book
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DegenerateBooks {
+    /// emitted flagged, see [`L2Snapshot`]
+    #[default]
+    Keep,
+    /// not emitted
+    Skip,
+}
+
+/// Sampled book, the levels may be fewer than the depth, `snapshot.flatten(depth)` zero pads
+/// them to the fixed width
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct L2Snapshot {
+    pub snapshot: BookSnapshot,
+    /// best bid is at or above the best ask
+    pub crossed: bool,
+}
+
+impl L2Snapshot {
+    /// any side of the book is empty
+    #[inline]
+    pub fn is_partial(&self) -> bool {
+        self.snapshot.bids.is_empty() || self.snapshot.asks.is_empty()
+    }
+}
+
+/// Top `depth` levels of the book replayed from the `messages`, `0` for all of them, sampled as
+/// `every` sets. The messages carry no time but the embedded `L2Message::Snapshot`s, the book
+/// time is the one of the last of them, so is the time of the
+/// [`EveryInterval`](SnapshotTrigger::EveryInterval) trigger, see [`timed_snapshots`] otherwise.
+///
+/// The iteration stops after the first message failing the book, see `L2Book::apply`.
+pub fn snapshots(
+    messages: impl IntoIterator<Item = L2Message>,
+    depth: usize,
+    every: SnapshotTrigger,
+) -> impl Iterator<Item = Result<L2Snapshot, QshError>> {
+    snapshots_with(messages, depth, every, DegenerateBooks::Keep)
+}
+
+/// Same as [`snapshots`], the crossed and the partial books are handled by `degenerate`
+pub fn snapshots_with(
+    messages: impl IntoIterator<Item = L2Message>,
+    depth: usize,
+    every: SnapshotTrigger,
+    degenerate: DegenerateBooks,
+) -> impl Iterator<Item = Result<L2Snapshot, QshError>> {
+    let timed = messages.into_iter().scan(0, |ts, msg| {
+        if let L2Message::Snapshot { ts: t, .. } = msg {
+            *ts = t;
+        }
+        Some((*ts, msg))
+    });
+    timed_snapshots(timed, depth, every, degenerate)
+}
+
+/// Same as [`snapshots_with`] over the messages with their time, unix nanos, e.g. the frame time
+/// of the Quotes the messages are of
+pub fn timed_snapshots<I>(
+    messages: I,
+    depth: usize,
+    every: SnapshotTrigger,
+    degenerate: DegenerateBooks,
+) -> L2Snapshots<I::IntoIter>
+where
+    I: IntoIterator<Item = (Timestamp, L2Message)>,
+{
+    assert!(
+        !matches!(every, SnapshotTrigger::EveryNMessages(0) | SnapshotTrigger::EveryInterval(..=0)),
+        "snapshot period must be positive"
+    );
+    L2Snapshots {
+        messages: messages.into_iter(),
+        book: L2Book::default(),
+        depth,
+        every,
+        degenerate,
+        count: 0,
+        next_ts: None,
+        pending: None,
+        done: false,
+    }
+}
+
+pub struct L2Snapshots<I> {
+    messages: I,
+    book: L2Book,
+    depth: usize,
+    every: SnapshotTrigger,
+    degenerate: DegenerateBooks,
+    // messages since the last snapshot
+    count: usize,
+    // the next interval boundary, unix nanos
+    next_ts: Option<Timestamp>,
+    // the message past the boundary, applied after the snapshot
+    pending: Option<(Timestamp, L2Message)>,
+    done: bool,
+}
+
+impl<I> L2Snapshots<I> {
+    fn sample(&self) -> Option<L2Snapshot> {
+        let snapshot = L2Snapshot {
+            snapshot: self.book.book_snapshot(self.depth),
+            crossed: self.book.is_crossed(),
+        };
+        let degenerate = snapshot.crossed || snapshot.is_partial();
+        (self.degenerate == DegenerateBooks::Keep || !degenerate).then_some(snapshot)
+    }
+
+    // the boundary is crossed by the message at `ts`
+    fn boundary(&mut self, ts: Timestamp) -> bool {
+        let SnapshotTrigger::EveryInterval(period) = self.every else { return false };
+        let due = self.next_ts.is_some_and(|next| ts >= next);
+        if due || self.next_ts.is_none() {
+            self.next_ts = Some((ts.div_euclid(period) + 1) * period);
+        }
+        due
+    }
+}
+
+impl<I> Iterator for L2Snapshots<I>
+where
+    I: Iterator<Item = (Timestamp, L2Message)>,
+{
+    type Item = Result<L2Snapshot, QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (ts, msg) = self.pending.take().or_else(|| self.messages.next())?;
+            if self.boundary(ts) {
+                self.pending = Some((ts, msg));
+                match self.sample() {
+                    Some(snapshot) => return Some(Ok(snapshot)),
+                    None => continue,
+                }
+            }
+            if let Err(e) = self.book.apply(msg) {
+                self.done = true;
+                return Some(Err(e));
+            }
+            self.book.set_timestamp(ts);
+            let due = match self.every {
+                SnapshotTrigger::EveryMessage => true,
+                SnapshotTrigger::EveryNMessages(n) => {
+                    self.count += 1;
+                    let due = self.count >= n;
+                    if due {
+                        self.count = 0;
+                    }
+                    due
+                }
+                SnapshotTrigger::EveryInterval(_) => false,
+            };
+            if let Some(snapshot) = due.then(|| self.sample()).flatten() {
+                return Some(Ok(snapshot));
+            }
+        }
+        None
+    }
+}
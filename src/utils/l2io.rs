@@ -0,0 +1,110 @@
+//! The `.bin` files of the `l3tol2` tool: the gzip stream of the `L2Message`s, `bincode`
+//! encoded with `config::standard()` back to back, see [`L2Writer`] and [`read`]
+
+use crate::{source_error, types::L2Message, QshError, QshRead};
+use bincode::{config, error::DecodeError, error::EncodeError};
+use flate2::{bufread::GzDecoder, write::GzEncoder, Compression};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, path::PathBuf};
+use std::{
+    io::{BufReader, ErrorKind, Read, Write},
+    mem,
+};
+
+/// Writes the messages the way [`read`] and [`L2Reader`] expect them
+pub struct L2Writer<W: Write>(GzEncoder<W>);
+
+impl<W: Write> L2Writer<W> {
+    /// the best compression, as the `l3tol2` tool writes
+    pub fn new(w: W) -> Self {
+        Self::with_compression(w, Compression::best())
+    }
+
+    pub fn with_compression(w: W, level: Compression) -> Self {
+        Self(GzEncoder::new(w, level))
+    }
+
+    pub fn write(&mut self, msg: &L2Message) -> Result<(), QshError> {
+        bincode::encode_into_std_write(msg, &mut self.0, config::standard()).map_err(
+            |e| match e {
+                EncodeError::Io { inner, .. } => inner.into(),
+                e => QshError::General { source: Box::new(e) },
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Writes the gzip trailer, the file lacking it fails to [`read`] to the end
+    pub fn finish(self) -> Result<W, QshError> {
+        Ok(self.0.finish()?)
+    }
+}
+
+/// Messages of the `l3tol2` output file, the iteration stops after the first error
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(
+    path: impl Into<PathBuf>,
+) -> Result<impl Iterator<Item = Result<L2Message, QshError>>, QshError> {
+    Ok(L2Reader::new(File::open(path.into())?))
+}
+
+/// Messages of the gzipped `source`, see [`read`]. The end of the data between the messages
+/// ends the iteration, inside the message it's `QshError::Corrupted`, the gzip stream cut off
+/// is `QshError::Truncated`.
+pub struct L2Reader<R: Read> {
+    source: Source<BufReader<GzDecoder<BufReader<R>>>>,
+    done: bool,
+}
+
+// tells the errors of the gzip decoder from the end of the data `bincode` runs into
+struct Source<R> {
+    inner: R,
+    failed: bool,
+}
+
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf).inspect_err(|_| self.failed = true)
+    }
+}
+
+impl<R: Read> L2Reader<R> {
+    pub fn new(source: R) -> Self {
+        let inner = BufReader::new(GzDecoder::new(BufReader::new(source)));
+        Self { source: Source { inner, failed: false }, done: false }
+    }
+
+    fn decode(&mut self) -> Result<L2Message, QshError> {
+        bincode::decode_from_std_read(&mut self.source, config::standard()).map_err(|e| match e {
+            DecodeError::Io { inner, .. } if mem::take(&mut self.source.failed) => {
+                source_error(inner)
+            }
+            DecodeError::Io { inner, .. } if inner.kind() == ErrorKind::UnexpectedEof => {
+                QshError::Corrupted("L2 message cut off by the end of the data".into())
+            }
+            DecodeError::UnexpectedEnd { .. } => {
+                QshError::Corrupted("L2 message cut off by the end of the data".into())
+            }
+            e => QshError::Parsing(format!("invalid L2 message: {e}")),
+        })
+    }
+}
+
+impl<R: Read> Iterator for L2Reader<R> {
+    type Item = Result<L2Message, QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let msg = match self.source.inner.eof() {
+            Ok(true) => None,
+            Ok(false) => Some(self.decode()),
+            Err(e) => Some(Err(e)),
+        };
+        self.done = !matches!(msg, Some(Ok(_)));
+        msg
+    }
+}
+
+impl<R: Read> std::iter::FusedIterator for L2Reader<R> {}
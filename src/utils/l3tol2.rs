@@ -1,36 +1,143 @@
 /// MOEX L3 messages to L2 messages conversion routine
 ///
 use crate::{
-    orderbook::{self as ob, PartitionBy},
-    types::{L2Message, L3Message, OLFlags, OrderLog},
-    QshError,
+    orderbook::{self as ob, L2Book, OrderLogStream, Quote, VecSink},
+    policy::ParsePolicy,
+    types::{L2Message, L3Message, OrderLog, Side, Timestamp},
+    QshError, Shunt,
 };
 
 use super::moex2conv::moex_to_l3;
 
+/// Options of [`convert_with`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConvertOptions {
+    /// top levels per side, `0` for all of them
+    pub depth: usize,
+    /// period of the [`L2Message::Snapshot`]s, none by default
+    pub snapshot_every: Option<SnapshotEvery>,
+    /// of the book replaying the records, see `OrderBook::with_policy`
+    pub policy: ParsePolicy,
+}
+
+/// Period of the snapshots embedded in the converted stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotEvery {
+    /// the snapshot follows every `n`th transaction
+    Transactions(usize),
+    /// the snapshot follows the first transaction past every multiple of the exchange time
+    /// period, nanos
+    Nanos(Timestamp),
+}
+
 struct L3L2Converter<I> {
     inner: I,
     book: ob::OrderBook,
-    _depth: usize,
+    // 0 - unbounded
+    depth: usize,
+    // top `depth` levels of (bids, asks) as of the emitted messages
+    visible: (Vec<Quote>, Vec<Quote>),
+    snapshot_every: Option<SnapshotEvery>,
+    // transactions since the last snapshot
+    txs: usize,
+    // time of the next snapshot, unix nanos
+    next_snapshot: Option<Timestamp>,
 }
 
 impl<I> L3L2Converter<I> {
-    fn new(inner: I, depth: usize) -> Self {
-        Self { inner, book: Default::default(), _depth: depth }
+    fn new(inner: I, options: ConvertOptions) -> Self {
+        Self {
+            inner,
+            book: ob::OrderBook::with_policy(options.policy),
+            depth: options.depth,
+            visible: Default::default(),
+            snapshot_every: options.snapshot_every,
+            txs: 0,
+            next_snapshot: None,
+        }
     }
 
-    fn process(&mut self, tx: Vec<OrderLog>) -> Result<Vec<L2Message>, QshError> {
+    // the book is cleared at the first NewSession record, the rest of the transaction is
+    // applied to the fresh book
+    fn process(&mut self, mut tx: Vec<OrderLog>) -> Result<Vec<L2Message>, QshError> {
         let mut events = Vec::with_capacity(100);
+        let after = tx.split_off(ob::new_session_start(&tx));
+        if !tx.is_empty() {
+            self.apply(tx, &mut events)?;
+        }
+        if !after.is_empty() {
+            self.book.clear();
+            self.visible.0.clear();
+            self.visible.1.clear();
+            events.push(L2Message::Clear);
+            self.apply(after, &mut events)?;
+        }
+        if self.snapshot_due() {
+            let top = self.book.book_snapshot(self.depth);
+            events.push(L2Message::Snapshot { ts: top.ts, bids: top.bids, asks: top.asks });
+        }
+        Ok(events)
+    }
+
+    fn snapshot_due(&mut self) -> bool {
+        match self.snapshot_every {
+            None => false,
+            Some(SnapshotEvery::Transactions(n)) => {
+                self.txs += 1;
+                let due = self.txs >= n;
+                if due {
+                    self.txs = 0;
+                }
+                due
+            }
+            Some(SnapshotEvery::Nanos(period)) => {
+                let Some(ts) = self.book.last_update() else {
+                    return false;
+                };
+                let due = self.next_snapshot.is_some_and(|next| ts >= next);
+                if due || self.next_snapshot.is_none() {
+                    self.next_snapshot = Some((ts.div_euclid(period) + 1) * period);
+                }
+                due
+            }
+        }
+    }
+
+    fn apply(&mut self, tx: Vec<OrderLog>, events: &mut Vec<L2Message>) -> Result<(), QshError> {
+        let mut updates = vec![];
         for tx in moex_to_l3(tx) {
+            let sink = if self.depth == 0 { &mut *events } else { &mut updates };
             for msg in tx? {
                 match msg {
-                    L3Message::Add(rec) => self.book.add(rec, &mut events),
-                    L3Message::Cancel(rec) => self.book.cancel(rec, &mut events),
-                    L3Message::Trade(rec) => self.book.trade(rec, &mut events),
+                    L3Message::Add(rec) => self.book.add(rec, &mut VecSink(sink)),
+                    L3Message::Cancel(rec) => self.book.cancel(rec, &mut VecSink(sink)),
+                    L3Message::Trade(rec) => self.book.trade(rec, &mut VecSink(sink)),
                 }?;
             }
+            if self.depth > 0 {
+                events.extend(updates.drain(..).filter(|m| matches!(m, L2Message::Trade { .. })));
+                self.emit_visible(events);
+            }
+        }
+        Ok(())
+    }
+
+    // Changes of the top `depth` levels since the last call. The level entering the top as a
+    // nearer one is removed is emitted as a Quote, though the book didn't change at its price.
+    fn emit_visible(&mut self, events: &mut Vec<L2Message>) {
+        let top = self.book.book_snapshot(self.depth);
+        for (side, visible, top) in [
+            (Side::Buy, &mut self.visible.0, top.bids),
+            (Side::Sell, &mut self.visible.1, top.asks),
+        ] {
+            for &(price, _) in visible.iter().filter(|(p, _)| top.iter().all(|q| q.0 != *p)) {
+                events.push(L2Message::Remove { side, price });
+            }
+            for &(price, size) in top.iter().filter(|q| !visible.contains(q)) {
+                events.push(L2Message::Quote { side, price, size });
+            }
+            *visible = top;
         }
-        Ok(events)
     }
 }
 
@@ -42,23 +149,73 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|tx| {
-            if OLFlags::NewSession % tx[0].order_flags {
-                self.book.clear();
-                Ok(vec![L2Message::Clear])
-            } else {
-                self.process(tx)
-            }
-        })
+        self.inner.next().map(|tx| self.process(tx))
     }
 }
 
+/// Same as [`convert`] over the fallible records, e.g. of `QshRead::try_iter`. The record error
+/// is yielded after the transactions completed before it, the iteration stops then.
+pub fn try_convert(
+    input: impl Iterator<Item = Result<OrderLog, QshError>>,
+    depth: usize,
+) -> impl Iterator<Item = Result<Vec<L2Message>, QshError>> {
+    try_convert_with(input, ConvertOptions { depth, ..Default::default() })
+}
+
+/// Same as [`convert_with`] over the fallible records, see [`try_convert`]
+pub fn try_convert_with(
+    input: impl Iterator<Item = Result<OrderLog, QshError>>,
+    options: ConvertOptions,
+) -> impl Iterator<Item = Result<Vec<L2Message>, QshError>> {
+    let (input, slot) = Shunt::new(input);
+    let mut converted = convert_with(input, options);
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        converted.next().or_else(|| {
+            done = true;
+            slot.take().map(Err)
+        })
+    })
+}
+
+/// Converts the orderlog into the L2 messages per transaction. The levels beyond the top `depth`
+/// of the side are left out, `0` for all of them. The trades are emitted at any depth.
 pub fn convert(
     input: impl Iterator<Item = OrderLog>,
     depth: usize,
 ) -> impl Iterator<Item = Result<Vec<L2Message>, QshError>> {
-    L3L2Converter::new(
-        input.filter(ob::system_record).partition_by(ob::tx_end).filter(ob::fiok_with_trades),
-        depth,
-    )
+    convert_with(input, ConvertOptions { depth, ..Default::default() })
+}
+
+/// Same as [`convert`], with the snapshots of the book, limited to the `depth`, embedded as
+/// the last message of the transaction as the `options` set
+pub fn convert_with(
+    input: impl Iterator<Item = OrderLog>,
+    options: ConvertOptions,
+) -> impl Iterator<Item = Result<Vec<L2Message>, QshError>> {
+    assert!(
+        !matches!(
+            options.snapshot_every,
+            Some(SnapshotEvery::Transactions(0) | SnapshotEvery::Nanos(..=0))
+        ),
+        "snapshot period must be positive"
+    );
+    L3L2Converter::new(input.transactions(), options)
+}
+
+/// Skips the `messages` up to the first snapshot at or after `ts`, unix nanos, returns the book
+/// restored from it, `None` if there is no such snapshot. The rest of the `messages` replays
+/// the book forward.
+pub fn seek_snapshot(
+    messages: &mut impl Iterator<Item = L2Message>,
+    ts: Timestamp,
+) -> Option<L2Book> {
+    let snapshot =
+        messages.find(|msg| matches!(msg, L2Message::Snapshot { ts: t, .. } if *t >= ts))?;
+    let mut book = L2Book::default();
+    book.apply(snapshot).ok()?;
+    Some(book)
 }
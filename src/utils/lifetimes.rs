@@ -0,0 +1,166 @@
+//! Lifecycle of the orders of the OrderLog stream, from the Add to the final record, see
+//! [`analyze`]
+
+use crate::{
+    orderbook::system_record,
+    time,
+    types::{OLFlagSet, OLMsgType, OrderLog, Price, Side, Timestamp, Volume, UID},
+};
+use std::collections::{HashMap, VecDeque};
+
+/// How the order life ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// the Fill of no rest
+    Filled,
+    /// the Cancel or Remove record, including the CrossTrade removal of the rest
+    Canceled,
+    /// the order moved to the other price, its Add starts the new life of the same `order_id`
+    Moved,
+    /// the order is live as the NewSession record comes
+    SessionEnd,
+    /// the order is live at the end of the stream
+    Open,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderLife {
+    pub order_id: UID,
+    pub side: Side,
+    pub price: Price,
+    /// exchange time of the Add, unix nanos
+    pub added_ts: Timestamp,
+    /// exchange time of the record closing the order, of the last record of the stream for the
+    /// `Open` one, unix nanos
+    pub closed_ts: Timestamp,
+    pub reason: CloseReason,
+    /// volume of the fills
+    pub filled: Volume,
+    /// amount of the Add
+    pub total: Volume,
+    /// fills leaving the rest, the final one is not counted
+    pub partial_fills: usize,
+}
+
+impl OrderLife {
+    /// nanos from the Add to the close
+    #[inline]
+    pub fn duration(&self) -> Timestamp {
+        self.closed_ts - self.added_ts
+    }
+
+    /// `filled` of the `total`, in `[0, 1]`
+    #[inline]
+    pub fn fill_ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.
+        } else {
+            self.filled as f64 / self.total as f64
+        }
+    }
+}
+
+/// Yields the [`OrderLife`] of every Add record once the order is closed, the lives of the
+/// orders still live at the end of the stream go last, as `Open`, by `order_id`. The records
+/// of the orders added before the stream start are skipped, as are the non-system records, see
+/// [`system_record`]. Holds the live orders only.
+pub fn analyze<I>(iter: I) -> Lifetimes<I::IntoIter>
+where
+    I: IntoIterator<Item = OrderLog>,
+{
+    Lifetimes { records: iter.into_iter(), live: HashMap::new(), closed: VecDeque::new(), ts: 0 }
+}
+
+pub struct Lifetimes<I> {
+    records: I,
+    live: HashMap<UID, OrderLife>,
+    // closed by the last record
+    closed: VecDeque<OrderLife>,
+    // time of the last record
+    ts: Timestamp,
+}
+
+impl<I> Lifetimes<I> {
+    fn close(&mut self, order_id: UID, reason: CloseReason) {
+        if let Some(life) = self.live.remove(&order_id) {
+            self.closed.push_back(OrderLife { closed_ts: self.ts, reason, ..life });
+        }
+    }
+
+    fn observe(&mut self, rec: &OrderLog) {
+        self.ts = time::ticks_to_unix_ns(rec.timestamp);
+        if rec.order_flags.contains(OLFlagSet::NEW_SESSION) {
+            let mut ids: Vec<_> = self.live.keys().copied().collect();
+            ids.sort_unstable();
+            ids.into_iter().for_each(|id| self.close(id, CloseReason::SessionEnd));
+        }
+        match OLMsgType::from(rec) {
+            OLMsgType::Add => {
+                // the moved order keeping its id, the Cancel record is not always there
+                self.close(rec.order_id, CloseReason::Moved);
+                self.live.insert(
+                    rec.order_id,
+                    OrderLife {
+                        order_id: rec.order_id,
+                        side: rec.side,
+                        price: rec.price,
+                        added_ts: self.ts,
+                        closed_ts: self.ts,
+                        reason: CloseReason::Open,
+                        filled: 0,
+                        total: rec.amount,
+                        partial_fills: 0,
+                    },
+                );
+            }
+            OLMsgType::Fill => {
+                let Some(life) = self.live.get_mut(&rec.order_id) else { return };
+                life.filled += rec.amount;
+                if rec.amount_rest == 0 {
+                    self.close(rec.order_id, CloseReason::Filled);
+                } else {
+                    life.partial_fills += 1;
+                }
+            }
+            OLMsgType::Cancel if rec.order_flags.contains(OLFlagSet::MOVED) => {
+                self.close(rec.order_id, CloseReason::Moved)
+            }
+            // the partial cancel leaves the order live
+            OLMsgType::Cancel | OLMsgType::Remove if rec.amount_rest == 0 => {
+                self.close(rec.order_id, CloseReason::Canceled)
+            }
+            OLMsgType::Remove if rec.order_flags.contains(OLFlagSet::CROSS_TRADE) => {
+                self.close(rec.order_id, CloseReason::Canceled)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<I> Iterator for Lifetimes<I>
+where
+    I: Iterator<Item = OrderLog>,
+{
+    type Item = OrderLife;
+
+    fn next(&mut self) -> Option<OrderLife> {
+        loop {
+            if let Some(life) = self.closed.pop_front() {
+                return Some(life);
+            }
+            match self.records.next() {
+                Some(rec) if system_record(&rec) => self.observe(&rec),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        if self.live.is_empty() {
+            return None;
+        }
+        let mut open: Vec<_> = self.live.drain().map(|(_, life)| life).collect();
+        open.sort_unstable_by_key(|life| life.order_id);
+        let ts = self.ts;
+        self.closed.extend(open.into_iter().map(|life| OrderLife { closed_ts: ts, ..life }));
+        self.closed.pop_front()
+    }
+}
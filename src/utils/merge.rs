@@ -0,0 +1,102 @@
+//! Time ordered records of several streams, see [`by_time`]
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{header, inflate, source::record_source};
+use crate::{
+    source::{Record, RecordSource},
+    time::FrameClock,
+    types::{Header, Timestamp},
+    QshError,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// Records of the stream with their frame time, see [`FrameClock`]
+pub struct TimedSource<'a> {
+    records: Box<dyn RecordSource + 'a>,
+    clock: FrameClock,
+}
+
+impl<'a> TimedSource<'a> {
+    /// `records` following the `header` of the same file
+    pub fn new(header: &Header, records: Box<dyn RecordSource + 'a>) -> Result<Self, QshError> {
+        Ok(Self { records, clock: FrameClock::new(header.recording_time_or_err()?) })
+    }
+
+    /// records of the qsh file of any stream having the reader
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: impl Into<PathBuf>) -> Result<TimedSource<'static>, QshError> {
+        let mut parser = inflate(path.into())?;
+        let header = header(&mut parser)?;
+        TimedSource::new(&header, record_source(header.stream, parser)?)
+    }
+}
+
+impl Iterator for TimedSource<'_> {
+    /// frame time, unix nanos, and the record
+    type Item = Result<(Timestamp, Record), QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rec = self.records.next()?;
+        Some(rec.map(|rec| (self.clock.advance(rec.frame_time_delta()), rec)))
+    }
+}
+
+/// K-way merge of the `sources` by the frame time, the receive time of the records. Yields the
+/// index of the source, the frame time, unix nanos, and the record. The records of the same
+/// time go in the order of their sources, the stream order is kept within the source.
+///
+/// Holds the single record per source, the iteration stops after the first error.
+pub fn by_time<'a>(
+    sources: Vec<TimedSource<'a>>,
+) -> impl Iterator<Item = Result<(usize, Timestamp, Record), QshError>> + 'a {
+    let mut merge = Merge {
+        heads: vec![None; sources.len()],
+        heap: BinaryHeap::with_capacity(sources.len()),
+        sources,
+        error: None,
+    };
+    (0..merge.sources.len()).for_each(|ix| merge.pull(ix));
+    merge
+}
+
+struct Merge<'a> {
+    sources: Vec<TimedSource<'a>>,
+    // the next record of the source
+    heads: Vec<Option<Record>>,
+    // frame time of the head and the source
+    heap: BinaryHeap<Reverse<(Timestamp, usize)>>,
+    // the error of the source is yielded once its records before the error are
+    error: Option<QshError>,
+}
+
+impl Merge<'_> {
+    fn pull(&mut self, ix: usize) {
+        match self.sources[ix].next() {
+            Some(Ok((ts, rec))) => {
+                self.heads[ix] = Some(rec);
+                self.heap.push(Reverse((ts, ix)));
+            }
+            Some(Err(e)) => {
+                self.error.get_or_insert(e);
+            }
+            None => {}
+        }
+    }
+}
+
+impl Iterator for Merge<'_> {
+    type Item = Result<(usize, Timestamp, Record), QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            self.heap.clear();
+            return Some(Err(e));
+        }
+        let Reverse((ts, ix)) = self.heap.pop()?;
+        let rec = self.heads[ix].take().expect("head of the queued source");
+        self.pull(ix);
+        Some(Ok((ix, ts, rec)))
+    }
+}
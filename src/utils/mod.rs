@@ -1,2 +1,22 @@
+pub mod bars;
+pub mod bbo;
+pub mod candles;
+pub mod checkpoint;
+pub mod crosscheck;
+pub mod exec;
+pub mod filename;
+pub mod flow;
+pub mod join;
+pub mod l2;
+pub mod l2io;
 pub mod l3tol2;
+pub mod lifetimes;
+pub mod merge;
 pub mod moex2conv;
+pub mod qa;
+pub mod report;
+pub mod resample;
+pub mod sessions;
+pub mod summary;
+pub mod trades;
+pub mod validate;
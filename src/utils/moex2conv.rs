@@ -5,81 +5,103 @@ use crate::{
     QshError,
 };
 
-enum Chunk {
-    Order(OrderLog),
-    Trades(Vec<OrderLog>, Vec<OrderLog>),
+/// Part of the transaction, see [`chunk_transaction`]
+#[derive(Debug, Clone)]
+pub enum TxChunk {
+    /// order of no part in the matching: the limit order add, the cancel, the remove of the
+    /// resting limit order
+    Standalone(OrderLog),
+    /// orders added within the transaction which trade, with the fills of both the orders and
+    /// the resting counterparts. The removal of the rest of such a limit order, e.g. by
+    /// CrossTrade, is among the `fills`.
+    Match { orders: Vec<OrderLog>, fills: Vec<OrderLog> },
 }
 
-// group orders within transaction constituting trade events.
-//
-// o - add order(limit, iok/fok, cancel)
-// x - execution event(fill)
-//
-// [o, o, o, x, x, o] -> [[o], [o, o, x, x], [o]]
-// [o] - orders which have no place in the ongoing fills
-// [o,o,x,x] orders causes a trades and their corresponding fill events
-fn chunks(tx: Vec<OrderLog>) -> Vec<Chunk> {
+/// Groups the MOEX transaction records constituting the trade events.
+///
+/// ```text
+/// o - add order(limit, iok/fok, cancel)
+/// x - execution event(fill, removal of the rest of the order added within the trades)
+///
+/// [o, o, o, x, x, o] -> [[o], [o, o, x, x], [o]]
+/// ```
+/// `[o]` are the orders which have no place in the ongoing fills, `[o, o, x, x]` the orders
+/// causing the trades and their fill events.
+///
+/// The IOK/FOK orders never hit the book, those aren't standalone and their removes are dropped.
+/// The removes of the limit orders, e.g. by CrossTrade, are standalone unless the order is added
+/// within the ongoing fills. Fails on the remove of the unknown order type.
+pub fn chunk_transaction(tx: Vec<OrderLog>) -> Result<Vec<TxChunk>, QshError> {
     let fill_ids: Vec<i64> = tx
         .iter()
         .filter_map(|rec| (OLMsgType::from(rec) == OLMsgType::Fill).then(|| rec.order_id))
         .collect();
 
-    if fill_ids.len() == 0 {
-        tx.into_iter()
-            .filter_map(|rec| {
-                let ord_t = OrderType::from(rec.order_flags);
-                let is_remove = OLMsgType::from(&rec) == OLMsgType::Remove;
-                let yield_ = !(is_remove || ord_t == OrderType::IOK || ord_t == OrderType::FOK);
-
-                yield_.then(|| Chunk::Order(rec))
-            })
-            .collect::<Vec<Chunk>>()
-    } else {
-        let mut chunks: Vec<Chunk> = vec![];
-        let mut src: Vec<OrderLog> = vec![];
-        let mut tgt: Vec<OrderLog> = vec![];
-
-        for rec in tx.into_iter() {
-            let msg_t = OLMsgType::from(&rec);
-            let in_fills = fill_ids.contains(&rec.order_id);
-            let ord_t = OrderType::from(rec.order_flags);
-
-            match (msg_t, in_fills) {
-                (OLMsgType::Add, true) => src.push(rec),
-                (OLMsgType::Fill, true) => tgt.push(rec),
-                (OLMsgType::Fill, false) => unreachable!("should already be captured"),
-                (OLMsgType::Remove, _) => {
-                    assert_eq!(ord_t, OrderType::IOK, "unreachable, logic error")
+    let mut chunks: Vec<TxChunk> = vec![];
+    let mut src: Vec<OrderLog> = vec![];
+    let mut tgt: Vec<OrderLog> = vec![];
+
+    for rec in tx.into_iter() {
+        let msg_t = OLMsgType::from(&rec);
+        let in_fills = fill_ids.contains(&rec.order_id);
+        let ord_t = OrderType::from(rec.order_flags);
+
+        match (msg_t, in_fills) {
+            (OLMsgType::Add, true) => src.push(rec),
+            (OLMsgType::Fill, true) => tgt.push(rec),
+            (OLMsgType::Fill, false) => {
+                return Err(QshError::InvalidState(format!("fill outside of the fills {rec}")))
+            }
+            (OLMsgType::Remove, _) => match ord_t {
+                OrderType::IOK | OrderType::FOK => (),
+                OrderType::Limit if src.iter().any(|o| o.order_id == rec.order_id) => tgt.push(rec),
+                OrderType::Limit => {
+                    flush(&mut chunks, &mut src, &mut tgt);
+                    chunks.push(TxChunk::Standalone(rec));
                 }
-                _ => {
-                    if src.len() + tgt.len() > 0 {
-                        chunks.push(Chunk::Trades(src.clone(), tgt.clone()));
-                        src.clear();
-                        tgt.clear();
-                    }
-                    if ord_t == OrderType::Limit {
-                        chunks.push(Chunk::Order(rec));
-                    }
+                _ => return Err(QshError::Parsing(format!("remove of the {ord_t:?} order {rec}"))),
+            },
+            _ => {
+                flush(&mut chunks, &mut src, &mut tgt);
+                if ord_t == OrderType::Limit {
+                    chunks.push(TxChunk::Standalone(rec));
                 }
             }
         }
+    }
+    flush(&mut chunks, &mut src, &mut tgt);
 
-        if src.len() + tgt.len() > 0 {
-            chunks.push(Chunk::Trades(src, tgt));
-        }
+    Ok(chunks)
+}
 
-        chunks
+fn flush(chunks: &mut Vec<TxChunk>, src: &mut Vec<OrderLog>, tgt: &mut Vec<OrderLog>) {
+    if src.len() + tgt.len() > 0 {
+        chunks.push(TxChunk::Match { orders: std::mem::take(src), fills: std::mem::take(tgt) });
     }
 }
 
+// the removes within the trades are of the `orders`, see `chunk_transaction`
+fn is_remove(rec: &OrderLog) -> bool {
+    OLMsgType::from(rec) == OLMsgType::Remove
+}
+
+/// L3 messages of the MOEX transaction per [`TxChunk`], the only error ends the iteration
 pub fn moex_to_l3(tx: Vec<OrderLog>) -> impl Iterator<Item = Result<Vec<L3Message>, QshError>> {
-    chunks(tx).into_iter().map(move |c| match c {
-        Chunk::Order(rec) => match OLMsgType::from(&rec) {
+    let (chunks, err) = match chunk_transaction(tx) {
+        Ok(chunks) => (chunks, None),
+        Err(err) => (vec![], Some(Err(err))),
+    };
+    chunks.into_iter().map(chunk_to_l3).chain(err)
+}
+
+fn chunk_to_l3(chunk: TxChunk) -> Result<Vec<L3Message>, QshError> {
+    match chunk {
+        TxChunk::Standalone(rec) => match OLMsgType::from(&rec) {
             OLMsgType::Add => Ok(vec![L3Message::Add(rec)]),
-            OLMsgType::Cancel => Ok(vec![L3Message::Cancel(rec)]),
-            _ => unreachable!(),
+            OLMsgType::Cancel | OLMsgType::Remove => Ok(vec![L3Message::Cancel(rec)]),
+            _ => Err(QshError::Parsing(format!("unknown order message {rec}"))),
         },
-        Chunk::Trades(src, tgt) if src.len() == 1 => {
+        TxChunk::Match { orders: src, fills: tgt } if src.len() == 1 => {
             // [[o], [x*]]
             // one added order that cause one-or-many trades
             let mut src = src[0];
@@ -89,6 +111,10 @@ pub fn moex_to_l3(tx: Vec<OrderLog>) -> impl Iterator<Item = Result<Vec<L3Messag
                 .into_iter()
                 .flat_map(|rec| {
                     if rec.order_id == src.order_id {
+                        if is_remove(&rec) {
+                            src.amount_rest = 0;
+                            return None;
+                        }
                         if src.amount < rec.amount {
                             return Some(Err(QshError::InvalidState(
                                 "rec.amount > src.amount".to_string(),
@@ -114,7 +140,7 @@ pub fn moex_to_l3(tx: Vec<OrderLog>) -> impl Iterator<Item = Result<Vec<L3Messag
 
             Ok(acts)
         }
-        Chunk::Trades(mut src, tgt) => {
+        TxChunk::Match { orders: mut src, fills: tgt } => {
             // [[o*], [x*]]
             // special case of matching between orders added within the same transaction.
             // NOTE: This implementation ignores such orders if they don't hit the book.
@@ -124,12 +150,17 @@ pub fn moex_to_l3(tx: Vec<OrderLog>) -> impl Iterator<Item = Result<Vec<L3Messag
             let mut actions = tgt
                 .into_iter()
                 .filter_map(|rec| {
-                    if OLMsgType::from(&rec) != OLMsgType::Fill {
+                    let remove = is_remove(&rec);
+                    if !remove && OLMsgType::from(&rec) != OLMsgType::Fill {
                         return Some(Err(QshError::Validation("wrong orderlog type".to_string())));
                     }
                     match src.binary_search_by_key(&rec.order_id, |&rec| rec.order_id) {
+                        Ok(ix) if remove => {
+                            src[ix].amount_rest = 0;
+                            None
+                        }
                         Ok(ix) => {
-                            let src = unsafe { src.get_unchecked_mut(ix) };
+                            let src = &mut src[ix];
                             if src.amount < rec.amount {
                                 return Some(Err(QshError::InvalidState(
                                     "src.amount < rec.amount".to_string(),
@@ -161,5 +192,5 @@ pub fn moex_to_l3(tx: Vec<OrderLog>) -> impl Iterator<Item = Result<Vec<L3Messag
 
             Ok(actions)
         }
-    })
+    }
 }
@@ -0,0 +1,149 @@
+//! Sequence anomalies of the OrderLog and the Deals files, e.g. the recorder hiccups, without
+//! building the book, see [`sequence_report`]
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    header, inflate, time::FrameClock, types::Stream, DealReader, OrderLogReader, QshError, QshRead,
+};
+use crate::{
+    time,
+    types::{Deal, OLFlagSet, OrderLog, Side, Timestamp, UID},
+};
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+const HOUR_NS: Timestamp = 3_600 * 1_000_000_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SeqReport {
+    pub records: usize,
+    /// `order_id` of the Add record below the one of the previous Add, OrderLog only
+    pub order_id_regressions: usize,
+    /// the largest step up of the Add `order_id`s
+    pub max_order_id_gap: UID,
+    /// `deal_id` below the previous one, of the Fill records of the OrderLog
+    pub deal_id_regressions: usize,
+    /// the largest step up of the `deal_id`s
+    pub max_deal_id_gap: UID,
+    /// exchange time below the one of the previous record, the records lacking it skipped
+    pub ts_regressions: usize,
+    pub unknown_side: usize,
+    /// records per hour of the frame time, by the hour start, unix nanos
+    pub hourly: BTreeMap<Timestamp, usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    last: Last,
+}
+
+// the previous ids and exchange time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Last {
+    order_id: Option<UID>,
+    deal_id: Option<UID>,
+    ts: Option<Timestamp>,
+}
+
+impl SeqReport {
+    /// Accounts the next record of the OrderLog stream received at `frame_time`, unix nanos
+    pub fn observe_orderlog(&mut self, frame_time: Timestamp, rec: &OrderLog) {
+        if rec.order_flags.contains(OLFlagSet::ADD) {
+            let (regressed, gap) = step(&mut self.last.order_id, rec.order_id);
+            self.order_id_regressions += regressed as usize;
+            self.max_order_id_gap = self.max_order_id_gap.max(gap);
+        }
+        if rec.order_flags.contains(OLFlagSet::FILL) && rec.deal_id != 0 {
+            self.observe_deal_id(rec.deal_id);
+        }
+        self.observe(frame_time, rec.timestamp, rec.side);
+    }
+
+    /// Accounts the next record of the Deals stream received at `frame_time`, unix nanos
+    pub fn observe_deal(&mut self, frame_time: Timestamp, deal: &Deal) {
+        self.observe_deal_id(deal.deal_id);
+        self.observe(frame_time, deal.timestamp, deal.side);
+    }
+
+    /// number of the anomalies of all kinds
+    pub fn anomalies(&self) -> usize {
+        self.order_id_regressions
+            + self.deal_id_regressions
+            + self.ts_regressions
+            + self.unknown_side
+    }
+
+    fn observe_deal_id(&mut self, deal_id: UID) {
+        let (regressed, gap) = step(&mut self.last.deal_id, deal_id);
+        self.deal_id_regressions += regressed as usize;
+        self.max_deal_id_gap = self.max_deal_id_gap.max(gap);
+    }
+
+    fn observe(&mut self, frame_time: Timestamp, timestamp: Timestamp, side: Side) {
+        self.records += 1;
+        *self.hourly.entry(frame_time - frame_time.rem_euclid(HOUR_NS)).or_default() += 1;
+        self.unknown_side += (side == Side::UNKNOWN) as usize;
+        if timestamp != 0 {
+            let ts = time::ticks_to_unix_ns(timestamp);
+            self.ts_regressions += self.last.ts.is_some_and(|last| ts < last) as usize;
+            self.last.ts = Some(ts);
+        }
+    }
+}
+
+// the id is below the previous one, and the step up
+fn step(last: &mut Option<UID>, id: UID) -> (bool, UID) {
+    let prev = last.replace(id);
+    prev.map_or((false, 0), |prev| (id < prev, (id - prev).max(0)))
+}
+
+impl std::fmt::Display for SeqReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "records: {}, anomalies: {}", self.records, self.anomalies())?;
+        writeln!(
+            f,
+            "order_id regressions: {}, max gap: {}",
+            self.order_id_regressions, self.max_order_id_gap
+        )?;
+        writeln!(
+            f,
+            "deal_id regressions: {}, max gap: {}",
+            self.deal_id_regressions, self.max_deal_id_gap
+        )?;
+        writeln!(
+            f,
+            "timestamp regressions: {}, unknown side: {}",
+            self.ts_regressions, self.unknown_side
+        )?;
+        for (hour, count) in &self.hourly {
+            writeln!(f, "{hour}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the whole OrderLog or Deals file for its [`SeqReport`], fails on the other streams
+#[cfg(not(target_arch = "wasm32"))]
+pub fn sequence_report(path: impl Into<PathBuf>) -> Result<SeqReport, QshError> {
+    let mut parser = inflate(path.into())?;
+    let header = header(&mut parser)?;
+    let mut clock = FrameClock::new(header.recording_time_or_err()?);
+    let mut report = SeqReport::default();
+    match header.stream {
+        Stream::ORDERLOG => {
+            for rec in parser.try_iter::<OrderLogReader>() {
+                let rec = rec?;
+                report.observe_orderlog(clock.advance(rec.frame_time_delta), &rec);
+            }
+        }
+        Stream::DEALS => {
+            for deal in parser.try_iter::<DealReader>() {
+                let deal = deal?;
+                report.observe_deal(clock.advance(deal.frame_time_delta), &deal);
+            }
+        }
+        other => {
+            return Err(QshError::Parsing(format!("no sequence report of the {other:?} stream")))
+        }
+    }
+    Ok(report)
+}
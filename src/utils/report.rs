@@ -0,0 +1,81 @@
+//! Quick profile of the OrderLog file: sizes and the time span, e.g. to size the buffers before
+//! the full reconstruction
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{header, inflate, types::Header, OrderLogReader, QshError, QshRead};
+use crate::{
+    orderbook as ob, time,
+    types::{OLFlagSet, OrderLog, Timestamp},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseReport {
+    pub records: usize,
+    /// transactions, complete or not, the non-system records included
+    pub transactions: usize,
+    pub max_tx_len: usize,
+    /// unix time(nanos) of the earliest and the latest records
+    pub min_ts: Option<Timestamp>,
+    pub max_ts: Option<Timestamp>,
+    /// transactions with the NewSession records, the session the file starts with is counted
+    /// even without one
+    pub sessions: usize,
+    // current transaction
+    tx_len: usize,
+    tx_new_session: bool,
+}
+
+impl ParseReport {
+    pub fn from_records(records: impl IntoIterator<Item = OrderLog>) -> Self {
+        let mut report = Self::default();
+        records.into_iter().for_each(|rec| report.observe(&rec));
+        report.finish();
+        report
+    }
+
+    /// Accounts the next record of the stream, [`finish`](Self::finish) completes the report
+    pub fn observe(&mut self, rec: &OrderLog) {
+        let ts = time::ticks_to_unix_ns(rec.timestamp);
+        self.min_ts = Some(self.min_ts.map_or(ts, |min| min.min(ts)));
+        self.max_ts = Some(self.max_ts.map_or(ts, |max| max.max(ts)));
+
+        if self.records == 0 && !rec.order_flags.contains(OLFlagSet::NEW_SESSION) {
+            self.sessions += 1;
+        }
+        self.records += 1;
+        self.tx_len += 1;
+        self.tx_new_session |= rec.order_flags.contains(OLFlagSet::NEW_SESSION);
+        if ob::tx_end(rec) {
+            self.end_tx();
+        }
+    }
+
+    /// accounts the trailing incomplete transaction, if any
+    pub fn finish(&mut self) {
+        if self.tx_len > 0 {
+            self.end_tx();
+        }
+    }
+
+    fn end_tx(&mut self) {
+        self.transactions += 1;
+        self.max_tx_len = self.max_tx_len.max(self.tx_len);
+        self.sessions += self.tx_new_session as usize;
+        (self.tx_len, self.tx_new_session) = (0, false);
+    }
+}
+
+/// Reads the whole OrderLog file for its [`ParseReport`]
+#[cfg(not(target_arch = "wasm32"))]
+pub fn parse_with_report(path: impl Into<PathBuf>) -> Result<(Header, ParseReport), QshError> {
+    let mut parser = inflate(path.into())?;
+    let header = header(&mut parser)?;
+    let mut report = ParseReport::default();
+    for rec in parser.try_iter::<OrderLogReader>() {
+        report.observe(&rec?);
+    }
+    report.finish();
+    Ok((header, report))
+}
@@ -0,0 +1,207 @@
+//! Book snapshots on the fixed grid of the exchange time
+//!
+//! The grid boundaries are the multiples of the interval since the unix epoch. The snapshot of
+//! a boundary reflects the book strictly before it: the transactions timed exactly at the
+//! boundary are applied after its snapshot. The boundaries up to the first transaction and
+//! after the last one are not emitted.
+//!
+//! [`quotes`] does the same for the quotes stream, on the frame time.
+
+use crate::{
+    orderbook::{BookSnapshot, NopListener, OrderBook},
+    time,
+    types::{Header, OrderLog, Quotes, Timestamp},
+    QshError,
+};
+
+/// Resampled snapshots of the `txs` transactions, see [`snapshots_capped`]
+pub fn snapshots<I>(txs: I, depth: usize, interval_ns: i64) -> Snapshots<I::IntoIter>
+where
+    I: IntoIterator<Item = Vec<OrderLog>>,
+{
+    snapshots_capped(txs, depth, interval_ns, usize::MAX)
+}
+
+/// Replays the `txs` into the fresh book, yields the top `depth` levels(`0` for all) at every
+/// grid boundary of `interval_ns`. Across the quiet periods the book state is repeated for at
+/// most `max_fill` boundaries after the first one, the rest are skipped. Snapshot `ts` is the
+/// boundary time, unix nanos.
+pub fn snapshots_capped<I>(
+    txs: I,
+    depth: usize,
+    interval_ns: i64,
+    max_fill: usize,
+) -> Snapshots<I::IntoIter>
+where
+    I: IntoIterator<Item = Vec<OrderLog>>,
+{
+    assert!(interval_ns > 0, "interval must be positive");
+    Snapshots {
+        txs: txs.into_iter(),
+        book: OrderBook::default(),
+        depth,
+        interval: interval_ns,
+        max_fill,
+        boundary: None,
+        held: None,
+        filled: 0,
+        failed: false,
+    }
+}
+
+pub struct Snapshots<I> {
+    txs: I,
+    book: OrderBook,
+    depth: usize,
+    interval: i64,
+    max_fill: usize,
+    // next grid boundary, unix nanos
+    boundary: Option<i64>,
+    // transaction waiting for the snapshots of the boundaries preceding it
+    held: Option<(i64, Vec<OrderLog>)>,
+    // snapshots emitted for the held transaction
+    filled: usize,
+    failed: bool,
+}
+
+impl<I> Snapshots<I> {
+    #[inline]
+    fn after(&self, t: i64) -> i64 {
+        (t.div_euclid(self.interval) + 1) * self.interval
+    }
+}
+
+impl<I> Snapshots<I>
+where
+    I: Iterator<Item = Vec<OrderLog>>,
+{
+    /// Same as `next` with the levels written into `out`, see [`OrderBook::snapshot_upto_into`],
+    /// the timestamp is the boundary time. The sides shallower than `depth` are zero padded.
+    /// The `0` depth width varies with the book, `next` suits it better.
+    pub fn next_into(&mut self, out: &mut [i64]) -> Option<Result<Timestamp, QshError>> {
+        Some(self.advance()?.and_then(|boundary| {
+            self.book.snapshot_upto_into(self.depth, out)?;
+            Ok(boundary)
+        }))
+    }
+
+    // moves to the next grid boundary, the book is in the state of its snapshot then
+    fn advance(&mut self) -> Option<Result<i64, QshError>> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            if let Some((t, tx)) = self.held.take() {
+                match self.boundary {
+                    Some(boundary) if boundary <= t && self.filled <= self.max_fill => {
+                        self.held = Some((t, tx));
+                        self.boundary = Some(boundary + self.interval);
+                        self.filled += 1;
+                        return Some(Ok(boundary));
+                    }
+                    _ => {
+                        // the boundaries up to `t` are either emitted or over the cap
+                        self.boundary = Some(self.after(t));
+                        self.filled = 0;
+                        if let Err(e) = self.book.apply_tx(&tx, &mut NopListener) {
+                            self.failed = true;
+                            return Some(Err(e));
+                        }
+                    }
+                }
+            }
+
+            let tx = self.txs.next()?;
+            let t = tx.last().map_or(0, |rec| time::ticks_to_unix_ns(rec.timestamp));
+            self.boundary.get_or_insert(self.after(t));
+            self.held = Some((t, tx));
+        }
+    }
+}
+
+impl<I> Iterator for Snapshots<I>
+where
+    I: Iterator<Item = Vec<OrderLog>>,
+{
+    type Item = Result<BookSnapshot, QshError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.advance()?.map(|boundary| {
+            let mut snapshot = self.book.book_snapshot(self.depth);
+            snapshot.ts = boundary;
+            snapshot
+        }))
+    }
+}
+
+/// Last `quotes` of every `bucket_ms` bucket of the frame time, with the bucket closing time,
+/// unix nanos. A bucket `[t, t + bucket_ms)` closes at `t + bucket_ms`, the frame timed exactly
+/// at it falls into the next bucket. The empty buckets repeat the preceding quotes when
+/// `forward_fill`, skipped otherwise. The `removed` levels are of the last frame only.
+pub fn quotes<I>(
+    quotes: I,
+    bucket_ms: i64,
+    header: &Header,
+    forward_fill: bool,
+) -> QuoteBuckets<I::IntoIter>
+where
+    I: IntoIterator<Item = Quotes>,
+{
+    assert!(bucket_ms > 0, "bucket must be positive");
+    let recording_time = header.recording_time_or_err().unwrap_or_else(|e| {
+        log::warn!("{e}, clamped to 0");
+        0
+    });
+    QuoteBuckets {
+        quotes: quotes.into_iter(),
+        bucket: bucket_ms,
+        forward_fill,
+        frame_time: time::recording_time_to_unix_ms(recording_time),
+        last: None,
+        fill: None,
+    }
+}
+
+pub struct QuoteBuckets<I> {
+    quotes: I,
+    bucket: i64,
+    forward_fill: bool,
+    // unix millis
+    frame_time: i64,
+    // latest quotes of the open bucket, with its closing time
+    last: Option<(i64, Quotes)>,
+    // empty buckets closing in `[from, to)` to repeat the quotes for
+    fill: Option<(i64, i64, Quotes)>,
+}
+
+impl<I> Iterator for QuoteBuckets<I>
+where
+    I: Iterator<Item = Quotes>,
+{
+    type Item = (Timestamp, Quotes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some((from, to, quotes)) = self.fill.take() {
+            if from + self.bucket < to {
+                self.fill = Some((from + self.bucket, to, quotes.clone()));
+            }
+            return Some((time::ms_to_ns(from), quotes));
+        }
+        loop {
+            let Some(quotes) = self.quotes.next() else {
+                return self.last.take().map(|(close, quotes)| (time::ms_to_ns(close), quotes));
+            };
+            self.frame_time += quotes.frame_time_delta;
+            let close = (self.frame_time.div_euclid(self.bucket) + 1) * self.bucket;
+            match self.last.replace((close, quotes)) {
+                Some((prev, quotes)) if prev < close => {
+                    if self.forward_fill && prev + self.bucket < close {
+                        self.fill = Some((prev + self.bucket, close, quotes.clone()));
+                    }
+                    return Some((time::ms_to_ns(prev), quotes));
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Trading sessions of the OrderLog stream, see [`split`]
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{header, inflate, OrderLogReader, QshError, QshRead};
+use crate::{
+    orderbook::{new_session_start, OrderLogStream},
+    time,
+    types::{OLFlagSet, OrderLog, Timestamp},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+/// Transactions of the session, see [`split`]
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    /// `0` for the first session of the stream
+    pub index: usize,
+    /// time of the first record, unix nanos
+    pub start_ts: Timestamp,
+    /// time of the last record, unix nanos
+    pub end_ts: Timestamp,
+    pub transactions: Vec<Vec<OrderLog>>,
+}
+
+/// Splits the transactions of the usual pipeline, see [`OrderLogStream::transactions`], by the
+/// `OLFlags::NewSession` records. The NewSession record starts the session, the transaction
+/// having it in the middle is split at it, as `OrderBook::apply_tx` does. The snapshot
+/// transactions right before the NewSession record seed the book of the session they open.
+///
+/// The records before the first NewSession one, if any, make the first session, so the
+/// sessions concatenated are the transactions of the stream.
+pub fn split(iter: impl IntoIterator<Item = OrderLog>) -> impl Iterator<Item = Session> {
+    let mut txs = iter.into_iter().transactions();
+    let (mut carry, mut index) = (vec![], 0);
+    std::iter::from_fn(move || {
+        let mut session: Vec<Vec<OrderLog>> = std::mem::take(&mut carry);
+        for mut tx in txs.by_ref() {
+            let at = new_session_start(&tx);
+            if at == tx.len() {
+                session.push(tx);
+                continue;
+            }
+            let after = tx.split_off(at);
+            if !tx.is_empty() {
+                session.push(tx);
+            }
+            carry = session.split_off(session.len() - seeding(&session));
+            carry.push(after);
+            if !session.is_empty() {
+                index += 1;
+                return Some(new_session(index - 1, session));
+            }
+            session = std::mem::take(&mut carry);
+        }
+        (!session.is_empty()).then(|| {
+            index += 1;
+            new_session(index - 1, session)
+        })
+    })
+}
+
+/// `(start_ts, end_ts)` of the [`split`] sessions of the qsh file, without keeping the
+/// transactions
+#[cfg(not(target_arch = "wasm32"))]
+pub fn session_bounds(path: impl Into<PathBuf>) -> Result<Vec<(Timestamp, Timestamp)>, QshError> {
+    let mut parser = inflate(path.into())?;
+    header(&mut parser)?;
+
+    let (mut bounds, mut err) = (vec![], None);
+    // the current session, the snapshot transactions at its tail apart
+    let (mut session, mut seed) = (None, None);
+    let records =
+        parser.try_iter::<OrderLogReader>().map_while(|rec| rec.map_err(|e| err = Some(e)).ok());
+    for mut tx in records.transactions() {
+        let after = tx.split_off(new_session_start(&tx));
+        observe(&tx, &mut session, &mut seed);
+        if !after.is_empty() {
+            bounds.extend(session.take());
+            observe(&after, &mut session, &mut seed);
+        }
+    }
+    if let Some(e) = err {
+        return Err(e);
+    }
+    bounds.extend(seed.into_iter().fold(session, join));
+    Ok(bounds)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn observe(
+    tx: &[OrderLog],
+    session: &mut Option<(Timestamp, Timestamp)>,
+    seed: &mut Option<(Timestamp, Timestamp)>,
+) {
+    let ts = |r: &OrderLog| time::ticks_to_unix_ns(r.timestamp);
+    let (Some(first), Some(last)) = (tx.first(), tx.last()) else { return };
+    let span = (ts(first), ts(last));
+    if is_snapshot(tx) {
+        *seed = join(*seed, span);
+    } else {
+        *session = seed.take().into_iter().chain([span]).fold(*session, join);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn join(
+    bounds: Option<(Timestamp, Timestamp)>,
+    span: (Timestamp, Timestamp),
+) -> Option<(Timestamp, Timestamp)> {
+    Some(bounds.map_or(span, |(start, _)| (start, span.1)))
+}
+
+fn new_session(index: usize, transactions: Vec<Vec<OrderLog>>) -> Session {
+    let records = || transactions.iter().flatten();
+    let ts = |rec: Option<&OrderLog>| rec.map_or(0, |r| time::ticks_to_unix_ns(r.timestamp));
+    Session { index, start_ts: ts(records().next()), end_ts: ts(records().last()), transactions }
+}
+
+#[inline]
+fn is_snapshot(tx: &[OrderLog]) -> bool {
+    tx.iter().all(|r| r.order_flags.contains(OLFlagSet::SNAPSHOT))
+}
+
+// number of the snapshot transactions at the tail of the session
+fn seeding(session: &[Vec<OrderLog>]) -> usize {
+    session.iter().rev().take_while(|tx| is_snapshot(tx)).count()
+}
@@ -0,0 +1,233 @@
+//! One-call summary of the qsh file of any stream, e.g. for the catalogue, see [`file_stats`]
+//! and [`time_span`]
+
+use super::report::ParseReport;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    header, inflate,
+    orderbook::{OrderBook, OrderLogStream, Strictness},
+    source::record_source,
+    time::FrameClock,
+    types::Stream,
+    AuxInfoReader, DealReader, OrderLogReader, QshError, QshRead, QuotesReader,
+};
+use crate::{
+    time,
+    types::{AuxInfo, Deal, OLMsgType, OrderLog, Quotes, Side, Timestamp, Volume, UID},
+};
+use std::collections::HashSet;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+/// Summary of the file, the fields not applicable to its stream are left zeroed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileStats {
+    /// `Stream` of the header, e.g. `ORDERLOG`
+    pub stream: String,
+    pub instrument: String,
+    pub records: usize,
+    /// OrderLog only, see [`ParseReport`]
+    pub transactions: usize,
+    /// OrderLog only, see [`ParseReport`]
+    pub sessions: usize,
+    /// Exchange time of the earliest and the latest records, unix nanos, the records lacking it
+    /// skipped. The frame time for the Quotes, as they have no exchange time.
+    pub first_ts: Option<Timestamp>,
+    pub last_ts: Option<Timestamp>,
+    /// volume of the deals, of the Fill records counted once per deal for the OrderLog
+    pub volume: Volume,
+    /// sum of the deal price by the amount, price steps
+    pub notional: i128,
+    /// `order_id`s of the Add records, of the deals for the Deals stream
+    pub unique_orders: usize,
+    /// the most levels of the side seen, of the replayed book for the OrderLog
+    pub max_depth: usize,
+    /// records per `OLMsgType`, OrderLog only
+    pub adds: usize,
+    pub fills: usize,
+    pub cancels: usize,
+    pub removes: usize,
+    pub unknown: usize,
+}
+
+/// Accumulates the [`FileStats`] of the records, see [`file_stats`]
+#[derive(Debug, Default)]
+pub struct StatsBuilder {
+    stats: FileStats,
+    report: ParseReport,
+    orders: HashSet<UID>,
+    // deals of the current transaction, the two Fill records of the deal are within it
+    tx_deals: HashSet<UID>,
+}
+
+impl StatsBuilder {
+    pub fn new(stream: impl Into<String>, instrument: impl Into<String>) -> Self {
+        let stats = FileStats {
+            stream: stream.into(),
+            instrument: instrument.into(),
+            ..Default::default()
+        };
+        Self { stats, ..Default::default() }
+    }
+
+    pub fn observe_orderlog(&mut self, rec: &OrderLog) {
+        self.report.observe(rec);
+        match rec.event {
+            OLMsgType::Add => {
+                self.stats.adds += 1;
+                self.orders.insert(rec.order_id);
+            }
+            OLMsgType::Fill => {
+                self.stats.fills += 1;
+                if self.tx_deals.insert(rec.deal_id) {
+                    self.deal(rec.deal_price, rec.amount);
+                }
+            }
+            OLMsgType::Cancel => self.stats.cancels += 1,
+            OLMsgType::Remove => self.stats.removes += 1,
+            OLMsgType::UNKNOWN => self.stats.unknown += 1,
+        }
+        if crate::orderbook::tx_end(rec) {
+            self.tx_deals.clear();
+        }
+    }
+
+    /// the book depth after the transaction, OrderLog only
+    pub fn observe_depth(&mut self, bids: usize, asks: usize) {
+        self.stats.max_depth = self.stats.max_depth.max(bids).max(asks);
+    }
+
+    pub fn observe_deal(&mut self, deal: &Deal) {
+        self.stats.records += 1;
+        self.observe_ts(deal.timestamp);
+        self.deal(deal.price, deal.amount);
+        if deal.order_id != 0 {
+            self.orders.insert(deal.order_id);
+        }
+    }
+
+    /// `frame_time` is unix nanos
+    pub fn observe_quotes(&mut self, frame_time: Timestamp, quotes: &Quotes) {
+        self.stats.records += 1;
+        let ts = Some(frame_time);
+        self.stats.first_ts = self.stats.first_ts.min(ts).or(ts);
+        self.stats.last_ts = self.stats.last_ts.max(ts);
+        self.observe_depth(quotes.bid.len(), quotes.ask.len());
+    }
+
+    pub fn observe_aux(&mut self, aux: &AuxInfo) {
+        self.stats.records += 1;
+        self.observe_ts(aux.timestamp);
+    }
+
+    pub fn finish(mut self) -> FileStats {
+        if self.report.records > 0 {
+            self.report.finish();
+            self.stats.records = self.report.records;
+            self.stats.transactions = self.report.transactions;
+            self.stats.sessions = self.report.sessions;
+            (self.stats.first_ts, self.stats.last_ts) = (self.report.min_ts, self.report.max_ts);
+        }
+        self.stats.unique_orders = self.orders.len();
+        self.stats
+    }
+
+    fn deal(&mut self, price: i64, amount: Volume) {
+        self.stats.volume += amount;
+        self.stats.notional += price as i128 * amount as i128;
+    }
+
+    // exchange time in ticks, `0` if absent
+    fn observe_ts(&mut self, timestamp: Timestamp) {
+        if timestamp != 0 {
+            let ts = Some(time::ticks_to_unix_ns(timestamp));
+            self.stats.first_ts = self.stats.first_ts.min(ts).or(ts);
+            self.stats.last_ts = self.stats.last_ts.max(ts);
+        }
+    }
+}
+
+impl std::fmt::Display for FileStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ts = |ts: Option<Timestamp>| ts.map_or("-".to_string(), |ts| ts.to_string());
+        writeln!(f, "{} {}", self.stream, self.instrument)?;
+        writeln!(
+            f,
+            "records: {}, transactions: {}, sessions: {}",
+            self.records, self.transactions, self.sessions
+        )?;
+        writeln!(f, "time: {} - {}", ts(self.first_ts), ts(self.last_ts))?;
+        writeln!(f, "volume: {}, notional: {}", self.volume, self.notional)?;
+        writeln!(f, "unique orders: {}, max depth: {}", self.unique_orders, self.max_depth)?;
+        write!(
+            f,
+            "adds: {}, fills: {}, cancels: {}, removes: {}, unknown: {}",
+            self.adds, self.fills, self.cancels, self.removes, self.unknown
+        )
+    }
+}
+
+/// Reads the whole file for its [`FileStats`], the fields are picked by the stream of the
+/// header. The OrderLog book is replayed `Lenient`, see [`Strictness`], for the depth.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn file_stats(path: impl Into<PathBuf>) -> Result<FileStats, QshError> {
+    let mut parser = inflate(path.into())?;
+    let header = header(&mut parser)?;
+    let mut stats = StatsBuilder::new(format!("{:?}", header.stream), header.instrument.as_str());
+    match header.stream {
+        Stream::ORDERLOG => {
+            let mut err = None;
+            let records = parser
+                .try_iter::<OrderLogReader>()
+                .map_while(|rec| rec.map_err(|e| err = Some(e)).ok())
+                .inspect(|rec| stats.observe_orderlog(rec));
+            let mut book = OrderBook::default();
+            book.set_policy(Strictness::Lenient);
+            let mut depth = (0, 0);
+            for tx in records.transactions() {
+                book.apply_tx(&tx, &mut crate::orderbook::NopListener)?;
+                depth = (depth.0.max(book.depth(Side::Buy)), depth.1.max(book.depth(Side::Sell)));
+            }
+            if let Some(e) = err {
+                return Err(e);
+            }
+            stats.observe_depth(depth.0, depth.1);
+        }
+        Stream::DEALS => {
+            for deal in parser.try_iter::<DealReader>() {
+                stats.observe_deal(&deal?);
+            }
+        }
+        Stream::QUOTES => {
+            let mut clock = FrameClock::new(header.recording_time_or_err()?);
+            for quotes in parser.try_iter::<QuotesReader>() {
+                let quotes = quotes?;
+                stats.observe_quotes(clock.advance(quotes.frame_time_delta), &quotes);
+            }
+        }
+        Stream::AUXINFO => {
+            for aux in parser.try_iter::<AuxInfoReader>() {
+                stats.observe_aux(&aux?);
+            }
+        }
+        other => return Err(QshError::Parsing(format!("no stats of the {other:?} stream"))),
+    }
+    Ok(stats.finish())
+}
+
+/// Frame time of the header `recording_time` and of the last record of the file, unix nanos,
+/// both on the millisecond grid of the `frame_time_delta`s. The format has no trailer, so the
+/// records are parsed to sum the deltas, though nothing is built of them. The span of the file
+/// of no records is empty, `(start, start)`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn time_span(path: impl Into<PathBuf>) -> Result<(Timestamp, Timestamp), QshError> {
+    let mut parser = inflate(path.into())?;
+    let header = header(&mut parser)?;
+    let mut clock = FrameClock::new(header.recording_time_or_err()?);
+    let start = clock.now();
+    for rec in record_source(header.stream, parser)? {
+        clock.advance(rec?.frame_time_delta());
+    }
+    Ok((start, clock.now()))
+}
@@ -0,0 +1,136 @@
+//! Trade tape of the OrderLog stream, the Deals stream substitute, see [`from_orderlog`]
+
+use super::moex2conv::{chunk_transaction, TxChunk};
+use crate::{
+    orderbook::{new_session_start, NopListener, OrderBook, OrderLogStream, Quote},
+    time,
+    types::{OrderLog, Price, Side, Timestamp, Volume, UID},
+    QshError,
+};
+use std::collections::VecDeque;
+
+/// Deal of the two Fill records of the same `deal_id`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    /// unix nanos
+    pub ts: Timestamp,
+    pub deal_id: UID,
+    pub price: Price,
+    /// volume of the deal, counted once for both of the legs
+    pub amount: Volume,
+    /// side of the taker, `UNKNOWN` if neither leg is the order added in the transaction
+    pub aggressor: Side,
+    /// resting order, `None` if its Fill record is absent or the roles are unknown
+    pub maker_order_id: Option<UID>,
+    /// order added in the transaction of the deal, `None` if its Fill record is absent or the
+    /// roles are unknown
+    pub taker_order_id: Option<UID>,
+}
+
+/// Reconstructs the deals of the Fill records, one per `deal_id`.
+///
+/// The legs are paired within the transaction, see [`chunk_transaction`]: the taker is the leg
+/// of the order added in the same transaction, the later added one if both are. The lone leg is
+/// the taker if its order is added in the transaction, the maker otherwise, e.g. of the order
+/// restored from the snapshot records before the session. The removes, CrossTrade ones
+/// included, bring no deals.
+pub fn from_orderlog(iter: impl IntoIterator<Item = OrderLog>) -> impl Iterator<Item = Trade> {
+    iter.into_iter().transactions().flat_map(tx_trades)
+}
+
+/// Fill record with the best bid and ask of the book prevailing as its transaction came, see
+/// [`zip_book`]
+pub type BookedFill = (OrderLog, Option<Quote>, Option<Quote>);
+
+/// Replays the `txs` into the fresh book, pairs every Fill record with the top of the book
+/// before its transaction, for the trade-through and slippage analysis.
+///
+/// The book is of before the transaction rather than right before the Fill record: within the
+/// transaction the aggressor order is added first and the fills take the resting orders one by
+/// one, so the book right before the later fills is crossed by the aggressor or short of the
+/// levels it has taken. The book after the transaction is past the trade. The Fill records of
+/// the transaction opening the session come with no quotes as the book is rebuilt by it. The
+/// iteration stops after the book error.
+pub fn zip_book<I>(txs: I) -> impl Iterator<Item = Result<BookedFill, QshError>>
+where
+    I: IntoIterator<Item = Vec<OrderLog>>,
+{
+    let (mut txs, mut book) = (txs.into_iter(), OrderBook::default());
+    let (mut pending, mut failed) = (VecDeque::new(), false);
+    std::iter::from_fn(move || loop {
+        if let Some(fill) = pending.pop_front() {
+            return Some(Ok(fill));
+        }
+        if failed {
+            return None;
+        }
+        let tx = txs.next()?;
+        let top = (book.best_bid(), book.best_ask());
+        if let Err(e) = book.apply_tx(&tx, &mut NopListener) {
+            failed = true;
+            return Some(Err(e));
+        }
+        let session = new_session_start(&tx);
+        for (i, rec) in tx.into_iter().enumerate() {
+            if rec.as_trade().is_some() {
+                let (bid, ask) = if i < session { top } else { (None, None) };
+                pending.push_back((rec, bid, ask));
+            }
+        }
+    })
+}
+
+// deals of the transaction, see `from_orderlog`
+pub(crate) fn tx_trades(tx: Vec<OrderLog>) -> Vec<Trade> {
+    let fills: Vec<_> = tx.iter().filter(|rec| rec.as_trade().is_some()).copied().collect();
+    let chunks = chunk_transaction(tx).unwrap_or_else(|err| {
+        log::warn!("the deal roles of the transaction are unknown, {err}");
+        vec![TxChunk::Match { orders: vec![], fills }]
+    });
+
+    let mut trades = vec![];
+    for chunk in chunks {
+        if let TxChunk::Match { orders, fills } = chunk {
+            match_trades(&orders, &fills, &mut trades);
+        }
+    }
+    trades
+}
+
+fn match_trades(orders: &[OrderLog], fills: &[OrderLog], out: &mut Vec<Trade>) {
+    let start = out.len();
+    // the index of the order added in the transaction
+    let added = |leg: &OrderLog| orders.iter().position(|o| o.order_id == leg.order_id);
+    for (i, leg) in fills.iter().enumerate() {
+        let Some(deal) = leg.as_trade() else { continue };
+        if out[start..].iter().any(|t| t.deal_id == deal.deal_id) {
+            continue;
+        }
+        let other =
+            fills[i + 1..].iter().find(|r| r.as_trade().is_some_and(|d| d.deal_id == deal.deal_id));
+        let (taker, maker) = match other {
+            Some(other) => match (added(leg), added(other)) {
+                (Some(a), Some(b)) if a > b => (Some(leg), Some(other)),
+                (Some(_), Some(_)) | (None, Some(_)) => (Some(other), Some(leg)),
+                (Some(_), None) => (Some(leg), Some(other)),
+                (None, None) => (None, None),
+            },
+            None if added(leg).is_some() => (Some(leg), None),
+            None => (None, Some(leg)),
+        };
+        let aggressor = match (taker, maker) {
+            (Some(taker), _) => taker.side,
+            (None, Some(maker)) => maker.side.opposite(),
+            (None, None) => Side::UNKNOWN,
+        };
+        out.push(Trade {
+            ts: time::ticks_to_unix_ns(leg.timestamp),
+            deal_id: deal.deal_id,
+            price: deal.deal_price,
+            amount: deal.amount,
+            aggressor,
+            maker_order_id: maker.map(|r| r.order_id),
+            taker_order_id: taker.map(|r| r.order_id),
+        });
+    }
+}
@@ -0,0 +1,156 @@
+//! Replay of the OrderLog by the lenient book, accounting the records inconsistent with it, see
+//! [`book_replay`]
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{header, inflate, OrderLogReader, QshError, QshRead};
+use crate::{
+    orderbook::{self as ob, Anomalies, NopListener, OrderBook, OrderLogStream, Quote, Strictness},
+    time,
+    types::{OrderLog, Timestamp, UID},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+/// number of the anomalies kept in the report by [`book_replay`]
+pub const MAX_ANOMALIES: usize = 100;
+
+/// The way the record is inconsistent with the book, see [`Anomalies`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnomalyKind {
+    /// cancel or fill at the price level the book doesn't have
+    MissingLevel,
+    /// cancel or fill of the order absent at its level
+    UnknownOrder,
+    /// cancel or fill over the order amount
+    VolumeMismatch,
+    /// cancel or fill taken off the synthetic order of the level
+    Synthetic,
+    /// record the lenient book fails on, the error message
+    Rejected(String),
+}
+
+/// Record inconsistent with the book
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookAnomaly {
+    pub kind: AnomalyKind,
+    /// number of the transaction, counting from 0
+    pub tx_index: usize,
+    /// exchange time of the record, unix nanos
+    pub ts: Timestamp,
+    pub order_id: UID,
+    /// top of the book once the record is applied or skipped
+    pub best_bid: Option<Quote>,
+    pub best_ask: Option<Quote>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookReplayReport {
+    pub records: usize,
+    pub transactions: usize,
+    /// number of the anomalies, including those not kept
+    pub total: usize,
+    /// the first `limit` anomalies
+    pub anomalies: Vec<BookAnomaly>,
+}
+
+impl BookReplayReport {
+    pub fn is_clean(&self) -> bool {
+        self.total == 0
+    }
+}
+
+impl std::fmt::Display for BookReplayReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "records: {}, transactions: {}, anomalies: {}",
+            self.records, self.transactions, self.total
+        )?;
+        for a in &self.anomalies {
+            writeln!(
+                f,
+                "tx #{} at {}, order {}: {:?}, bid {:?}, ask {:?}",
+                a.tx_index, a.ts, a.order_id, a.kind, a.best_bid, a.best_ask
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays the OrderLog file by the `Lenient` book and reports the first [`MAX_ANOMALIES`]
+/// records inconsistent with it
+#[cfg(not(target_arch = "wasm32"))]
+pub fn book_replay(path: impl Into<PathBuf>) -> Result<BookReplayReport, QshError> {
+    book_replay_with(path, MAX_ANOMALIES)
+}
+
+/// Same as [`book_replay`], keeping the first `limit` anomalies
+#[cfg(not(target_arch = "wasm32"))]
+pub fn book_replay_with(
+    path: impl Into<PathBuf>,
+    limit: usize,
+) -> Result<BookReplayReport, QshError> {
+    let mut parser = inflate(path.into())?;
+    header(&mut parser)?;
+    let mut err = None;
+    let records =
+        parser.try_iter::<OrderLogReader>().map_while(|rec| rec.map_err(|e| err = Some(e)).ok());
+    let report = replay_records(records, limit);
+    match err {
+        Some(e) => Err(e),
+        None => Ok(report),
+    }
+}
+
+/// Same as [`book_replay`] over the parsed records, keeping the first `limit` anomalies. The
+/// records are applied one by one, so that the anomaly points at the offending record.
+pub fn replay_records(records: impl Iterator<Item = OrderLog>, limit: usize) -> BookReplayReport {
+    let mut book = OrderBook::default();
+    book.set_policy(Strictness::Lenient);
+    let mut report = BookReplayReport::default();
+    for (tx_index, tx) in records.transactions().enumerate() {
+        let new_session = ob::new_session_start(&tx);
+        for (i, &rec) in tx.iter().enumerate() {
+            if i == new_session {
+                book.clear();
+            }
+            let before = book.anomalies().clone();
+            let kinds = match book.apply(rec, &mut NopListener) {
+                Ok(()) => tolerated(&before, book.anomalies()),
+                Err(e) => vec![AnomalyKind::Rejected(e.to_string())],
+            };
+            for kind in kinds {
+                report.total += 1;
+                if report.anomalies.len() < limit {
+                    report.anomalies.push(BookAnomaly {
+                        kind,
+                        tx_index,
+                        ts: time::ticks_to_unix_ns(rec.timestamp),
+                        order_id: rec.order_id,
+                        best_bid: book.best_bid(),
+                        best_ask: book.best_ask(),
+                    });
+                }
+            }
+        }
+        report.records += tx.len();
+        report.transactions += 1;
+    }
+    report
+}
+
+// kinds of the anomalies counted by the book since `before`
+fn tolerated(before: &Anomalies, after: &Anomalies) -> Vec<AnomalyKind> {
+    [
+        (after.missing_level - before.missing_level, AnomalyKind::MissingLevel),
+        (after.unknown_order - before.unknown_order, AnomalyKind::UnknownOrder),
+        (after.volume_mismatch - before.volume_mismatch, AnomalyKind::VolumeMismatch),
+        (after.synthetic - before.synthetic, AnomalyKind::Synthetic),
+    ]
+    .into_iter()
+    .flat_map(|(n, kind)| std::iter::repeat_n(kind, n))
+    .collect()
+}
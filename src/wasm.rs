@@ -0,0 +1,50 @@
+//! `wasm-bindgen` bindings for parsing the qsh bytes in the browser, e.g. a `File` read into an
+//! `Uint8Array`. No filesystem access, everything goes through [`inflate_reader`].
+
+use crate::{header, inflate_reader, source::record_source, time, types::Header, QshError};
+use wasm_bindgen::prelude::*;
+
+/// [`Header`] of the qsh file, `recording_time` in unix nanoseconds
+#[wasm_bindgen(getter_with_clone)]
+pub struct QshHeader {
+    pub recording_time: i64,
+    pub version: u8,
+    pub stream: String,
+    pub instrument: String,
+    pub recorder: String,
+    pub comment: String,
+}
+
+impl From<Header> for QshHeader {
+    fn from(h: Header) -> Self {
+        Self {
+            recording_time: time::recording_time_to_unix_ns(h.recording_time),
+            version: h.version,
+            stream: format!("{:?}", h.stream),
+            instrument: h.instrument,
+            recorder: h.recorder,
+            comment: h.comment,
+        }
+    }
+}
+
+/// Reads the header of the gzipped qsh bytes
+#[wasm_bindgen]
+pub fn parse_header(bytes: &[u8]) -> Result<QshHeader, JsError> {
+    header(&mut inflate_reader(bytes)).map(QshHeader::from).map_err(js_error)
+}
+
+/// Number of the records in the gzipped qsh bytes, the stream is taken from the header
+#[wasm_bindgen]
+pub fn record_count(bytes: &[u8]) -> Result<usize, JsError> {
+    let mut parser = inflate_reader(bytes);
+    let stream = header(&mut parser).map_err(js_error)?.stream;
+    record_source(stream, parser)
+        .and_then(|mut records| records.try_fold(0, |n, rec| rec.map(|_| n + 1)))
+        .map_err(js_error)
+}
+
+// Debug, as the io errors have no message of their own
+fn js_error(err: QshError) -> JsError {
+    JsError::new(&format!("{err:?}"))
+}
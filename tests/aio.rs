@@ -0,0 +1,27 @@
+#![cfg(feature = "tokio")]
+mod common;
+
+use common::*;
+use futures_core::Stream;
+use qsh_rs::{aio::inflate_async, types::Side, OrderLogReader};
+use std::{future::poll_fn, io::Cursor, pin::Pin};
+
+#[tokio::test]
+async fn records() {
+    let records: Vec<_> = Workload::new(3, 10).take(10_000).collect();
+    let mut enc = Encoder::new(0x70, 637200251900000000);
+    records.iter().for_each(|r| enc.orderlog(1, r));
+
+    let (header, mut stream) =
+        inflate_async::<OrderLogReader, _>(Cursor::new(enc.gzip())).await.unwrap();
+    assert_eq!(header.instrument, "Plaza2:Si-3.20::1252209:1");
+
+    let mut n = 0;
+    while let Some(rec) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        let rec = rec.unwrap();
+        assert_eq!((rec.order_id, rec.price), (records[n].order_id, records[n].price));
+        assert_ne!(rec.side, Side::UNKNOWN);
+        n += 1;
+    }
+    assert_eq!(n, records.len());
+}
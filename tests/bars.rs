@@ -0,0 +1,54 @@
+mod common;
+
+use common::{timed_deals as deals, S};
+use qsh_rs::utils::bars::{bars, bars_with_gap, Bar, Period, SESSION_GAP_NS};
+
+#[test]
+fn time_bars() {
+    let bars: Vec<_> = bars(deals(), Period::Time(60 * S)).collect();
+    let bar = |start_ts, open, high, low, close, volume, trades, turnover| Bar {
+        start_ts,
+        open,
+        high,
+        low,
+        close,
+        volume,
+        trades,
+        turnover,
+    };
+    assert_eq!(
+        bars,
+        [
+            bar(0, 100, 103, 99, 99, 6, 3, 603),
+            bar(60 * S, 101, 101, 101, 101, 1, 1, 101),
+            bar(120 * S, 102, 102, 102, 102, 5, 1, 510),
+            bar(300 * S, 110, 111, 110, 111, 2, 2, 221),
+        ]
+    );
+    // the session gap within the period
+    let bars: Vec<_> = bars_with_gap(deals(), Period::Time(60 * S), 30 * S).collect();
+    assert_eq!(
+        bars[..2],
+        [bar(0, 100, 103, 100, 103, 3, 2, 306), bar(0, 99, 99, 99, 99, 3, 1, 297)]
+    );
+    assert_eq!(bars.len(), 5);
+}
+
+#[test]
+fn volume_and_tick_bars() {
+    let volumes: Vec<_> = bars(deals(), Period::Volume(3)).map(|b| (b.volume, b.trades)).collect();
+    // the session gap closes the bar short of the volume
+    assert_eq!(volumes, [(3, 2), (3, 1), (6, 2), (2, 2)]);
+
+    let ticks: Vec<_> = bars(deals(), Period::Ticks(2)).map(|b| (b.start_ts, b.close)).collect();
+    assert_eq!(
+        ticks,
+        [(10 * S, 103), (59 * S, 101), (150 * S, 102), (150 * S + SESSION_GAP_NS + 1, 111)]
+    );
+
+    // a bar per deal, the one following the gap too
+    assert_eq!(bars(deals(), Period::Ticks(1)).count(), 7);
+    let trades: Vec<_> =
+        bars_with_gap(deals(), Period::Ticks(3), 30 * S).map(|b| b.trades).collect();
+    assert_eq!(trades, [2, 2, 1, 2]);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::*;
+use qsh_rs::types::{OLFlags, Side};
+use qsh_rs::utils::bbo::{quotes, stream, BboTick};
+use qsh_rs::{header, time, QshRead, QuotesReader};
+
+const TS: i64 = 63_800_000_000_000;
+
+fn tick(dt: i64, bid: Option<(i64, i64)>, ask: Option<(i64, i64)>) -> BboTick {
+    BboTick {
+        ts: time::ticks_to_unix_ns(TS + dt),
+        bid_px: bid.map(|q| q.0),
+        bid_sz: bid.map_or(0, |q| q.1),
+        ask_px: ask.map(|q| q.0),
+        ask_sz: ask.map_or(0, |q| q.1),
+    }
+}
+
+#[test]
+fn orderlog_ticks() {
+    let txs = [
+        (0, add(1, Side::Buy, 100, 5)),
+        (1, add(2, Side::Sell, 102, 5)),
+        // behind the top
+        (2, add(3, Side::Buy, 99, 1)),
+        (3, add(4, Side::Sell, 102, 2)),
+        (4, cancel(1, Side::Buy, 100, 0)),
+        (5, cancel(3, Side::Buy, 99, 0)),
+        (6, with(add(5, Side::Sell, 103, 1), OLFlags::NewSession)),
+    ]
+    .map(|(dt, rec)| vec![at(rec, TS + dt)]);
+    let ticks: Vec<_> = stream(txs).map(Result::unwrap).collect();
+    assert_eq!(
+        ticks,
+        [
+            tick(0, Some((100, 5)), None),
+            tick(1, Some((100, 5)), Some((102, 5))),
+            tick(3, Some((100, 5)), Some((102, 7))),
+            tick(4, Some((99, 1)), Some((102, 7))),
+            tick(5, None, Some((102, 7))),
+            tick(6, None, Some((103, 1))),
+        ]
+    );
+    assert_eq!(ticks[4].bid(), None);
+    assert_eq!(ticks[5].ask(), Some((103, 1)));
+
+    // the book error stops the stream
+    let mut ticks = stream([vec![at(fill(9, Side::Buy, 100, 1, 0), TS)]]);
+    assert!(ticks.next().unwrap().is_err());
+    assert!(ticks.next().is_none());
+}
+
+#[test]
+fn quote_ticks() {
+    let t0 = 637200251900000000;
+    let mut enc = Encoder::new(0x10, t0);
+    enc.quotes(0, &[(100, -5), (102, 3)]);
+    // behind the top
+    enc.quotes(5, &[(99, -1), (104, 2)]);
+    enc.quotes(5, &[(101, -2)]);
+    enc.quotes(5, &[(102, 0)]);
+    enc.quotes(5, &[(99, 0), (100, 0), (101, 0)]);
+    let mut rd = reader(&enc.buf);
+    let hdr = header(&mut rd).unwrap();
+
+    let frame = |ms: i64| time::recording_time_to_unix_ms(t0) * 1_000_000 + ms * 1_000_000;
+    let ticks: Vec<_> =
+        quotes(rd.into_iter::<QuotesReader>(), &hdr).map(|t| (t.ts, t.bid(), t.ask())).collect();
+    assert_eq!(
+        ticks,
+        [
+            (frame(0), Some((100, 5)), Some((102, 3))),
+            (frame(10), Some((101, 2)), Some((102, 3))),
+            (frame(15), Some((101, 2)), Some((104, 2))),
+            (frame(20), None, Some((104, 2))),
+        ]
+    );
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::{timed_deal, timed_deals as deals, S};
+use qsh_rs::utils::candles::{time_bars, time_bars_with, Candle, EmptyBars};
+
+fn vwaps(candles: &[Candle]) -> Vec<(i64, f64)> {
+    candles.iter().map(|c| (c.bar.start_ts / S, c.vwap)).collect()
+}
+
+#[test]
+fn skip_empty() {
+    let candles: Vec<_> = time_bars(deals(), 60 * S).collect();
+    assert_eq!(vwaps(&candles), [(0, 100.5), (60, 101.), (120, 102.), (300, 110.5)]);
+    assert_eq!(candles.iter().map(|c| c.bar.volume).sum::<i64>(), 14);
+    assert_eq!(time_bars(vec![], S).next(), None);
+}
+
+#[test]
+fn forward_fill() {
+    let candles: Vec<_> = time_bars_with(deals(), 30 * S, EmptyBars::ForwardFill).collect();
+    // the session gap isn't filled
+    assert_eq!(
+        vwaps(&candles),
+        [(0, 102.), (30, 99.), (60, 101.), (90, 101.), (120, 101.), (150, 102.), (330, 110.5)]
+    );
+    let empty = candles[3].bar;
+    assert_eq!((empty.open, empty.high, empty.low, empty.close), (101, 101, 101, 101));
+    assert_eq!((empty.volume, empty.trades, candles[4].bar.volume), (0, 0, 0));
+    assert_eq!(candles.iter().map(|c| c.bar.volume).sum::<i64>(), 14);
+
+    // the overnight break of the one second bars
+    let night = vec![timed_deal(0, 100, 1), timed_deal(16 * 3600 * S, 101, 1)];
+    assert_eq!(time_bars_with(night, S, EmptyBars::ForwardFill).count(), 2);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::*;
+use qsh_rs::orderbook::{self as ob, NopListener, OrderBook, PartitionBy};
+use qsh_rs::types::{OLFlags, OrderLog, Price, Side, Volume};
+use qsh_rs::utils::checkpoint;
+
+// unix time of the records is the same as of the workload
+const UNIX_EPOCH: i64 = 62135596800000;
+
+fn records() -> Vec<OrderLog> {
+    Workload::new(13, 30)
+        .take(20_000)
+        .enumerate()
+        .map(|(i, r)| {
+            let r = at(r, UNIX_EPOCH + r.timestamp);
+            if i % 3 != 1 {
+                with(r, OLFlags::TxEnd)
+            } else {
+                r
+            }
+        })
+        .collect()
+}
+
+type State = Vec<Vec<(Price, Volume, usize)>>;
+
+fn state(book: &OrderBook) -> State {
+    Side::both()
+        .map(|side| book.iter_levels(side).map(|(p, v, o)| (*p, *v, o.len())).collect())
+        .to_vec()
+}
+
+// book states after every transaction
+fn replay(book: &mut OrderBook, records: impl Iterator<Item = OrderLog>) -> Vec<State> {
+    records
+        .filter(ob::system_record)
+        .partition_by(ob::tx_end)
+        .filter(ob::fiok_with_trades)
+        .map(|tx| {
+            book.apply_tx(&tx, &mut NopListener).unwrap();
+            state(book)
+        })
+        .collect()
+}
+
+#[test]
+fn resume() {
+    let records = records();
+    let full = replay(&mut OrderBook::default(), records.iter().copied());
+
+    let unix_ns = |rec: &OrderLog| (rec.timestamp - UNIX_EPOCH) * 1_000_000;
+    let until = unix_ns(&records[records.len() / 2]);
+    let mut buf = vec![];
+    let saved = checkpoint::save(records.iter().copied(), until, &mut buf).unwrap();
+
+    let (mut book, skip) = checkpoint::load(&buf[..]).unwrap();
+    assert_eq!(state(&book), state(&saved));
+    assert_eq!(book.len(), saved.len());
+    assert!(unix_ns(&records[skip]) > until);
+    assert!(ob::tx_end(&records[skip - 1]));
+
+    let resumed = replay(&mut book, records.iter().copied().skip(skip));
+    assert!(!resumed.is_empty());
+    assert_eq!(resumed[..], full[full.len() - resumed.len()..]);
+}
+
+#[test]
+fn invalid_checkpoint() {
+    assert!(OrderBook::load(&b"QSHX\x01"[..]).is_err());
+    assert!(OrderBook::load(&b"QSHB\x02"[..]).is_err());
+
+    let mut buf = vec![];
+    OrderBook::default().save(&mut buf).unwrap();
+    assert!(OrderBook::load(&buf[..]).unwrap().is_empty());
+}
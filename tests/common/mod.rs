@@ -0,0 +1,258 @@
+#![allow(dead_code)]
+use qsh_rs::types::{Deal, OLFlags, OLMsgType, OrderLog, OrderType, Price, Side, Volume, UID};
+use qsh_rs::utils::bars::SESSION_GAP_NS;
+use qsh_rs::TimedDeal;
+use std::io::Write;
+
+fn side_flag(side: Side) -> u16 {
+    match side {
+        Side::Buy => OLFlags::Buy as u16,
+        Side::Sell => OLFlags::Sell as u16,
+        Side::UNKNOWN => 0,
+    }
+}
+
+fn record(order_flags: u16, id: UID, side: Side, price: Price, amount: Volume) -> OrderLog {
+    let mut rec = OrderLog {
+        order_id: id,
+        side,
+        price,
+        amount,
+        order_flags: (order_flags | side_flag(side)).into(),
+        ..Default::default()
+    };
+    rec.type_ = OrderType::from(rec.order_flags);
+    rec
+}
+
+fn finish(mut rec: OrderLog) -> OrderLog {
+    rec.event = OLMsgType::from(&rec);
+    rec
+}
+
+pub fn add(id: UID, side: Side, price: Price, amount: Volume) -> OrderLog {
+    let mut rec = record(OLFlags::Add as u16 | OLFlags::Quote as u16, id, side, price, amount);
+    rec.amount_rest = amount;
+    finish(rec)
+}
+
+pub fn fill(id: UID, side: Side, price: Price, amount: Volume, rest: Volume) -> OrderLog {
+    let mut rec = record(OLFlags::Fill as u16 | OLFlags::Quote as u16, id, side, price, amount);
+    rec.amount_rest = rest;
+    finish(rec)
+}
+
+pub fn cancel(id: UID, side: Side, price: Price, rest: Volume) -> OrderLog {
+    let mut rec = record(OLFlags::Canceled as u16 | OLFlags::Quote as u16, id, side, price, 0);
+    rec.amount_rest = rest;
+    finish(rec)
+}
+
+/// removal of the rest of the order, e.g. the killed IOK/FOK or by CrossTrade
+pub fn remove(id: UID, side: Side, price: Price) -> OrderLog {
+    finish(record(OLFlags::Quote as u16, id, side, price, 0))
+}
+
+pub fn at(mut rec: OrderLog, timestamp: i64) -> OrderLog {
+    rec.timestamp = timestamp;
+    rec
+}
+
+pub fn with(mut rec: OrderLog, flag: OLFlags) -> OrderLog {
+    rec.order_flags |= flag.into();
+    finish(rec)
+}
+
+/// deterministic stream of valid add/cancel/fill records over `levels` price levels per side
+pub struct Workload {
+    state: u64,
+    levels: i64,
+    next_id: UID,
+    timestamp: i64,
+    live: Vec<(UID, Side, Price, Volume)>,
+    full_cancels: bool,
+}
+
+impl Workload {
+    pub fn new(seed: u64, levels: i64) -> Self {
+        Self { state: seed, levels, next_id: 1, timestamp: 0, live: vec![], full_cancels: false }
+    }
+
+    /// cancels remove the whole order, as QSH stream keeps no rest of the canceled orders
+    pub fn full_cancels(self) -> Self {
+        Self { full_cancels: true, ..self }
+    }
+
+    fn rand(&mut self, n: u64) -> u64 {
+        self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.state >> 33) % n
+    }
+}
+
+impl Iterator for Workload {
+    type Item = OrderLog;
+
+    fn next(&mut self) -> Option<OrderLog> {
+        self.timestamp += self.rand(3) as i64;
+        let rec = if self.live.len() < 10 || self.rand(100) < 50 {
+            let side = if self.rand(2) == 0 { Side::Buy } else { Side::Sell };
+            let offset = self.rand(self.levels as u64) as i64;
+            let price = if side == Side::Buy { 1000 - offset } else { 1001 + offset };
+            let amount = 1 + self.rand(10) as i64;
+            let id = self.next_id;
+            self.next_id += 1;
+            self.live.push((id, side, price, amount));
+            add(id, side, price, amount)
+        } else {
+            let ix = self.rand(self.live.len() as u64) as usize;
+            let (id, side, price, amount) = self.live[ix];
+            let qty = 1 + self.rand(amount as u64) as i64;
+            let is_cancel = self.rand(3) == 0;
+            let qty = if is_cancel && self.full_cancels { amount } else { qty };
+            let rest = amount - qty;
+            if rest == 0 {
+                self.live.swap_remove(ix);
+            } else {
+                self.live[ix].3 = rest;
+            }
+            if is_cancel {
+                // the canceled volume, as of the QSH stream
+                OrderLog { amount: qty, ..cancel(id, side, price, rest) }
+            } else {
+                fill(id, side, price, qty, rest)
+            }
+        };
+        Some(at(rec, self.timestamp))
+    }
+}
+
+/// minimal QSH v4 encoder, the inverse of the crate readers
+pub struct Encoder {
+    pub buf: Vec<u8>,
+    timestamp: i64,
+    order_id: UID,
+    price: Price,
+    deal_id: UID,
+    deal_price: Price,
+    oi: Volume,
+}
+
+impl Encoder {
+    pub fn new(stream: u8, recording_time: i64) -> Self {
+        let mut enc = Self {
+            buf: b"QScalp History Data".to_vec(),
+            timestamp: 0,
+            order_id: 0,
+            price: 0,
+            deal_id: 0,
+            deal_price: 0,
+            oi: 0,
+        };
+        enc.buf.push(4);
+        enc.string("QshWriter.6870");
+        enc.string("Zerich QSH Service");
+        enc.buf.extend_from_slice(&recording_time.to_le_bytes());
+        enc.buf.extend_from_slice(&[1, stream]);
+        enc.string("Plaza2:Si-3.20::1252209:1");
+        enc
+    }
+
+    pub fn uleb(&mut self, v: u64) {
+        leb128::write::unsigned(&mut self.buf, v).unwrap();
+    }
+
+    pub fn leb(&mut self, v: i64) {
+        leb128::write::signed(&mut self.buf, v).unwrap();
+    }
+
+    pub fn growing(&mut self, v: i64) {
+        if (0..268_435_455).contains(&v) {
+            self.uleb(v as u64);
+        } else {
+            self.uleb(268_435_455);
+            self.leb(v);
+        }
+    }
+
+    pub fn string(&mut self, s: &str) {
+        self.leb(s.len() as i64);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    /// OrderLog record with all the entry fields present
+    pub fn orderlog(&mut self, frame_time_delta: i64, rec: &OrderLog) {
+        self.growing(frame_time_delta);
+        self.buf.push(0xff);
+        self.buf.extend_from_slice(&rec.order_flags.bits().to_le_bytes());
+        self.growing(rec.timestamp - self.timestamp);
+        self.timestamp = rec.timestamp;
+        if OLFlags::Add % rec.order_flags {
+            self.growing(rec.order_id - self.order_id);
+            self.order_id = rec.order_id;
+        } else {
+            self.leb(rec.order_id - self.order_id);
+        }
+        self.leb(rec.price - self.price);
+        self.price = rec.price;
+        self.leb(rec.amount);
+        if OLFlags::Fill % rec.order_flags {
+            self.leb(rec.amount_rest);
+            self.growing(rec.deal_id - self.deal_id);
+            self.leb(rec.deal_price - self.deal_price);
+            self.leb(rec.oi - self.oi);
+            (self.deal_id, self.deal_price, self.oi) = (rec.deal_id, rec.deal_price, rec.oi);
+        }
+    }
+
+    /// Quotes frame, `rows` are absolute (price, volume) updates, negative volume is a bid, zero
+    /// removes the level
+    pub fn quotes(&mut self, frame_time_delta: i64, rows: &[(Price, Volume)]) {
+        self.growing(frame_time_delta);
+        self.leb(rows.len() as i64);
+        for &(price, volume) in rows {
+            self.leb(price - self.price);
+            self.price = price;
+            self.leb(volume);
+        }
+    }
+
+    pub fn gzip(&self) -> Vec<u8> {
+        let mut enc = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        enc.write_all(&self.buf).unwrap();
+        enc.finish().unwrap()
+    }
+}
+
+/// uncompressed ORDERLOG stream
+pub fn orderlog_stream(records: &[OrderLog]) -> Vec<u8> {
+    let mut enc = Encoder::new(0x70, 637200251900000000);
+    for rec in records {
+        enc.orderlog(1, rec);
+    }
+    enc.buf
+}
+
+pub fn reader(bytes: &[u8]) -> std::io::BufReader<&[u8]> {
+    std::io::BufReader::new(bytes)
+}
+
+pub const S: i64 = 1_000_000_000;
+
+/// deal at the unix nanos `ts`
+pub fn timed_deal(ts: i64, price: Price, amount: Volume) -> TimedDeal {
+    TimedDeal { ts, deal: Deal { price, amount, ..Default::default() } }
+}
+
+/// deals of the two sessions split by the session gap
+pub fn timed_deals() -> Vec<TimedDeal> {
+    vec![
+        timed_deal(10 * S, 100, 1),
+        timed_deal(20 * S, 103, 2),
+        timed_deal(59 * S, 99, 3),
+        timed_deal(60 * S, 101, 1),
+        timed_deal(150 * S, 102, 5),
+        // the next session
+        timed_deal(150 * S + SESSION_GAP_NS + 1, 110, 1),
+        timed_deal(150 * S + SESSION_GAP_NS + 2, 111, 1),
+    ]
+}
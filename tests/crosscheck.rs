@@ -0,0 +1,75 @@
+mod common;
+
+use common::*;
+use qsh_rs::orderbook::{NopListener, OrderBook};
+use qsh_rs::types::{OLFlags, Side};
+use qsh_rs::utils::crosscheck::validate_streams;
+use qsh_rs::{header, OrderLogReader, QshRead, QuotesReader};
+use std::collections::BTreeMap;
+
+const RECORDING_TIME: i64 = 637200251900000000;
+
+// OrderLog stream of `n` records and the Quotes stream of the book after every 5 of them,
+// `corrupt` frame has the best bid volume off by one
+fn streams(n: usize, corrupt: Option<usize>) -> (Vec<u8>, Vec<u8>) {
+    let (mut ol, mut q) = (Encoder::new(0x70, RECORDING_TIME), Encoder::new(0x10, RECORDING_TIME));
+    let (mut book, mut prev) = (OrderBook::default(), BTreeMap::<i64, i64>::new());
+    for (i, rec) in Workload::new(17, 10).full_cancels().take(n).enumerate() {
+        let rec = with(rec, OLFlags::TxEnd);
+        ol.orderlog(1, &rec);
+        book.apply(rec, &mut NopListener).unwrap();
+        if i % 5 != 4 {
+            continue;
+        }
+
+        // negative volume is a bid
+        let mut levels = BTreeMap::new();
+        for side in Side::both() {
+            for (&p, &v, _) in book.iter_levels(side) {
+                levels.insert(p, if side == Side::Buy { -v } else { v });
+            }
+        }
+        if corrupt == Some(i / 5) {
+            *levels.get_mut(&book.best_bid().unwrap().0).unwrap() -= 1;
+        }
+        let mut rows: Vec<_> =
+            prev.keys().filter(|p| !levels.contains_key(p)).map(|&p| (p, 0)).collect();
+        rows.extend(levels.iter().filter(|(p, v)| prev.get(p) != Some(v)).map(|(&p, &v)| (p, v)));
+        rows.sort();
+        q.quotes(5, &rows);
+        prev = levels;
+    }
+    (ol.buf, q.buf)
+}
+
+#[test]
+fn lockstep() {
+    for corrupt in [None, Some(100)] {
+        let (ol, q) = streams(2_000, corrupt);
+        let (mut ol, mut q) = (reader(&ol), reader(&q));
+        header(&mut ol).unwrap();
+        header(&mut q).unwrap();
+        let report = validate_streams(
+            ol.into_iter::<OrderLogReader>(),
+            RECORDING_TIME,
+            q.into_iter::<QuotesReader>(),
+            RECORDING_TIME,
+            5,
+        )
+        .unwrap();
+
+        assert_eq!(report.frames, 400);
+        assert_eq!(report.compared + report.skipped, report.frames);
+        assert!(report.compared > 300);
+        match corrupt {
+            None => assert_eq!(report.match_pct(), 100.),
+            Some(frame) => {
+                assert_eq!(report.matched + 1, report.compared);
+                let m = &report.mismatches[0];
+                assert_eq!(m.frame, frame);
+                assert_eq!(m.quotes[1], m.book[1] + 1);
+                assert!(report.to_string().contains("#100"));
+            }
+        }
+    }
+}
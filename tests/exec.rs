@@ -0,0 +1,68 @@
+use qsh_rs::time;
+use qsh_rs::types::{Deal, PriceScale};
+use qsh_rs::utils::exec::{rolling_vwap, vwap, ExecStats};
+
+fn tape() -> Vec<(i64, i64, i64)> {
+    // the deal lacking the time is skipped
+    vec![(0, 500, 9), (10, 100, 1), (20, 102, 3), (40, 98, 2)]
+}
+
+#[test]
+fn window() {
+    let stats = vwap(tape(), 0, 100).unwrap();
+    assert_eq!((stats.volume, stats.trades, stats.high, stats.low), (6, 3, 102, 98));
+    assert_eq!(stats.vwap, 602. / 6.);
+    // 100 for 10ns, 102 for 20ns
+    assert_eq!(stats.twap, 3040. / 30.);
+
+    // the single deal, the bounds are [from, to)
+    let single = ExecStats { vwap: 102., twap: 102., volume: 3, trades: 1, high: 102, low: 102 };
+    assert_eq!(vwap(tape(), 15, 40), Some(single));
+    assert_eq!(vwap(tape(), 41, 100), None);
+    assert_eq!(vwap(Vec::<(i64, i64, i64)>::new(), 0, 100), None);
+    assert_eq!(single.scaled_vwap(&PriceScale::new(0.5)), 51.);
+
+    // the deals of the same time, and of no volume
+    let flat = vwap([(5, 100, 0), (5, 103, 0)], 0, 10).unwrap();
+    assert_eq!((flat.vwap, flat.twap), (101.5, 101.5));
+
+    // the notional beyond i64
+    let big = vwap(
+        [(1, 1_000_000_000_000, 10_000_000_000), (2, 1_000_000_000_002, 10_000_000_000)],
+        0,
+        10,
+    );
+    assert_eq!(big.unwrap().vwap, 1_000_000_000_001.);
+
+    // the exchange time of the deals
+    let ts = 63_800_000_000_000;
+    let deals = [Deal { timestamp: ts, price: 100, amount: 2, ..Default::default() }];
+    let t = time::ticks_to_unix_ns(ts);
+    assert_eq!(vwap(deals.clone(), t, t + 1).map(|s| s.vwap), Some(100.));
+    assert_eq!(vwap(deals, t + 1, t + 2), None);
+}
+
+#[test]
+fn rolling() {
+    let series: Vec<_> = rolling_vwap(tape(), 15).collect();
+    let summary: Vec<_> =
+        series.iter().map(|(ts, s)| (*ts, s.vwap, s.twap, s.high, s.low, s.trades)).collect();
+    assert_eq!(
+        summary,
+        [
+            (10, 100., 100., 100, 100, 1),
+            (20, 406. / 4., 100., 102, 100, 2),
+            // the deals at or before 25 are out
+            (40, 98., 98., 98, 98, 1),
+        ]
+    );
+
+    // the window of all the deals is the same as the whole tape
+    let (_, last) = rolling_vwap(tape(), 1_000).last().unwrap();
+    assert_eq!(Some(last), vwap(tape(), 0, 1_000));
+
+    // the high and the low leaving the window
+    let tape = [(1, 105, 1), (2, 95, 1), (3, 100, 1), (4, 101, 1), (5, 99, 1)];
+    let lows: Vec<_> = rolling_vwap(tape, 2).map(|(_, s)| (s.high, s.low)).collect();
+    assert_eq!(lows, [(105, 105), (105, 95), (100, 95), (101, 100), (101, 99)]);
+}
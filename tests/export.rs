@@ -0,0 +1,44 @@
+#![cfg(feature = "jsonl")]
+mod common;
+
+use common::*;
+use qsh_rs::export::jsonl::write_snapshots_jsonl;
+use qsh_rs::orderbook::{self as ob, L2Book, NopListener, OrderBook};
+use qsh_rs::types::{OLFlags, Side};
+
+#[test]
+fn jsonl() {
+    let tx = [
+        add(1, Side::Buy, 100, 5),
+        add(2, Side::Buy, 99, 2),
+        add(3, Side::Sell, 101, 3),
+        with(add(4, Side::Sell, 102, 1), OLFlags::TxEnd),
+    ]
+    .map(|r| at(r, 62135596800000 + 1_000));
+    let mut book = OrderBook::default();
+    book.apply_tx(&tx, &mut NopListener).unwrap();
+
+    let mut out = vec![];
+    let snapshots = [book.book_snapshot(0), book.snapshot(1).into()];
+    assert_eq!(write_snapshots_jsonl(snapshots, 0, &mut out).unwrap(), 2);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"ts\":1000000000,\"bids\":[[100,5],[99,2]],\"asks\":[[101,3],[102,1]]}\n\
+         {\"ts\":1000000000,\"bids\":[[100,5]],\"asks\":[[101,3]]}\n"
+    );
+
+    // truncated, the zero padding is skipped
+    let mut l2 = L2Book::default();
+    l2.apply(qsh_rs::types::L2Message::Quote { side: Side::Buy, price: 100, size: 5 }).unwrap();
+    let mut out = vec![];
+    write_snapshots_jsonl([book.snapshot(2), l2.snapshot(2)], 1, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"ts\":1000000000,\"bids\":[[100,5]],\"asks\":[[101,3]]}\n{\"ts\":0,\"bids\":[[100,5]],\"asks\":[]}\n"
+    );
+
+    let mut out = vec![];
+    let n =
+        write_snapshots_jsonl(ob::reconstruct(tx.into_iter(), 2).map(Result::unwrap), 2, &mut out);
+    assert_eq!(n.unwrap(), 1);
+}
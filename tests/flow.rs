@@ -0,0 +1,186 @@
+mod common;
+
+use common::*;
+use qsh_rs::time;
+use qsh_rs::types::{OLFlags, OrderLog, Side};
+use qsh_rs::utils::flow::{message_rates, ofi, signed_trades, tx_ofi, RateBucket};
+
+const TS: i64 = 63_800_000_000_000;
+
+fn scripted() -> Vec<Vec<OrderLog>> {
+    [
+        (0, add(1, Side::Buy, 100, 5)),
+        (0, add(2, Side::Sell, 102, 5)),
+        (1, add(3, Side::Buy, 100, 3)),
+        (2, add(4, Side::Buy, 101, 2)),
+        (3, add(5, Side::Sell, 103, 4)),
+        (4, cancel(2, Side::Sell, 102, 0)),
+        (5, add(6, Side::Sell, 102, 1)),
+        (6, fill(4, Side::Buy, 101, 2, 0)),
+        (7, with(add(7, Side::Buy, 90, 1), OLFlags::NewSession)),
+    ]
+    .into_iter()
+    .map(|(dt, rec)| vec![at(rec, TS + dt)])
+    .collect()
+}
+
+#[test]
+fn per_transaction() {
+    let flow: Vec<_> = tx_ofi(scripted()).map(Result::unwrap).collect();
+    assert_eq!(
+        flow.iter().map(|&(_, e)| e).collect::<Vec<_>>(),
+        [
+            // the ask side is empty before
+            0.,  // the same bid
+            0.,  // the bid size up
+            3.,  // the better bid, of its size
+            2.,  // the ask behind the best one
+            0.,  // the best ask is gone, of its size
+            5.,  // the better ask, of its size
+            -1., // the best bid is gone
+            -2., // the book is rebuilt by the session start
+            0.,
+        ]
+    );
+    assert_eq!(flow[2].0, time::ticks_to_unix_ns(TS + 1));
+}
+
+#[test]
+fn buckets() {
+    let ms = 1_000_000;
+    let flow: Vec<_> = ofi(scripted(), ms).map(Result::unwrap).collect();
+    let t0 = time::ticks_to_unix_ns(TS);
+    assert_eq!(
+        flow,
+        [(t0, 0.), (t0 + ms, 3.), (t0 + 2 * ms, 2.), (t0 + 3 * ms, 0.), (t0 + 4 * ms, 5.)]
+            .into_iter()
+            .chain([(t0 + 5 * ms, -1.), (t0 + 6 * ms, -2.), (t0 + 7 * ms, 0.)])
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(ofi(scripted(), 1_000 * ms).map(Result::unwrap).collect::<Vec<_>>(), [(t0, 7.)]);
+
+    // the bucket before the error goes first
+    let mut txs = scripted();
+    txs.insert(3, vec![at(fill(42, Side::Buy, 100, 1, 0), TS + 1)]);
+    let mut flow = ofi(txs, 1_000 * ms);
+    assert_eq!(flow.next().unwrap().unwrap(), (t0, 3.));
+    assert!(flow.next().unwrap().is_err());
+    assert!(flow.next().is_none());
+}
+
+#[test]
+fn rates() {
+    let deal = |rec| OrderLog { deal_id: 7, deal_price: 100, ..rec };
+    let records = [
+        (0, add(1, Side::Buy, 100, 5)),
+        (100, add(2, Side::Sell, 101, 5)),
+        (200, with(cancel(2, Side::Sell, 101, 0), OLFlags::TxEnd)),
+        // the deal of the two Fill records
+        (1_500, deal(fill(1, Side::Buy, 100, 1, 4))),
+        (1_500, with(deal(fill(9, Side::Sell, 99, 1, 0)), OLFlags::TxEnd)),
+        // the quiet seconds within the session
+        (4_000, add(3, Side::Buy, 99, 1)),
+        // the intersession gap
+        (204_000, add(4, Side::Buy, 99, 1)),
+        (207_000, with(add(5, Side::Buy, 99, 1), OLFlags::NewSession)),
+    ];
+    let records = records.into_iter().map(|(dt, rec)| at(rec, TS + dt));
+    let buckets: Vec<_> = message_rates(records.clone(), 1_000_000_000).collect();
+    let t = |dt| time::ticks_to_unix_ns(TS + dt);
+    let starts: Vec<_> = buckets.iter().map(|b| b.start).collect();
+    assert_eq!(starts, [t(0), t(1_000), t(2_000), t(3_000), t(4_000), t(204_000), t(207_000)]);
+
+    let first =
+        RateBucket { start: t(0), messages: 3, adds: 2, cancels: 1, trades: 0, peak_per_second: 3 };
+    assert_eq!(buckets[0], first);
+    assert_eq!((buckets[0].cancel_to_trade(), buckets[0].add_to_trade()), (None, None));
+    assert_eq!((buckets[1].messages, buckets[1].trades, buckets[1].peak_per_second), (2, 1, 2));
+    assert_eq!((buckets[1].cancel_to_trade(), buckets[1].add_to_trade()), (Some(0.), Some(0.)));
+    assert_eq!(buckets[2], RateBucket { start: t(2_000), ..Default::default() });
+    assert!(buckets[4..].iter().all(|b| b.adds == 1 && b.messages == 1));
+
+    // the bucket of a minute, the totals are kept
+    let minutes: Vec<_> = message_rates(records, 60_000_000_000).collect();
+    assert_eq!(minutes.len(), 2);
+    assert_eq!(minutes.iter().map(|b| b.messages).sum::<usize>(), 8);
+    assert_eq!(minutes[0].cancel_to_trade(), Some(1.));
+    assert_eq!((minutes[0].add_to_trade(), minutes[0].peak_per_second), (Some(3.), 3));
+}
+
+fn deal(mut rec: OrderLog, deal_id: i64, deal_price: i64) -> OrderLog {
+    (rec.deal_id, rec.deal_price) = (deal_id, deal_price);
+    rec
+}
+
+fn tx(mut recs: Vec<OrderLog>) -> Vec<OrderLog> {
+    let last = recs.pop().unwrap();
+    recs.push(with(last, OLFlags::TxEnd));
+    recs.into_iter().map(|rec| at(rec, TS)).collect()
+}
+
+fn signed(txs: Vec<Vec<OrderLog>>) -> Vec<(i64, i64, Side, bool)> {
+    signed_trades(txs)
+        .map(|t| t.map(|t| (t.price, t.signed_amount(), t.consumed(), t.level_exhausted)))
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn buy_sweep() {
+    let txs = vec![
+        tx(vec![
+            add(1, Side::Sell, 101, 2),
+            add(2, Side::Sell, 101, 1),
+            add(3, Side::Sell, 102, 3),
+            add(4, Side::Buy, 99, 1),
+        ]),
+        // the limit order taking the 101 level whole and the part of the 102 one
+        tx(vec![
+            add(10, Side::Buy, 102, 5),
+            deal(fill(1, Side::Sell, 101, 2, 0), 100, 101),
+            deal(fill(10, Side::Buy, 102, 2, 3), 100, 101),
+            deal(fill(2, Side::Sell, 101, 1, 0), 101, 101),
+            deal(fill(10, Side::Buy, 102, 1, 2), 101, 101),
+            deal(fill(3, Side::Sell, 102, 2, 1), 102, 102),
+            deal(fill(10, Side::Buy, 102, 2, 0), 102, 102),
+        ]),
+    ];
+    assert_eq!(
+        signed(txs.clone()),
+        [(101, 2, Side::Sell, false), (101, 1, Side::Sell, true), (102, 2, Side::Sell, false)]
+    );
+    let first = signed_trades(txs).next().unwrap().unwrap();
+    assert_eq!(
+        (first.ts, first.amount, first.aggressor),
+        (time::ticks_to_unix_ns(TS), 2, Side::Buy)
+    );
+}
+
+#[test]
+fn sell_sweep() {
+    let txs = vec![
+        tx(vec![add(1, Side::Buy, 100, 1), add(2, Side::Buy, 99, 2), add(3, Side::Buy, 98, 4)]),
+        // the IOK taking the two levels, its rest removed
+        tx(vec![
+            with(add(10, Side::Sell, 99, 5), OLFlags::Counter),
+            deal(fill(1, Side::Buy, 100, 1, 0), 200, 100),
+            deal(fill(10, Side::Sell, 99, 1, 4), 200, 100),
+            deal(fill(2, Side::Buy, 99, 2, 0), 201, 99),
+            deal(fill(10, Side::Sell, 99, 2, 2), 201, 99),
+            with(remove(10, Side::Sell, 99), OLFlags::Counter),
+        ]),
+        // the maker restored from the snapshot, the roles are of its side
+        tx(vec![deal(fill(3, Side::Buy, 98, 4, 0), 202, 98)]),
+    ];
+    let trades = signed(txs);
+    assert_eq!(
+        trades,
+        [(100, -1, Side::Buy, true), (99, -2, Side::Buy, true), (98, -4, Side::Buy, true)]
+    );
+
+    // the fill of the order the book doesn't have
+    let txs = vec![tx(vec![deal(fill(7, Side::Buy, 100, 1, 0), 203, 100)])];
+    let mut iter = signed_trades(txs);
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+}
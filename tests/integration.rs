@@ -1,5 +1,8 @@
+use qsh_rs::orderbook::{self as ob, NopListener, OrderLogStream, PartitionBy};
+use qsh_rs::types::{OLFlags, OLMsgType, Side};
 use qsh_rs::{
-    header, inflate, AuxInfoReader, DealReader, OrderLogReader, QshParser, QshRead, QuotesReader,
+    header, inflate, time, AuxInfoReader, DealReader, OrderLogReader, QshParser, QshRead,
+    QuotesReader,
 };
 
 fn parse<T: QshParser>(f: &str) {
@@ -9,6 +12,20 @@ fn parse<T: QshParser>(f: &str) {
     println!("{}", iter.count());
 }
 
+// replays first `n` transactions of the orderlog file
+fn replay(f: &str, n: usize) -> ob::OrderBook {
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+
+    let mut book = ob::OrderBook::default();
+    let iter = parser.into_iter::<OrderLogReader>().transactions().take(n);
+
+    for tx in iter {
+        book.apply_tx(&tx, &mut NopListener).unwrap();
+    }
+    book
+}
+
 #[test]
 fn orderlog() {
     parse::<OrderLogReader>("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh");
@@ -32,3 +49,846 @@ fn aux() {
     parse::<AuxInfoReader>("data/zerich/SBER.2020-03-17.AuxInfo.qsh");
     parse::<AuxInfoReader>("data/erinrv/SBER.2020-03-17.AuxInfo.qsh");
 }
+
+#[test]
+fn levels() {
+    let book = replay("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh", 100_000);
+    for side in Side::both() {
+        let mut prev = None;
+        for (&price, &volume, orders) in book.iter_levels(side) {
+            assert_eq!(volume, orders.iter().map(|r| r.amount).sum::<i64>());
+            assert_eq!(book.orders_at(side, price).len(), orders.len());
+            if let Some(prev) = prev {
+                assert!(if side == Side::Buy { prev > price } else { prev < price });
+            }
+            prev = Some(price);
+        }
+    }
+}
+
+#[test]
+fn total_volume() {
+    let book = replay("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh", 100_000);
+    for side in Side::both() {
+        let sum: i64 = book.iter_levels(side).map(|(_, v, _)| v).sum();
+        assert_eq!(book.total_volume(side), sum);
+    }
+}
+
+#[test]
+fn apply_tx() {
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+
+    let mut book = ob::OrderBook::default();
+    let iter = parser
+        .into_iter::<OrderLogReader>()
+        .filter(ob::system_record)
+        .partition_by(ob::tx_end)
+        .filter(ob::fiok_with_trades)
+        .take(100_000);
+    for tx in iter {
+        if OLFlags::NewSession % tx[0].order_flags {
+            book.clear();
+        }
+        for r in tx {
+            match OLMsgType::from(&r) {
+                OLMsgType::Add => book.add(r, &mut NopListener),
+                OLMsgType::Fill => book.trade(r, &mut NopListener),
+                OLMsgType::Cancel | OLMsgType::Remove => book.cancel(r, &mut NopListener),
+                OLMsgType::UNKNOWN => unreachable!(),
+            }
+            .unwrap()
+        }
+    }
+
+    assert_eq!(replay(f, 100_000).snapshot(20), book.snapshot(20));
+}
+
+#[test]
+fn l2book() {
+    use qsh_rs::utils::l3tol2::convert;
+
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let l2 = convert(parser.into_iter::<OrderLogReader>(), 0);
+
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let txs = parser
+        .into_iter::<OrderLogReader>()
+        .filter(ob::system_record)
+        .partition_by(ob::tx_end)
+        .filter(ob::fiok_with_trades);
+
+    let (mut book, mut l2book) = (ob::OrderBook::default(), ob::L2Book::default());
+    for (tx, events) in txs.zip(l2).take(100_000) {
+        book.apply_tx(&tx, &mut NopListener).unwrap();
+        events.unwrap().into_iter().for_each(|e| l2book.apply(e).unwrap());
+
+        let depth = book.depth(Side::Buy).min(book.depth(Side::Sell));
+        assert_eq!(l2book.snapshot(depth).1, book.snapshot(depth).1);
+        assert!(!l2book.is_crossed());
+    }
+}
+
+#[test]
+fn checkpoint() {
+    use qsh_rs::utils::checkpoint;
+
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let records = || {
+        let mut parser = inflate(f.into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>()
+    };
+    let replay = |book: &mut ob::OrderBook, skip| {
+        records()
+            .skip(skip)
+            .filter(ob::system_record)
+            .partition_by(ob::tx_end)
+            .filter(ob::fiok_with_trades)
+            .map(|tx| {
+                book.apply_tx(&tx, &mut NopListener).unwrap();
+                (book.best_bid(), book.best_ask(), book.len())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // 2020-03-17 16:45 MSK
+    let mut buf = vec![];
+    checkpoint::save(records(), 1584452700000 * 1_000_000, &mut buf).unwrap();
+    let (mut book, skip) = checkpoint::load(&buf[..]).unwrap();
+
+    let full = replay(&mut ob::OrderBook::default(), 0);
+    let resumed = replay(&mut book, skip);
+    assert_eq!(resumed[..], full[full.len() - resumed.len()..]);
+}
+
+#[test]
+fn regression() {
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+
+    // manual dispatch vs `apply_tx` of the bounded book
+    let (mut book, mut bounded) = (ob::OrderBook::default(), ob::OrderBook::with_max_depth(10));
+    let iter = parser
+        .into_iter::<OrderLogReader>()
+        .filter(ob::system_record)
+        .partition_by(ob::tx_end)
+        .filter(ob::fiok_with_trades);
+    for tx in iter {
+        if OLFlags::NewSession % tx[0].order_flags {
+            book.clear();
+        }
+        for &r in &tx {
+            match OLMsgType::from(&r) {
+                OLMsgType::Add => book.add(r, &mut NopListener),
+                OLMsgType::Fill => book.trade(r, &mut NopListener),
+                OLMsgType::Cancel | OLMsgType::Remove => book.cancel(r, &mut NopListener),
+                OLMsgType::UNKNOWN => unreachable!(),
+            }
+            .unwrap()
+        }
+        bounded.apply_tx(&tx, &mut NopListener).unwrap();
+        if book.checksum(10) != bounded.checksum(10) {
+            panic!("diverged at {:?}\n{}", tx[0].timestamp, book.diff(&bounded));
+        }
+    }
+}
+
+#[test]
+fn crosscheck() {
+    use qsh_rs::utils::crosscheck::validate;
+
+    let report = validate(
+        "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh",
+        "data/erinrv/Si-3.20_FT.2020-03-17.Quotes.qsh",
+        5,
+    )
+    .unwrap();
+    print!("{report}");
+    assert!(report.compared > 0);
+}
+
+#[test]
+fn transactions() {
+    let records = || {
+        let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>().take(1_000_000)
+    };
+    let manual =
+        records().filter(ob::system_record).partition_by(ob::tx_end).filter(ob::fiok_with_trades);
+    let mut n = 0;
+    for (a, b) in manual.zip(records().transactions()) {
+        assert_eq!(a.len(), b.len());
+        assert!(a.iter().zip(&b).all(|(a, b)| a.order_id == b.order_id && a.event == b.event));
+        n += 1;
+    }
+    assert_eq!(n, records().transactions().count());
+}
+
+#[test]
+fn record_filter() {
+    let records = || {
+        let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>()
+    };
+    let mut filter = ob::RecordFilter::default();
+    let passed = records().filter_with(&mut filter).count();
+    let stats = filter.stats();
+    println!("{stats:?}");
+    assert_eq!(passed, records().filter(ob::system_record).count());
+    assert_eq!(stats.passed, passed);
+    assert_eq!(stats.dropped(), records().count() - passed);
+}
+
+#[test]
+fn resample() {
+    use qsh_rs::utils::resample;
+
+    let records = || {
+        let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>().transactions()
+    };
+    let (first, last) = records().fold((None, 0), |(first, _), tx| {
+        let ts = time::ticks_to_unix_ns(tx[tx.len() - 1].timestamp);
+        (first.or(Some(ts)), ts)
+    });
+    let seconds = (last / 1_000_000_000 - first.unwrap() / 1_000_000_000) as usize;
+
+    let snapshots: Vec<_> =
+        resample::snapshots(records(), 1, 1_000_000_000).map(Result::unwrap).collect();
+    assert_eq!(snapshots.len(), seconds);
+    assert!(snapshots.windows(2).all(|w| w[1].ts - w[0].ts == 1_000_000_000));
+    for s in snapshots.iter().filter(|s| !s.bids.is_empty() && !s.asks.is_empty()) {
+        // loose bounds, Si-3.20 traded around 74000 that day
+        let mid = (s.bids[0].0 + s.asks[0].0) / 2;
+        assert!((70_000..80_000).contains(&mid), "{s:?}");
+    }
+}
+
+#[test]
+fn order_counts() {
+    let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    header(&mut parser).unwrap();
+
+    let mut book = ob::OrderBook::default();
+    for tx in parser.into_iter::<OrderLogReader>().transactions().take(200_000) {
+        book.apply_tx(&tx, &mut NopListener).unwrap();
+        let depth = book.depth(Side::Buy).min(book.depth(Side::Sell)).min(5);
+        for level in book.snapshot_with_counts(depth).1.chunks_exact(6) {
+            assert_eq!(book.orders_at(Side::Buy, level[0]).len() as i64, level[2]);
+            assert_eq!(book.orders_at(Side::Sell, level[3]).len() as i64, level[5]);
+        }
+    }
+}
+
+#[test]
+fn parse_report() {
+    use qsh_rs::utils::report::{parse_with_report, ParseReport};
+
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let (_, report) = parse_with_report(f).unwrap();
+
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let txs: Vec<_> = parser.into_iter::<OrderLogReader>().partition_by(ob::tx_end).collect();
+    assert_eq!(report.transactions, txs.len());
+    assert_eq!(report.records, txs.iter().map(Vec::len).sum::<usize>());
+    assert_eq!(report.max_tx_len, txs.iter().map(Vec::len).max().unwrap());
+    assert!(report.sessions >= 1);
+    assert!(report.min_ts <= report.max_ts);
+    assert_eq!(report, ParseReport::from_records(txs.into_iter().flatten()));
+}
+
+#[test]
+fn storage_equivalence() {
+    let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    header(&mut parser).unwrap();
+
+    let mut sorted = ob::OrderBook::default();
+    let mut ladder = ob::OrderBook::with_storage(ob::Storage::Ladder { step: 1 });
+    for tx in parser.into_iter::<OrderLogReader>().transactions() {
+        sorted.apply_tx(&tx, &mut NopListener).unwrap();
+        ladder.apply_tx(&tx, &mut NopListener).unwrap();
+        assert_eq!(sorted.checksum(50), ladder.checksum(50), "{}", tx[0]);
+    }
+    assert_eq!(sorted.checksum(usize::MAX), ladder.checksum(usize::MAX));
+}
+
+#[test]
+fn session_time() {
+    let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    let header = header(&mut parser).unwrap();
+    let first = parser.into_iter::<OrderLogReader>().next().unwrap();
+
+    // the recording starts ahead of the 10:00 MSK open of the 2020-03-17 day session,
+    // after the 19:00 MSK open of the evening session of the day before
+    let (evening, open) = (1584374400 * 1_000_000_000, 1584428400 * 1_000_000_000);
+    let ts = time::ticks_to_unix_ns(first.timestamp);
+    assert!((evening..open).contains(&ts), "{ts}");
+    assert_eq!(time::ns_to_ms(ts), time::ticks_to_unix_ms(first.timestamp));
+    let recording = time::recording_time_to_unix_ns(header.recording_time);
+    assert!((evening..open).contains(&recording), "{recording}");
+}
+
+#[test]
+fn resample_quotes() {
+    use qsh_rs::utils::resample;
+
+    let mut parser = inflate("data/erinrv/Si-3.20_FT.2020-03-17.Quotes.qsh".into()).unwrap();
+    let header = header(&mut parser).unwrap();
+    let buckets: Vec<_> =
+        resample::quotes(parser.into_iter::<QuotesReader>(), 100, &header, true).collect();
+    assert!(buckets.windows(2).all(|w| w[1].0 - w[0].0 == 100_000_000));
+    assert!(buckets.iter().all(|(ts, _)| ts % 100_000_000 == 0));
+}
+
+#[test]
+fn seeded_book() {
+    let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    header(&mut parser).unwrap();
+    let txs: Vec<_> = parser.into_iter::<OrderLogReader>().transactions().collect();
+    let (head, tail) = txs.split_at(txs.len() / 2);
+
+    let mut full = ob::OrderBook::default();
+    head.iter().for_each(|tx| full.apply_tx(tx, &mut NopListener).unwrap());
+    let mut seeded = ob::OrderBook::from_snapshot(&full.book_snapshot(0));
+    for tx in tail {
+        full.apply_tx(tx, &mut NopListener).unwrap();
+        seeded.apply_tx(tx, &mut NopListener).unwrap();
+        assert_eq!(seeded.book_snapshot(10), full.book_snapshot(10), "{}", tx[0]);
+    }
+    assert!(seeded.anomalies().synthetic > 0);
+}
+
+#[test]
+fn deal_bars() {
+    use qsh_rs::utils::bars::{bars, Period};
+
+    let deals = || {
+        let mut parser = inflate("data/zerich/SBER.2020-03-17.Deals.qsh".into()).unwrap();
+        let header = header(&mut parser).unwrap();
+        parser.into_iter_with(DealReader::with_absolute_time(&header))
+    };
+    let volume: i64 = deals().map(|d| d.deal.amount).sum();
+    for period in [Period::Time(60_000_000_000), Period::Volume(10_000), Period::Ticks(100)] {
+        let bars: Vec<_> = bars(deals(), period).collect();
+        assert_eq!(bars.iter().map(|b| b.volume).sum::<i64>(), volume);
+        assert!(bars.iter().all(|b| b.low <= b.open.min(b.close) && b.high >= b.open.max(b.close)));
+        assert!(bars.windows(2).all(|w| w[0].start_ts <= w[1].start_ts));
+    }
+}
+
+#[test]
+fn l2book_depth() {
+    use qsh_rs::utils::l3tol2::convert;
+
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let l2 = convert(parser.into_iter::<OrderLogReader>(), 5);
+
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let (mut book, mut l2book) = (ob::OrderBook::default(), ob::L2Book::default());
+    for (tx, events) in parser.into_iter::<OrderLogReader>().transactions().zip(l2) {
+        book.apply_tx(&tx, &mut NopListener).unwrap();
+        events.unwrap().into_iter().for_each(|e| l2book.apply(e).unwrap());
+        assert_eq!(l2book.snapshot(5).1, book.snapshot_upto(5).0.flatten(5));
+    }
+}
+
+#[test]
+fn l2_snapshots() {
+    use qsh_rs::utils::l3tol2::{convert_with, seek_snapshot, ConvertOptions, SnapshotEvery};
+
+    let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    header(&mut parser).unwrap();
+    let every = Some(SnapshotEvery::Transactions(10_000));
+    let options = ConvertOptions { depth: 0, snapshot_every: every, ..Default::default() };
+    let messages: Vec<_> = convert_with(parser.into_iter::<OrderLogReader>(), options)
+        .flat_map(Result::unwrap)
+        .collect();
+
+    let mut full = ob::L2Book::default();
+    messages.iter().cloned().for_each(|msg| full.apply(msg).unwrap());
+    // 2020-03-17 16:45 MSK
+    let mut rest = messages.into_iter();
+    let mut book = seek_snapshot(&mut rest, 1584452700000 * 1_000_000).unwrap();
+    rest.for_each(|msg| book.apply(msg).unwrap());
+    assert_eq!(book.snapshot(50), full.snapshot(50));
+}
+
+#[test]
+fn orderlog_trades() {
+    use qsh_rs::utils::trades::from_orderlog;
+    use std::collections::HashMap;
+
+    // no Deals fixture of the same instrument, the tape is checked against the Fill records
+    let records = || {
+        let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>().filter(ob::system_record)
+    };
+    // legs and their volume per deal
+    let mut legs: HashMap<_, (i64, i64)> = HashMap::new();
+    for deal in records().filter_map(|r| r.as_trade()) {
+        let e = legs.entry(deal.deal_id).or_default();
+        (e.0, e.1) = (e.0 + 1, e.1 + deal.amount);
+    }
+    let trades: Vec<_> = from_orderlog(records()).collect();
+
+    assert_eq!(trades.len(), legs.len());
+    // the volume is counted once per deal
+    for t in &trades {
+        let (n, volume) = legs[&t.deal_id];
+        assert_eq!(volume, t.amount * n, "{t:?}");
+    }
+    let known = trades.iter().filter(|t| t.aggressor != Side::UNKNOWN).count();
+    assert!(known * 100 >= trades.len() * 99, "{known} of {}", trades.len());
+}
+
+#[test]
+fn deal_candles() {
+    use qsh_rs::utils::candles::{time_bars, time_bars_with, EmptyBars};
+
+    let deals = || {
+        let mut parser = inflate("data/zerich/SBER.2020-03-17.Deals.qsh".into()).unwrap();
+        let header = header(&mut parser).unwrap();
+        parser.into_iter_with(DealReader::with_absolute_time(&header))
+    };
+    let volume: i64 = deals().map(|d| d.deal.amount).sum();
+    let candles: Vec<_> = time_bars(deals(), 60_000_000_000).collect();
+    assert_eq!(candles.iter().map(|c| c.bar.volume).sum::<i64>(), volume);
+    assert!(candles.windows(2).all(|w| w[0].bar.start_ts <= w[1].bar.start_ts));
+    assert!(candles.iter().all(|c| c.bar.low as f64 <= c.vwap && c.vwap <= c.bar.high as f64));
+
+    let filled: Vec<_> = time_bars_with(deals(), 60_000_000_000, EmptyBars::ForwardFill).collect();
+    assert_eq!(filled.iter().map(|c| c.bar.volume).sum::<i64>(), volume);
+    // a trading day of the minute bars at most
+    assert!(filled.len() >= candles.len() && filled.len() <= 24 * 60);
+}
+
+#[test]
+fn ordlog_sessions() {
+    use qsh_rs::types::OLFlagSet;
+    use qsh_rs::utils::sessions::{session_bounds, split};
+
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let records = || {
+        let mut parser = inflate(f.into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>()
+    };
+    let sessions: Vec<_> = split(records()).collect();
+    let txs: Vec<_> = records().transactions().collect();
+    let opened = txs
+        .iter()
+        .filter(|tx| tx.iter().any(|r| r.order_flags.contains(OLFlagSet::NEW_SESSION)))
+        .count();
+    // a session per NewSession transaction, and the one before the first of them, if any
+    assert!(!sessions.is_empty() && (sessions.len() == opened || sessions.len() == opened + 1));
+    assert!(sessions.iter().enumerate().all(|(i, s)| s.index == i && s.start_ts <= s.end_ts));
+
+    let concat = sessions.iter().flat_map(|s| s.transactions.iter()).flatten();
+    assert!(concat.map(|r| r.order_id).eq(txs.iter().flatten().map(|r| r.order_id)));
+    let bounds: Vec<_> = sessions.iter().map(|s| (s.start_ts, s.end_ts)).collect();
+    assert_eq!(session_bounds(f).unwrap(), bounds);
+}
+
+#[test]
+fn merge_streams() {
+    use qsh_rs::utils::merge::{by_time, TimedSource};
+
+    let files =
+        ["data/zerich/Si-3.20.2020-03-17.OrdLog.qsh", "data/zerich/SBER.2020-03-17.Deals.qsh"];
+    let sources = files.iter().map(|f| TimedSource::open(*f).unwrap()).collect();
+    let (mut counts, mut last) = ([0; 2], i64::MIN);
+    for rec in by_time(sources) {
+        let (ix, ts, _) = rec.unwrap();
+        assert!(ts >= last);
+        (counts[ix], last) = (counts[ix] + 1, ts);
+    }
+    for (f, count) in files.iter().zip(counts) {
+        assert_eq!(TimedSource::open(*f).unwrap().count(), count);
+    }
+}
+
+#[test]
+fn sequence_reports() {
+    use qsh_rs::utils::qa::sequence_report;
+
+    let ordlog = sequence_report("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh").unwrap();
+    assert!(ordlog.records > 0 && ordlog.ts_regressions == 0);
+    // the non-system records, the restored orders of the next session
+    assert!(ordlog.anomalies() <= ordlog.records / 10_000);
+    assert_eq!(ordlog.hourly.values().sum::<usize>(), ordlog.records);
+
+    let deals = sequence_report("data/zerich/SBER.2020-03-17.Deals.qsh").unwrap();
+    assert!(deals.records > 0 && deals.deal_id_regressions == 0 && deals.ts_regressions == 0);
+    assert!(deals.order_id_regressions == 0 && deals.max_order_id_gap == 0);
+}
+
+#[test]
+fn l2io_roundtrip() {
+    use qsh_rs::utils::{
+        l2io::{self, L2Writer},
+        l3tol2::{try_convert_with, ConvertOptions},
+    };
+
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let path = std::env::temp_dir().join(format!("qsh-l2io-{}.bin", std::process::id()));
+    // as the `l3tol2` tool writes
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let mut writer = L2Writer::new(std::fs::File::create(&path).unwrap());
+    let mut written = 0;
+    for tx in try_convert_with(parser.try_iter::<OrderLogReader>(), ConvertOptions::default()) {
+        for msg in &tx.unwrap() {
+            writer.write(msg).unwrap();
+            written += 1;
+        }
+    }
+    writer.finish().unwrap();
+
+    let mut l2book = ob::L2Book::default();
+    let mut read = 0;
+    for msg in l2io::read(&path).unwrap() {
+        l2book.apply(msg.unwrap()).unwrap();
+        read += 1;
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read, written);
+
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let mut book = ob::OrderBook::default();
+    for tx in parser.into_iter::<OrderLogReader>().transactions() {
+        book.apply_tx(&tx, &mut NopListener).unwrap();
+    }
+    for side in Side::both() {
+        assert_eq!(l2book.depth(side), book.depth(side));
+    }
+    assert_eq!(l2book.snapshot(50).1, book.snapshot(50).1);
+}
+
+#[test]
+fn order_lifetimes() {
+    use qsh_rs::types::OLFlagSet;
+    use qsh_rs::utils::lifetimes::{analyze, CloseReason};
+    use std::collections::HashMap;
+
+    let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    header(&mut parser).unwrap();
+    let records: Vec<_> = parser.into_iter::<OrderLogReader>().collect();
+    // Adds per order_id, the moved orders keep theirs
+    let mut adds: HashMap<_, usize> = HashMap::new();
+    for rec in records.iter().filter(|r| ob::system_record(r)) {
+        if rec.order_flags.contains(OLFlagSet::ADD) {
+            *adds.entry(rec.order_id).or_default() += 1;
+        }
+    }
+    let mut lives: HashMap<_, usize> = HashMap::new();
+    for life in analyze(records) {
+        assert!(life.filled <= life.total, "{life:?}");
+        assert!(life.closed_ts >= life.added_ts, "{life:?}");
+        assert!(life.reason != CloseReason::Filled || life.filled == life.total, "{life:?}");
+        *lives.entry(life.order_id).or_default() += 1;
+    }
+    assert_eq!(lives, adds);
+}
+
+#[test]
+fn order_flow_imbalance() {
+    use qsh_rs::utils::flow::{ofi, tx_ofi};
+
+    let txs = || {
+        let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>().transactions()
+    };
+    let flow: Vec<_> = tx_ofi(txs()).map(Result::unwrap).collect();
+    assert!(!flow.is_empty());
+    assert!(flow.iter().all(|(_, e)| e.is_finite()));
+
+    let minutes: Vec<_> = ofi(txs(), 60_000_000_000).map(Result::unwrap).collect();
+    assert!(minutes.iter().all(|(_, e)| e.is_finite()));
+    assert_eq!(
+        minutes.iter().map(|(_, e)| e).sum::<f64>(),
+        flow.iter().map(|(_, e)| e).sum::<f64>()
+    );
+}
+
+#[test]
+fn bbo_ticks() {
+    use qsh_rs::utils::bbo::{quotes, stream};
+
+    let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    header(&mut parser).unwrap();
+    let ticks: Vec<_> =
+        stream(parser.into_iter::<OrderLogReader>().transactions()).map(Result::unwrap).collect();
+    assert!(!ticks.is_empty());
+    assert!(ticks.windows(2).all(|w| w[0].ts <= w[1].ts));
+    assert!(ticks.windows(2).all(|w| (w[0].bid(), w[0].ask()) != (w[1].bid(), w[1].ask())));
+
+    let mut parser = inflate("data/erinrv/Si-3.20_FT.2020-03-17.Quotes.qsh".into()).unwrap();
+    let hdr = header(&mut parser).unwrap();
+    let ticks: Vec<_> = quotes(parser.into_iter::<QuotesReader>(), &hdr).collect();
+    assert!(ticks.windows(2).all(|w| w[0].ts <= w[1].ts));
+    assert!(ticks.windows(2).all(|w| (w[0].bid(), w[0].ask()) != (w[1].bid(), w[1].ask())));
+}
+
+#[test]
+fn fills_with_book() {
+    use qsh_rs::utils::trades::zip_book;
+
+    let txs = || {
+        let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>().transactions()
+    };
+    let fills = txs().flatten().filter(|r| r.as_trade().is_some()).count();
+    let mut quoted = 0;
+    for fill in zip_book(txs()) {
+        let (rec, bid, ask) = fill.unwrap();
+        assert!(rec.as_trade().is_some());
+        quoted += (bid.is_some() && ask.is_some()) as usize;
+    }
+    assert!(quoted > 0 && quoted <= fills);
+}
+
+#[test]
+fn file_summaries() {
+    use qsh_rs::utils::summary::file_stats;
+
+    // from the 19:00 MSK open of the 2020-03-16 evening session to the 2020-03-17 midnight MSK,
+    // the recording of the quotes frames may outlast the 23:50 close
+    let day = 1584374400 * 1_000_000_000..=1584478800 * 1_000_000_000;
+    let within_day = |first: Option<i64>, last: Option<i64>| {
+        let (first, last) = (first.unwrap(), last.unwrap());
+        first <= last && day.contains(&first) && day.contains(&last)
+    };
+
+    let stats = file_stats("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh").unwrap();
+    assert_eq!(stats.stream, "ORDERLOG");
+    assert_eq!(stats.instrument, "Plaza2:Si-3.20::1252209:1");
+    assert!(within_day(stats.first_ts, stats.last_ts), "{stats}");
+    assert_eq!(
+        stats.adds + stats.fills + stats.cancels + stats.removes + stats.unknown,
+        stats.records
+    );
+    assert!(stats.sessions >= 1 && stats.transactions > stats.sessions);
+    assert!(stats.records > stats.transactions && stats.unique_orders <= stats.adds);
+    // loose bounds, Si-3.20 traded around 74000 that day
+    let avg = stats.notional / stats.volume as i128;
+    assert!((70_000..80_000).contains(&avg), "{stats}");
+
+    let stats = file_stats("data/zerich/SBER.2020-03-17.Deals.qsh").unwrap();
+    assert_eq!(stats.stream, "DEALS");
+    assert!(within_day(stats.first_ts, stats.last_ts), "{stats}");
+    assert!(stats.records > 0 && stats.volume >= stats.records as i64);
+
+    let stats = file_stats("data/zerich/USD000UTSTOM.2020-03-17.Quotes.qsh").unwrap();
+    assert_eq!(stats.stream, "QUOTES");
+    assert!(within_day(stats.first_ts, stats.last_ts), "{stats}");
+    assert!(stats.records > 0 && stats.max_depth > 0);
+
+    let stats = file_stats("data/zerich/SBER.2020-03-17.AuxInfo.qsh").unwrap();
+    assert_eq!(stats.stream, "AUXINFO");
+    assert!(stats.records > 0);
+    println!("{stats}");
+}
+
+#[test]
+fn l2_resnapshots() {
+    use qsh_rs::utils::l2::{snapshots, SnapshotTrigger};
+    use qsh_rs::utils::l3tol2::convert;
+
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let txs: Vec<_> =
+        convert(parser.into_iter::<OrderLogReader>(), 0).map(Result::unwrap).collect();
+    let mut sampled = snapshots(txs.iter().flatten().cloned(), 5, SnapshotTrigger::EveryMessage);
+
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let (mut book, mut last) = (ob::OrderBook::default(), None);
+    for (tx, messages) in parser.into_iter::<OrderLogReader>().transactions().zip(&txs) {
+        book.apply_tx(&tx, &mut NopListener).unwrap();
+        for _ in messages {
+            last = Some(sampled.next().unwrap().unwrap());
+        }
+        if let Some(last) = &last {
+            assert_eq!(last.snapshot.flatten(5), book.snapshot_upto(5).0.flatten(5));
+        }
+    }
+    assert!(sampled.next().is_none());
+}
+
+#[test]
+fn message_rates() {
+    use qsh_rs::utils::flow::message_rates;
+
+    let records = || {
+        let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>()
+    };
+    let buckets: Vec<_> = message_rates(records(), 60_000_000_000).collect();
+    let count = |event| records().filter(|r| r.event == event).count();
+    assert_eq!(buckets.iter().map(|b| b.messages).sum::<usize>(), records().count());
+    assert_eq!(buckets.iter().map(|b| b.adds).sum::<usize>(), count(OLMsgType::Add));
+    assert_eq!(buckets.iter().map(|b| b.cancels).sum::<usize>(), count(OLMsgType::Cancel));
+    assert!(buckets.iter().map(|b| b.trades).sum::<usize>() <= count(OLMsgType::Fill));
+    assert!(buckets.iter().all(|b| b.peak_per_second <= b.messages));
+}
+
+#[test]
+fn execution_prices() {
+    use qsh_rs::utils::exec::{rolling_vwap, vwap};
+    use qsh_rs::utils::trades::from_orderlog;
+
+    let mut parser = inflate("data/zerich/SBER.2020-03-17.Deals.qsh".into()).unwrap();
+    header(&mut parser).unwrap();
+    let deals: Vec<_> = parser.into_iter::<DealReader>().collect();
+    let stats = vwap(deals.clone(), 0, i64::MAX).unwrap();
+    assert!(stats.low as f64 <= stats.vwap && stats.vwap <= stats.high as f64);
+    assert!(stats.low as f64 <= stats.twap && stats.twap <= stats.high as f64);
+    let mut minute = rolling_vwap(deals, 60_000_000_000);
+    assert!(minute.all(|(_, s)| s.low as f64 <= s.vwap && s.vwap <= s.high as f64));
+
+    let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+    header(&mut parser).unwrap();
+    let tape: Vec<_> = from_orderlog(parser.into_iter::<OrderLogReader>()).collect();
+    let stats = vwap(tape.clone(), 0, i64::MAX).unwrap();
+    assert_eq!(stats.trades, tape.len());
+    assert!(stats.low as f64 <= stats.vwap && stats.vwap <= stats.high as f64);
+}
+
+#[test]
+fn auxinfo_join() {
+    use qsh_rs::types::Deal;
+    use qsh_rs::utils::join::{frame_timed, with_auxinfo};
+
+    let mut parser = inflate("data/zerich/SBER.2020-03-17.AuxInfo.qsh".into()).unwrap();
+    let hdr = header(&mut parser).unwrap();
+    let aux: Vec<_> = frame_timed(parser.into_iter::<AuxInfoReader>(), &hdr).unwrap().collect();
+    assert!(aux.len() > 1);
+
+    // the deals right before, at and right after the AuxInfo frame times
+    let mut times: Vec<_> = aux.iter().map(|(ts, _)| *ts).collect();
+    times.dedup();
+    let deals = times.iter().flat_map(|ts| [ts - 1, *ts, ts + 1]).map(|ts| (ts, Deal::default()));
+    let joined: Vec<_> = with_auxinfo(deals, aux.iter().cloned()).collect();
+    assert_eq!(joined.len(), times.len() * 3);
+    assert_eq!(joined[0].1.ts, None);
+    for ((ts, _), state) in joined.iter().skip(1) {
+        // the last AuxInfo record at or before the deal
+        let (t, last) = &aux[aux.partition_point(|(t, _)| t <= ts) - 1];
+        assert_eq!(state.ts, Some(*t));
+        assert_eq!((state.oi, state.price), (last.oi, last.price));
+    }
+}
+
+#[test]
+fn book_replay_anomalies() {
+    use qsh_rs::utils::validate::{book_replay, replay_records, AnomalyKind};
+    use std::collections::HashMap;
+
+    let f = "data/zerich/Si-3.20.2020-03-17.OrdLog.qsh";
+    let report = book_replay(f).unwrap();
+    assert!(report.is_clean(), "{report}");
+    assert!(report.transactions > 0);
+
+    let mut parser = inflate(f.into()).unwrap();
+    header(&mut parser).unwrap();
+    let txs: Vec<_> = parser.into_iter::<OrderLogReader>().transactions().collect();
+    // the first transaction `k` to cancel or fill the order added by the earlier one `j`
+    let mut added = HashMap::new();
+    let (j, k) = txs
+        .iter()
+        .enumerate()
+        .find_map(|(k, tx)| {
+            let j = tx
+                .iter()
+                .filter(|rec| rec.event != OLMsgType::Add)
+                .find_map(|rec| added.get(&rec.order_id).copied());
+            tx.iter().filter(|rec| rec.event == OLMsgType::Add).for_each(|rec| {
+                added.insert(rec.order_id, k);
+            });
+            j.map(|j| (j, k))
+        })
+        .unwrap();
+
+    // `k` moved ahead of `j`, the transactions past `k` swapped pairwise
+    let mut shuffled = txs[..j].to_vec();
+    shuffled.push(txs[k].clone());
+    shuffled.extend_from_slice(&txs[j..k]);
+    for pair in txs[k + 1..].chunks(2) {
+        shuffled.extend(pair.iter().rev().cloned());
+    }
+    let report = replay_records(shuffled.into_iter().flatten(), 10);
+    print!("{report}");
+    assert!(report.total > 100);
+    assert_eq!(report.anomalies.len(), 10);
+    assert_eq!(report.anomalies[0].tx_index, j);
+    assert!(!matches!(report.anomalies[0].kind, AnomalyKind::Rejected(_)));
+}
+
+#[test]
+fn time_spans() {
+    use qsh_rs::source::record_source;
+    use qsh_rs::utils::join::frame_timed;
+    use qsh_rs::utils::summary::time_span;
+
+    for f in ["data/zerich/Si-3.20.2020-03-17.OrdLog.qsh", "data/zerich/SBER.2020-03-17.Deals.qsh"]
+    {
+        let (start, end) = time_span(f).unwrap();
+        let mut parser = inflate(f.into()).unwrap();
+        let hdr = header(&mut parser).unwrap();
+        let records = record_source(hdr.stream, parser).unwrap().map(Result::unwrap);
+        let times: Vec<_> = frame_timed(records, &hdr).unwrap().map(|(ts, _)| ts).collect();
+        assert!(start <= times[0]);
+        assert_eq!(end, *times.last().unwrap());
+    }
+}
+
+#[test]
+fn signed_trade_flow() {
+    use qsh_rs::utils::flow::signed_trades;
+    use qsh_rs::utils::trades::from_orderlog;
+
+    let records = || {
+        let mut parser = inflate("data/zerich/Si-3.20.2020-03-17.OrdLog.qsh".into()).unwrap();
+        header(&mut parser).unwrap();
+        parser.into_iter::<OrderLogReader>()
+    };
+    let signed: Vec<_> = signed_trades(records().transactions()).collect::<Result<_, _>>().unwrap();
+    let tape: Vec<_> = from_orderlog(records()).collect();
+    assert_eq!(signed.len(), tape.len());
+
+    let tape_flow: i64 = tape
+        .iter()
+        .map(|t| match t.aggressor {
+            Side::Buy => t.amount,
+            Side::Sell => -t.amount,
+            Side::UNKNOWN => 0,
+        })
+        .sum();
+    assert_eq!(signed.iter().map(|t| t.signed_amount()).sum::<i64>(), tape_flow);
+    assert_eq!(
+        signed.iter().map(|t| t.amount).sum::<i64>(),
+        tape.iter().map(|t| t.amount).sum::<i64>()
+    );
+    let exhausted = signed.iter().filter(|t| t.level_exhausted).count();
+    assert!(exhausted > 0 && exhausted < signed.len());
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::*;
+use qsh_rs::header;
+use qsh_rs::time;
+use qsh_rs::types::{AuxInfo, Deal};
+use qsh_rs::utils::join::{frame_timed, with_auxinfo, AuxSnapshot};
+
+const T0: i64 = 637200251900000000;
+
+fn aux(frame_time_delta: i64, price: i64, oi: i64) -> AuxInfo {
+    AuxInfo { frame_time_delta, price, oi, hi_limit: 110, low_limit: 90, ..Default::default() }
+}
+
+#[test]
+fn as_of() {
+    let enc = Encoder::new(0x60, T0);
+    let hdr = header(&mut reader(&enc.buf)).unwrap();
+    let t0 = time::recording_time_to_unix_ns(T0);
+    let ms = 1_000_000;
+
+    let aux = [aux(10, 100, 5), aux(10, 101, 6), aux(0, 102, 7), aux(30, 103, 8)];
+    let aux = frame_timed(aux, &hdr).unwrap();
+    let deals =
+        [5, 10, 19, 20, 21, 100].map(|dt| (t0 + dt * ms, Deal { price: dt, ..Default::default() }));
+    let joined: Vec<_> = with_auxinfo(deals, aux).collect();
+
+    let summary: Vec<_> =
+        joined.iter().map(|((_, deal), s)| (deal.price, s.ts, s.price, s.oi)).collect();
+    let at = |dt| Some(t0 + dt * ms);
+    assert_eq!(
+        summary,
+        [
+            // before the first AuxInfo record
+            (5, None, 0, 0),
+            // the AuxInfo record of the same time is available
+            (10, at(10), 100, 5),
+            (19, at(10), 100, 5),
+            // the last of the records of the same time
+            (20, at(20), 102, 7),
+            (21, at(20), 102, 7),
+            (100, at(50), 103, 8),
+        ]
+    );
+    assert_eq!(joined[0].1, AuxSnapshot::default());
+    assert_eq!((joined[1].1.hi_limit, joined[1].1.low_limit), (110, 90));
+
+    // a single AuxInfo record past the primary ones is read ahead
+    let mut aux = (0..).map(|i| (t0 + i * ms, AuxInfo { oi: i, ..Default::default() }));
+    let joined: Vec<_> = with_auxinfo([(t0 + 3 * ms, ())], aux.by_ref()).collect();
+    assert_eq!(joined[0].1.oi, 3);
+    assert_eq!(aux.next().map(|(_, a)| a.oi), Some(5));
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::*;
+use qsh_rs::orderbook::{NopListener, OrderBook};
+use qsh_rs::types::{L2Message, OLFlags, Side};
+use qsh_rs::utils::l2::{
+    snapshots, snapshots_with, timed_snapshots, DegenerateBooks, SnapshotTrigger,
+};
+use qsh_rs::utils::l3tol2::convert;
+use qsh_rs::QshError;
+
+fn quote(side: Side, price: i64, size: i64) -> L2Message {
+    L2Message::Quote { side, price, size }
+}
+
+#[test]
+fn orderbook_snapshots() {
+    let records: Vec<_> =
+        Workload::new(11, 20).take(10_000).map(|r| with(r, OLFlags::TxEnd)).collect();
+    let txs: Vec<_> = convert(records.clone().into_iter(), 0).map(Result::unwrap).collect();
+    let sampled: Vec<_> =
+        snapshots(txs.iter().flatten().cloned(), 5, SnapshotTrigger::EveryMessage)
+            .map(Result::unwrap)
+            .collect();
+    assert_eq!(sampled.len(), txs.iter().map(Vec::len).sum::<usize>());
+
+    // the snapshot after the last message of the transaction
+    let mut book = OrderBook::default();
+    let mut applied = 0;
+    for (rec, tx) in records.into_iter().zip(&txs) {
+        book.apply(rec, &mut NopListener).unwrap();
+        applied += tx.len();
+        if applied > 0 {
+            let sample = &sampled[applied - 1];
+            assert_eq!(sample.snapshot.flatten(5), book.snapshot_upto(5).0.flatten(5));
+            assert!(sample.snapshot.bids.len() <= 5 && sample.snapshot.asks.len() <= 5);
+        }
+    }
+
+    let every = SnapshotTrigger::EveryNMessages(100);
+    let nth: Vec<_> = snapshots(txs.into_iter().flatten(), 5, every).map(Result::unwrap).collect();
+    assert_eq!(nth.len(), sampled.len() / 100);
+    assert!(nth.iter().zip(sampled.iter().skip(99).step_by(100)).all(|(a, b)| a == b));
+}
+
+#[test]
+fn interval_snapshots() {
+    let messages = [
+        (5, quote(Side::Buy, 100, 1)),
+        (7, quote(Side::Sell, 101, 2)),
+        // the boundary of 10, the book before the message
+        (12, quote(Side::Buy, 100, 3)),
+        // the boundaries of 20 and 30 at once
+        (31, quote(Side::Sell, 99, 1)),
+        (35, L2Message::Trade { side: Side::Buy, price: 99, size: 1 }),
+        (40, L2Message::Clear),
+        (55, quote(Side::Buy, 100, 1)),
+    ];
+    let every = SnapshotTrigger::EveryInterval(10);
+    let sampled: Vec<_> = timed_snapshots(messages.clone(), 0, every, DegenerateBooks::Keep)
+        .map(Result::unwrap)
+        .collect();
+    let summary: Vec<_> = sampled
+        .iter()
+        .map(|s| (s.snapshot.ts, s.snapshot.bids.clone(), s.snapshot.asks.clone(), s.crossed))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (7, vec![(100, 1)], vec![(101, 2)], false),
+            (12, vec![(100, 3)], vec![(101, 2)], false),
+            (35, vec![(100, 3)], vec![(99, 1), (101, 2)], true),
+            (40, vec![], vec![], false),
+        ]
+    );
+    assert!(sampled[3].is_partial() && !sampled[0].is_partial());
+
+    // the crossed and the empty books are left out
+    let kept: Vec<_> = timed_snapshots(messages, 0, every, DegenerateBooks::Skip)
+        .map(|s| s.unwrap().snapshot.ts)
+        .collect();
+    assert_eq!(kept, [7, 12]);
+}
+
+#[test]
+fn untimed_snapshots() {
+    let messages = vec![
+        L2Message::Snapshot { ts: 1_000, bids: vec![(100, 1)], asks: vec![(102, 1)] },
+        quote(Side::Sell, 101, 1),
+        L2Message::Snapshot { ts: 2_500, bids: vec![(100, 2)], asks: vec![(101, 1)] },
+        quote(Side::Buy, 99, 1),
+        quote(Side::Sell, 101, 5),
+    ];
+    // the time is the one of the embedded snapshots
+    let every = SnapshotTrigger::EveryInterval(1_000);
+    let sampled: Vec<_> = snapshots(messages.clone(), 1, every).map(Result::unwrap).collect();
+    assert_eq!(sampled.len(), 1);
+    assert_eq!(sampled[0].snapshot.ts, 1_000);
+    assert_eq!(
+        (sampled[0].snapshot.bids.clone(), sampled[0].snapshot.asks.clone()),
+        (vec![(100, 1)], vec![(101, 1)])
+    );
+
+    let every = SnapshotTrigger::EveryNMessages(2);
+    let ts: Vec<_> = snapshots(messages, 0, every).map(|s| s.unwrap().snapshot.ts).collect();
+    assert_eq!(ts, [1_000, 2_500]);
+
+    // the iteration stops at the failing message
+    let failing = [quote(Side::Buy, 100, 1), L2Message::Remove { side: Side::Sell, price: 1 }];
+    let mut iter = snapshots_with(
+        failing.into_iter().cycle(),
+        0,
+        SnapshotTrigger::EveryMessage,
+        DegenerateBooks::Skip,
+    );
+    assert!(matches!(iter.next(), Some(Err(QshError::InvalidState(_)))));
+    assert!(iter.next().is_none());
+}
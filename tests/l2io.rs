@@ -0,0 +1,71 @@
+mod common;
+
+use common::*;
+use qsh_rs::orderbook::{L2Book, OrderBook, VecSink};
+use qsh_rs::types::{L2Message, Side};
+use qsh_rs::utils::l2io::{read, L2Reader, L2Writer};
+use qsh_rs::QshError;
+use std::io::Write;
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut enc = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+    enc.write_all(bytes).unwrap();
+    enc.finish().unwrap()
+}
+
+#[test]
+fn roundtrip() {
+    let (mut book, mut messages) = (OrderBook::default(), vec![]);
+    for rec in Workload::new(5, 30).take(10_000) {
+        book.apply(rec, &mut VecSink(&mut messages)).unwrap();
+    }
+    messages.push(L2Message::Snapshot { ts: 1, bids: vec![(99, 1)], asks: vec![(101, 2)] });
+    messages.push(L2Message::Clear);
+
+    let mut writer = L2Writer::new(vec![]);
+    messages.iter().for_each(|msg| writer.write(msg).unwrap());
+    let buf = writer.finish().unwrap();
+
+    let decoded: Vec<_> = L2Reader::new(&buf[..]).map(Result::unwrap).collect();
+    assert_eq!(format!("{decoded:?}"), format!("{messages:?}"));
+
+    let mut l2 = L2Book::default();
+    decoded.into_iter().take(messages.len() - 2).for_each(|msg| l2.apply(msg).unwrap());
+    for side in Side::both() {
+        assert_eq!(l2.depth(side), book.depth(side));
+    }
+    assert_eq!(l2.snapshot(0).1, book.snapshot(0).1);
+
+    let path = std::env::temp_dir().join(format!("qsh-l2io-{}.bin", std::process::id()));
+    std::fs::write(&path, &buf).unwrap();
+    let count = read(&path).unwrap().map(Result::unwrap).count();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(count, messages.len());
+
+    assert_eq!(L2Reader::new(&L2Writer::new(vec![]).finish().unwrap()[..]).count(), 0);
+}
+
+#[test]
+fn cut_off() {
+    let msg = L2Message::Quote { side: Side::Buy, price: 100_000, size: 7 };
+    let frame = bincode::encode_to_vec(&msg, bincode::config::standard()).unwrap();
+    let frames = [&frame[..], &frame[..]].concat();
+
+    // the end of the data inside the second message, the gzip stream is intact
+    let cut = gzip(&frames[..frames.len() - 1]);
+    let mut reader = L2Reader::new(&cut[..]);
+    assert!(reader.next().unwrap().is_ok());
+    assert!(matches!(reader.next(), Some(Err(QshError::Corrupted(_)))));
+    assert!(reader.next().is_none());
+
+    // the gzip stream cut off
+    let compressed = gzip(&frames);
+    let mut reader = L2Reader::new(&compressed[..compressed.len() - 4]);
+    assert!(matches!(reader.find_map(Result::err), Some(QshError::Truncated(_))));
+    assert!(reader.next().is_none());
+
+    // not a message
+    let garbage = gzip(&[0xff; 4]);
+    let mut reader = L2Reader::new(&garbage[..]);
+    assert!(matches!(reader.next(), Some(Err(QshError::Parsing(_)))));
+}
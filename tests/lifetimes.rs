@@ -0,0 +1,77 @@
+mod common;
+
+use common::*;
+use qsh_rs::types::{OLFlags, Side};
+use qsh_rs::utils::lifetimes::{analyze, CloseReason};
+
+const TS: i64 = 63_800_000_000_000;
+
+#[test]
+fn partial_fills() {
+    let records = [
+        at(add(1, Side::Buy, 100, 10), TS),
+        at(add(2, Side::Sell, 101, 5), TS),
+        at(fill(1, Side::Buy, 100, 3, 7), TS + 1),
+        at(fill(1, Side::Buy, 100, 3, 4), TS + 2),
+        at(add(3, Side::Sell, 102, 5), TS + 2),
+        at(fill(1, Side::Buy, 100, 4, 0), TS + 5),
+        // partial cancel, the order is live
+        at(cancel(2, Side::Sell, 101, 2), TS + 6),
+        at(fill(2, Side::Sell, 101, 1, 1), TS + 7),
+        at(cancel(2, Side::Sell, 101, 0), TS + 8),
+        // of the order added before the stream start
+        at(fill(9, Side::Buy, 99, 1, 0), TS + 9),
+        at(with(add(4, Side::Buy, 99, 1), OLFlags::NonSystem), TS + 9),
+    ];
+    let lives: Vec<_> = analyze(records).collect();
+    assert_eq!(lives.iter().map(|l| l.order_id).collect::<Vec<_>>(), [1, 2, 3]);
+
+    let (first, second, open) = (&lives[0], &lives[1], &lives[2]);
+    assert_eq!(first.reason, CloseReason::Filled);
+    assert_eq!((first.filled, first.total, first.partial_fills), (10, 10, 2));
+    assert_eq!(first.duration(), 5_000_000);
+    assert_eq!(first.fill_ratio(), 1.);
+
+    assert_eq!(second.reason, CloseReason::Canceled);
+    assert_eq!((second.filled, second.total, second.partial_fills), (1, 5, 1));
+    assert_eq!(second.duration(), 8_000_000);
+    assert_eq!(second.fill_ratio(), 0.2);
+
+    // closed at the last record of the stream
+    assert_eq!((open.reason, open.price, open.side), (CloseReason::Open, 102, Side::Sell));
+    assert_eq!(open.duration(), 7_000_000);
+}
+
+#[test]
+fn moves_and_sessions() {
+    let records = [
+        at(add(1, Side::Buy, 100, 5), TS),
+        at(add(2, Side::Buy, 99, 5), TS),
+        at(add(3, Side::Sell, 105, 5), TS),
+        at(with(cancel(1, Side::Buy, 100, 0), OLFlags::Moved), TS + 1),
+        at(with(add(1, Side::Buy, 98, 5), OLFlags::Moved), TS + 1),
+        // moved without the Cancel record
+        at(with(add(2, Side::Buy, 97, 5), OLFlags::Moved), TS + 2),
+        at(with(remove(3, Side::Sell, 105), OLFlags::CrossTrade), TS + 3),
+        at(with(add(4, Side::Sell, 104, 5), OLFlags::NewSession), TS + 10),
+    ];
+    let reasons: Vec<_> = analyze(records).map(|l| (l.order_id, l.price, l.reason)).collect();
+    assert_eq!(
+        reasons,
+        [
+            (1, 100, CloseReason::Moved),
+            (2, 99, CloseReason::Moved),
+            (3, 105, CloseReason::Canceled),
+            (1, 98, CloseReason::SessionEnd),
+            (2, 97, CloseReason::SessionEnd),
+            (4, 104, CloseReason::Open),
+        ]
+    );
+
+    // every Add yields the single life
+    let records: Vec<_> = Workload::new(11, 20).take(20_000).collect();
+    let adds = records.iter().filter(|r| r.order_flags.contains(qsh_rs::types::OLFlagSet::ADD));
+    let lives: Vec<_> = analyze(records.iter().copied()).collect();
+    assert_eq!(lives.len(), adds.count());
+    assert!(lives.iter().all(|l| l.filled <= l.total && l.duration() >= 0));
+}
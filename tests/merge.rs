@@ -0,0 +1,63 @@
+mod common;
+
+use common::*;
+use qsh_rs::source::{record_source, Record};
+use qsh_rs::time;
+use qsh_rs::utils::merge::{by_time, TimedSource};
+use qsh_rs::{header, QshError};
+
+const T0: i64 = 637200251900000000;
+
+fn source(bytes: &[u8]) -> TimedSource<'_> {
+    let mut parser = reader(bytes);
+    let header = header(&mut parser).unwrap();
+    TimedSource::new(&header, record_source(header.stream, parser).unwrap()).unwrap()
+}
+
+#[test]
+fn merge() {
+    use qsh_rs::types::Side;
+
+    let mut ordlog = Encoder::new(0x70, T0);
+    for (delta, id) in [(1, 1), (2, 2), (0, 3)] {
+        ordlog.orderlog(delta, &add(id, Side::Buy, 100, 1));
+    }
+    // 2ms later
+    let mut quotes = Encoder::new(0x10, T0 + 20_000);
+    quotes.quotes(1, &[(100, 1)]);
+    quotes.quotes(2, &[(101, -1)]);
+
+    let merged: Vec<_> =
+        by_time(vec![source(&ordlog.buf), source(&quotes.buf)]).map(Result::unwrap).collect();
+    let t0 = time::ns_to_ms(time::recording_time_to_unix_ns(T0));
+    let order: Vec<_> = merged.iter().map(|(ix, ts, _)| (*ix, time::ns_to_ms(*ts) - t0)).collect();
+    // the ties go in the order of the sources
+    assert_eq!(order, [(0, 1), (0, 3), (0, 3), (1, 3), (1, 5)]);
+    let ids: Vec<_> = merged
+        .iter()
+        .filter_map(|(_, _, rec)| match rec {
+            Record::OrderLog(r) => Some(r.order_id),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ids, [1, 2, 3]);
+    assert_eq!(by_time(vec![]).count(), 0);
+}
+
+#[test]
+fn merge_error() {
+    use qsh_rs::types::Side;
+
+    let mut ordlog = Encoder::new(0x70, T0);
+    ordlog.orderlog(1, &add(1, Side::Buy, 100, 1));
+    ordlog.orderlog(1, &add(2, Side::Buy, 100, 1));
+    let mut quotes = Encoder::new(0x10, T0);
+    quotes.quotes(5, &[(100, 1)]);
+
+    // the record cut off
+    let cut = &ordlog.buf[..ordlog.buf.len() - 1];
+    let merged: Vec<_> = by_time(vec![source(cut), source(&quotes.buf)]).collect();
+    assert_eq!(merged.len(), 2);
+    assert!(matches!(merged[0], Ok((0, _, Record::OrderLog(_)))));
+    assert!(matches!(merged[1], Err(QshError::Corrupted(_))));
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use common::*;
+use qsh_rs::types::{L3Message, OLFlags, OrderLog, Side, UID};
+use qsh_rs::utils::moex2conv::{chunk_transaction, moex_to_l3, TxChunk};
+
+// chunks by the order ids
+#[derive(Debug, PartialEq)]
+enum Shape {
+    Standalone(UID),
+    Match(Vec<UID>, Vec<UID>),
+}
+
+fn shape(tx: Vec<OrderLog>) -> Vec<Shape> {
+    let ids = |recs: Vec<OrderLog>| recs.iter().map(|r| r.order_id).collect();
+    chunk_transaction(tx)
+        .unwrap()
+        .into_iter()
+        .map(|chunk| match chunk {
+            TxChunk::Standalone(rec) => Shape::Standalone(rec.order_id),
+            TxChunk::Match { orders, fills } => Shape::Match(ids(orders), ids(fills)),
+        })
+        .collect()
+}
+
+fn iok(rec: OrderLog) -> OrderLog {
+    with(rec, OLFlags::Counter)
+}
+
+#[test]
+fn no_fills() {
+    let tx = vec![
+        add(1, Side::Buy, 100, 5),
+        // the IOK of no counterpart, removed right away
+        iok(add(2, Side::Buy, 101, 1)),
+        iok(remove(2, Side::Buy, 101)),
+        cancel(3, Side::Sell, 102, 0),
+        with(remove(4, Side::Sell, 103), OLFlags::CrossTrade),
+    ];
+    assert_eq!(shape(tx), [Shape::Standalone(1), Shape::Standalone(3), Shape::Standalone(4)]);
+}
+
+#[test]
+fn sweep() {
+    // the limit order takes two ask levels and rests with the rest
+    let tx = vec![
+        add(1, Side::Buy, 102, 10),
+        fill(7, Side::Sell, 101, 2, 0),
+        fill(1, Side::Buy, 101, 2, 8),
+        fill(8, Side::Sell, 102, 3, 1),
+        fill(1, Side::Buy, 102, 3, 5),
+    ];
+    assert_eq!(shape(tx.clone()), [Shape::Match(vec![1], vec![7, 1, 8, 1])]);
+
+    let msgs: Vec<_> = moex_to_l3(tx).map(Result::unwrap).collect();
+    let [L3Message::Trade(a), L3Message::Trade(b), L3Message::Add(rest)] = msgs[0][..] else {
+        panic!("{msgs:?}");
+    };
+    assert_eq!((a.order_id, a.amount, b.order_id, b.amount), (7, 2, 8, 3));
+    assert_eq!((rest.order_id, rest.price, rest.amount_rest), (1, 102, 5));
+}
+
+#[test]
+fn self_match() {
+    // the order 1 is filled by the order 2 of the same transaction
+    let tx = vec![
+        add(1, Side::Buy, 101, 1),
+        add(2, Side::Sell, 100, 4),
+        fill(1, Side::Buy, 101, 1, 0),
+        fill(2, Side::Sell, 101, 1, 3),
+        fill(9, Side::Buy, 100, 2, 0),
+        fill(2, Side::Sell, 100, 2, 1),
+        add(3, Side::Buy, 99, 1),
+    ];
+    assert_eq!(shape(tx), [Shape::Match(vec![1, 2], vec![1, 2, 9, 2]), Shape::Standalone(3)]);
+}
+
+#[test]
+fn iok_rest_removal() {
+    let tx = vec![
+        iok(add(1, Side::Buy, 101, 5)),
+        fill(9, Side::Sell, 101, 2, 0),
+        fill(1, Side::Buy, 101, 2, 3),
+        iok(remove(1, Side::Buy, 101)),
+    ];
+    assert_eq!(shape(tx.clone()), [Shape::Match(vec![1], vec![9, 1])]);
+    let msgs: Vec<_> = moex_to_l3(tx).map(Result::unwrap).collect();
+    assert!(
+        matches!(msgs[..], [ref m] if matches!(m[..], [L3Message::Trade(t)] if t.order_id == 9))
+    );
+
+    // the rest of the limit order removed by CrossTrade stays with its fills
+    let tx = vec![
+        add(2, Side::Buy, 101, 5),
+        fill(9, Side::Sell, 101, 2, 0),
+        fill(2, Side::Buy, 101, 2, 3),
+        with(remove(2, Side::Buy, 101), OLFlags::CrossTrade),
+    ];
+    assert_eq!(shape(tx), [Shape::Match(vec![2], vec![9, 2, 2])]);
+}
+
+#[test]
+fn interleaved_adds() {
+    let tx = vec![
+        add(5, Side::Sell, 110, 1),
+        add(1, Side::Buy, 101, 2),
+        fill(9, Side::Sell, 101, 2, 0),
+        fill(1, Side::Buy, 101, 2, 0),
+        add(6, Side::Sell, 111, 1),
+        cancel(4, Side::Buy, 95, 0),
+        iok(add(2, Side::Sell, 99, 1)),
+        fill(8, Side::Buy, 99, 1, 3),
+        fill(2, Side::Sell, 99, 1, 0),
+        add(7, Side::Sell, 112, 1),
+    ];
+    assert_eq!(
+        shape(tx),
+        [
+            Shape::Standalone(5),
+            Shape::Match(vec![1], vec![9, 1]),
+            Shape::Standalone(6),
+            Shape::Standalone(4),
+            Shape::Match(vec![2], vec![8, 2]),
+            Shape::Standalone(7),
+        ]
+    );
+}
+
+#[test]
+fn unknown_remove() {
+    let mut rec = remove(1, Side::Buy, 100);
+    rec.order_flags = OLFlags::CrossTrade.into();
+    assert!(chunk_transaction(vec![rec]).is_err());
+    assert!(moex_to_l3(vec![rec]).any(|msgs| msgs.is_err()));
+}
@@ -0,0 +1,1425 @@
+mod common;
+
+use common::*;
+use qsh_rs::orderbook::{NopListener, OrderBook, VecSink};
+use qsh_rs::time;
+use qsh_rs::types::Side;
+
+fn book() -> OrderBook {
+    let mut book = OrderBook::default();
+    for rec in [
+        add(1, Side::Buy, 100, 5),
+        add(2, Side::Buy, 100, 3),
+        add(3, Side::Buy, 99, 7),
+        add(4, Side::Sell, 101, 2),
+        add(5, Side::Sell, 103, 4),
+        add(6, Side::Sell, 101, 1),
+    ] {
+        book.add(rec, &mut NopListener).unwrap();
+    }
+    book
+}
+
+#[test]
+fn iter_levels() {
+    let book = book();
+
+    let bids: Vec<_> = book.iter_levels(Side::Buy).map(|(p, v, o)| (*p, *v, o.len())).collect();
+    let asks: Vec<_> = book.iter_levels(Side::Sell).map(|(p, v, o)| (*p, *v, o.len())).collect();
+    assert_eq!(bids, [(100, 8, 2), (99, 7, 1)]);
+    assert_eq!(asks, [(101, 3, 2), (103, 4, 1)]);
+
+    let ids: Vec<_> = book.orders_at(Side::Buy, 100).iter().map(|r| r.order_id).collect();
+    assert_eq!(ids, [1, 2]);
+    assert!(book.orders_at(Side::Sell, 102).is_empty());
+    assert_eq!(book.level_at(Side::Sell, 103).map(|l| l.1), Some(4));
+    assert!(book.level_at(Side::Buy, 101).is_none());
+}
+
+#[test]
+fn queue_position() {
+    let mut book = book();
+    book.add(add(7, Side::Buy, 100, 4), &mut NopListener).unwrap();
+
+    let pos = book.find_order(7).unwrap();
+    assert_eq!((pos.side, pos.price, pos.index, pos.ahead), (Side::Buy, 100, 2, 8));
+
+    // partial fill of the head of the queue
+    book.trade(fill(1, Side::Buy, 100, 2, 3), &mut NopListener).unwrap();
+    assert_eq!(book.queue_ahead(7), Some(6));
+
+    // the head is fully filled
+    book.trade(fill(1, Side::Buy, 100, 3, 0), &mut NopListener).unwrap();
+    let pos = book.find_order(7).unwrap();
+    assert_eq!((pos.index, pos.ahead), (1, 3));
+
+    assert_eq!(book.queue_ahead(1), None);
+    assert_eq!(book.queue_ahead(4), Some(0));
+}
+
+#[test]
+fn order_index() {
+    use qsh_rs::types::{L2Message, OLMsgType};
+    use std::collections::{BTreeMap, HashMap};
+
+    // naive reference: aggregated levels and the live orders
+    let mut levels: [BTreeMap<i64, i64>; 2] = Default::default();
+    let mut orders = HashMap::new();
+
+    let mut book = OrderBook::default();
+    let mut events = vec![];
+    for rec in Workload::new(7, 20).take(200_000) {
+        events.clear();
+        let lvl = &mut levels[(rec.side == Side::Sell) as usize];
+        let expected = match rec.event {
+            OLMsgType::Add => {
+                book.add(rec, &mut VecSink(&mut events)).unwrap();
+                orders.insert(rec.order_id, rec.amount);
+                *lvl.entry(rec.price).or_default() += rec.amount;
+                lvl[&rec.price]
+            }
+            _ => {
+                let amount = orders[&rec.order_id];
+                if rec.event == OLMsgType::Fill {
+                    book.trade(rec, &mut VecSink(&mut events)).unwrap();
+                } else {
+                    book.cancel(rec, &mut VecSink(&mut events)).unwrap();
+                }
+                if rec.amount_rest == 0 {
+                    orders.remove(&rec.order_id);
+                } else {
+                    orders.insert(rec.order_id, rec.amount_rest);
+                }
+                *lvl.get_mut(&rec.price).unwrap() -= amount - rec.amount_rest;
+                if lvl[&rec.price] == 0 {
+                    lvl.remove(&rec.price);
+                }
+                lvl.get(&rec.price).copied().unwrap_or(0)
+            }
+        };
+
+        // the print precedes the level update
+        if rec.event == OLMsgType::Fill {
+            let trade = L2Message::Trade { side: rec.side, price: rec.price, size: rec.amount };
+            assert_eq!(format!("{:?}", events.remove(0)), format!("{trade:?}"));
+        }
+        match events[..] {
+            [L2Message::Quote { side, price, size }] => {
+                assert_eq!((side, price, size), (rec.side, rec.price, expected))
+            }
+            [L2Message::Remove { side, price }] => {
+                assert_eq!((side, price, expected), (rec.side, rec.price, 0))
+            }
+            _ => panic!("unexpected events {events:?}"),
+        }
+        assert_eq!(book.len(), orders.len());
+        assert_eq!(book.find_order(rec.order_id).is_some(), orders.contains_key(&rec.order_id));
+    }
+}
+
+#[test]
+fn max_depth() {
+    use qsh_rs::types::OLMsgType;
+
+    let (mut full, mut bounded) = (OrderBook::default(), OrderBook::with_max_depth(5));
+    let (mut full_events, mut bounded_events) = (vec![], vec![]);
+    for rec in Workload::new(11, 40).take(20_000) {
+        full_events.clear();
+        bounded_events.clear();
+        for (book, events) in [(&mut full, &mut full_events), (&mut bounded, &mut bounded_events)] {
+            match rec.event {
+                OLMsgType::Add => book.add(rec, &mut VecSink(events)),
+                OLMsgType::Fill => book.trade(rec, &mut VecSink(events)),
+                _ => book.cancel(rec, &mut VecSink(events)),
+            }
+            .unwrap();
+        }
+        assert_eq!(format!("{full_events:?}"), format!("{bounded_events:?}"));
+        assert_eq!(full.len(), bounded.len());
+
+        for side in Side::both() {
+            assert_eq!(bounded.depth(side), full.depth(side).min(5));
+            let top = |b: &OrderBook| {
+                b.iter_levels(side).take(5).map(|(p, v, _)| (*p, *v)).collect::<Vec<_>>()
+            };
+            assert_eq!(top(&full), top(&bounded));
+        }
+    }
+    for side in Side::both() {
+        let queues = |b: &OrderBook| {
+            b.iter_levels(side)
+                .take(5)
+                .map(|(_, _, o)| o.iter().map(|r| (r.order_id, r.amount)).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(queues(&full), queues(&bounded));
+    }
+    // only the top levels keep the full records
+    let resident: usize = Side::both()
+        .into_iter()
+        .flat_map(|side| bounded.iter_levels(side).map(|(_, _, o)| o.len()).collect::<Vec<_>>())
+        .sum();
+    assert!(resident < full.len() / 4);
+}
+
+#[test]
+fn aggressor() {
+    use qsh_rs::orderbook::infer_aggressor;
+    use qsh_rs::types::Deal;
+
+    let book = book();
+    let deal = |price, side| Deal { price, side, ..Default::default() };
+    assert_eq!(infer_aggressor(&deal(101, Side::UNKNOWN), &book), Side::Buy);
+    assert_eq!(infer_aggressor(&deal(103, Side::UNKNOWN), &book), Side::Buy);
+    assert_eq!(infer_aggressor(&deal(100, Side::UNKNOWN), &book), Side::Sell);
+    assert_eq!(infer_aggressor(&deal(100, Side::Buy), &book), Side::Buy);
+    assert_eq!(infer_aggressor(&deal(100, Side::UNKNOWN), &OrderBook::default()), Side::UNKNOWN);
+}
+
+#[test]
+fn volume_queries() {
+    let mut book = book();
+    book.add(add(7, Side::Sell, 106, 9), &mut NopListener).unwrap();
+
+    assert_eq!(book.total_volume(Side::Buy), 15);
+    assert_eq!(book.total_volume(Side::Sell), 16);
+    assert_eq!(book.volume_within_ticks(Side::Buy, 0), 8);
+    assert_eq!(book.volume_within_ticks(Side::Buy, 1), 15);
+    assert_eq!(book.volume_within_ticks(Side::Sell, 2), 7);
+    assert_eq!(book.volume_within_ticks(Side::Sell, 4), 7);
+    assert_eq!(book.volume_within_ticks(Side::Sell, 5), 16);
+    assert_eq!(book.volume_better_than(Side::Buy, 99), 8);
+    assert_eq!(book.volume_better_than(Side::Buy, 100), 0);
+    assert_eq!(book.volume_better_than(Side::Sell, 104), 7);
+    assert_eq!(OrderBook::default().volume_within_ticks(Side::Buy, 10), 0);
+
+    // far levels of the bounded book are accounted
+    let mut bounded = OrderBook::with_max_depth(1);
+    for side in Side::both() {
+        for (&p, _, orders) in book.iter_levels(side) {
+            orders.iter().for_each(|&r| {
+                bounded.add(add(r.order_id, side, p, r.amount), &mut NopListener).unwrap()
+            });
+        }
+        assert_eq!(bounded.total_volume(side), book.total_volume(side));
+        assert_eq!(bounded.volume_within_ticks(side, 3), book.volume_within_ticks(side, 3));
+    }
+}
+
+#[test]
+fn imbalance() {
+    let mut book = book();
+    // bids: 100x8, 99x7; asks: 101x3, 103x4
+    assert_eq!(book.imbalance(1), Some(8. / 11.));
+    assert_eq!(book.imbalance(2), Some(15. / 22.));
+    assert_eq!(book.microprice(), Some((100. * 3. + 101. * 8.) / 11.));
+
+    let (bid, ask) = ((100. * 8. + 99. * 7.) / 15., (101. * 3. + 103. * 4.) / 7.);
+    assert_eq!(book.weighted_mid(2), Some((bid * 7. + ask * 15.) / 22.));
+    assert_eq!(book.weighted_mid(1), book.microprice());
+    // all the levels
+    assert_eq!(book.weighted_mid(0), book.weighted_mid(2));
+    assert_eq!(book.weighted_mid(usize::MAX), book.weighted_mid(2));
+
+    for (id, p) in [(4, 101), (6, 101), (5, 103)] {
+        book.cancel(cancel(id, Side::Sell, p, 0), &mut NopListener).unwrap();
+    }
+    assert_eq!(book.imbalance(1), None);
+    assert_eq!(book.microprice(), None);
+    assert_eq!(book.weighted_mid(5), None);
+    assert_eq!(book.weighted_mid(0), None);
+}
+
+#[test]
+fn vwap_for_size() {
+    use qsh_rs::orderbook::FillEstimate;
+
+    // asks: 101x3, 103x4
+    let book = book();
+    let est = |vwap, worst_price, levels_consumed, unfilled| {
+        Some(FillEstimate { vwap, worst_price, levels_consumed, unfilled })
+    };
+    assert_eq!(book.vwap_for_size(Side::Buy, 3), est(101., 101, 1, 0));
+    assert_eq!(book.vwap_for_size(Side::Buy, 5), est((303. + 206.) / 5., 103, 2, 0));
+    assert_eq!(book.vwap_for_size(Side::Buy, 10), est((303. + 412.) / 7., 103, 2, 3));
+    assert_eq!(book.vwap_for_size(Side::Sell, 2), est(100., 100, 1, 0));
+    assert_eq!(OrderBook::default().vwap_for_size(Side::Buy, 1), None);
+}
+
+#[test]
+fn apply_tx() {
+    use qsh_rs::types::{L2Message, OLFlags, OLMsgType};
+
+    let mut book = book();
+    let mut events = vec![];
+    let tx = [with(add(7, Side::Buy, 98, 1), OLFlags::NewSession), add(8, Side::Sell, 102, 2)];
+    book.apply_tx(&tx, &mut VecSink(&mut events)).unwrap();
+    assert!(matches!(events[0], L2Message::Clear));
+    assert_eq!(events.len(), 3);
+    assert_eq!((book.best_bid(), book.best_ask()), (Some((98, 1)), Some((102, 2))));
+
+    book.apply_tx(&[fill(8, Side::Sell, 102, 2, 0)], &mut NopListener).unwrap();
+    assert_eq!(book.best_ask(), None);
+
+    let mut unknown = add(9, Side::Buy, 98, 1);
+    unknown.event = OLMsgType::UNKNOWN;
+    assert!(book.apply(unknown, &mut NopListener).is_err());
+}
+
+#[test]
+fn l2book() {
+    use qsh_rs::orderbook::L2Book;
+    use qsh_rs::types::{L2Message, Quotes};
+
+    // replays the L3 book events
+    let mut book = OrderBook::default();
+    let mut l2 = L2Book::default();
+    let mut events = vec![];
+    for rec in Workload::new(3, 20).take(20_000) {
+        book.apply(rec, &mut VecSink(&mut events)).unwrap();
+        events.drain(..).for_each(|e| l2.apply(e).unwrap());
+
+        for side in Side::both() {
+            assert_eq!(l2.depth(side), book.depth(side));
+        }
+        let depth = book.depth(Side::Buy).min(book.depth(Side::Sell));
+        assert_eq!(l2.snapshot(depth).1, book.snapshot(depth).1);
+        assert!(!l2.is_crossed());
+    }
+
+    let quotes = Quotes { bid: vec![(99, 7), (100, 8)], ask: vec![(101, 3)], ..Default::default() };
+    let mut l2 = L2Book::from_quotes(&quotes);
+    assert_eq!(l2.snapshot(2).1, [100, 8, 101, 3, 99, 7, 0, 0]);
+    assert_eq!(l2.mid_price(), 100.5);
+
+    l2.apply(L2Message::Quote { side: Side::Buy, price: 101, size: 1 }).unwrap();
+    assert!(l2.is_crossed());
+    assert!(l2.apply(L2Message::Remove { side: Side::Sell, price: 102 }).is_err());
+    l2.apply(L2Message::Clear).unwrap();
+    assert_eq!((l2.best_bid(), l2.best_ask()), (None, None));
+}
+
+#[test]
+fn partition_by_reuse() {
+    use qsh_rs::orderbook::{tx_end, PartitionBy};
+    use qsh_rs::types::OLFlags;
+
+    let records: Vec<_> = Workload::new(5, 10)
+        .take(1_000)
+        .enumerate()
+        .map(|(i, r)| if i % 7 == 3 || i % 5 == 0 { with(r, OLFlags::TxEnd) } else { r })
+        .collect();
+
+    let owned: Vec<_> = records.iter().copied().partition_by(tx_end).collect();
+    let mut reuse = records.iter().copied().partition_by_reuse(tx_end);
+    let mut n = 0;
+    while let Some(tx) = reuse.next_partition() {
+        let ids =
+            |tx: &[qsh_rs::types::OrderLog]| tx.iter().map(|r| r.order_id).collect::<Vec<_>>();
+        assert_eq!(ids(tx), ids(&owned[n]));
+        n += 1;
+    }
+    assert_eq!(n, owned.len());
+
+    let mut n = 0;
+    records.iter().copied().partition_by_reuse(tx_end).for_each_partition(|tx| {
+        assert_eq!(tx.len(), owned[n].len());
+        assert!(tx.iter().zip(&owned[n]).all(|(a, b)| a.order_id == b.order_id));
+        n += 1;
+    });
+    assert_eq!(n, owned.len());
+}
+
+#[test]
+fn diff() {
+    use qsh_rs::orderbook::LevelDiff;
+
+    let (left, mut right) = (book(), book());
+    assert!(left.diff(&right).is_empty());
+    assert_eq!(left.checksum(5), right.checksum(5));
+    assert_eq!(left.diff(&right).to_string(), "no differences");
+
+    right.cancel(cancel(3, Side::Buy, 99, 0), &mut NopListener).unwrap();
+    right.trade(fill(1, Side::Buy, 100, 5, 0), &mut NopListener).unwrap();
+    right.add(add(7, Side::Buy, 100, 5), &mut NopListener).unwrap();
+    right.add(add(8, Side::Sell, 102, 1), &mut NopListener).unwrap();
+    // the top levels are the same
+    assert_eq!(left.checksum(1), right.checksum(1));
+    assert_ne!(left.checksum(2), right.checksum(2));
+
+    let diff = left.diff(&right);
+    assert_eq!(
+        diff.0,
+        [
+            LevelDiff::Orders { side: Side::Buy, price: 100, missing: vec![1], extra: vec![7] },
+            LevelDiff::Missing { side: Side::Buy, price: 99, volume: 7 },
+            LevelDiff::Extra { side: Side::Sell, price: 102, volume: 1 },
+        ]
+    );
+    assert_eq!(diff.to_string().lines().count(), 3);
+}
+
+#[test]
+fn listener() {
+    use qsh_rs::orderbook::{BookListener, L2Book};
+    use qsh_rs::types::{L2Message, OLMsgType, Price, Volume};
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<L2Message>,
+        trades: Vec<(Side, Price, Volume, i64)>,
+    }
+    impl BookListener for Recorder {
+        fn on_quote(&mut self, side: Side, price: Price, size: Volume, _: i64) {
+            self.events.push(L2Message::Quote { side, price, size });
+        }
+        fn on_remove(&mut self, side: Side, price: Price, _: i64) {
+            self.events.push(L2Message::Remove { side, price });
+        }
+        fn on_trade(&mut self, side: Side, price: Price, amount: Volume, ts: i64) {
+            self.events.push(L2Message::Trade { side, price, size: amount });
+            self.trades.push((side, price, amount, ts));
+        }
+    }
+
+    let records: Vec<_> = Workload::new(9, 20).take(20_000).collect();
+    let (mut book, mut events) = (OrderBook::default(), vec![]);
+    records.iter().for_each(|&r| book.apply(r, &mut VecSink(&mut events)).unwrap());
+
+    let (mut book, mut recorder) = (OrderBook::default(), Recorder::default());
+    records.iter().for_each(|&r| book.apply(r, &mut recorder).unwrap());
+    assert_eq!(format!("{events:?}"), format!("{:?}", recorder.events));
+
+    let fills: Vec<_> = records.iter().filter(|r| r.event == OLMsgType::Fill).collect();
+    assert_eq!(recorder.trades.len(), fills.len());
+    assert!(recorder
+        .trades
+        .iter()
+        .zip(fills)
+        .all(|(t, r)| *t == (r.side, r.price, r.amount, time::ticks_to_unix_ns(r.timestamp))));
+
+    // L2Book is a listener itself
+    let (mut book, mut mirror, mut l2) =
+        (OrderBook::default(), L2Book::default(), L2Book::default());
+    records.iter().for_each(|&r| book.apply(r, &mut mirror).unwrap());
+    events.into_iter().for_each(|e| l2.apply(e).unwrap());
+    assert_eq!(mirror.snapshot(10).1, l2.snapshot(10).1);
+}
+
+#[test]
+fn crossed() {
+    let mut book = book();
+    assert!(!book.is_crossed());
+    book.add(add(7, Side::Buy, 101, 1), &mut NopListener).unwrap();
+    assert!(book.is_crossed());
+
+    let mut strict = OrderBook::strict();
+    strict.add(add(1, Side::Sell, 101, 1), &mut NopListener).unwrap();
+    strict.add(add(2, Side::Buy, 100, 1), &mut NopListener).unwrap();
+    let err = strict.add(add(3, Side::Buy, 101, 2), &mut NopListener);
+    assert!(matches!(err, Err(qsh_rs::QshError::InvalidState(_))));
+
+    // the aggressive order crosses the book within the transaction only
+    let mut strict = OrderBook::strict();
+    strict.add(add(1, Side::Sell, 101, 1), &mut NopListener).unwrap();
+    let tx =
+        [add(2, Side::Buy, 101, 1), fill(2, Side::Buy, 101, 1, 0), fill(1, Side::Sell, 101, 1, 0)];
+    strict.apply_tx(&tx, &mut NopListener).unwrap();
+    assert!(strict.is_empty());
+    strict.add(add(1, Side::Sell, 101, 1), &mut NopListener).unwrap();
+    assert!(strict.apply_tx(&tx[..1], &mut NopListener).is_err());
+}
+
+#[test]
+fn cross_trade() {
+    use qsh_rs::types::{L2Message, OLFlags};
+
+    let mut book = book();
+    let mut events = vec![];
+    // the rest doesn't match the resting amount
+    let rec = with(cancel(1, Side::Buy, 100, 2), OLFlags::CrossTrade);
+    book.cancel(rec, &mut VecSink(&mut events)).unwrap();
+    assert_eq!(book.level_at(Side::Buy, 100).map(|l| l.1), Some(3));
+    assert!(book.find_order(1).is_none());
+
+    // the last order of the level
+    book.cancel(with(cancel(3, Side::Buy, 99, 0), OLFlags::CrossTrade), &mut VecSink(&mut events))
+        .unwrap();
+    assert_eq!(
+        format!("{events:?}"),
+        format!(
+            "{:?}",
+            [
+                L2Message::Quote { side: Side::Buy, price: 100, size: 3 },
+                L2Message::Remove { side: Side::Buy, price: 99 }
+            ]
+        )
+    );
+
+    // already gone
+    book.cancel(with(cancel(1, Side::Buy, 100, 0), OLFlags::CrossTrade), &mut NopListener).unwrap();
+    assert_eq!(book.cross_trades(), 3);
+    assert_eq!(book.len(), 4);
+}
+
+#[test]
+fn reconstruct() {
+    use qsh_rs::orderbook as ob;
+    use qsh_rs::types::{OLFlags, OLMsgType};
+
+    let mut unknown = with(add(5, Side::Buy, 97, 1), OLFlags::TxEnd);
+    unknown.event = OLMsgType::UNKNOWN;
+    let records = [
+        add(1, Side::Buy, 100, 5),
+        with(add(2, Side::Sell, 101, 2), OLFlags::TxEnd),
+        with(add(3, Side::Buy, 99, 7), OLFlags::TxEnd),
+        with(add(4, Side::Sell, 102, 1), OLFlags::TxEnd),
+        unknown,
+        with(add(6, Side::Sell, 103, 1), OLFlags::TxEnd),
+    ];
+
+    let mut snapshots = ob::reconstruct(records.into_iter(), 2);
+    assert_eq!(snapshots.next().unwrap().unwrap().1, [100, 5, 101, 2, 99, 7, 102, 1]);
+    assert!(snapshots.next().unwrap().is_err());
+    assert!(snapshots.next().is_none());
+
+    assert_eq!(ob::reconstruct(records[..2].iter().copied(), 1).count(), 1);
+    assert_eq!(ob::reconstruct(records[..2].iter().copied(), 2).count(), 0);
+}
+
+#[test]
+fn partition_flush() {
+    use qsh_rs::orderbook::{FlushPolicy, PartitionBy};
+
+    let split = |x: &i32| x % 3 == 0;
+    let on_boundary = [1, 2, 3, 4, 5, 6];
+    let truncated = [1, 2, 3, 4, 5];
+
+    for policy in [FlushPolicy::Drop, FlushPolicy::Yield, FlushPolicy::Error] {
+        let mut iter = on_boundary.into_iter().partition_by_with(split, policy);
+        let parts: Vec<_> = iter.by_ref().map(Result::unwrap).collect();
+        assert_eq!(parts, [vec![1, 2, 3], vec![4, 5, 6]]);
+        assert_eq!(iter.dropped(), 0);
+    }
+
+    let mut iter = truncated.into_iter().partition_by(split);
+    assert_eq!(iter.by_ref().collect::<Vec<_>>(), [vec![1, 2, 3]]);
+    assert_eq!(iter.dropped(), 2);
+
+    let mut iter = truncated.into_iter().partition_by_with(split, FlushPolicy::Drop);
+    assert_eq!(iter.by_ref().map(Result::unwrap).collect::<Vec<_>>(), [vec![1, 2, 3]]);
+    assert_eq!(iter.dropped(), 2);
+
+    let mut iter = truncated.into_iter().partition_by_with(split, FlushPolicy::Yield);
+    let parts: Vec<_> = iter.by_ref().map(Result::unwrap).collect();
+    assert_eq!(parts, [vec![1, 2, 3], vec![4, 5]]);
+    assert_eq!(iter.dropped(), 0);
+
+    let mut iter = truncated.into_iter().partition_by_with(split, FlushPolicy::Error);
+    assert_eq!(iter.next().unwrap().unwrap(), [1, 2, 3]);
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+    assert_eq!(iter.dropped(), 2);
+}
+
+#[test]
+fn transactions() {
+    use qsh_rs::orderbook::{self as ob, FlushPolicy, OrderLogStream, PartitionBy, TxOptions};
+    use qsh_rs::types::{OLFlags, OrderLog, OrderType};
+
+    let records: Vec<_> = Workload::new(9, 10)
+        .take(1_000)
+        .enumerate()
+        .map(|(i, r)| match i % 11 {
+            0 => with(r, OLFlags::NonSystem),
+            1 | 4 | 8 => with(r, OLFlags::TxEnd),
+            _ => r,
+        })
+        .chain([add(10_000, Side::Buy, 1, 1)])
+        .collect();
+    let ids = |txs: Vec<Vec<OrderLog>>| {
+        txs.into_iter().map(|tx| tx.iter().map(|r| r.order_id).collect()).collect::<Vec<Vec<_>>>()
+    };
+
+    let manual: Vec<_> = records
+        .iter()
+        .copied()
+        .filter(ob::system_record)
+        .partition_by(ob::tx_end)
+        .filter(ob::fiok_with_trades)
+        .collect();
+    let default: Vec<_> = records.iter().copied().transactions().collect();
+    assert_eq!(ids(default), ids(manual.clone()));
+    let with_default: Vec<_> = records
+        .iter()
+        .copied()
+        .transactions_with(TxOptions::default())
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(ids(with_default), ids(manual.clone()));
+
+    let all = TxOptions { system_only: false, ..Default::default() };
+    let txs: Vec<_> = records.iter().copied().transactions_with(all).map(Result::unwrap).collect();
+    let nonsystem = |tx: &Vec<OrderLog>| tx.iter().any(|r| OLFlags::NonSystem % r.order_flags);
+    assert!(txs.iter().any(nonsystem));
+
+    let flush = TxOptions { flush: FlushPolicy::Yield, ..Default::default() };
+    let txs: Vec<_> =
+        records.iter().copied().transactions_with(flush).map(Result::unwrap).collect();
+    assert_eq!(txs.len(), manual.len() + 1);
+    assert_eq!(txs.last().unwrap().last().unwrap().order_id, 10_000);
+
+    let flush = TxOptions { flush: FlushPolicy::Error, ..Default::default() };
+    assert!(records.iter().copied().transactions_with(flush).last().unwrap().is_err());
+
+    let fiok = with(add(10_001, Side::Buy, 1, 1), OLFlags::FillOrKill);
+    let fiok = [with(fiok, OLFlags::TxEnd)];
+    assert_eq!(OrderType::from(fiok[0].order_flags), OrderType::FOK);
+    assert_eq!(fiok.into_iter().transactions().count(), 0);
+    let keep = TxOptions { fiok_with_trades: false, ..Default::default() };
+    assert_eq!(fiok.into_iter().transactions_with(keep).count(), 1);
+}
+
+#[test]
+fn new_session_mid_tx() {
+    use qsh_rs::types::{L2Message, OLFlags};
+    use qsh_rs::utils::l3tol2::convert;
+
+    let tx = [
+        add(1, Side::Buy, 100, 5),
+        with(add(2, Side::Buy, 99, 1), OLFlags::NewSession),
+        add(3, Side::Sell, 101, 2),
+        with(add(4, Side::Sell, 102, 3), OLFlags::TxEnd),
+    ];
+    let expected = |events: &[L2Message]| {
+        assert!(matches!(events[0], L2Message::Quote { side: Side::Buy, price: 100, size: 5 }));
+        assert!(matches!(events[1], L2Message::Clear));
+        assert!(matches!(events[2], L2Message::Quote { side: Side::Buy, price: 99, size: 1 }));
+        assert_eq!(events.len(), 5);
+    };
+
+    let (mut book, mut events) = (OrderBook::default(), vec![]);
+    book.apply_tx(&tx, &mut VecSink(&mut events)).unwrap();
+    expected(&events);
+    assert_eq!((book.best_bid(), book.best_ask()), (Some((99, 1)), Some((101, 2))));
+    assert_eq!(book.len(), 3);
+
+    let events: Vec<_> = convert(tx.into_iter(), 0).map(Result::unwrap).collect();
+    assert_eq!(events.len(), 1);
+    expected(&events[0]);
+}
+
+#[test]
+fn convert_depth() {
+    use qsh_rs::orderbook::L2Book;
+    use qsh_rs::types::OLFlags;
+    use qsh_rs::utils::l3tol2::convert;
+
+    let records: Vec<_> =
+        Workload::new(5, 20).take(20_000).map(|r| with(r, OLFlags::TxEnd)).collect();
+    let l2 = convert(records.clone().into_iter(), 5);
+    let (mut book, mut l2book) = (OrderBook::default(), L2Book::default());
+    for (rec, events) in records.into_iter().zip(l2) {
+        book.apply(rec, &mut NopListener).unwrap();
+        events.unwrap().into_iter().for_each(|e| l2book.apply(e).unwrap());
+        assert_eq!(l2book.snapshot(5).1, book.snapshot_upto(5).0.flatten(5));
+        assert!(l2book.depth(Side::Buy) <= 5 && l2book.depth(Side::Sell) <= 5);
+    }
+}
+
+#[test]
+fn convert_snapshots() {
+    use qsh_rs::orderbook::L2Book;
+    use qsh_rs::types::{L2Message, OLFlags};
+    use qsh_rs::utils::l3tol2::{convert_with, seek_snapshot, ConvertOptions, SnapshotEvery};
+
+    const UNIX_EPOCH: i64 = 62135596800000;
+    let records: Vec<_> = Workload::new(7, 20)
+        .take(20_000)
+        .map(|r| with(at(r, UNIX_EPOCH + r.timestamp), OLFlags::TxEnd))
+        .collect();
+    for (depth, every, snapshots) in [
+        (0, SnapshotEvery::Transactions(1000), 20),
+        (5, SnapshotEvery::Transactions(1000), 20),
+        (0, SnapshotEvery::Nanos(1_000_000_000), 20),
+    ] {
+        let options = ConvertOptions { depth, snapshot_every: Some(every), ..Default::default() };
+        let messages: Vec<_> =
+            convert_with(records.clone().into_iter(), options).flat_map(Result::unwrap).collect();
+        let is_snapshot = |msg: &&L2Message| matches!(msg, L2Message::Snapshot { .. });
+        assert_eq!(messages.iter().filter(is_snapshot).count(), snapshots);
+
+        let mut full = L2Book::default();
+        messages.iter().cloned().for_each(|msg| full.apply(msg).unwrap());
+
+        // the mid-stream start converges to the from-start replay
+        let mut rest = messages.iter().cloned();
+        let mut book = seek_snapshot(&mut rest, 10_000 * 1_000_000).unwrap();
+        assert!(rest.len() < messages.len() * 2 / 3);
+        rest.for_each(|msg| book.apply(msg).unwrap());
+        assert_eq!(book.snapshot(20), full.snapshot(20));
+        assert!(seek_snapshot(&mut messages.into_iter(), i64::MAX).is_none());
+    }
+}
+
+#[test]
+fn moex_same_tx_matching() {
+    use qsh_rs::types::{L3Message, OLFlags};
+    use qsh_rs::utils::moex2conv::moex_to_l3;
+
+    // [[+1], [-1], [+2]]: the order 1 is filled by the order 2 of the same transaction, which
+    // goes on to fill the resting order 9 and rests itself
+    let tx = vec![
+        add(1, Side::Buy, 101, 1),
+        add(2, Side::Sell, 100, 4),
+        fill(1, Side::Buy, 101, 1, 0),
+        fill(2, Side::Sell, 101, 1, 3),
+        fill(9, Side::Buy, 100, 2, 0),
+        fill(2, Side::Sell, 100, 2, 1),
+        with(add(3, Side::Buy, 99, 1), OLFlags::TxEnd),
+    ];
+    let msgs: Vec<_> = moex_to_l3(tx.clone()).map(Result::unwrap).collect();
+    assert_eq!(msgs.len(), 2);
+    let [L3Message::Trade(trade), L3Message::Add(rest)] = msgs[0][..] else {
+        panic!("{:?}", msgs[0]);
+    };
+    assert_eq!((trade.order_id, trade.amount), (9, 2));
+    assert_eq!((rest.order_id, rest.side, rest.price, rest.amount), (2, Side::Sell, 100, 1));
+    assert!(matches!(msgs[1][..], [L3Message::Add(rec)] if rec.order_id == 3));
+
+    // applies cleanly over the resting order
+    let mut book = OrderBook::default();
+    book.add(add(9, Side::Buy, 100, 2), &mut NopListener).unwrap();
+    for msg in msgs.into_iter().flatten() {
+        match msg {
+            L3Message::Add(rec) => book.add(rec, &mut NopListener),
+            L3Message::Cancel(rec) => book.cancel(rec, &mut NopListener),
+            L3Message::Trade(rec) => book.trade(rec, &mut NopListener),
+        }
+        .unwrap();
+    }
+    assert_eq!((book.best_bid(), book.best_ask()), (Some((99, 1)), Some((100, 1))));
+
+    // the fill over the order added in the transaction
+    let mut tx = tx;
+    tx[3].amount = 5;
+    assert!(moex_to_l3(tx).any(|msgs| msgs.is_err()));
+}
+
+#[test]
+fn moex_removes() {
+    use qsh_rs::types::{L3Message, OLFlags};
+    use qsh_rs::utils::moex2conv::moex_to_l3;
+
+    let apply = |book: &mut OrderBook, tx: Vec<_>| {
+        let msgs: Vec<_> = moex_to_l3(tx).map(Result::unwrap).collect();
+        for msg in msgs.iter().flatten() {
+            match *msg {
+                L3Message::Add(rec) => book.add(rec, &mut NopListener),
+                L3Message::Cancel(rec) => book.cancel(rec, &mut NopListener),
+                L3Message::Trade(rec) => book.trade(rec, &mut NopListener),
+            }
+            .unwrap();
+        }
+        msgs
+    };
+    let mut book = OrderBook::default();
+    book.add(add(9, Side::Sell, 101, 2), &mut NopListener).unwrap();
+    book.add(add(8, Side::Buy, 100, 4), &mut NopListener).unwrap();
+
+    // the partially matched FOK is killed, the resting order is removed by CrossTrade
+    let tx = vec![
+        with(add(1, Side::Buy, 101, 5), OLFlags::FillOrKill),
+        fill(9, Side::Sell, 101, 2, 0),
+        fill(1, Side::Buy, 101, 2, 3),
+        with(remove(1, Side::Buy, 101), OLFlags::FillOrKill),
+        with(with(remove(8, Side::Buy, 100), OLFlags::CrossTrade), OLFlags::TxEnd),
+    ];
+    let msgs = apply(&mut book, tx);
+    assert!(matches!(msgs[0][..], [L3Message::Trade(rec)] if rec.order_id == 9));
+    assert!(matches!(msgs[1][..], [L3Message::Cancel(rec)] if rec.order_id == 8));
+    assert!(book.is_empty());
+
+    // the rest of the limit order added within the fills never hits the book
+    book.add(add(7, Side::Sell, 102, 1), &mut NopListener).unwrap();
+    let tx = vec![
+        add(2, Side::Buy, 102, 3),
+        fill(7, Side::Sell, 102, 1, 0),
+        fill(2, Side::Buy, 102, 1, 2),
+        with(with(remove(2, Side::Buy, 102), OLFlags::CrossTrade), OLFlags::TxEnd),
+    ];
+    let msgs = apply(&mut book, tx);
+    assert!(matches!(msgs[..], [ref trades] if matches!(trades[..], [L3Message::Trade(_)])));
+    assert!(book.is_empty());
+
+    // the unknown order type is an error, not a panic
+    let mut tx = vec![
+        add(3, Side::Buy, 103, 1),
+        fill(3, Side::Buy, 103, 1, 0),
+        fill(4, Side::Sell, 103, 1, 0),
+        remove(5, Side::UNKNOWN, 103),
+    ];
+    tx[3].order_flags = OLFlags::CrossTrade.into();
+    assert!(moex_to_l3(tx).any(|msgs| msgs.is_err()));
+}
+
+#[test]
+fn partition_capacity() {
+    use qsh_rs::orderbook::PartitionBy;
+
+    let split = |x: &i32| x % 3 == 0;
+    let parts: Vec<_> = (1..=30).partition_by_with_capacity(split, 64).collect();
+    assert_eq!(parts, (1..=30).partition_by(split).collect::<Vec<_>>());
+    assert!(parts.iter().all(|p| p.capacity() >= 64));
+
+    // the running average
+    let parts: Vec<_> = (1..=30).partition_by(split).collect();
+    assert!(parts[1..].iter().all(|p| p.capacity() >= 3));
+}
+
+#[test]
+fn record_filter() {
+    use qsh_rs::orderbook::{self as ob, OrderLogStream, RecordFilter};
+    use qsh_rs::types::OLFlags;
+
+    let mut unknown = at(add(4, Side::Buy, 100, 1), 30);
+    unknown.side = Side::UNKNOWN;
+    let records = [
+        at(add(1, Side::Buy, 100, 1), 10),
+        at(with(with(add(2, Side::Buy, 100, 1), OLFlags::NonSystem), OLFlags::NonZeroReplAct), 20),
+        unknown,
+        at(with(add(5, Side::Sell, 101, 1), OLFlags::NonZeroReplAct), 40),
+        at(add(6, Side::Sell, 101, 1), 50),
+    ];
+
+    let mut filter = RecordFilter::default();
+    let passed: Vec<_> = records.into_iter().filter_with(&mut filter).map(|r| r.order_id).collect();
+    assert_eq!(passed, [1, 6]);
+    assert_eq!(passed.len(), records.iter().filter(|r| ob::system_record(r)).count());
+    let stats = filter.stats();
+    assert_eq!(
+        (stats.passed, stats.non_system, stats.non_zero_repl_act, stats.unknown_side),
+        (2, 1, 1, 1)
+    );
+    assert_eq!(stats.dropped(), 3);
+    assert_eq!(
+        (stats.first_dropped, stats.last_dropped),
+        (Some(time::ticks_to_unix_ns(20)), Some(time::ticks_to_unix_ns(40)))
+    );
+
+    let mut filter = RecordFilter::default();
+    (filter.non_system, filter.unknown_side) = (false, false);
+    let passed: Vec<_> = records.into_iter().filter_with(&mut filter).map(|r| r.order_id).collect();
+    assert_eq!(passed, [1, 4, 6]);
+    assert_eq!(filter.stats().non_zero_repl_act, 2);
+}
+
+#[test]
+fn snapshot_band() {
+    use qsh_rs::orderbook::BandAnchor;
+
+    // mid is 100.5
+    let book = book();
+    let band = book.snapshot_band(1);
+    assert_eq!((band.bids, band.asks), (vec![(100, 8)], vec![(101, 3)]));
+    let band = book.snapshot_band(2);
+    assert_eq!((band.bids, band.asks), (vec![(100, 8), (99, 7)], vec![(101, 3)]));
+    assert!(book.snapshot_band(0).bids.is_empty());
+
+    // exactly at the boundary
+    let band = book.snapshot_band_with(1, BandAnchor::Best);
+    assert_eq!((band.bids, band.asks), (vec![(100, 8), (99, 7)], vec![(101, 3)]));
+    let band = book.snapshot_band_with(2, BandAnchor::Best);
+    assert_eq!(band.asks, [(101, 3), (103, 4)]);
+    assert_eq!(band.flatten(1), [100, 8, 101, 3]);
+    assert_eq!(band.flatten(3), [100, 8, 101, 3, 99, 7, 103, 4, 0, 0, 0, 0]);
+
+    // the empty side
+    let mut book = book;
+    for (id, price) in [(4, 101), (6, 101), (5, 103)] {
+        book.cancel(cancel(id, Side::Sell, price, 0), &mut NopListener).unwrap();
+    }
+    let band = book.snapshot_band(1);
+    assert_eq!((band.bids, band.asks), (vec![(100, 8), (99, 7)], vec![]));
+    assert!(OrderBook::default().snapshot_band(10).bids.is_empty());
+}
+
+#[test]
+fn snapshot_band_replay() {
+    use qsh_rs::orderbook::BandAnchor;
+
+    let mut book = OrderBook::default();
+    for rec in Workload::new(21, 20).take(20_000) {
+        book.apply(rec, &mut NopListener).unwrap();
+        let band = book.snapshot_band_with(3, BandAnchor::Best);
+        assert_eq!(band.bids.first().copied(), book.best_bid());
+        assert_eq!(band.asks.first().copied(), book.best_ask());
+        if let (Some((bid, _)), Some((ask, _))) = (book.best_bid(), book.best_ask()) {
+            let band = book.snapshot_band((ask - bid + 1) / 2);
+            assert_eq!(
+                (band.bids[0], band.asks[0]),
+                (book.best_bid().unwrap(), book.best_ask().unwrap())
+            );
+        }
+    }
+}
+
+#[test]
+fn order_counts() {
+    let book = book();
+    let (_, levels) = book.snapshot_with_counts(2);
+    assert_eq!(levels, [100, 8, 2, 101, 3, 2, 99, 7, 1, 103, 4, 1]);
+    assert_eq!(book.snapshot(2).1, [100, 8, 101, 3, 99, 7, 103, 4]);
+
+    let snapshot = book.book_snapshot_with_counts(0);
+    assert_eq!(snapshot.counts, Some((vec![2, 1], vec![2, 1])));
+    assert_eq!(book.book_snapshot(0).counts, None);
+
+    // the far levels of the bounded book are counted too
+    let (mut book, mut bounded) = (OrderBook::default(), OrderBook::with_max_depth(2));
+    for rec in Workload::new(8, 20).take(10_000) {
+        book.apply(rec, &mut NopListener).unwrap();
+        bounded.apply(rec, &mut NopListener).unwrap();
+
+        let snapshot = bounded.book_snapshot_with_counts(0);
+        assert_eq!(snapshot, book.book_snapshot_with_counts(0));
+        let (bids, asks) = snapshot.counts.unwrap();
+        for (side, levels, counts) in
+            [(Side::Buy, snapshot.bids, bids), (Side::Sell, snapshot.asks, asks)]
+        {
+            for ((price, _), count) in levels.into_iter().zip(counts) {
+                assert_eq!(book.orders_at(side, price).len(), count);
+            }
+        }
+    }
+}
+
+#[test]
+fn lenient() {
+    use qsh_rs::orderbook::{Anomalies, Strictness};
+
+    let records = [
+        // cancel before the add
+        cancel(7, Side::Buy, 98, 0),
+        add(7, Side::Buy, 98, 2),
+        // unknown order at the existing level
+        fill(42, Side::Buy, 100, 1, 0),
+        // over the order amount
+        fill(4, Side::Sell, 101, 5, 0),
+        // cancel leaving more than the order has
+        cancel(5, Side::Sell, 103, 4),
+        cancel(3, Side::Buy, 99, 0),
+    ];
+
+    let mut strict = book();
+    let errors =
+        records.iter().filter(|&&rec| strict.apply(rec, &mut NopListener).is_err()).count();
+    assert_eq!(errors, 4);
+    assert!(book().apply(records[0], &mut NopListener).is_err());
+
+    let mut book = book();
+    book.set_policy(Strictness::Lenient);
+    for rec in records {
+        book.apply(rec, &mut NopListener).unwrap();
+    }
+    assert_eq!(
+        book.anomalies(),
+        &Anomalies { missing_level: 1, unknown_order: 1, volume_mismatch: 2, synthetic: 0 }
+    );
+    assert_eq!(book.best_ask(), Some((101, 1)));
+    assert_eq!(book.level_at(Side::Sell, 103).unwrap().1, 4);
+    assert_eq!(book.level_at(Side::Buy, 98).unwrap().1, 2);
+    assert!(book.level_at(Side::Buy, 99).is_none());
+}
+
+#[test]
+fn index_lookup() {
+    use qsh_rs::orderbook::{Lookup, Strictness};
+
+    let records: Vec<_> = Workload::new(11, 40).take(50_000).collect();
+    let (mut indexed, mut scanned) = (OrderBook::default(), OrderBook::default());
+    scanned.set_lookup(Lookup::Price);
+    let (mut left, mut right) = (vec![], vec![]);
+    for tx in records.chunks(3) {
+        indexed.apply_tx(tx, &mut VecSink(&mut left)).unwrap();
+        scanned.apply_tx(tx, &mut VecSink(&mut right)).unwrap();
+        assert_eq!(indexed.checksum(20), scanned.checksum(20), "{tx:?}");
+    }
+    assert_eq!(format!("{left:?}"), format!("{right:?}"));
+    assert_eq!(indexed.book_snapshot_with_counts(0), scanned.book_snapshot_with_counts(0));
+    assert!(indexed.diff(&scanned).is_empty());
+
+    // the records disagreeing with the index fail the same
+    for lookup in [Lookup::Index, Lookup::Price] {
+        let mut book = OrderBook::default();
+        book.set_lookup(lookup);
+        book.add(add(1, Side::Buy, 100, 5), &mut NopListener).unwrap();
+        book.add(add(2, Side::Buy, 101, 5), &mut NopListener).unwrap();
+        let errors = [
+            book.trade(fill(1, Side::Buy, 101, 2, 3), &mut NopListener),
+            book.cancel(cancel(1, Side::Sell, 99, 0), &mut NopListener),
+            book.cancel(cancel(2, Side::Buy, 100, 0), &mut NopListener),
+        ];
+        let errors: Vec<_> = errors.into_iter().map(|e| e.unwrap_err().to_string()).collect();
+        assert!(errors[0].contains("order to modify not found"), "{errors:?}");
+        assert!(errors[1].contains("level not found"), "{errors:?}");
+        assert!(errors[2].contains("order to remove not found"), "{errors:?}");
+        assert_eq!(book.volume_at(Side::Buy, 100) + book.volume_at(Side::Buy, 101), 10);
+
+        // or are skipped as the anomalies
+        book.set_policy(Strictness::Lenient);
+        book.cancel(cancel(1, Side::Sell, 99, 0), &mut NopListener).unwrap();
+        book.trade(fill(1, Side::Buy, 101, 2, 3), &mut NopListener).unwrap();
+        assert_eq!((book.anomalies().missing_level, book.anomalies().unknown_order), (1, 1));
+        assert_eq!(book.volume_at(Side::Buy, 100) + book.volume_at(Side::Buy, 101), 10);
+    }
+}
+
+#[test]
+fn storage_equivalence() {
+    use qsh_rs::orderbook::Storage;
+
+    for levels in [3, 40, 2_000] {
+        let records: Vec<_> = Workload::new(23, levels).take(50_000).collect();
+        let (mut sorted, mut ladder) =
+            (OrderBook::default(), OrderBook::with_storage(Storage::Ladder { step: 1 }));
+        let (mut left, mut right) = (vec![], vec![]);
+        for tx in records.chunks(3) {
+            sorted.apply_tx(tx, &mut VecSink(&mut left)).unwrap();
+            ladder.apply_tx(tx, &mut VecSink(&mut right)).unwrap();
+            assert_eq!(sorted.checksum(20), ladder.checksum(20), "{tx:?}");
+        }
+        assert_eq!(sorted.checksum(usize::MAX), ladder.checksum(usize::MAX));
+        assert_eq!(format!("{left:?}"), format!("{right:?}"));
+        for side in Side::both() {
+            assert_eq!(sorted.depth(side), ladder.depth(side));
+            let quotes = |book: &OrderBook| -> Vec<_> {
+                book.iter_levels(side).map(|(&p, &v, o)| (p, v, o.len())).collect()
+            };
+            assert_eq!(quotes(&sorted), quotes(&ladder));
+        }
+        assert_eq!(sorted.book_snapshot_with_counts(0), ladder.book_snapshot_with_counts(0));
+        assert!(sorted.diff(&ladder).is_empty());
+    }
+
+    // off the grid
+    let mut ladder = OrderBook::with_storage(Storage::Ladder { step: 5 });
+    ladder.add(add(1, Side::Buy, 100, 1), &mut NopListener).unwrap();
+    ladder.add(add(2, Side::Buy, 90, 1), &mut NopListener).unwrap();
+    assert!(ladder.add(add(3, Side::Buy, 93, 1), &mut NopListener).is_err());
+    ladder.cancel(cancel(1, Side::Buy, 100, 0), &mut NopListener).unwrap();
+    ladder.cancel(cancel(2, Side::Buy, 90, 0), &mut NopListener).unwrap();
+    assert!(ladder.add(add(4, Side::Buy, 92, 1), &mut NopListener).is_err());
+    ladder.add(add(5, Side::Buy, 115, 1), &mut NopListener).unwrap();
+    assert_eq!(ladder.best_bid(), Some((115, 1)));
+}
+
+#[test]
+fn moved() {
+    use qsh_rs::types::{OLFlagSet, OLFlags};
+
+    // removal of the moved order, its rest is carried over by the Add
+    let moved = |id, side, price, rest| {
+        let mut rec = cancel(id, side, price, rest);
+        rec.order_flags.remove(OLFlagSet::CANCELED);
+        with(rec, OLFlags::Moved)
+    };
+
+    let mut book = book();
+    let volume = book.total_volume(Side::Buy);
+    let mut events = vec![];
+    let tx = [moved(1, Side::Buy, 100, 5), with(add(7, Side::Buy, 98, 5), OLFlags::Moved)];
+    book.apply_tx(&tx, &mut VecSink(&mut events)).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(book.total_volume(Side::Buy), volume);
+    assert_eq!(book.level_at(Side::Buy, 100).unwrap().1, 3);
+    assert_eq!(book.level_at(Side::Buy, 98).unwrap().1, 5);
+    assert!(book.find_order(1).is_none());
+
+    // the same id at the new price
+    book.apply(with(add(2, Side::Buy, 99, 3), OLFlags::Moved), &mut NopListener).unwrap();
+    assert_eq!(book.total_volume(Side::Buy), volume);
+    assert!(book.level_at(Side::Buy, 100).is_none());
+    assert_eq!(book.orders_at(Side::Buy, 99).len(), 2);
+    assert_eq!(book.len(), 6);
+
+    assert!(book.apply(moved(42, Side::Sell, 101, 1), &mut NopListener).is_err());
+}
+
+#[test]
+fn snapshot_into() {
+    let book = book();
+    let mut out = [0; 8];
+    assert_eq!(book.snapshot_into(2, &mut out).unwrap(), book.snapshot(2).0);
+    assert_eq!(out[..], book.snapshot(2).1[..]);
+
+    assert!(book.snapshot_into(2, &mut [0; 4]).is_err());
+    // 2 ask levels only
+    assert!(book.snapshot_into(3, &mut [0; 12]).is_err());
+    // `0` is the whole book, 2 levels a side here
+    assert!(book.snapshot_into(0, &mut []).is_err());
+    assert!(book.snapshot_into(0, &mut out).is_ok());
+}
+
+#[test]
+fn asymmetric_snapshots() {
+    // bids 100, 99; asks 101, 103
+    let mut book = book();
+    let snapshot = book.snapshot_sides(3, 1);
+    assert_eq!(snapshot.bids, [(100, 8), (99, 7), (0, 0)]);
+    assert_eq!(snapshot.asks, [(101, 3)]);
+    assert!(book.snapshot_sides(0, 0).bids.is_empty());
+
+    let (snapshot, depths) = book.snapshot_upto(3);
+    assert_eq!(depths, (2, 2));
+    assert_eq!(snapshot.flatten(3), [100, 8, 101, 3, 99, 7, 103, 4, 0, 0, 0, 0]);
+    let mut out = [1; 12];
+    assert_eq!(book.snapshot_upto_into(3, &mut out).unwrap(), depths);
+    assert_eq!(out[..], snapshot.flatten(3)[..]);
+    assert!(book.snapshot_upto_into(3, &mut [0; 8]).is_err());
+
+    // one-sided
+    for rec in [
+        cancel(4, Side::Sell, 101, 0),
+        cancel(6, Side::Sell, 101, 0),
+        cancel(5, Side::Sell, 103, 0),
+    ] {
+        book.cancel(rec, &mut NopListener).unwrap();
+    }
+    let (snapshot, depths) = book.snapshot_upto(1);
+    assert_eq!(depths, (1, 0));
+    assert_eq!((&snapshot.bids[..], &snapshot.asks[..]), (&[(100, 8)][..], &[(0, 0)][..]));
+    assert_eq!(book.snapshot_upto_into(1, &mut out[..4]).unwrap(), (1, 0));
+    assert_eq!(out[..4], [100, 8, 0, 0]);
+}
+
+#[test]
+fn unlimited_snapshots() {
+    use qsh_rs::orderbook::L2Book;
+
+    // bids 100, 99, 98; asks 101, 103
+    let (mut book, mut events) = (OrderBook::default(), vec![]);
+    for rec in [
+        add(1, Side::Buy, 100, 5),
+        add(2, Side::Buy, 100, 3),
+        add(3, Side::Buy, 99, 7),
+        add(7, Side::Buy, 98, 2),
+        add(4, Side::Sell, 101, 2),
+        add(5, Side::Sell, 103, 4),
+        add(6, Side::Sell, 101, 1),
+    ] {
+        book.add(rec, &mut VecSink(&mut events)).unwrap();
+    }
+    let all = [100, 8, 101, 3, 99, 7, 103, 4, 98, 2, 0, 0];
+    assert_eq!(book.snapshot_width(0), 3);
+    assert_eq!(book.snapshot_width(2), 2);
+    assert_eq!(book.snapshot(0).1, all);
+    assert_eq!(book.snapshot(2).1, all[..8]);
+
+    let mut out = [1; 12];
+    book.snapshot_into(0, &mut out).unwrap();
+    assert_eq!(out, all);
+    assert!(book.snapshot_into(0, &mut [0; 8]).is_err());
+    assert_eq!(book.snapshot_upto_into(0, &mut out).unwrap(), (3, 2));
+    let (snapshot, depths) = book.snapshot_upto(0);
+    assert_eq!((snapshot.flatten(3), depths), (all.to_vec(), (3, 2)));
+
+    assert_eq!(
+        book.snapshot_with_counts(0).1,
+        [100, 8, 2, 101, 3, 2, 99, 7, 1, 103, 4, 1, 98, 2, 1, 0, 0, 0]
+    );
+
+    let mut l2 = L2Book::default();
+    for e in events {
+        l2.apply(e).unwrap();
+    }
+    assert_eq!(l2.snapshot(0).1, all);
+    assert!(OrderBook::default().snapshot(0).1.is_empty());
+}
+
+#[test]
+fn counters() {
+    use qsh_rs::orderbook::BookCounters;
+    use qsh_rs::types::OLFlags;
+
+    const UNIX_EPOCH: i64 = 62135596800000;
+    let mut book = OrderBook::default();
+    assert_eq!(book.last_update(), None);
+
+    let txs = [
+        vec![
+            at(add(1, Side::Buy, 100, 5), UNIX_EPOCH + 10),
+            at(add(2, Side::Sell, 101, 2), UNIX_EPOCH + 10),
+            at(with(add(3, Side::Sell, 101, 1), OLFlags::TxEnd), UNIX_EPOCH + 11),
+        ],
+        vec![
+            at(fill(1, Side::Buy, 100, 2, 3), UNIX_EPOCH + 20),
+            at(fill(2, Side::Sell, 101, 2, 0), UNIX_EPOCH + 20),
+            at(with(cancel(3, Side::Sell, 101, 0), OLFlags::TxEnd), UNIX_EPOCH + 25),
+        ],
+    ];
+    txs.iter().for_each(|tx| book.apply_tx(tx, &mut NopListener).unwrap());
+    assert_eq!(
+        book.events_since_clear(),
+        BookCounters { adds: 3, cancels: 1, trades: 2, clears: 0 }
+    );
+    assert_eq!(book.last_update(), Some(25 * 1_000_000));
+
+    let tx = [
+        at(add(4, Side::Buy, 99, 1), UNIX_EPOCH + 30),
+        at(with(add(5, Side::Buy, 98, 1), OLFlags::NewSession), UNIX_EPOCH + 30),
+        at(with(add(6, Side::Sell, 102, 1), OLFlags::TxEnd), UNIX_EPOCH + 31),
+    ];
+    book.apply_tx(&tx, &mut NopListener).unwrap();
+    assert_eq!(
+        book.events_since_clear(),
+        BookCounters { adds: 2, cancels: 0, trades: 0, clears: 1 }
+    );
+    assert_eq!(book.last_update(), Some(31 * 1_000_000));
+}
+
+#[test]
+fn shadow_orders() {
+    use qsh_rs::orderbook::ShadowStatus;
+
+    let mut book = book();
+    let now = book.last_update().unwrap();
+    let front = book.shadow_add(Side::Buy, 100, now);
+    let deeper = book.shadow_add(Side::Buy, 99, now);
+    let empty = book.shadow_add(Side::Sell, 102, now);
+    let later = book.shadow_add(Side::Buy, 100, now + 1);
+    assert_eq!(book.shadow_status(front), ShadowStatus { ahead: 8, filled: 0, done: false });
+    assert_eq!(book.shadow_status(empty).ahead, 0);
+
+    let records = [
+        cancel(2, Side::Buy, 100, 0),
+        // behind the virtual ones
+        add(7, Side::Buy, 100, 4),
+        add(8, Side::Buy, 99, 1),
+        fill(1, Side::Buy, 100, 2, 3),
+        fill(1, Side::Buy, 100, 3, 0),
+        fill(7, Side::Buy, 100, 1, 3),
+        fill(7, Side::Buy, 100, 3, 0),
+        fill(3, Side::Buy, 99, 2, 5),
+    ];
+    let (mut events, mut expected) = (vec![], vec![]);
+    let mut plain = self::book();
+    for (i, rec) in records.into_iter().enumerate() {
+        book.apply(rec, &mut VecSink(&mut events)).unwrap();
+        plain.apply(rec, &mut VecSink(&mut expected)).unwrap();
+        let status = book.shadow_status(front);
+        match i {
+            0 => assert_eq!(status.ahead, 5),
+            3 => assert_eq!(status, ShadowStatus { ahead: 3, filled: 0, done: false }),
+            4 => assert_eq!(status, ShadowStatus { ahead: 0, filled: 0, done: false }),
+            5 => assert_eq!(status, ShadowStatus { ahead: 0, filled: 1, done: true }),
+            _ => {}
+        }
+    }
+    // and the trade at the worse price
+    assert_eq!(book.shadow_status(front).filled, 6);
+    assert_eq!(book.shadow_status(later), ShadowStatus { ahead: 0, filled: 0, done: false });
+    assert_eq!(book.shadow_status(deeper), ShadowStatus { ahead: 5, filled: 0, done: false });
+    assert_eq!(format!("{events:?}"), format!("{expected:?}"));
+
+    // traded through, the level 99 is gone
+    book.apply(fill(3, Side::Buy, 99, 5, 0), &mut NopListener).unwrap();
+    book.apply(add(9, Side::Buy, 98, 1), &mut NopListener).unwrap();
+    assert!(!book.shadow_status(deeper).done);
+    book.apply(fill(9, Side::Buy, 98, 1, 0), &mut NopListener).unwrap();
+    assert_eq!(book.shadow_status(deeper), ShadowStatus { ahead: 0, filled: 1, done: true });
+}
+
+#[test]
+fn price_queries() {
+    let book = book();
+    assert_eq!(book.volume_at(Side::Buy, 100), 8);
+    assert_eq!(book.volume_at(Side::Sell, 103), 4);
+    assert_eq!(book.volume_at(Side::Sell, 102), 0);
+    assert_eq!(book.volume_at(Side::Sell, 100), 0);
+    assert!(book.contains_level(Side::Sell, 101));
+    assert!(!book.contains_level(Side::Buy, 101));
+    assert_eq!(book.price_at_depth(Side::Buy, 0), Some(100));
+    assert_eq!(book.price_at_depth(Side::Buy, 1), Some(99));
+    assert_eq!(book.price_at_depth(Side::Sell, 1), Some(103));
+    assert_eq!(book.price_at_depth(Side::Sell, 2), None);
+
+    // the far levels of the bounded book
+    let mut bounded = OrderBook::with_max_depth(1);
+    for rec in [add(1, Side::Sell, 101, 2), add(2, Side::Sell, 102, 3)] {
+        bounded.add(rec, &mut NopListener).unwrap();
+    }
+    assert_eq!(bounded.volume_at(Side::Sell, 102), 3);
+    assert_eq!(bounded.price_at_depth(Side::Sell, 1), Some(102));
+}
+
+#[test]
+fn unknown_side() {
+    let mut book = book();
+    assert_eq!(book.volume_at(Side::UNKNOWN, 100), 0);
+    assert!(!book.contains_level(Side::UNKNOWN, 101));
+    assert_eq!(book.price_at_depth(Side::UNKNOWN, 0), None);
+    assert!(book.level_at(Side::UNKNOWN, 100).is_none());
+    assert!(book.orders_at(Side::UNKNOWN, 100).is_empty());
+
+    // rejected rather than panicking
+    for rec in [
+        add(7, Side::UNKNOWN, 100, 1),
+        cancel(1, Side::UNKNOWN, 100, 0),
+        fill(1, Side::UNKNOWN, 100, 1, 4),
+    ] {
+        assert!(book.apply(rec, &mut NopListener).is_err());
+    }
+    assert_eq!(book.volume_at(Side::Buy, 100), 8);
+}
+
+#[test]
+fn seeded_book() {
+    use qsh_rs::orderbook::{is_synthetic, Strictness};
+    use qsh_rs::types::{OLFlags, OrderLog};
+
+    let records: Vec<_> = Workload::new(13, 20).take(20_000).collect();
+    let (head, tail) = records.split_at(10_000);
+    let mut full = OrderBook::default();
+    head.iter().for_each(|&r| full.apply(r, &mut NopListener).unwrap());
+
+    let mut seeded = OrderBook::from_snapshot(&full.book_snapshot(0));
+    assert_eq!(seeded.book_snapshot(0), full.book_snapshot(0));
+    assert!(seeded.orders_at(Side::Buy, full.best_bid().unwrap().0).iter().all(is_synthetic));
+    for &rec in tail {
+        full.apply(rec, &mut NopListener).unwrap();
+        seeded.apply(rec, &mut NopListener).unwrap();
+        assert_eq!(seeded.book_snapshot(0), full.book_snapshot(0), "{rec}");
+    }
+    assert!(seeded.anomalies().synthetic > 0);
+    assert_eq!(seeded.anomalies().unknown_order, 0);
+
+    // the strict book fails on the orders placed before the snapshot
+    let mut strict = OrderBook::from_snapshot(&book().book_snapshot(0));
+    strict.set_policy(Strictness::Strict);
+    let rec = OrderLog { amount: 5, ..cancel(1, Side::Buy, 100, 0) };
+    assert!(strict.cancel(rec, &mut NopListener).is_err());
+    let mut lenient = OrderBook::from_snapshot(&book().book_snapshot(0));
+    lenient.cancel(rec, &mut NopListener).unwrap();
+    assert_eq!(lenient.volume_at(Side::Buy, 100), 3);
+
+    // the leading Snapshot records
+    let snapshot =
+        |(i, &rec): (usize, &OrderLog)| if i < 10_000 { with(rec, OLFlags::Snapshot) } else { rec };
+    let mut input = records.iter().enumerate().map(snapshot).peekable();
+    let mut book = OrderBook::default();
+    assert_eq!(book.seed_from_records(&mut input).unwrap(), 10_000);
+    assert_eq!(book.checksum(usize::MAX), {
+        let mut book = OrderBook::default();
+        head.iter().for_each(|&r| book.apply(r, &mut NopListener).unwrap());
+        book.checksum(usize::MAX)
+    });
+    assert_eq!(input.next().map(|r| r.order_id), Some(tail[0].order_id));
+}
+
+#[test]
+fn level_times() {
+    let t = |ticks| time::ticks_to_unix_ns(ticks);
+    for mut book in [OrderBook::default(), OrderBook::with_max_depth(1)] {
+        for rec in [
+            at(add(1, Side::Buy, 100, 5), 10),
+            at(add(2, Side::Buy, 99, 3), 20),
+            at(add(3, Side::Buy, 100, 1), 30),
+            at(add(4, Side::Sell, 101, 2), 40),
+        ] {
+            book.add(rec, &mut NopListener).unwrap();
+        }
+        assert_eq!(book.level_summary(Side::Buy, 0), (100, 6, t(30)));
+        assert_eq!(book.level_updated_at(Side::Buy, 99), Some(t(20)));
+
+        book.trade(at(fill(4, Side::Sell, 101, 1, 1), 50), &mut NopListener).unwrap();
+        book.cancel(at(cancel(2, Side::Buy, 99, 1), 60), &mut NopListener).unwrap();
+        assert_eq!(book.level_summary(Side::Sell, 0), (101, 1, t(50)));
+        assert_eq!(book.level_updated_at(Side::Buy, 99), Some(t(60)));
+        // the stale level
+        assert_eq!(book.level_updated_at(Side::Buy, 100), Some(t(30)));
+
+        // the level is gone and back
+        book.cancel(at(cancel(2, Side::Buy, 99, 0), 70), &mut NopListener).unwrap();
+        assert_eq!(book.level_updated_at(Side::Buy, 99), None);
+        book.add(at(add(5, Side::Buy, 99, 1), 80), &mut NopListener).unwrap();
+        assert_eq!(book.level_updated_at(Side::Buy, 99), Some(t(80)));
+        assert_eq!(book.level_updated_at(Side::Sell, 100), None);
+        assert_eq!(book.level_updated_at(Side::UNKNOWN, 100), None);
+
+        book.clear();
+        assert_eq!(book.level_updated_at(Side::Buy, 100), None);
+    }
+}
+
+#[test]
+fn reuse_across_clears() {
+    use qsh_rs::orderbook::Storage;
+
+    let records: Vec<_> = Workload::new(7, 40).take(20_000).collect();
+    for storage in [Storage::Sorted, Storage::Ladder { step: 1 }] {
+        let (mut fresh, mut reused) =
+            (OrderBook::with_storage(storage), OrderBook::with_storage(storage));
+        let (mut left, mut right) = (vec![], vec![]);
+        records.iter().for_each(|&rec| fresh.apply(rec, &mut VecSink(&mut left)).unwrap());
+        // the pooled queues of the former session are not to leak into the next one
+        for _ in 0..3 {
+            reused.clear();
+            right.clear();
+            records.iter().for_each(|&rec| reused.apply(rec, &mut VecSink(&mut right)).unwrap());
+        }
+        assert_eq!(format!("{left:?}"), format!("{right:?}"));
+        assert_eq!(fresh.checksum(usize::MAX), reused.checksum(usize::MAX));
+        assert_eq!(fresh.book_snapshot_with_counts(0), reused.book_snapshot_with_counts(0));
+
+        let mut buf = vec![];
+        reused.save(&mut buf).unwrap();
+        let loaded = OrderBook::load(&buf[..]).unwrap();
+        assert_eq!(loaded.checksum(usize::MAX), fresh.checksum(usize::MAX));
+    }
+}
+
+#[test]
+fn snapshot_view() {
+    use qsh_rs::orderbook::{microprice, weighted_mid, L2Book, SnapshotView};
+    use qsh_rs::types::L2Message;
+
+    let mut book = OrderBook::default();
+    for rec in Workload::new(13, 30).take(5_000) {
+        book.apply(rec, &mut NopListener).unwrap();
+        let snapshot = book.snapshot(0);
+        let view = SnapshotView::new(&snapshot);
+        assert_eq!(view.ts, snapshot.0);
+        assert_eq!(view.best_bid(), book.best_bid());
+        assert_eq!(view.best_ask(), book.best_ask());
+        assert_eq!(microprice(&snapshot), book.microprice());
+        assert_eq!(weighted_mid(&snapshot, 5), book.weighted_mid(5));
+        assert_eq!(weighted_mid(&snapshot, 0), book.weighted_mid(0));
+        for side in Side::both() {
+            assert_eq!(view.levels(side).count(), book.depth(side));
+        }
+    }
+
+    let mut l2 = L2Book::default();
+    for (side, price, size) in [(Side::Buy, 99, 1), (Side::Buy, 100, 3), (Side::Sell, 102, 1)] {
+        l2.apply(L2Message::Quote { side, price, size }).unwrap();
+    }
+    let snapshot = l2.snapshot(3);
+    let view = SnapshotView::new(&snapshot);
+    assert_eq!(view.depth(), 3);
+    assert_eq!((view.bid_px(1), view.bid_vol(1), view.ask_px(0), view.ask_vol(0)), (99, 1, 102, 1));
+    // zero padding
+    assert_eq!((view.ask_px(1), view.ask_vol(1)), (0, 0));
+    assert_eq!((view.level(Side::Sell, 1), view.level(Side::Buy, 5)), (None, None));
+    assert_eq!(view.levels(Side::Buy).collect::<Vec<_>>(), [(100, 3), (99, 1)]);
+    assert_eq!((view.mid_price(), view.spread()), (Some(101.), Some(2)));
+    assert_eq!(view.effective_spread(102), Some(2.));
+    // (100 * 1 + 102 * 3) / 4
+    assert_eq!(view.microprice(), Some(101.5));
+    // bids (100 * 3 + 99) / 4 weighted by 1, the ask 102 by 4
+    assert_eq!(view.weighted_mid(2), Some((99.75 + 102. * 4.) / 5.));
+    assert_eq!(view.weighted_mid(0), view.weighted_mid(2));
+
+    let empty = SnapshotView::from_levels(0, &[100, 1, 0, 0]);
+    assert_eq!((empty.microprice(), empty.spread(), empty.mid_price()), (None, None, None));
+}
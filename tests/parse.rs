@@ -0,0 +1,618 @@
+mod common;
+
+use common::*;
+use qsh_rs::types::{Side, Stream};
+use qsh_rs::{header, time, OrderLogReader, QshError, QshParser, QshRead};
+
+#[test]
+fn orderlog_roundtrip() {
+    let records = [
+        at(add(10, Side::Buy, 100, 5), 1_000),
+        at(add(11, Side::Sell, 101, 2), 1_001),
+        at(fill(10, Side::Buy, 100, 2, 3), 1_005),
+        at(cancel(11, Side::Sell, 101, 0), 1_010),
+    ];
+    let bytes = orderlog_stream(&records);
+    let mut rd = reader(&bytes);
+    assert_eq!(header(&mut rd).unwrap().stream, Stream::ORDERLOG);
+
+    let parsed: Vec<_> = rd.into_iter::<OrderLogReader>().collect();
+    assert_eq!(parsed.len(), records.len());
+    for (p, r) in parsed.iter().zip(records.iter()) {
+        assert_eq!(
+            (p.timestamp, p.order_id, p.side, p.price, p.amount, p.amount_rest, p.event),
+            (r.timestamp, r.order_id, r.side, r.price, r.amount, r.amount_rest, r.event)
+        );
+    }
+}
+
+#[test]
+fn strict_orderlog() {
+    let records = [add(10, Side::Buy, 100, 5), fill(10, Side::Buy, 100, 5, -1)];
+    let bytes = orderlog_stream(&records);
+
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    assert_eq!(rd.into_iter::<OrderLogReader>().count(), 2);
+
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let mut parser = OrderLogReader::strict();
+    assert!(parser.parse(&mut rd).is_ok());
+    assert!(matches!(parser.parse(&mut rd), Err(QshError::Parsing(_))));
+}
+
+#[test]
+fn lenient_orderlog() {
+    use qsh_rs::types::OLFlags;
+
+    let records = [
+        add(10, Side::Buy, 100, 5),
+        with(add(11, Side::Buy, 101, 2), OLFlags::Sell),
+        add(12, Side::Sell, 105, 1),
+    ];
+    let bytes = orderlog_stream(&records);
+
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let mut parser = OrderLogReader::default();
+    assert!(parser.parse(&mut rd).is_ok());
+    assert!(matches!(parser.parse(&mut rd), Err(QshError::Parsing(_))));
+
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let mut iter = rd.into_iter_with(OrderLogReader::lenient());
+    let parsed: Vec<_> = iter.by_ref().map(|r| (r.order_id, r.price)).collect();
+    assert_eq!(parsed, [(10, 100), (12, 105)]);
+    assert_eq!(iter.reader().skipped_count(), 1);
+}
+
+#[test]
+fn quotes_parse_into() {
+    use qsh_rs::types::Quotes;
+    use qsh_rs::QuotesReader;
+
+    // rows of (price delta, volume), negative volume is a bid, zero removes the level
+    let frames: [&[(i64, i64)]; 3] =
+        [&[(100, -5), (1, 3), (1, 4)], &[(-2, -7), (1, 0)], &[(1, 0), (-4, -1)]];
+    let mut enc = Encoder::new(0x10, 637200251900000000);
+    for rows in frames {
+        enc.growing(1);
+        enc.leb(rows.len() as i64);
+        rows.iter().for_each(|&(dp, v)| {
+            enc.leb(dp);
+            enc.leb(v);
+        });
+    }
+
+    let mut rd = reader(&enc.buf);
+    header(&mut rd).unwrap();
+    let parsed: Vec<_> = rd.into_iter::<QuotesReader>().map(|q| (q.bid, q.ask)).collect();
+    assert_eq!(parsed[1], (vec![(100, 7)], vec![(102, 4)]));
+    assert_eq!(parsed[2], (vec![(98, 1), (100, 7)], vec![]));
+
+    let mut rd = reader(&enc.buf);
+    header(&mut rd).unwrap();
+    let (mut parser, mut out) = (QuotesReader::default(), Quotes::default());
+    for expected in parsed {
+        parser.parse_into(&mut rd, &mut out).unwrap();
+        assert_eq!((out.bid.clone(), out.ask.clone()), expected);
+    }
+    assert!(rd.eof().unwrap());
+}
+
+#[test]
+fn raw_recording_time() {
+    let enc = Encoder::new(0x70, -5);
+    let hdr = header(&mut reader(&enc.buf)).unwrap();
+    assert_eq!(hdr.recording_time, -5);
+    assert!(matches!(hdr.recording_time_or_err(), Err(QshError::Validation(_))));
+
+    let enc = Encoder::new(0x70, 637200251900000000);
+    let hdr = header(&mut reader(&enc.buf)).unwrap();
+    assert_eq!(hdr.recording_time_or_err().unwrap(), 637200251900000000);
+}
+
+#[test]
+fn deals_absolute_time() {
+    use qsh_rs::DealReader;
+
+    // 2020-03-17 10:00:00 UTC
+    let unix = 1584439200000;
+    let recording_time = (unix + 62135596800000) * 10_000;
+    let mut enc = Encoder::new(0x20, recording_time);
+    // price and amount only, the exchange time is not known yet
+    enc.growing(5);
+    enc.buf.push(1 << 5 | 1 << 6 | 1);
+    enc.leb(100);
+    enc.leb(1);
+    // with the exchange time
+    enc.growing(10);
+    enc.buf.push(1 << 2 | 2);
+    enc.growing(unix + 62135596800000 + 3);
+    // carries the previous exchange time
+    enc.growing(10);
+    enc.buf.push(1 << 6 | 1);
+    enc.leb(2);
+
+    let mut rd = reader(&enc.buf);
+    let hdr = header(&mut rd).unwrap();
+    let deals: Vec<_> = rd
+        .into_iter_with(DealReader::with_absolute_time(&hdr))
+        .map(|d| (d.ts, d.deal.side, d.deal.amount))
+        .collect();
+    assert_eq!(
+        deals,
+        [
+            ((unix + 5) * 1_000_000, Side::Buy, 1),
+            ((unix + 3) * 1_000_000, Side::Sell, 1),
+            ((unix + 3) * 1_000_000, Side::Buy, 2)
+        ]
+    );
+}
+
+#[test]
+fn inflate_reader() {
+    use qsh_rs::inflate_reader;
+
+    let records = [add(1, Side::Buy, 100, 5), add(2, Side::Sell, 101, 3)];
+    let mut enc = Encoder::new(0x70, 637200251900000000);
+    records.iter().for_each(|rec| enc.orderlog(1, rec));
+
+    // non-seekable source, as of the pipe
+    let gzip = enc.gzip();
+    let mut bytes = inflate_reader(std::io::Read::chain(&gzip[..1], &gzip[1..]));
+    assert_eq!(header(&mut bytes).unwrap().stream, Stream::ORDERLOG);
+    let ids: Vec<_> = bytes.into_iter::<OrderLogReader>().map(|r| r.order_id).collect();
+    assert_eq!(ids, [1, 2]);
+}
+
+#[test]
+fn quotes_removed() {
+    use qsh_rs::QuotesReader;
+
+    // removed ask 101, then ask 102, then bid 100 removed and restored within the frame
+    let frames: [&[(i64, i64)]; 4] = [
+        &[(100, -5), (1, 3), (1, 4)],
+        &[(-2, -7), (1, 0)],
+        &[(1, 0), (-4, -1)],
+        &[(2, 0), (0, -2)],
+    ];
+    let mut enc = Encoder::new(0x10, 637200251900000000);
+    for rows in frames {
+        enc.growing(1);
+        enc.leb(rows.len() as i64);
+        rows.iter().for_each(|&(dp, v)| {
+            enc.leb(dp);
+            enc.leb(v);
+        });
+    }
+
+    let mut rd = reader(&enc.buf);
+    header(&mut rd).unwrap();
+    let removed: Vec<_> = rd.into_iter::<QuotesReader>().map(|q| q.removed).collect();
+    assert_eq!(removed, [vec![], vec![(Side::Sell, 101)], vec![(Side::Sell, 102)], vec![]]);
+}
+
+#[test]
+fn quotes_totals() {
+    use qsh_rs::QuotesReader;
+
+    let frames: [&[(i64, i64)]; 2] = [&[(100, -5), (1, 3), (1, 4)], &[(-2, -7), (1, 0)]];
+    let mut enc = Encoder::new(0x10, 637200251900000000);
+    for rows in frames {
+        enc.growing(1);
+        enc.leb(rows.len() as i64);
+        rows.iter().for_each(|&(dp, v)| {
+            enc.leb(dp);
+            enc.leb(v);
+        });
+    }
+
+    let mut rd = reader(&enc.buf);
+    header(&mut rd).unwrap();
+    let totals: Vec<_> = rd
+        .into_iter_with(QuotesReader::with_totals())
+        .map(|q| (q.bid_total, q.ask_total))
+        .collect();
+    assert_eq!(totals, [(5, 7), (7, 4)]);
+
+    let mut rd = reader(&enc.buf);
+    header(&mut rd).unwrap();
+    assert!(rd.into_iter::<QuotesReader>().all(|q| (q.bid_total, q.ask_total) == (0, 0)));
+}
+
+#[test]
+fn try_iter() {
+    use qsh_rs::orderbook::{tx_end, PartitionBy};
+    use qsh_rs::process_results;
+    use qsh_rs::types::OLFlags;
+    use qsh_rs::utils::l3tol2::try_convert;
+
+    let records = [
+        add(1, Side::Buy, 100, 5),
+        with(add(2, Side::Sell, 101, 3), OLFlags::TxEnd),
+        with(add(3, Side::Buy, 99, 1), OLFlags::TxEnd),
+    ];
+    let mut bytes = orderlog_stream(&records);
+    // the last record is cut
+    bytes.truncate(bytes.len() - 2);
+
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let mut iter = rd.try_iter::<OrderLogReader>();
+    assert_eq!(iter.next().unwrap().unwrap().order_id, 1);
+    assert_eq!(iter.next().unwrap().unwrap().order_id, 2);
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let txs =
+        process_results(rd.try_iter::<OrderLogReader>(), |recs| recs.partition_by(tx_end).count());
+    assert!(txs.is_err());
+
+    let bytes = orderlog_stream(&records);
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let txs =
+        process_results(rd.try_iter::<OrderLogReader>(), |recs| recs.partition_by(tx_end).count());
+    assert_eq!(txs.unwrap(), 2);
+
+    // the complete transaction, then the error
+    let mut bytes = orderlog_stream(&records);
+    bytes.truncate(bytes.len() - 2);
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let l2: Vec<_> = try_convert(rd.try_iter::<OrderLogReader>(), 0).collect();
+    assert_eq!(l2.len(), 2);
+    assert_eq!(l2[0].as_ref().unwrap().len(), 2);
+    assert!(l2[1].is_err());
+}
+
+#[test]
+fn parse_report() {
+    use qsh_rs::types::OLFlags;
+    use qsh_rs::utils::report::{parse_with_report, ParseReport};
+
+    const T0: i64 = 63_800_000_000_000;
+    let records = [
+        at(add(1, Side::Buy, 100, 5), T0 + 5),
+        at(with(add(2, Side::Sell, 101, 3), OLFlags::TxEnd), T0 + 5),
+        at(with(add(3, Side::Buy, 99, 1), OLFlags::TxEnd), T0 + 2),
+        at(with(add(4, Side::Sell, 102, 1), OLFlags::NewSession), T0 + 9),
+        at(add(5, Side::Sell, 103, 1), T0 + 9),
+        at(with(add(6, Side::Sell, 104, 1), OLFlags::TxEnd), T0 + 9),
+        // incomplete trailing transaction
+        at(add(7, Side::Buy, 98, 1), T0 + 10),
+    ];
+    let mut enc = Encoder::new(0x70, 637200251900000000);
+    records.iter().for_each(|rec| enc.orderlog(1, rec));
+    let path = std::env::temp_dir().join(format!("qsh-report-{}.qsh", std::process::id()));
+    std::fs::write(&path, enc.gzip()).unwrap();
+    let parsed = parse_with_report(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let (header, report) = parsed.unwrap();
+    assert_eq!(header.stream, Stream::ORDERLOG);
+    assert_eq!(report, ParseReport::from_records(records));
+    assert_eq!(report.records, 7);
+    assert_eq!(report.transactions, 4);
+    assert_eq!(report.max_tx_len, 3);
+    assert_eq!(report.sessions, 2);
+    assert_eq!(report.min_ts, Some(time::ticks_to_unix_ns(T0 + 2)));
+    assert_eq!(report.max_ts, Some(time::ticks_to_unix_ns(T0 + 10)));
+}
+
+#[test]
+fn quotes_missing_key() {
+    use qsh_rs::QuotesReader;
+
+    // the logger captures the warnings of this thread only, the tests run in parallel
+    struct Capture;
+    thread_local!(static WARNINGS: std::cell::RefCell<Vec<String>> = Default::default());
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            WARNINGS.with(|w| w.borrow_mut().push(record.args().to_string()));
+        }
+        fn flush(&self) {}
+    }
+    log::set_logger(&Capture).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+
+    // removal of the ask 102 that was never quoted
+    let frames: [&[(i64, i64)]; 2] = [&[(100, -5), (1, 3)], &[(1, 0), (-1, 0)]];
+    let mut enc = Encoder::new(0x10, 637200251900000000);
+    for rows in frames {
+        enc.growing(1);
+        enc.leb(rows.len() as i64);
+        rows.iter().for_each(|&(dp, v)| {
+            enc.leb(dp);
+            enc.leb(v);
+        });
+    }
+
+    let mut rd = reader(&enc.buf);
+    header(&mut rd).unwrap();
+    let quotes: Vec<_> = rd.into_iter::<QuotesReader>().collect();
+    assert_eq!(quotes[1].removed, [(Side::Sell, 101)]);
+    assert_eq!(quotes[1].bid, [(100, 5)]);
+    WARNINGS.with(|w| assert_eq!(*w.borrow(), ["removal of the missing quote at 102"]));
+}
+
+#[test]
+fn qsh_filename() {
+    use qsh_rs::utils::filename::{parse_qsh_filename, QshFileInfo};
+
+    let info = |instrument: &str, stream| QshFileInfo {
+        instrument: instrument.into(),
+        date: "2020-03-17".into(),
+        stream,
+    };
+    assert_eq!(
+        parse_qsh_filename("Si-3.20.2020-03-17.OrdLog.qsh"),
+        Some(info("Si-3.20", Stream::ORDERLOG))
+    );
+    assert_eq!(
+        parse_qsh_filename("data/erinrv/Si-3.20_FT.2020-03-17.Quotes.qsh"),
+        Some(info("Si-3.20_FT", Stream::QUOTES))
+    );
+    assert_eq!(parse_qsh_filename("SBER.2020-03-17.Deals.qsh"), Some(info("SBER", Stream::DEALS)));
+    assert_eq!(
+        parse_qsh_filename("SBER.2020-03-17.AuxInfo.qsh").map(|i| i.stream),
+        Some(Stream::AUXINFO)
+    );
+    for name in [
+        "SBER.2020-03-17.Deals",
+        "SBER.2020-03-17.Trades.qsh",
+        "SBER.20-03-17.Deals.qsh",
+        ".2020-03-17.Deals.qsh",
+        "2020-03-17.Deals.qsh",
+        "x.qsh",
+    ] {
+        assert_eq!(parse_qsh_filename(name), None, "{name}");
+    }
+}
+
+#[test]
+fn truncated_streams() {
+    use qsh_rs::inflate_reader;
+
+    let count = |bytes: &[u8]| -> Result<usize, QshError> {
+        let mut rd = inflate_reader(bytes);
+        header(&mut rd)?;
+        rd.try_iter::<OrderLogReader>().try_fold(0, |n, rec| rec.map(|_| n + 1))
+    };
+    let mut enc = Encoder::new(0x70, 637200251900000000);
+    (1..=50).for_each(|id| enc.orderlog(1, &add(id, Side::Buy, 100 - id, id)));
+    let gz = enc.gzip();
+    assert_eq!(count(&gz).unwrap(), 50);
+
+    // the gzip stream is cut off inside the deflate data and inside the trailer
+    assert!(matches!(count(&gz[..gz.len() - 12]), Err(QshError::Truncated(_))));
+    assert!(matches!(count(&gz[..gz.len() - 4]), Err(QshError::Truncated(_))));
+    // CRC mismatch
+    let mut bad = gz.clone();
+    let crc = bad.len() - 8;
+    bad[crc] ^= 0xff;
+    assert!(matches!(count(&bad), Err(QshError::Truncated(_))));
+
+    // the intact gzip of the data ending inside the last record
+    enc.buf.pop();
+    assert!(matches!(count(&enc.gzip()), Err(QshError::Corrupted(_))));
+    let mut rd = inflate_reader(&gz[..40]);
+    assert!(matches!(header(&mut rd), Err(QshError::Truncated(_))));
+}
+
+#[test]
+fn leb128_limits() {
+    for v in [0, 1, -1, 63, -64, 64, -65, i64::MAX, i64::MIN] {
+        let mut buf = vec![];
+        leb128::write::signed(&mut buf, v).unwrap();
+        assert_eq!(reader(&buf).leb().unwrap(), v);
+    }
+    for v in [0, 127, 128, u64::MAX] {
+        let mut buf = vec![];
+        leb128::write::unsigned(&mut buf, v).unwrap();
+        assert_eq!(reader(&buf).uleb().unwrap(), v);
+    }
+    assert!(matches!(reader(&[0xff; 11]).uleb(), Err(QshError::Parsing(_))));
+    assert!(matches!(reader(&[0xff; 11]).leb(), Err(QshError::Parsing(_))));
+}
+
+#[test]
+fn record_source() {
+    use qsh_rs::source::{record_source, Record, RecordSource};
+    use std::io::BufRead;
+
+    let mut ol = Encoder::new(0x70, 637200251900000000);
+    ol.orderlog(1, &add(1, Side::Buy, 100, 5));
+    ol.orderlog(1, &add(2, Side::Sell, 101, 3));
+    let mut q = Encoder::new(0x10, 637200251900000000);
+    q.quotes(1, &[(100, -5), (101, 3)]);
+
+    // both the stream and the bytes are known at runtime only
+    let sources: Vec<Box<dyn RecordSource>> = [ol.buf, q.buf]
+        .into_iter()
+        .map(|bytes| {
+            let mut rd: Box<dyn BufRead> = Box::new(std::io::Cursor::new(bytes));
+            let stream = header(&mut rd).unwrap().stream;
+            record_source(stream, rd).unwrap()
+        })
+        .collect();
+    assert_eq!(
+        sources.iter().map(|s| s.stream()).collect::<Vec<_>>(),
+        [Stream::ORDERLOG, Stream::QUOTES]
+    );
+
+    let records: Vec<Vec<_>> =
+        sources.into_iter().map(|s| s.collect::<Result<_, _>>().unwrap()).collect();
+    assert!(
+        matches!(records[0][..], [Record::OrderLog(a), Record::OrderLog(b)] if (a.order_id, b.order_id) == (1, 2))
+    );
+    assert!(
+        matches!(&records[1][..], [Record::Quotes(q)] if q.bid == [(100, 5)] && q.ask == [(101, 3)])
+    );
+
+    assert!(record_source(Stream::MESSAGES, reader(&[])).is_err());
+}
+
+#[test]
+fn record_ranges() {
+    let records: Vec<_> =
+        (0..10).map(|i| at(add(100 + i * 7, Side::Buy, 100 - i, 1), 1_000 + i * i)).collect();
+    let bytes = orderlog_stream(&records);
+    let key = |r: &qsh_rs::types::OrderLog| (r.order_id, r.timestamp, r.price);
+    let full: Vec<_> = records.iter().map(key).collect();
+
+    let chunk = 3;
+    let mut joined = vec![];
+    for k in 0..4 {
+        let mut rd = reader(&bytes);
+        header(&mut rd).unwrap();
+        let mut iter = rd.into_iter::<OrderLogReader>();
+        assert_eq!(iter.skip_records(k * chunk), k * chunk);
+        joined.extend(iter.take_records(chunk).map(|r| key(&r)));
+    }
+    // the chunks concatenated are the stream
+    assert_eq!(joined, full);
+
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let mut iter = rd.try_iter::<OrderLogReader>();
+    assert_eq!(iter.skip_records(8).unwrap(), 8);
+    assert_eq!(iter.skip_records(5).unwrap(), 2);
+    assert_eq!(iter.take_records(1).count(), 0);
+
+    // the error of the skipped record
+    let mut rd = reader(&bytes[..bytes.len() - 1]);
+    header(&mut rd).unwrap();
+    assert!(matches!(
+        rd.try_iter::<OrderLogReader>().skip_records(20),
+        Err(QshError::Corrupted(_))
+    ));
+}
+
+#[test]
+fn checked_iter() {
+    use qsh_rs::{DealTimeReader, QuotesReader};
+
+    let mut enc = Encoder::new(0x70, 637200251900000000);
+    enc.orderlog(0, &add(1, Side::Buy, 100, 5));
+    let mut rd = reader(&enc.buf);
+    let hdr = header(&mut rd).unwrap();
+    assert_eq!(OrderLogReader::EXPECTED_STREAM, Stream::ORDERLOG);
+    assert_eq!(DealTimeReader::EXPECTED_STREAM, Stream::DEALS);
+    assert!(matches!(
+        reader(&[]).into_checked_iter::<QuotesReader>(&hdr),
+        Err(QshError::Validation(_))
+    ));
+    let records: Vec<_> = rd.into_checked_iter::<OrderLogReader>(&hdr).unwrap().collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!((records[0].order_id, records[0].amount), (1, 5));
+}
+
+#[test]
+fn parse_policy() {
+    use qsh_rs::orderbook::{NopListener, OrderBook};
+    use qsh_rs::policy::{MissingKey, Overflow, ParsePolicy};
+    use qsh_rs::types::OLFlags;
+    use qsh_rs::QuotesReader;
+
+    let quotes = |frames: &[&[(i64, i64)]]| {
+        let mut enc = Encoder::new(0x10, 637200251900000000);
+        for rows in frames {
+            enc.growing(1);
+            enc.leb(rows.len() as i64);
+            rows.iter().for_each(|&(dp, v)| {
+                enc.leb(dp);
+                enc.leb(v);
+            });
+        }
+        enc.buf
+    };
+    let parse = |bytes: &[u8], policy| {
+        let mut rd = reader(bytes);
+        header(&mut rd).unwrap();
+        rd.try_iter_with_policy::<QuotesReader>(policy).collect::<Vec<_>>()
+    };
+
+    // the default is of the plain reader, the missing key is logged
+    let missing = quotes(&[&[(100, -5)], &[(2, 0)]]);
+    assert!(parse(&missing, ParsePolicy::default()).iter().all(Result::is_ok));
+    let policy = ParsePolicy { on_missing_key: MissingKey::Fail, ..Default::default() };
+    let parsed = parse(&missing, policy);
+    assert!(matches!(parsed[..], [Ok(_), Err(QshError::Parsing(_))]));
+
+    let overflow = quotes(&[&[(i64::MAX, -5)], &[(1, 3)]]);
+    let policy = ParsePolicy { on_overflow: Overflow::Fail, ..Default::default() };
+    match &parse(&overflow, policy)[..] {
+        [Ok(_), Err(QshError::Parsing(msg))] => assert_eq!(msg, "key overflow"),
+        other => panic!("{other:?}"),
+    }
+    let panicked = std::panic::catch_unwind(|| parse(&overflow, ParsePolicy::default()));
+    assert!(panicked.is_err());
+
+    // the record of both sides is skipped by the lenient policy
+    let records = [add(10, Side::Buy, 100, 5), with(add(11, Side::Buy, 101, 2), OLFlags::Sell)];
+    let bytes = orderlog_stream(&records);
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    assert_eq!(rd.into_iter_with_policy::<OrderLogReader>(ParsePolicy::lenient()).count(), 1);
+    let mut rd = reader(&bytes);
+    header(&mut rd).unwrap();
+    let parsed: Vec<_> = rd.try_iter_with_policy::<OrderLogReader>(ParsePolicy::strict()).collect();
+    assert!(matches!(parsed[..], [Ok(_), Err(QshError::Parsing(_))]));
+
+    // the book of the strict policy fails on the crossed book and the unknown order
+    let crossed = [add(1, Side::Buy, 101, 1), add(2, Side::Sell, 100, 1)];
+    let mut book = OrderBook::with_policy(ParsePolicy::strict());
+    book.apply(crossed[0], &mut NopListener).unwrap();
+    assert!(matches!(book.apply(crossed[1], &mut NopListener), Err(QshError::InvalidState(_))));
+    let mut book = OrderBook::with_policy(ParsePolicy::lenient());
+    crossed.into_iter().for_each(|rec| book.apply(rec, &mut NopListener).unwrap());
+    book.apply(cancel(7, Side::Buy, 99, 0), &mut NopListener).unwrap();
+    assert_eq!(book.anomalies().missing_level, 1);
+    let mut book = OrderBook::with_policy(ParsePolicy::default());
+    assert!(book.apply(cancel(7, Side::Buy, 99, 0), &mut NopListener).is_err());
+}
+
+#[test]
+fn capacity_hint() {
+    let records: Vec<_> = (0..10).map(|i| at(add(i, Side::Buy, 100, 1), 1_000)).collect();
+    let bytes = orderlog_stream(&records);
+    let iter = || {
+        let mut rd = reader(&bytes);
+        header(&mut rd).unwrap();
+        rd
+    };
+
+    // the estimate is no lower bound, the file may hold fewer records
+    let mut hinted = iter().into_iter::<OrderLogReader>().with_capacity_hint(20);
+    assert_eq!(hinted.size_hint(), (0, None));
+    hinted.skip_records(3);
+    assert_eq!(hinted.size_hint(), (0, None));
+    let parsed = hinted.collect_vec();
+    assert!(parsed.capacity() >= 17 && parsed.len() == 7);
+    // the records past the estimate are yielded all the same
+    let parsed = iter().into_iter::<OrderLogReader>().with_capacity_hint(4).collect_vec();
+    assert_eq!(parsed.len(), 10);
+
+    let hinted = iter().try_iter::<OrderLogReader>().with_capacity_hint(12);
+    assert_eq!(hinted.size_hint(), (0, None));
+    let parsed = hinted.collect_vec().unwrap();
+    assert!(parsed.capacity() >= 12 && parsed.len() == 10);
+
+    // nothing past the error
+    let mut rd = reader(&bytes[..bytes.len() - 1]);
+    header(&mut rd).unwrap();
+    let mut failing = rd.try_iter::<OrderLogReader>().with_capacity_hint(20);
+    assert!(failing.by_ref().any(|rec| rec.is_err()));
+    assert_eq!(failing.size_hint(), (0, Some(0)));
+    let mut rd = reader(&bytes[..bytes.len() - 1]);
+    header(&mut rd).unwrap();
+    assert!(rd.try_iter::<OrderLogReader>().with_capacity_hint(20).collect_vec().is_err());
+}
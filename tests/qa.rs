@@ -0,0 +1,86 @@
+mod common;
+
+use common::*;
+use qsh_rs::types::{Deal, Side};
+use qsh_rs::utils::qa::{sequence_report, SeqReport};
+use qsh_rs::QshError;
+
+const T0: i64 = 637200251900000000;
+const HOUR_MS: i64 = 3_600_000;
+
+fn deal(rec: qsh_rs::types::OrderLog, deal_id: i64) -> qsh_rs::types::OrderLog {
+    qsh_rs::types::OrderLog { deal_id, deal_price: rec.price, ..rec }
+}
+
+#[test]
+fn orderlog_sequence() {
+    let ts = 63_800_000_000_000;
+    let records = [
+        (0, at(add(1, Side::Buy, 100, 5), ts)),
+        (0, at(add(2, Side::Sell, 101, 5), ts)),
+        // the gap of 8
+        (1, at(add(10, Side::Buy, 99, 1), ts + 1)),
+        // the regression
+        (1, at(add(5, Side::Buy, 98, 1), ts + 2)),
+        (1, at(deal(fill(1, Side::Buy, 100, 1, 4), 100), ts + 3)),
+        (0, at(deal(fill(2, Side::Sell, 100, 1, 4), 100), ts + 3)),
+        // the exchange time back
+        (1, at(deal(fill(1, Side::Buy, 100, 1, 3), 99), ts + 2)),
+        (HOUR_MS, at(deal(fill(1, Side::Buy, 100, 1, 2), 105), ts + 4)),
+        // no exchange time
+        (0, at(cancel(10, Side::UNKNOWN, 99, 0), 0)),
+    ];
+    let mut enc = Encoder::new(0x70, T0);
+    records.iter().for_each(|(delta, rec)| enc.orderlog(*delta, rec));
+    let path = std::env::temp_dir().join(format!("qsh-qa-{}.qsh", std::process::id()));
+    std::fs::write(&path, enc.gzip()).unwrap();
+    let report = sequence_report(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let report = report.unwrap();
+    assert_eq!(report.records, 9);
+    assert_eq!((report.order_id_regressions, report.max_order_id_gap), (1, 8));
+    assert_eq!((report.deal_id_regressions, report.max_deal_id_gap), (1, 6));
+    assert_eq!((report.ts_regressions, report.unknown_side), (1, 1));
+    assert_eq!(report.anomalies(), 4);
+    // the recording starts at 13:13:10
+    assert_eq!(report.hourly.values().copied().collect::<Vec<_>>(), [7, 2]);
+    assert!(report.to_string().starts_with("records: 9, anomalies: 4"));
+
+    let quotes = Encoder::new(0x10, T0);
+    std::fs::write(&path, quotes.gzip()).unwrap();
+    let report = sequence_report(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(report, Err(QshError::Parsing(_))));
+}
+
+#[test]
+fn deal_sequence() {
+    let mut report = SeqReport::default();
+    let ts = 63_800_000_000_000;
+    for (deal_id, timestamp, side) in [
+        (10, 0, Side::Buy),
+        (12, ts + 5, Side::Sell),
+        (11, ts + 4, Side::Buy),
+        (20, ts + 6, Side::UNKNOWN),
+    ] {
+        report.observe_deal(0, &Deal { deal_id, timestamp, side, ..Default::default() });
+    }
+    assert_eq!((report.deal_id_regressions, report.max_deal_id_gap), (1, 9));
+    assert_eq!(
+        (report.ts_regressions, report.unknown_side, report.order_id_regressions),
+        (1, 1, 0)
+    );
+    assert_eq!(report.hourly, [(0, 4)].into());
+}
+
+#[cfg(feature = "jsonl")]
+#[test]
+fn json_stats() {
+    let mut report = SeqReport::default();
+    report.observe_deal(0, &Deal { deal_id: 1, side: Side::Buy, ..Default::default() });
+    let json = serde_json::to_string(&report).unwrap();
+    assert!(json.contains(r#""records":1"#) && json.contains(r#""hourly":{"0":1}"#));
+    let back: SeqReport = serde_json::from_str(&json).unwrap();
+    assert_eq!((back.records, back.hourly), (report.records, report.hourly));
+}
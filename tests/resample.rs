@@ -0,0 +1,102 @@
+mod common;
+
+use common::*;
+use qsh_rs::types::{OLFlags, OrderLog, Side};
+use qsh_rs::utils::resample;
+
+// unix time of the records is the same as of the workload
+const UNIX_EPOCH: i64 = 62135596800000;
+const MS: i64 = 1_000_000;
+
+fn tx(ms: i64, recs: impl IntoIterator<Item = OrderLog>) -> Vec<OrderLog> {
+    let mut tx: Vec<_> = recs.into_iter().map(|r| at(r, UNIX_EPOCH + ms)).collect();
+    let last = tx.pop().unwrap();
+    tx.push(with(last, OLFlags::TxEnd));
+    tx
+}
+
+fn txs() -> Vec<Vec<OrderLog>> {
+    vec![
+        tx(50, [add(1, Side::Buy, 100, 5), add(2, Side::Sell, 102, 1)]),
+        tx(100, [add(3, Side::Buy, 101, 1)]),
+        tx(150, [add(4, Side::Sell, 103, 1)]),
+        tx(520, [add(5, Side::Sell, 101, 2)]),
+    ]
+}
+
+#[test]
+fn boundaries() {
+    let snapshots: Vec<_> = resample::snapshots(txs(), 1, 100 * MS).map(Result::unwrap).collect();
+    let ts: Vec<_> = snapshots.iter().map(|s| s.ts).collect();
+    assert_eq!(ts, [100 * MS, 200 * MS, 300 * MS, 400 * MS, 500 * MS]);
+
+    // strictly before the boundary: the transaction at 100 isn't applied yet
+    assert_eq!(
+        (&snapshots[0].bids[..], &snapshots[0].asks[..]),
+        (&[(100, 5)][..], &[(102, 1)][..])
+    );
+    // forward-filled across the quiet period
+    for s in &snapshots[1..] {
+        assert_eq!((&s.bids[..], &s.asks[..]), (&[(101, 1)][..], &[(102, 1)][..]));
+    }
+
+    // at most 2 repeats of the state at 200
+    let capped: Vec<_> =
+        resample::snapshots_capped(txs(), 0, 100 * MS, 2).map(|s| s.unwrap().ts).collect();
+    assert_eq!(capped, [100 * MS, 200 * MS, 300 * MS, 400 * MS]);
+    let capped: Vec<_> =
+        resample::snapshots_capped(txs(), 0, 100 * MS, 0).map(|s| s.unwrap().ts).collect();
+    assert_eq!(capped, [100 * MS, 200 * MS]);
+    assert_eq!(resample::snapshots(txs(), 0, 100 * MS).nth(1).unwrap().unwrap().asks.len(), 2);
+}
+
+#[test]
+fn failed() {
+    let mut txs = txs();
+    txs.insert(2, tx(120, [cancel(42, Side::Buy, 100, 0)]));
+    let mut snapshots = resample::snapshots(txs, 1, 100 * MS);
+    assert!(snapshots.next().unwrap().is_ok());
+    assert!(snapshots.next().unwrap().is_err());
+    assert!(snapshots.next().is_none());
+}
+
+#[test]
+fn next_into() {
+    let mut snapshots = resample::snapshots(txs(), 1, 100 * MS);
+    let mut out = [0; 4];
+    assert_eq!(snapshots.next_into(&mut out).unwrap().unwrap(), 100 * MS);
+    assert_eq!(out, [100, 5, 102, 1]);
+    assert_eq!(snapshots.next_into(&mut out).unwrap().unwrap(), 200 * MS);
+    assert_eq!(out, [101, 1, 102, 1]);
+    assert!(snapshots.next_into(&mut [0; 8]).unwrap().is_err());
+    let ts: Vec<_> = snapshots.map(|s| s.unwrap().ts).collect();
+    assert_eq!(ts, [400 * MS, 500 * MS]);
+}
+
+#[test]
+fn quotes() {
+    use qsh_rs::types::{Header, Quotes, Stream};
+
+    // 2020-03-17 10:00:00 UTC
+    let unix = 1584439200000;
+    let header = Header {
+        recording_time: (UNIX_EPOCH + unix) * 10_000,
+        version: 4,
+        stream: Stream::QUOTES,
+        instrument: String::new(),
+        recorder: String::new(),
+        comment: String::new(),
+    };
+    let frame =
+        |delta, bid| Quotes { frame_time_delta: delta, bid: vec![(bid, 1)], ..Default::default() };
+    let frames = || [frame(30, 100), frame(50, 101), frame(20, 102), frame(290, 103)];
+    let buckets = |fill| {
+        resample::quotes(frames(), 100, &header, fill)
+            .map(|(ts, q)| ((ts / MS - unix), q.bid[0].0))
+            .collect::<Vec<_>>()
+    };
+    // the frame at 100 closes the next bucket
+    assert_eq!(buckets(false), [(100, 101), (200, 102), (400, 103)]);
+    assert_eq!(buckets(true), [(100, 101), (200, 102), (300, 102), (400, 103)]);
+    assert_eq!(resample::quotes(frames(), 1000, &header, true).count(), 1);
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::*;
+use qsh_rs::orderbook::OrderLogStream;
+use qsh_rs::time;
+use qsh_rs::types::{OLFlags, OrderLog, Side};
+use qsh_rs::utils::sessions::{session_bounds, split};
+
+const T0: i64 = 637200251900000000;
+
+fn records() -> Vec<OrderLog> {
+    let snapshot = |rec| with(with(rec, OLFlags::Snapshot), OLFlags::TxEnd);
+    vec![
+        at(add(1, Side::Buy, 100, 1), T0 + 10),
+        at(with(add(2, Side::Buy, 99, 1), OLFlags::TxEnd), T0 + 11),
+        // the NewSession record in the middle of the transaction
+        at(add(3, Side::Buy, 98, 1), T0 + 20),
+        at(with(add(4, Side::Buy, 100, 1), OLFlags::NewSession), T0 + 21),
+        at(with(add(5, Side::Sell, 101, 1), OLFlags::TxEnd), T0 + 22),
+        // the snapshot seeding the next session
+        at(snapshot(add(6, Side::Buy, 97, 1)), T0 + 30),
+        at(with(with(add(7, Side::Buy, 100, 1), OLFlags::NewSession), OLFlags::TxEnd), T0 + 40),
+        at(with(add(8, Side::Sell, 102, 1), OLFlags::TxEnd), T0 + 50),
+    ]
+}
+
+fn ids(txs: &[Vec<OrderLog>]) -> Vec<Vec<i64>> {
+    txs.iter().map(|tx| tx.iter().map(|r| r.order_id).collect()).collect()
+}
+
+#[test]
+fn sessions() {
+    let sessions: Vec<_> = split(records()).collect();
+    let t = |ticks| time::ticks_to_unix_ns(T0 + ticks);
+    let summary: Vec<_> = sessions.iter().map(|s| (s.index, s.start_ts, s.end_ts)).collect();
+    assert_eq!(summary, [(0, t(10), t(20)), (1, t(21), t(22)), (2, t(30), t(50))]);
+    assert_eq!(ids(&sessions[0].transactions), [vec![1, 2], vec![3]]);
+    assert_eq!(ids(&sessions[1].transactions), [vec![4, 5]]);
+    assert_eq!(ids(&sessions[2].transactions), [vec![6], vec![7], vec![8]]);
+
+    // the sessions concatenated are the records of the transactions
+    let concat: Vec<_> = sessions.into_iter().flat_map(|s| s.transactions).flatten().collect();
+    let txs: Vec<_> = records().into_iter().transactions().flatten().collect();
+    assert_eq!(format!("{concat:?}"), format!("{txs:?}"));
+
+    // no NewSession records, the snapshot opening the stream
+    let one: Vec<_> =
+        split(records().into_iter().skip(5).take(1).chain(records().drain(..2))).collect();
+    assert_eq!(one.len(), 1);
+    assert_eq!(ids(&one[0].transactions), [vec![6], vec![1, 2]]);
+    let seeded: Vec<_> = split(records().into_iter().skip(5)).collect();
+    assert_eq!(seeded.iter().map(|s| s.index).collect::<Vec<_>>(), [0]);
+    assert_eq!(split(vec![]).count(), 0);
+}
+
+#[test]
+fn bounds() {
+    for records in [records(), records()[5..].to_vec(), records()[..2].to_vec(), vec![]] {
+        let mut enc = Encoder::new(0x70, T0);
+        records.iter().for_each(|rec| enc.orderlog(1, rec));
+        let path = std::env::temp_dir().join(format!("qsh-sessions-{}.qsh", std::process::id()));
+        std::fs::write(&path, enc.gzip()).unwrap();
+        let bounds = session_bounds(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let expected: Vec<_> = split(records).map(|s| (s.start_ts, s.end_ts)).collect();
+        assert_eq!(bounds.unwrap(), expected);
+    }
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::*;
+use qsh_rs::time;
+use qsh_rs::types::{AuxInfo, Deal, OLFlags, OrderLog, Side};
+use qsh_rs::utils::summary::{file_stats, time_span, FileStats, StatsBuilder};
+use qsh_rs::QshError;
+
+const T0: i64 = 637200251900000000;
+const TS: i64 = 63_800_000_000_000;
+
+fn deal(rec: OrderLog, deal_id: i64, deal_price: i64) -> OrderLog {
+    OrderLog { deal_id, deal_price, ..rec }
+}
+
+fn stats_of(bytes: Vec<u8>) -> Result<FileStats, QshError> {
+    let path = std::env::temp_dir().join(format!("qsh-summary-{}.qsh", std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    let stats = file_stats(&path);
+    std::fs::remove_file(&path).unwrap();
+    stats
+}
+
+#[test]
+fn orderlog_stats() {
+    let records = [
+        add(1, Side::Buy, 100, 5),
+        add(2, Side::Buy, 99, 3),
+        with(add(3, Side::Sell, 101, 4), OLFlags::TxEnd),
+        // the deal of the two Fill records
+        deal(fill(3, Side::Sell, 101, 2, 2), 7, 101),
+        with(deal(fill(4, Side::Buy, 102, 2, 0), 7, 101), OLFlags::TxEnd),
+        with(cancel(2, Side::Buy, 99, 0), OLFlags::TxEnd),
+        with(remove(1, Side::Buy, 100), OLFlags::TxEnd),
+    ];
+    let mut enc = Encoder::new(0x70, T0);
+    records.iter().enumerate().for_each(|(i, rec)| enc.orderlog(1, &at(*rec, TS + i as i64)));
+    let stats = stats_of(enc.gzip()).unwrap();
+
+    assert_eq!(stats.stream, "ORDERLOG");
+    assert_eq!((stats.records, stats.transactions, stats.sessions), (7, 4, 1));
+    let ts = |ticks| Some(time::ticks_to_unix_ns(TS + ticks));
+    assert_eq!((stats.first_ts, stats.last_ts), (ts(0), ts(6)));
+    assert_eq!((stats.volume, stats.notional), (2, 202));
+    assert_eq!((stats.unique_orders, stats.max_depth), (3, 2));
+    assert_eq!(
+        (stats.adds, stats.fills, stats.cancels, stats.removes, stats.unknown),
+        (3, 2, 1, 1, 0)
+    );
+    assert!(stats.to_string().contains("records: 7, transactions: 4, sessions: 1"));
+}
+
+#[test]
+fn quotes_stats() {
+    let mut enc = Encoder::new(0x10, T0);
+    enc.quotes(0, &[(99, -3), (100, -5), (101, 4)]);
+    enc.quotes(1_000, &[(98, -1), (101, 0)]);
+    let stats = stats_of(enc.gzip()).unwrap();
+
+    let t0 = time::recording_time_to_unix_ns(T0);
+    assert_eq!(stats.stream, "QUOTES");
+    assert_eq!(
+        (stats.records, stats.first_ts, stats.last_ts),
+        (2, Some(t0), Some(t0 + 1_000_000_000))
+    );
+    assert_eq!(stats.max_depth, 3);
+    assert_eq!((stats.transactions, stats.volume, stats.adds), (0, 0, 0));
+}
+
+#[test]
+fn deal_and_aux_stats() {
+    let mut builder = StatsBuilder::new("DEALS", "SBER");
+    for (order_id, timestamp, price, amount) in
+        [(0, 0, 100, 1), (5, TS, 101, 2), (6, TS + 1, 99, 3)]
+    {
+        builder.observe_deal(&Deal { order_id, timestamp, price, amount, ..Default::default() });
+    }
+    let stats = builder.finish();
+    assert_eq!((stats.records, stats.volume, stats.notional), (3, 6, 100 + 202 + 297));
+    let ts = |ticks| Some(time::ticks_to_unix_ns(TS + ticks));
+    assert_eq!((stats.first_ts, stats.last_ts, stats.unique_orders), (ts(0), ts(1), 2));
+
+    let mut builder = StatsBuilder::new("AUXINFO", "SBER");
+    builder.observe_aux(&AuxInfo { timestamp: TS, ..Default::default() });
+    let stats = builder.finish();
+    assert_eq!((stats.records, stats.first_ts, stats.last_ts), (1, ts(0), ts(0)));
+    assert!(stats.to_string().starts_with("AUXINFO SBER"));
+}
+
+#[cfg(feature = "jsonl")]
+#[test]
+fn json_stats() {
+    let stats =
+        FileStats { stream: "DEALS".into(), records: 1, first_ts: Some(1), ..Default::default() };
+    let json = serde_json::to_string(&stats).unwrap();
+    assert!(json.contains(r#""records":1"#) && json.contains(r#""first_ts":1"#));
+    assert_eq!(serde_json::from_str::<FileStats>(&json).unwrap(), stats);
+}
+
+#[test]
+fn time_spans() {
+    let span_of = |bytes: Vec<u8>| {
+        let path = std::env::temp_dir().join(format!("qsh-span-{}.qsh", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let span = time_span(&path);
+        std::fs::remove_file(&path).unwrap();
+        span
+    };
+    let t0 = time::recording_time_to_unix_ns(T0);
+
+    let mut enc = Encoder::new(0x10, T0);
+    enc.quotes(0, &[(99, -3), (101, 4)]);
+    enc.quotes(1_000, &[(101, 0)]);
+    enc.quotes(250, &[(100, 2)]);
+    assert_eq!(span_of(enc.gzip()).unwrap(), (t0, t0 + 1_250_000_000));
+
+    let mut enc = Encoder::new(0x70, T0);
+    for (i, rec) in Workload::new(3, 5).take(100).enumerate() {
+        enc.orderlog(i as i64 % 3, &at(rec, TS));
+    }
+    assert_eq!(span_of(enc.gzip()).unwrap(), (t0, t0 + 99_000_000));
+    assert_eq!(span_of(Encoder::new(0x70, T0).gzip()).unwrap(), (t0, t0));
+
+    // the truncated record fails the scan
+    let mut enc = Encoder::new(0x10, T0);
+    enc.quotes(5, &[(99, -3)]);
+    enc.buf.pop();
+    assert!(span_of(enc.gzip()).is_err());
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::*;
+use qsh_rs::orderbook::{NopListener, OrderBook};
+use qsh_rs::time;
+use qsh_rs::types::Side;
+
+// 2020-03-17 00:00:00 UTC
+const UNIX_MS: i64 = 1584403200000;
+const TICKS_MS: i64 = UNIX_MS + 62135596800000;
+
+#[test]
+fn conversions() {
+    assert_eq!(time::ticks_to_unix_ms(TICKS_MS), UNIX_MS);
+    assert_eq!(time::ticks_to_unix_ns(TICKS_MS + 1), (UNIX_MS + 1) * 1_000_000);
+    assert_eq!(time::recording_time_to_unix_ns(TICKS_MS * 10_000 + 3), UNIX_MS * 1_000_000 + 300);
+    assert_eq!(time::ticks_to_unix_ms(time::UNIX_EPOCH_MS), 0);
+
+    assert_eq!(time::ns_to_ms(time::ms_to_ns(UNIX_MS)), UNIX_MS);
+    assert_eq!(time::ns_to_ms(1_999_999), 1);
+    assert_eq!(time::ns_to_ms(-1), -1);
+    // out of the nanos range
+    assert_eq!(time::ticks_to_unix_ns(0), i64::MIN);
+}
+
+#[test]
+fn frame_clock() {
+    let mut clock = time::FrameClock::new(TICKS_MS * 10_000 + 3);
+    assert_eq!(time::recording_time_to_unix_ms(TICKS_MS * 10_000 + 3), UNIX_MS);
+    assert_eq!(clock.now(), UNIX_MS * 1_000_000);
+    assert_eq!(clock.advance(5), (UNIX_MS + 5) * 1_000_000);
+    assert_eq!(clock.advance(0), clock.now());
+}
+
+#[test]
+fn snapshot_unit() {
+    // the book and the frame times of the same instant are of the same unit, unix nanos
+    let mut book = OrderBook::default();
+    book.apply(at(add(1, Side::Buy, 100, 1), TICKS_MS + 2), &mut NopListener).unwrap();
+    book.apply(at(add(2, Side::Sell, 101, 1), TICKS_MS + 5), &mut NopListener).unwrap();
+    let mut clock = time::FrameClock::new(TICKS_MS * 10_000);
+    assert_eq!(book.snapshot(1).0, clock.advance(5));
+    assert_eq!(book.book_snapshot(1).ts, (UNIX_MS + 5) * 1_000_000);
+    assert_eq!(book.last_update(), Some(time::ticks_to_unix_ns(TICKS_MS + 5)));
+}
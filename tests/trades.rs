@@ -0,0 +1,157 @@
+mod common;
+
+use common::*;
+use qsh_rs::time;
+use qsh_rs::types::{OLFlags, OrderLog, Side, UID};
+use qsh_rs::utils::trades::{from_orderlog, Trade};
+
+fn deal(mut rec: OrderLog, deal_id: UID) -> OrderLog {
+    (rec.deal_id, rec.deal_price) = (deal_id, rec.price);
+    rec
+}
+
+fn tx(mut recs: Vec<OrderLog>) -> Vec<OrderLog> {
+    let last = recs.pop().unwrap();
+    recs.push(with(last, OLFlags::TxEnd));
+    recs
+}
+
+fn trade(
+    deal_id: UID,
+    price: i64,
+    amount: i64,
+    aggressor: Side,
+    ids: (Option<UID>, Option<UID>),
+) -> Trade {
+    Trade {
+        ts: time::ticks_to_unix_ns(0),
+        deal_id,
+        price,
+        amount,
+        aggressor,
+        maker_order_id: ids.0,
+        taker_order_id: ids.1,
+    }
+}
+
+#[test]
+fn sweep() {
+    let records = tx(vec![
+        add(1, Side::Buy, 102, 5),
+        deal(fill(7, Side::Sell, 101, 2, 0), 100),
+        deal(fill(1, Side::Buy, 101, 2, 3), 100),
+        deal(fill(8, Side::Sell, 102, 3, 0), 101),
+        deal(fill(1, Side::Buy, 102, 3, 0), 101),
+    ]);
+    let trades: Vec<_> = from_orderlog(records).collect();
+    assert_eq!(
+        trades,
+        [
+            trade(100, 101, 2, Side::Buy, (Some(7), Some(1))),
+            trade(101, 102, 3, Side::Buy, (Some(8), Some(1))),
+        ]
+    );
+}
+
+#[test]
+fn edge_cases() {
+    let records = [
+        // the maker is restored from the snapshot, its leg only
+        tx(vec![deal(fill(9, Side::Sell, 100, 1, 0), 102)]),
+        // the taker leg only
+        tx(vec![add(3, Side::Sell, 99, 1), deal(fill(3, Side::Sell, 99, 1, 0), 103)]),
+        // the order 2 crosses the order 1 of the same transaction
+        tx(vec![
+            add(1, Side::Buy, 101, 1),
+            add(2, Side::Sell, 100, 1),
+            deal(fill(1, Side::Buy, 101, 1, 0), 104),
+            deal(fill(2, Side::Sell, 101, 1, 0), 104),
+        ]),
+        // the cross trade brings no deal
+        tx(vec![add(4, Side::Buy, 100, 1), with(remove(5, Side::Sell, 100), OLFlags::CrossTrade)]),
+        // the roles are unknown
+        tx(vec![
+            deal(fill(10, Side::Buy, 98, 2, 0), 105),
+            deal(fill(11, Side::Sell, 98, 2, 0), 105),
+        ]),
+    ];
+    let trades: Vec<_> = from_orderlog(records.into_iter().flatten()).collect();
+    assert_eq!(
+        trades,
+        [
+            trade(102, 100, 1, Side::Buy, (Some(9), None)),
+            trade(103, 99, 1, Side::Sell, (None, Some(3))),
+            trade(104, 101, 1, Side::Sell, (Some(1), Some(2))),
+            trade(105, 98, 2, Side::UNKNOWN, (None, None)),
+        ]
+    );
+}
+
+#[test]
+fn iok_rest() {
+    // the IOK rest removal and the deal timestamp
+    let records = tx(vec![
+        with(add(1, Side::Sell, 99, 4), OLFlags::Counter),
+        at(deal(fill(6, Side::Buy, 100, 1, 0), 106), 63_800_000_000_000),
+        at(deal(fill(1, Side::Sell, 100, 1, 3), 106), 63_800_000_000_000),
+        with(remove(1, Side::Sell, 99), OLFlags::Counter),
+    ]);
+    let trades: Vec<_> = from_orderlog(records).collect();
+    let mut expected = trade(106, 100, 1, Side::Sell, (Some(6), Some(1)));
+    expected.ts = time::ticks_to_unix_ns(63_800_000_000_000);
+    assert_eq!(trades, [expected]);
+}
+
+#[test]
+fn prevailing_book() {
+    use qsh_rs::utils::trades::zip_book;
+
+    let txs = vec![
+        tx(vec![add(1, Side::Buy, 100, 5)]),
+        tx(vec![add(2, Side::Sell, 102, 3)]),
+        tx(vec![add(3, Side::Sell, 101, 2)]),
+        // the buy sweeps two levels
+        tx(vec![
+            add(4, Side::Buy, 102, 4),
+            deal(fill(3, Side::Sell, 101, 2, 0), 1),
+            deal(fill(4, Side::Buy, 102, 2, 2), 1),
+            deal(fill(2, Side::Sell, 102, 2, 1), 2),
+            deal(fill(4, Side::Buy, 102, 2, 0), 2),
+        ]),
+        tx(vec![
+            add(5, Side::Sell, 100, 1),
+            deal(fill(1, Side::Buy, 100, 1, 4), 3),
+            deal(fill(5, Side::Sell, 100, 1, 0), 3),
+        ]),
+        tx(vec![
+            with(add(6, Side::Sell, 100, 1), OLFlags::NewSession),
+            add(7, Side::Buy, 100, 1),
+            deal(fill(6, Side::Sell, 100, 1, 0), 4),
+            deal(fill(7, Side::Buy, 100, 1, 0), 4),
+        ]),
+    ];
+    let fills: Vec<_> = zip_book(txs)
+        .map(Result::unwrap)
+        .map(|(rec, bid, ask)| (rec.deal_id, rec.order_id, bid, ask))
+        .collect();
+    let sweep = (Some((100, 5)), Some((101, 2)));
+    assert_eq!(
+        fills,
+        [
+            // all the fills of the sweep see the book before the aggressor
+            (1, 3, sweep.0, sweep.1),
+            (1, 4, sweep.0, sweep.1),
+            (2, 2, sweep.0, sweep.1),
+            (2, 4, sweep.0, sweep.1),
+            (3, 1, Some((100, 5)), Some((102, 1))),
+            (3, 5, Some((100, 5)), Some((102, 1))),
+            // of the session opening transaction
+            (4, 6, None, None),
+            (4, 7, None, None),
+        ]
+    );
+
+    let mut fills = zip_book([tx(vec![deal(fill(9, Side::Buy, 100, 1, 0), 1)])]);
+    assert!(fills.next().unwrap().is_err());
+    assert!(fills.next().is_none());
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use common::*;
+use qsh_rs::types::{Side, TradeView};
+
+#[test]
+fn side() {
+    assert_eq!(Side::Buy.opposite(), Side::Sell);
+    assert_eq!(Side::Sell.opposite(), Side::Buy);
+    assert_eq!(Side::UNKNOWN.opposite(), Side::UNKNOWN);
+    assert_eq!(Side::both().map(Side::as_sign), [1, -1]);
+    assert_eq!(Side::UNKNOWN.as_sign(), 0);
+}
+
+#[test]
+fn as_trade() {
+    let mut rec = fill(1, Side::Buy, 100, 2, 3);
+    (rec.deal_id, rec.deal_price, rec.oi) = (10, 101, 500);
+    assert_eq!(
+        rec.as_trade(),
+        Some(TradeView { deal_id: 10, deal_price: 101, amount: 2, oi: 500 })
+    );
+
+    rec = add(1, Side::Buy, 100, 5);
+    rec.deal_id = 10;
+    assert_eq!(rec.as_trade(), None);
+    assert_eq!(cancel(1, Side::Buy, 100, 0).as_trade(), None);
+}
+
+#[test]
+fn price_scale() {
+    use qsh_rs::types::PriceScale;
+
+    let enc = Encoder::new(0x70, 0);
+    let hdr = qsh_rs::header(&mut reader(&enc.buf)).unwrap();
+    assert_eq!(hdr.price_step(), Some(1.));
+    assert_eq!(PriceScale::from_header(&hdr).unwrap().scaled_price(76_543), 76_543.);
+
+    let sber = qsh_rs::types::Header { instrument: "QUIK:SBER:TQBR::0.01".into(), ..hdr };
+    assert_eq!(PriceScale::from_header(&sber).unwrap().scaled_price(18_050), 180.5);
+    let unknown = qsh_rs::types::Header { instrument: "QUIK:SBER".into(), ..sber };
+    assert_eq!(unknown.price_step(), None);
+    assert_eq!(PriceScale::new(0.5).scaled_price(3), 1.5);
+}
+
+#[test]
+fn recorder_info() {
+    use qsh_rs::types::RecorderInfo;
+
+    let enc = Encoder::new(0x70, 0);
+    let hdr = qsh_rs::header(&mut reader(&enc.buf)).unwrap();
+    let info = |name: &str, version: Option<&str>, source: Option<&str>| RecorderInfo {
+        name: name.into(),
+        version: version.map(Into::into),
+        source: source.map(Into::into),
+    };
+    assert_eq!(hdr.recorder_info(), info("QshWriter", Some("6870"), Some("zerich")));
+
+    let qscalp = qsh_rs::types::Header {
+        recorder: "QScalp 5.9.2.1".into(),
+        comment: "ERINRV qsh service".into(),
+        ..hdr
+    };
+    assert_eq!(qscalp.recorder_info(), info("QScalp", Some("5.9.2.1"), Some("erinrv")));
+    let unknown = qsh_rs::types::Header {
+        recorder: "Recorder".into(),
+        comment: "QSH Service".into(),
+        ..qscalp
+    };
+    assert_eq!(unknown.recorder_info(), info("Recorder", None, None));
+    assert_eq!(unknown.recorder, "Recorder");
+}
+
+#[test]
+fn flag_set() {
+    use qsh_rs::types::{OLFlagSet, OLFlags};
+
+    let rec = with(add(1, Side::Buy, 100, 5), OLFlags::TxEnd);
+    let flags = rec.order_flags;
+    assert!(flags.contains(OLFlagSet::ADD));
+    assert!(flags.contains(OLFlagSet::ADD | OLFlagSet::TX_END));
+    assert!(!flags.contains(OLFlagSet::ADD | OLFlagSet::FILL));
+    assert!(flags.intersects(OLFlagSet::ADD | OLFlagSet::FILL));
+    assert!(!flags.intersects(OLFlagSet::FILL | OLFlagSet::CANCELED));
+    // the operator form is kept
+    assert!(OLFlags::TxEnd % flags);
+
+    let mut flags = OLFlagSet::empty();
+    flags.insert(OLFlagSet::NEW_SESSION | OLFlagSet::SELL);
+    flags.remove(OLFlagSet::SELL);
+    assert_eq!(flags, OLFlagSet::from(OLFlags::NewSession));
+    assert_eq!(u16::from(flags), 1 << 1);
+}
+
+#[test]
+fn stream_bytes() {
+    use qsh_rs::types::Stream;
+
+    for stream in Stream::ALL {
+        assert_eq!(Stream::from(stream.as_byte()), stream);
+        assert_eq!(u8::from(stream), stream.as_byte());
+    }
+    assert_eq!(Stream::OWNTRADES.as_byte(), 0x40);
+    assert_eq!(Stream::from_byte(0x80), None);
+
+    for byte in [0x10, 0x20, 0x60, 0x70] {
+        let hdr = qsh_rs::header(&mut reader(&Encoder::new(byte, 0).buf)).unwrap();
+        assert_eq!(hdr.stream.as_byte(), byte);
+    }
+    // known, but of no reader
+    assert!(qsh_rs::header(&mut reader(&Encoder::new(0x50, 0).buf)).is_err());
+}
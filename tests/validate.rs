@@ -0,0 +1,65 @@
+mod common;
+
+use common::*;
+use qsh_rs::time;
+use qsh_rs::types::{OLFlags, Side};
+use qsh_rs::utils::validate::{book_replay, replay_records, AnomalyKind};
+
+const T0: i64 = 637200251900000000;
+const TS: i64 = 63_800_000_000_000;
+
+#[test]
+fn anomalies() {
+    let records = [
+        add(1, Side::Buy, 100, 5),
+        with(add(2, Side::Sell, 101, 4), OLFlags::TxEnd),
+        // #1, the cancel of the order never added
+        with(cancel(3, Side::Buy, 100, 0), OLFlags::TxEnd),
+        // #2, the level the book doesn't have, then the cancel over the order amount
+        cancel(4, Side::Sell, 105, 0),
+        with(cancel(1, Side::Buy, 100, 7), OLFlags::TxEnd),
+        with(fill(2, Side::Sell, 101, 1, 3), OLFlags::TxEnd),
+    ];
+    let records = records.iter().enumerate().map(|(i, &rec)| at(rec, TS + i as i64));
+    let report = replay_records(records.clone(), 2);
+
+    assert_eq!((report.records, report.transactions, report.total), (6, 4, 3));
+    assert!(!report.is_clean());
+    assert_eq!(report.anomalies.len(), 2);
+    let first = &report.anomalies[0];
+    assert_eq!(
+        (first.kind.clone(), first.tx_index, first.order_id),
+        (AnomalyKind::UnknownOrder, 1, 3)
+    );
+    assert_eq!(first.ts, time::ticks_to_unix_ns(TS + 2));
+    assert_eq!((first.best_bid, first.best_ask), (Some((100, 5)), Some((101, 4))));
+    assert_eq!(
+        (report.anomalies[1].kind.clone(), report.anomalies[1].tx_index),
+        (AnomalyKind::MissingLevel, 2)
+    );
+
+    let all = replay_records(records, usize::MAX);
+    assert_eq!(all.anomalies.len(), 3);
+    assert_eq!(
+        (all.anomalies[2].kind.clone(), all.anomalies[2].order_id),
+        (AnomalyKind::VolumeMismatch, 1)
+    );
+    assert!(all.to_string().starts_with("records: 6, transactions: 4, anomalies: 3\n"));
+    assert_eq!(all.to_string().lines().count(), 4);
+}
+
+#[test]
+fn clean_file() {
+    let mut enc = Encoder::new(0x70, T0);
+    for (i, rec) in Workload::new(5, 10).full_cancels().take(2_000).enumerate() {
+        enc.orderlog(1, &at(with(rec, OLFlags::TxEnd), TS + i as i64));
+    }
+    let path = std::env::temp_dir().join(format!("qsh-validate-{}.qsh", std::process::id()));
+    std::fs::write(&path, enc.gzip()).unwrap();
+    let report = book_replay(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let report = report.unwrap();
+    assert!(report.is_clean(), "{report}");
+    assert_eq!((report.records, report.transactions), (2_000, 2_000));
+}
@@ -1,18 +1,26 @@
 use anyhow::{self as ah, Context};
-use bincode::{config, encode_into_std_write};
-use flate2::{write::GzEncoder, Compression};
-use qsh_rs::{inflate, utils::l3tol2::convert, OrderLogReader, QshRead};
+use flate2::bufread::GzDecoder;
+use memmap2::Mmap;
+use qsh_rs::{
+    inflate, inflate_reader,
+    types::{Header, Stream},
+    utils::{
+        l2io::L2Writer,
+        l3tol2::{try_convert_with, ConvertOptions},
+    },
+    OrderLogReader, QshError, QshRead,
+};
 use rayon::prelude::*;
 use std::{
-    fs::OpenOptions,
-    io::{BufWriter, Write},
-    path::PathBuf,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
 };
 
 struct Job {
     input: PathBuf,
     output: Box<dyn Write>,
-    depth: usize,
+    options: ConvertOptions,
 }
 
 unsafe impl Send for Job {}
@@ -24,32 +32,47 @@ pub struct Stat {
     len: usize,
 }
 
-fn process_job(Job { input, output, depth }: Job) -> ah::Result<Stat> {
+fn process_job(Job { input, output, options }: Job) -> ah::Result<Stat> {
     let mut bytes = inflate(input.to_path_buf())?;
     let _ = qsh_rs::header(&mut bytes)?;
-    let reader = bytes.into_iter::<OrderLogReader>();
+    write_l2(bytes, input, output, options)
+}
+
+// `bytes` are positioned right after the header
+fn write_l2(
+    bytes: impl BufRead,
+    input: PathBuf,
+    output: Box<dyn Write>,
+    options: ConvertOptions,
+) -> ah::Result<Stat> {
+    let reader = bytes.try_iter::<OrderLogReader>();
 
-    let mut encoder =
-        GzEncoder::new(BufWriter::with_capacity(50 << 20, output), Compression::best());
-    let config = config::standard();
+    let mut writer = L2Writer::new(BufWriter::with_capacity(50 << 20, output));
     let mut stat = Stat { _input: input, len: 0 };
-    for tx in convert(reader, depth) {
+    for tx in try_convert_with(reader, options) {
         let tx = tx?;
         stat.len += tx.len();
-        for msg in tx {
-            encode_into_std_write(msg, &mut encoder, config)?;
+        for msg in &tx {
+            writer.write(msg)?;
         }
     }
-    encoder.finish()?.flush()?;
+    writer.finish()?.flush()?;
 
     Ok(stat)
 }
 
-fn out_sink(input: &PathBuf, output: Option<PathBuf>) -> ah::Result<Box<dyn Write>> {
+/// name of the output file of the `input`, the input name with the `suffix` in place of its
+/// extension, the stream kind is kept, e.g. `Si-3.20.2020-03-17.OrdLog.bin`
+pub fn out_name(input: &Path, suffix: &str) -> PathBuf {
+    let mut name = input.file_stem().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    name.into()
+}
+
+fn out_sink(input: &Path, output: Option<&Path>, suffix: &str) -> ah::Result<Box<dyn Write>> {
     match output {
-        Some(ref dir) => {
-            let fname = input.file_name().unwrap().to_string_lossy();
-            let file_path = dir.join(&fname[..fname.len() - 4]).with_extension("bin");
+        Some(dir) => {
+            let file_path = dir.join(out_name(input, suffix));
             let file = OpenOptions::new()
                 .write(true)
                 .create(true)
@@ -62,17 +85,97 @@ fn out_sink(input: &PathBuf, output: Option<PathBuf>) -> ah::Result<Box<dyn Writ
     }
 }
 
+/// reads qsh header decompressing the memory-mapped file just enough to get it
+pub fn read_header(path: &Path) -> Result<Header, QshError> {
+    let file = File::open(path)?;
+    // SAFETY: the mapping is read-only, input files are not expected to be modified while
+    // the tool is running
+    let mmap = unsafe { Mmap::map(&file)? };
+    let mut reader = BufReader::with_capacity(1 << 10, GzDecoder::new(&mmap[..]));
+    qsh_rs::header(&mut reader)
+}
+
+/// canonical path of the input `line`, trimmed of the whitespace, e.g. the `\r` of the CRLF line
+/// endings, and of the UTF-8 BOM the first line of the Windows tools output may have
+pub fn input_path(line: &str) -> std::io::Result<PathBuf> {
+    std::fs::canonicalize(line.trim_start_matches('\u{feff}').trim())
+}
+
+/// reads headers of the `paths` in parallel
+pub fn validate_headers(paths: &[PathBuf]) -> Vec<Result<Header, QshError>> {
+    paths.par_iter().map(|path| read_header(path)).collect()
+}
+
+/// converts the qsh bytes piped to the standard input, the header is validated on the fly as
+/// the stream can't be read twice. Output file, if any, is named `stdin{suffix}`
+pub fn process_stdin(
+    output: Option<PathBuf>,
+    suffix: &str,
+    options: ConvertOptions,
+) -> ah::Result<Stat> {
+    let mut bytes = inflate_reader(std::io::stdin().lock());
+    let header = qsh_rs::header(&mut bytes).context("failed to read qsh header from stdin")?;
+    if header.stream != Stream::ORDERLOG {
+        ah::bail!(
+            "failed to validate stdin\n{header:?}\n expecting file of 'Stream::ORDERLOG' stream type"
+        );
+    }
+    let input = PathBuf::from("stdin.qsh");
+    let out = out_sink(&input, output.as_deref(), suffix)?;
+    write_l2(bytes, input, out, options)
+}
+
 pub fn schedule(
     inputs: Vec<PathBuf>,
     output: Option<PathBuf>,
-    depth: usize,
+    suffix: &str,
+    options: ConvertOptions,
 ) -> Vec<ah::Result<Stat>> {
     inputs
         .into_par_iter()
         .map(|input| {
-            out_sink(&input, output.clone())
-                .map(|out| Job { output: out, input, depth })
+            let context = format!("failed to convert {input:?}");
+            out_sink(&input, output.as_deref(), suffix)
+                .map(|out| Job { output: out, input, options })
                 .and_then(process_job)
+                .context(context)
         })
         .collect::<_>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{input_path, out_name};
+    use std::path::Path;
+
+    #[test]
+    fn output_names() {
+        let name = |input: &str, suffix| out_name(Path::new(input), suffix);
+        assert_eq!(
+            name("/data/Si-3.20.2020-03-17.OrdLog.qsh", ".bin"),
+            Path::new("Si-3.20.2020-03-17.OrdLog.bin")
+        );
+        assert_eq!(
+            name("Si-3.20.2020-03-17.OrdLog.qsh", ".l2.bin"),
+            Path::new("Si-3.20.2020-03-17.OrdLog.l2.bin")
+        );
+        // any other name keeps all but the extension too
+        assert_eq!(name("dir/book.qsh", ".bin"), Path::new("book.bin"));
+        assert_eq!(name("a.gz", ".l2.bin"), Path::new("a.l2.bin"));
+        assert_eq!(name("q", ".bin"), Path::new("q.bin"));
+        assert_eq!(name(".qsh", ".bin"), Path::new(".qsh.bin"));
+    }
+
+    #[test]
+    fn windows_input() {
+        let path = std::env::temp_dir().join(format!("l3tol2-input-{}.qsh", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        let line = path.to_str().unwrap();
+        let expected = std::fs::canonicalize(&path).unwrap();
+        for input in [format!("{line}\r\n"), format!("\u{feff}{line}\r"), format!(" {line} ")] {
+            assert_eq!(input_path(&input).unwrap(), expected);
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!(input_path(&format!("{line}\r\n")).is_err());
+    }
+}
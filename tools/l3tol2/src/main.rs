@@ -2,7 +2,9 @@ use ah::Context;
 use anyhow as ah;
 use clap::Parser;
 use faccess::PathExt;
-use qsh_rs::{inflate, types::Stream};
+use qsh_rs::types::Stream;
+use qsh_rs::utils::l3tol2::{ConvertOptions, SnapshotEvery};
+use qsh_rs::QshError;
 use std::{io::BufRead, path::PathBuf};
 
 /// Reads standard input for the paths to the qsh files containing L3 market data, and produces L2 incremental events for each file.
@@ -13,6 +15,18 @@ struct Args {
     #[clap(short, long, value_parser, default_value_t = 0)]
     depth: u16,
 
+    /// Embed the book snapshot after every N transactions, '0' for none
+    #[clap(long, value_parser, default_value_t = 0)]
+    snapshot_every: usize,
+
+    /// Read the qsh file bytes from standard input instead of the paths
+    #[clap(long)]
+    stdin: bool,
+
+    /// Suffix of the output files, replacing the input extension, e.g. '.l2.bin'
+    #[clap(long, value_parser, default_value = ".bin")]
+    suffix: String,
+
     /// Path to save files in if specified, otherwise outputs to stdout
     #[clap(parse(from_os_str))]
     output: Option<PathBuf>,
@@ -21,15 +35,30 @@ struct Args {
 fn main() -> ah::Result<()> {
     let args = Args::parse();
 
+    let output = validate_output(&args.output)?;
+    let options = ConvertOptions {
+        depth: args.depth as usize,
+        snapshot_every: (args.snapshot_every > 0)
+            .then_some(SnapshotEvery::Transactions(args.snapshot_every)),
+        ..Default::default()
+    };
+    if args.stdin {
+        l3tol2::process_stdin(output, &args.suffix, options)?;
+        return Ok(());
+    }
+
     // collect input, validate
     let mut inputs = Vec::with_capacity(50);
     for line in std::io::stdin().lock().lines() {
-        // file
+        // file, e.g. of the `dir /b` output with the CRLF line endings
         let line = line?;
+        if line.trim_start_matches('\u{feff}').trim().is_empty() {
+            continue;
+        }
 
         // do exists
-        let path = std::fs::canonicalize(PathBuf::from(line.clone()))
-            .with_context(|| format!("input file \"{line}\" not exists"))?;
+        let path = l3tol2::input_path(&line)
+            .with_context(|| format!("input file \"{}\" not exists", line.trim()))?;
 
         // is readable
         if !path.readable() {
@@ -41,22 +70,49 @@ fn main() -> ah::Result<()> {
             ah::bail!("{path:?} is not a 'QScalp History File(qsh)'");
         }
 
-        // is valid qsh file of expected stream type
-        let mut parser = inflate(path.clone())?;
-        let header = qsh_rs::header(&mut parser)
-            .with_context(|| format!("failed to read qsh header from {path:?}"))?;
+        inputs.push(path);
+    }
+
+    // is valid qsh file of expected stream type
+    for (path, header) in inputs.iter().zip(l3tol2::validate_headers(&inputs)) {
+        let header = header.with_context(|| format!("failed to read qsh header from {path:?}"))?;
 
         if header.stream != Stream::ORDERLOG {
             ah::bail!(
                 "failed to validate {path:?}\n{header:?}\n expecting file of 'Stream::ORDERLOG' stream type"
             );
         }
+    }
 
-        inputs.push(path);
+    // process
+    let stats = l3tol2::schedule(inputs, output, &args.suffix, options);
+    //println!("{stats:?}");
+
+    // a bad file doesn't stop the rest
+    let failed: Vec<_> = stats.iter().filter_map(|stat| stat.as_ref().err()).collect();
+    for err in &failed {
+        eprintln!("{err:#}");
+    }
+    if !failed.is_empty() {
+        // the truncated files are worth fetching again, the corrupted ones are not
+        let count = |f: fn(&QshError) -> bool| {
+            failed.iter().filter(|err| err.downcast_ref::<QshError>().is_some_and(f)).count()
+        };
+        let truncated = count(|err| matches!(err, QshError::Truncated(_)));
+        let corrupted = count(|err| matches!(err, QshError::Corrupted(_)));
+        ah::bail!(
+            "{} of {} files failed, {truncated} truncated, {corrupted} corrupted",
+            failed.len(),
+            stats.len()
+        );
     }
 
-    // validate output path
-    let output = if let Some(path) = &args.output {
+    Ok(())
+}
+
+// validates output path
+fn validate_output(output: &Option<PathBuf>) -> ah::Result<Option<PathBuf>> {
+    Ok(if let Some(path) = output {
         let output = std::fs::canonicalize(path)
             .with_context(|| format!("output path {path:?} not exists/reachable"))?;
         if !output.is_dir() {
@@ -67,13 +123,7 @@ fn main() -> ah::Result<()> {
         Some(output)
     } else {
         None
-    };
-
-    // process
-    let stats = l3tol2::schedule(inputs, output, args.depth as usize);
-    //println!("{stats:?}");
-
-    Ok(())
+    })
 }
 
 // 05.09.14-01.05.21 ~ 7 years
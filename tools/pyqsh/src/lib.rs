@@ -1,32 +1,42 @@
 use ndarray::Array2;
 use numpy::{IntoPyArray, PyArray2};
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::wrap_pyfunction;
 
-use qsh_rs::orderbook::{self as ob, PartitionBy};
+use qsh_rs::orderbook::{self as ob, OrderLogStream};
 use qsh_rs::types::OrderLog;
 use qsh_rs::types::OrderType;
-use qsh_rs::types::Timestamp;
-use qsh_rs::types::{OLFlags, OLMsgType, Side};
-use qsh_rs::{header, inflate, OrderLogReader, QshRead, QuotesReader};
+use qsh_rs::types::OLMsgType;
+use qsh_rs::{header, inflate, time, OrderLogReader, QshRead, QuotesReader};
+
+// rough compressed bytes per record, the records are the deltas of the previous ones, of no
+// fixed size
+const ORDERLOG_RECORD_BYTES: u64 = 8;
+const QUOTES_RECORD_BYTES: u64 = 16;
+
+// estimate of the number of the records of the `file`, the initial capacity of the rows built
+// of them, which grow past it all the same
+fn records_estimate(file: &str, record_bytes: u64) -> usize {
+    std::fs::metadata(file).map_or(0, |m| (m.len() / record_bytes) as usize)
+}
 
 #[inline]
 fn ol_transactions(file: String) -> impl Iterator<Item = Vec<OrderLog>> {
     let mut parser = inflate(file.into()).unwrap();
     header(&mut parser).unwrap();
 
-    parser
-        .into_iter::<OrderLogReader>()
-        .filter(ob::system_record)
-        .partition_by(ob::tx_end)
-        .filter(ob::fiok_with_trades)
+    parser.into_iter::<OrderLogReader>().transactions()
 }
 
 #[pyfunction]
 pub fn orders(file: String) -> PyResult<Py<PyArray2<i64>>> {
-    let records = ol_transactions(file).fold(Vec::with_capacity(10 << 20), |mut acc, tx| {
+    let capacity = records_estimate(&file, ORDERLOG_RECORD_BYTES) * 6;
+    let records = ol_transactions(file).fold(Vec::with_capacity(capacity), |mut acc, tx| {
         // [timestamp, order_id, kind, side, price, amount] : i64
         //
+        // timestamp: unix nanos, as of `lob` and `quotes`
+        //
         // kind:
         // 0 - Limit
         // 1 - IOK
@@ -45,11 +55,12 @@ pub fn orders(file: String) -> PyResult<Py<PyArray2<i64>>> {
                     OrderType::FOK => 2,
                     _ => unreachable!("unknown order type"),
                 };
-                acc.extend([r.timestamp, r.order_id, kind, r.side as i64, r.price, r.amount]);
+                let ts = time::ticks_to_unix_ns(r.timestamp);
+                acc.extend([ts, r.order_id, kind, r.side as i64, r.price, r.amount]);
             }
             OLMsgType::Cancel | OLMsgType::Remove => {
                 if OrderType::from(r.order_flags) == OrderType::Limit {
-                    acc.extend([r.timestamp, r.order_id, 3, 0, 0, 0])
+                    acc.extend([time::ticks_to_unix_ns(r.timestamp), r.order_id, 3, 0, 0, 0])
                 }
             }
             OLMsgType::Fill => (),
@@ -69,31 +80,25 @@ pub fn orders(file: String) -> PyResult<Py<PyArray2<i64>>> {
 
 #[pyfunction]
 pub fn lob(file: String, depth: usize) -> PyResult<Py<PyArray2<i64>>> {
-    let mut book: ob::OrderBook = Default::default();
-
-    let snapshots = ol_transactions(file).fold(Vec::with_capacity(10 << 20), |mut acc, tx| {
-        if OLFlags::NewSession % tx[0].order_flags {
-            book.clear();
-        }
-        tx.into_iter().for_each(|r| {
-            match OLMsgType::from(&r) {
-                OLMsgType::Add => book.add(r, None),
-                OLMsgType::Fill => book.trade(r, None),
-                OLMsgType::Cancel | OLMsgType::Remove => book.cancel(r, None),
-                OLMsgType::UNKNOWN => unreachable!(),
-            }
-            .unwrap()
-        });
-        if book.depth(Side::Buy) >= depth && book.depth(Side::Sell) >= depth {
-            let (ts, s) = book.snapshot(depth);
-            acc.push(ts);
-            acc.extend(s);
-        }
-        acc
-    });
+    let mut book = ob::OrderBook::default();
+    // a row per transaction, there are fewer of them than the records
+    let rows = records_estimate(&file, ORDERLOG_RECORD_BYTES) / 2;
+    let (mut snapshots, mut widths) =
+        (Vec::with_capacity(rows * (depth.max(1) * 2 * 2 + 1)), Vec::with_capacity(rows));
+    for tx in ol_transactions(file) {
+        book.apply_tx(&tx, &mut ob::NopListener).unwrap();
+        // the row is written in place, no snapshot allocation per transaction, the sides
+        // thinner than `depth` are zero padded
+        let width = book.snapshot_width(depth);
+        let row = snapshots.len();
+        snapshots.resize(row + width * 2 * 2 + 1, 0);
+        book.snapshot_upto_into(width, &mut snapshots[row + 1..]).unwrap();
+        snapshots[row] = book.last_update().unwrap_or_default();
+        widths.push(width);
+    }
 
-    let row_size = depth * 2 * 2 + 1;
-    let output_shape = (snapshots.len() / row_size, row_size);
+    let (snapshots, row_size) = pad_rows(snapshots, &widths);
+    let output_shape = (widths.len(), row_size);
 
     Ok(Python::with_gil(|py| {
         Array2::from_shape_vec(output_shape, snapshots).unwrap().into_pyarray(py).to_owned()
@@ -102,33 +107,58 @@ pub fn lob(file: String, depth: usize) -> PyResult<Py<PyArray2<i64>>> {
 
 #[pyfunction]
 pub fn quotes(file: String, depth: usize) -> PyResult<Py<PyArray2<i64>>> {
+    let rows = records_estimate(&file, QUOTES_RECORD_BYTES);
+    let capacity = rows * (depth.max(1) * 2 * 2 + 1);
     let mut parser = inflate(file.into()).unwrap();
     let header = header(&mut parser).unwrap();
     let iter = parser.into_iter::<QuotesReader>();
-    let unix_time_start = header.recording_time / 1e4 as Timestamp - 62135596800000;
+    let recording_time =
+        header.recording_time_or_err().map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let unix_time_start = time::ns_to_ms(time::recording_time_to_unix_ns(recording_time));
+    let mut widths = Vec::with_capacity(rows);
     let quotes = iter
         .filter(|q| q.ask.len() >= depth && q.bid.len() >= depth)
-        .fold((Vec::with_capacity(10 << 20), unix_time_start), |(mut vec, mut time), q| {
+        .fold((Vec::with_capacity(capacity), unix_time_start), |(mut vec, mut time), q| {
             time += q.frame_time_delta;
-            vec.push(time);
-            vec.extend(
-                q.bid
-                    .into_iter()
-                    .take(depth)
-                    .zip(q.ask.into_iter().take(depth))
-                    .flat_map(|(b, a)| [b.0, b.1, a.0, a.1]),
-            );
+            vec.push(time::ms_to_ns(time));
+            // `0` depth takes all the levels, the shallower side zero padded
+            let width = if depth == 0 { q.bid.len().max(q.ask.len()) } else { depth };
+            let level = |side: &[(i64, i64)], i: usize| side.get(i).copied().unwrap_or_default();
+            vec.extend((0..width).flat_map(|i| {
+                let (b, a) = (level(&q.bid, i), level(&q.ask, i));
+                [b.0, b.1, a.0, a.1]
+            }));
+            widths.push(width);
             (vec, time)
         })
         .0;
-    let row_size = depth * 2 * 2 + 1;
-    let output_shape = (quotes.len() / row_size, row_size);
+    let (quotes, row_size) = pad_rows(quotes, &widths);
+    let output_shape = (widths.len(), row_size);
 
     Ok(Python::with_gil(|py| {
         Array2::from_shape_vec(output_shape, quotes).unwrap().into_pyarray(py).to_owned()
     }))
 }
 
+// `[timestamp, levels..]` rows of `widths` levels each, zero padded to the widest one, as
+// the `0`(unlimited) depth rows are ragged
+fn pad_rows(rows: Vec<i64>, widths: &[usize]) -> (Vec<i64>, usize) {
+    let max = widths.iter().copied().max().unwrap_or_default();
+    let row_size = max * 2 * 2 + 1;
+    if widths.iter().all(|&w| w == max) {
+        return (rows, row_size);
+    }
+    let mut padded = Vec::with_capacity(widths.len() * row_size);
+    let mut offset = 0;
+    for &width in widths {
+        let len = width * 2 * 2 + 1;
+        padded.extend_from_slice(&rows[offset..offset + len]);
+        padded.resize(padded.len() + (max - width) * 2 * 2, 0);
+        offset += len;
+    }
+    (padded, row_size)
+}
+
 #[pymodule]
 fn pyqsh(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(lob, m)?)?;
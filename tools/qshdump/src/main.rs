@@ -0,0 +1,149 @@
+use ah::Context;
+use anyhow as ah;
+use clap::Parser;
+use qsh_rs::{
+    header, inflate,
+    source::{record_source, Record},
+    time,
+    types::{Side, Stream, Timestamp},
+};
+use std::{
+    io::{BufWriter, ErrorKind, Write},
+    path::PathBuf,
+};
+
+/// Prints the records of the qsh file of any stream, the reader is picked by the file header. The header goes to stderr.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the qsh file, '-' for the standard input
+    #[clap(parse(from_os_str))]
+    input: PathBuf,
+
+    /// Output format, 'csv' or 'debug'
+    #[clap(short, long, value_enum, default_value = "csv")]
+    format: Format,
+
+    /// Print the first N records only, '0' for all
+    #[clap(short = 'n', long, value_parser, default_value_t = 0)]
+    limit: usize,
+
+    /// Print the header only
+    #[clap(long)]
+    header_only: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Debug,
+}
+
+fn main() -> ah::Result<()> {
+    let args = Args::parse();
+    match dump(&args) {
+        // e.g. `qshdump file.qsh | head`
+        Err(err)
+            if err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        ret => ret,
+    }
+}
+
+fn dump(args: &Args) -> ah::Result<()> {
+    let mut parser =
+        inflate(args.input.clone()).with_context(|| format!("failed to open {:?}", args.input))?;
+    let header = header(&mut parser)
+        .with_context(|| format!("failed to read qsh header from {:?}", args.input))?;
+    eprintln!("{header:#?}");
+    if args.header_only {
+        return Ok(());
+    }
+
+    let records = record_source(header.stream, parser)?;
+    let limit = if args.limit == 0 { usize::MAX } else { args.limit };
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    if args.format == Format::Csv {
+        writeln!(out, "{}", columns(header.stream))?;
+    }
+    let mut clock = time::FrameClock::new(header.recording_time_or_err()?);
+    for (i, rec) in records.take(limit).enumerate() {
+        let rec = rec.with_context(|| format!("failed to read record {i}"))?;
+        let frame_time = clock.advance(rec.frame_time_delta());
+        match args.format {
+            Format::Csv => write_csv(&mut out, frame_time, &rec)?,
+            Format::Debug => writeln!(out, "{rec:?}")?,
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// all the times are unix nanos, the quotes are one row per level, the removed ones of `0` volume
+fn columns(stream: Stream) -> &'static str {
+    match stream {
+        Stream::QUOTES => "frame_time,side,price,volume",
+        Stream::DEALS => "frame_time,timestamp,deal_id,order_id,side,price,amount,oi",
+        Stream::AUXINFO => {
+            "frame_time,timestamp,price,bid_total,ask_total,oi,hi_limit,low_limit,deposit,rate,message"
+        }
+        _ => "frame_time,timestamp,order_id,side,price,amount,amount_rest,deal_id,deal_price,oi,order_flags",
+    }
+}
+
+fn write_csv(out: &mut impl Write, frame_time: Timestamp, rec: &Record) -> std::io::Result<()> {
+    let ts = time::ticks_to_unix_ns;
+    match rec {
+        Record::Quotes(q) => {
+            let levels = q.bid.iter().map(|&(p, v)| (Side::Buy, p, v));
+            let levels = levels.chain(q.ask.iter().map(|&(p, v)| (Side::Sell, p, v)));
+            for (side, price, volume) in levels.chain(q.removed.iter().map(|&(s, p)| (s, p, 0))) {
+                writeln!(out, "{frame_time},{side:?},{price},{volume}")?;
+            }
+            Ok(())
+        }
+        Record::Deal(d) => writeln!(
+            out,
+            "{frame_time},{},{},{},{:?},{},{},{}",
+            ts(d.timestamp),
+            d.deal_id,
+            d.order_id,
+            d.side,
+            d.price,
+            d.amount,
+            d.oi
+        ),
+        Record::AuxInfo(a) => writeln!(
+            out,
+            "{frame_time},{},{},{},{},{},{},{},{},{},\"{}\"",
+            ts(a.timestamp),
+            a.price,
+            a.bid_total,
+            a.ask_total,
+            a.oi,
+            a.hi_limit,
+            a.low_limit,
+            a.deposit,
+            a.rate,
+            a.message.replace('"', "\"\"")
+        ),
+        Record::OrderLog(r) => writeln!(
+            out,
+            "{frame_time},{},{},{:?},{},{},{},{},{},{},{:#06x}",
+            ts(r.timestamp),
+            r.order_id,
+            r.side,
+            r.price,
+            r.amount,
+            r.amount_rest,
+            r.deal_id,
+            r.deal_price,
+            r.oi,
+            r.order_flags.0
+        ),
+    }
+}